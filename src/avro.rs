@@ -0,0 +1,483 @@
+//! Avro output: JSON messages re-encoded as Avro single-object encoding, or in the
+//! Confluent wire format once the schema is registered, against an Avro schema derived
+//! from the generator's JSON Schema.
+
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+/// Namespace of every generated Avro type name.
+const NAMESPACE: &str = "fluxfakr";
+/// Marker bytes that open an Avro single-object encoded message.
+const MAGIC: [u8; 2] = [0xC3, 0x01];
+/// Magic byte that opens a Confluent wire-format message, ahead of the schema id.
+const REGISTRY_MAGIC: u8 = 0x00;
+/// Seed of the CRC-64-AVRO (Rabin) fingerprint, from the Avro specification.
+const FINGERPRINT_EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+/// An Avro type, as far as generator schemas need: nullable fields become a
+/// `["null", T]` union.
+#[derive(Debug, Clone, PartialEq)]
+enum AvroType {
+    Boolean,
+    Long,
+    Double,
+    String,
+    Enum { name: String, symbols: Vec<String> },
+    Record { name: String, fields: Vec<Field> },
+    Nullable(Box<AvroType>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    name: String,
+    doc: Option<String>,
+    ty: AvroType,
+    /// Not required by the JSON Schema: omitted from JSON when null, defaulted in Avro.
+    optional: bool,
+}
+
+/// `store_format` -> `StoreFormat`, for type names derived from field names.
+fn pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word[..1].to_ascii_uppercase() + &word[1..])
+        .collect()
+}
+
+/// Whether `name` is a valid Avro name: a letter or `_`, then letters, digits or `_`.
+fn is_avro_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Convert one JSON Schema node to an Avro type. `path` is the field's dotted path, for
+/// errors; `type_name` names it if it becomes a record or enum.
+fn convert(schema: &Value, path: &str, type_name: &str, names: &mut HashSet<String>) -> Result<AvroType, String> {
+    let named = |names: &mut HashSet<String>| {
+        let name = format!("{}.{}", NAMESPACE, type_name);
+        if is_avro_name(type_name) && names.insert(name.clone()) {
+            Ok(name)
+        } else {
+            Err(format!("field '{}' cannot be named '{}' in Avro", path, type_name))
+        }
+    };
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        let symbols: Vec<String> = values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect();
+        if symbols.len() != values.len() || !symbols.iter().all(|s| is_avro_name(s)) {
+            return Err(format!("field '{}' has enum values that are not valid Avro symbols", path));
+        }
+        return Ok(AvroType::Enum { name: named(names)?, symbols });
+    }
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(t)) => vec![t.as_str()],
+        _ => Vec::new(),
+    };
+    let nullable = types.contains(&"null");
+    let ty = match types.iter().filter(|t| **t != "null").collect::<Vec<_>>()[..] {
+        [&"boolean"] => AvroType::Boolean,
+        [&"integer"] => AvroType::Long,
+        [&"number"] => AvroType::Double,
+        [&"string"] => AvroType::String,
+        [&"object"] => {
+            let name = named(names)?;
+            let required: Vec<&str> = schema
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|r| r.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let mut fields = Vec::new();
+            for (field, child) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+                if !is_avro_name(field) {
+                    return Err(format!("field '{}.{}' is not a valid Avro name", path, field));
+                }
+                let child_path = if path.is_empty() { field.clone() } else { format!("{}.{}", path, field) };
+                let ty = convert(child, &child_path, &pascal_case(field), names)?;
+                let optional = !required.contains(&field.as_str());
+                let ty = match ty {
+                    AvroType::Nullable(_) => ty,
+                    ty if optional => AvroType::Nullable(Box::new(ty)),
+                    ty => ty,
+                };
+                fields.push(Field {
+                    name: field.clone(),
+                    doc: child.get("description").and_then(Value::as_str).map(str::to_string),
+                    ty,
+                    optional,
+                });
+            }
+            AvroType::Record { name, fields }
+        }
+        _ => return Err(format!("field '{}' has no single JSON type to map to Avro", path)),
+    };
+    Ok(if nullable { AvroType::Nullable(Box::new(ty)) } else { ty })
+}
+
+/// The schema in Avro's Parsing Canonical Form, which its fingerprint is taken over.
+fn canonical_form(ty: &AvroType) -> String {
+    match ty {
+        AvroType::Boolean => "\"boolean\"".to_string(),
+        AvroType::Long => "\"long\"".to_string(),
+        AvroType::Double => "\"double\"".to_string(),
+        AvroType::String => "\"string\"".to_string(),
+        AvroType::Enum { name, symbols } => format!(
+            r#"{{"name":"{}","type":"enum","symbols":[{}]}}"#,
+            name,
+            symbols.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(",")
+        ),
+        AvroType::Record { name, fields } => format!(
+            r#"{{"name":"{}","type":"record","fields":[{}]}}"#,
+            name,
+            fields
+                .iter()
+                .map(|f| format!(r#"{{"name":"{}","type":{}}}"#, f.name, canonical_form(&f.ty)))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        AvroType::Nullable(ty) => format!(r#"["null",{}]"#, canonical_form(ty)),
+    }
+}
+
+/// The schema as a full Avro schema document, with docs and null defaults.
+fn schema_document(ty: &AvroType) -> Value {
+    match ty {
+        AvroType::Boolean => json!("boolean"),
+        AvroType::Long => json!("long"),
+        AvroType::Double => json!("double"),
+        AvroType::String => json!("string"),
+        AvroType::Enum { name, symbols } => json!({"type": "enum", "name": name, "symbols": symbols}),
+        AvroType::Record { name, fields } => {
+            let fields: Vec<Value> = fields
+                .iter()
+                .map(|f| {
+                    let mut field = json!({"name": f.name, "type": schema_document(&f.ty)});
+                    if let Some(doc) = &f.doc {
+                        field["doc"] = json!(doc);
+                    }
+                    if f.optional {
+                        field["default"] = Value::Null;
+                    }
+                    field
+                })
+                .collect();
+            json!({"type": "record", "name": name, "fields": fields})
+        }
+        AvroType::Nullable(ty) => json!(["null", schema_document(ty)]),
+    }
+}
+
+/// CRC-64-AVRO (Rabin) fingerprint of `data`.
+fn fingerprint(data: &[u8]) -> u64 {
+    let mut table = [0u64; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut fp = i as u64;
+        for _ in 0..8 {
+            fp = (fp >> 1) ^ (FINGERPRINT_EMPTY & (fp & 1).wrapping_neg());
+        }
+        *entry = fp;
+    }
+    data.iter()
+        .fold(FINGERPRINT_EMPTY, |fp, &b| (fp >> 8) ^ table[((fp ^ b as u64) & 0xff) as usize])
+}
+
+/// Zigzag varint, as Avro writes `long`s and lengths.
+fn push_long(out: &mut Vec<u8>, n: i64) {
+    let mut z = ((n << 1) ^ (n >> 63)) as u64;
+    while z >= 0x80 {
+        out.push((z as u8 & 0x7f) | 0x80);
+        z >>= 7;
+    }
+    out.push(z as u8);
+}
+
+fn encode_value(value: &Value, ty: &AvroType, path: &str, out: &mut Vec<u8>) -> Result<(), String> {
+    let mismatch = || format!("{} does not match the Avro schema: {}", path, value);
+    match ty {
+        AvroType::Boolean => out.push(value.as_bool().ok_or_else(mismatch)? as u8),
+        AvroType::Long => push_long(out, value.as_i64().ok_or_else(mismatch)?),
+        AvroType::Double => out.extend(value.as_f64().ok_or_else(mismatch)?.to_le_bytes()),
+        AvroType::String => {
+            let s = value.as_str().ok_or_else(mismatch)?;
+            push_long(out, s.len() as i64);
+            out.extend(s.as_bytes());
+        }
+        AvroType::Enum { symbols, .. } => {
+            let s = value.as_str().ok_or_else(mismatch)?;
+            let index = symbols.iter().position(|symbol| symbol == s).ok_or_else(mismatch)?;
+            push_long(out, index as i64);
+        }
+        AvroType::Record { fields, .. } => {
+            let map = value.as_object().ok_or_else(mismatch)?;
+            if let Some(extra) = map.keys().find(|key| !fields.iter().any(|f| &f.name == *key)) {
+                return Err(format!("field '{}' is not in the Avro schema", join(path, extra)));
+            }
+            for field in fields {
+                let value = map.get(&field.name).unwrap_or(&Value::Null);
+                encode_value(value, &field.ty, &join(path, &field.name), out)?;
+            }
+        }
+        AvroType::Nullable(_) if value.is_null() => push_long(out, 0),
+        AvroType::Nullable(ty) => {
+            push_long(out, 1);
+            encode_value(value, ty, path, out)?;
+        }
+    }
+    Ok(())
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+/// Reads Avro binary data back, to recover the JSON of an encoded message.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        if self.data.len() < n {
+            return Err("truncated Avro data".to_string());
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn long(&mut self) -> Result<i64, String> {
+        let mut z = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            z |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((z >> 1) as i64 ^ -((z & 1) as i64));
+            }
+        }
+        Err("Avro long is too long".to_string())
+    }
+
+    fn value(&mut self, ty: &AvroType) -> Result<Value, String> {
+        Ok(match ty {
+            AvroType::Boolean => json!(self.take(1)?[0] != 0),
+            AvroType::Long => json!(self.long()?),
+            AvroType::Double => json!(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            AvroType::String => {
+                let len = usize::try_from(self.long()?).map_err(|_| "negative Avro string length")?;
+                let bytes = self.take(len)?;
+                json!(std::str::from_utf8(bytes).map_err(|e| e.to_string())?)
+            }
+            AvroType::Enum { symbols, .. } => {
+                let index = self.long()?;
+                let symbol = usize::try_from(index).ok().and_then(|i| symbols.get(i));
+                json!(symbol.ok_or_else(|| format!("Avro enum index {} out of range", index))?)
+            }
+            AvroType::Record { fields, .. } => {
+                let mut map = Map::new();
+                for field in fields {
+                    let value = self.value(&field.ty)?;
+                    if !(field.optional && value.is_null()) {
+                        map.insert(field.name.clone(), value);
+                    }
+                }
+                Value::Object(map)
+            }
+            AvroType::Nullable(ty) => match self.long()? {
+                0 => Value::Null,
+                1 => self.value(ty)?,
+                branch => return Err(format!("Avro union branch {} out of range", branch)),
+            },
+        })
+    }
+}
+
+/// Encodes messages as Avro single-object encoding (`C3 01` marker, the schema's
+/// CRC-64-AVRO fingerprint little-endian, then the binary datum), or with a Schema
+/// Registry id in the Confluent wire format (a `00` byte, the id big-endian, then the
+/// datum).
+#[derive(Debug)]
+pub struct AvroEncoder {
+    schema: AvroType,
+    fingerprint: u64,
+    registry_id: Option<u32>,
+}
+
+impl AvroEncoder {
+    /// Derive the Avro schema from a generator's JSON Schema (see `Generator::schema`).
+    /// Fields outside `required` become nullable with a null default.
+    pub fn from_json_schema(schema: &Value) -> Result<Self, String> {
+        let title = schema.get("title").and_then(Value::as_str).unwrap_or("message");
+        let name = pascal_case(title.strip_prefix("fluxfakr ").unwrap_or(title));
+        let schema = convert(schema, "", &name, &mut HashSet::new())?;
+        if !matches!(schema, AvroType::Record { .. }) {
+            return Err("only object messages can be encoded as Avro".to_string());
+        }
+        let fingerprint = fingerprint(canonical_form(&schema).as_bytes());
+        Ok(AvroEncoder {
+            schema,
+            fingerprint,
+            registry_id: None,
+        })
+    }
+
+    /// Frame messages with the id the schema was registered under, instead of the
+    /// fingerprint.
+    pub fn with_registry_id(mut self, id: u32) -> Self {
+        self.registry_id = Some(id);
+        self
+    }
+
+    /// CRC-64-AVRO fingerprint of the schema, as embedded in every message.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// The Avro schema document, for registering with consumers.
+    pub fn schema(&self) -> Value {
+        schema_document(&self.schema)
+    }
+
+    /// Encode one JSON message. Fails if it doesn't match the schema.
+    pub fn encode(&self, message: &str) -> Result<Vec<u8>, String> {
+        let value: Value = serde_json::from_str(message).map_err(|e| format!("invalid JSON: {}", e))?;
+        let mut out = Vec::with_capacity(message.len());
+        match self.registry_id {
+            Some(id) => {
+                out.push(REGISTRY_MAGIC);
+                out.extend(id.to_be_bytes());
+            }
+            None => {
+                out.extend(MAGIC);
+                out.extend(self.fingerprint.to_le_bytes());
+            }
+        }
+        encode_value(&value, &self.schema, "", &mut out)?;
+        Ok(out)
+    }
+
+    /// Decode a message this encoder produced back to JSON.
+    pub fn decode(&self, data: &[u8]) -> Result<Value, String> {
+        let mut reader = Reader { data };
+        match self.registry_id {
+            Some(id) => {
+                if reader.take(1)? != [REGISTRY_MAGIC] {
+                    return Err("not in the Confluent wire format".to_string());
+                }
+                if reader.take(4)? != id.to_be_bytes() {
+                    return Err("written with a different registered schema".to_string());
+                }
+            }
+            None => {
+                if reader.take(2)? != MAGIC {
+                    return Err("not Avro single-object encoded".to_string());
+                }
+                if reader.take(8)? != self.fingerprint.to_le_bytes() {
+                    return Err("written with a different Avro schema".to_string());
+                }
+            }
+        }
+        let value = reader.value(&self.schema)?;
+        if !reader.data.is_empty() {
+            return Err("trailing data after the Avro datum".to_string());
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::stock::{StockConfig, StockDataGenerator};
+    use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+    use crate::generator::Generator;
+
+    #[test]
+    fn test_fingerprint_and_long_encoding() {
+        // Test vectors from the Avro specification's reference implementation.
+        assert_eq!(fingerprint(br#""null""#) as i64, 7_195_948_357_588_979_594);
+        assert_eq!(fingerprint(br#""boolean""#) as i64, -6_970_731_678_124_411_036);
+        let mut out = Vec::new();
+        for n in [0, -1, 1, -64, 64] {
+            push_long(&mut out, n);
+        }
+        assert_eq!(out, [0x00, 0x01, 0x02, 0x7f, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_messages_round_trip() {
+        let mut stock = StockDataGenerator::new(
+            3,
+            StockConfig {
+                emit_deltas: true,
+                ..StockConfig::default()
+            },
+        );
+        let mut supermarket = SalesGenerator::new(SalesConfig {
+            abandon_rate: 0.1,
+            stockout_rate: 0.05,
+            coupon_rate: 0.2,
+            rich_demographics: true,
+            ..SalesConfig::default()
+        });
+        let generators: [&mut dyn Generator; 2] = [&mut stock, &mut supermarket];
+        for generator in generators {
+            let encoder = AvroEncoder::from_json_schema(&generator.schema()).unwrap();
+            for _ in 0..500 {
+                let message = generator.generate();
+                let encoded = encoder.encode(&message).unwrap();
+                assert_eq!(encoded[..2], MAGIC);
+                assert_eq!(encoded[2..10], encoder.fingerprint().to_le_bytes());
+                let expected: Value = serde_json::from_str(&message).unwrap();
+                assert_eq!(encoder.decode(&encoded).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry_framing_carries_the_schema_id() {
+        let mut stock = StockDataGenerator::new(2, StockConfig::default());
+        let encoder = AvroEncoder::from_json_schema(&stock.schema()).unwrap().with_registry_id(42);
+        let message = stock.generate();
+        let encoded = encoder.encode(&message).unwrap();
+        assert_eq!(encoded[..5], [0x00, 0x00, 0x00, 0x00, 42]);
+        let expected: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(encoder.decode(&encoded).unwrap(), expected);
+        let other = AvroEncoder::from_json_schema(&stock.schema()).unwrap().with_registry_id(7);
+        assert!(other.decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_schema_mapping_and_mismatches() {
+        let schema = crate::generator::object_schema(
+            "fluxfakr test event",
+            &[
+                ("id", json!({"type": "integer"})),
+                ("kind", json!({"enum": ["a", "b"]})),
+                ("note", json!({"type": ["string", "null"]})),
+                ("store", json!({"type": "object"})),
+                ("store.lat", json!({"type": "number"})),
+            ],
+            &["id", "kind", "note"],
+        );
+        let encoder = AvroEncoder::from_json_schema(&schema).unwrap();
+        assert_eq!(
+            canonical_form(&encoder.schema),
+            r#"{"name":"fluxfakr.TestEvent","type":"record","fields":[{"name":"id","type":"long"},{"name":"kind","type":{"name":"fluxfakr.Kind","type":"enum","symbols":["a","b"]}},{"name":"note","type":["null","string"]},{"name":"store","type":["null",{"name":"fluxfakr.Store","type":"record","fields":[{"name":"lat","type":["null","double"]}]}]}]}"#
+        );
+        assert_eq!(encoder.schema()["fields"][3]["default"], Value::Null);
+        // A required nullable field keeps its null; an absent optional field stays absent.
+        let message = r#"{"id":-3,"kind":"b","note":null}"#;
+        let decoded = encoder.decode(&encoder.encode(message).unwrap()).unwrap();
+        assert_eq!(decoded, serde_json::from_str::<Value>(message).unwrap());
+
+        assert!(encoder.encode(r#"{"id":1,"kind":"c","note":null}"#).unwrap_err().contains("kind"));
+        assert!(encoder.encode(r#"{"kind":"a","note":null}"#).unwrap_err().contains("id"));
+        assert!(encoder.encode(r#"{"id":1,"kind":"a","note":null,"x":1}"#).unwrap_err().contains("'x'"));
+        let untyped = crate::generator::object_schema("t", &[("a", json!({}))], &[]);
+        assert!(AvroEncoder::from_json_schema(&untyped).is_err());
+    }
+}
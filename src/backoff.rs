@@ -0,0 +1,201 @@
+use std::time::{Duration, Instant};
+
+/// Consecutive failures that trip the circuit breaker open.
+const FAILURE_THRESHOLD: u32 = 5;
+/// First pause after the circuit opens; each failed probe doubles it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// State of a circuit breaker guarding an unreliable sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Healthy: everything is sent.
+    Closed,
+    /// Down: nothing is sent until the backoff expires.
+    Open,
+    /// Backoff expired: a single probe is in flight to test recovery.
+    HalfOpen,
+}
+
+/// Circuit breaker with exponential backoff. After `FAILURE_THRESHOLD` consecutive
+/// failures the circuit opens and sending pauses; once the backoff expires one probe
+/// is let through, and its outcome either closes the circuit or reopens it for twice
+/// as long.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    /// How many outages there have been (closed-to-open transitions).
+    trips: u64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+            trips: 0,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    pub fn trips(&self) -> u64 {
+        self.trips
+    }
+
+    /// Whether a message may be sent now. An open circuit whose backoff has expired
+    /// moves to half-open and admits exactly one probe.
+    pub fn admit(&mut self, now: Instant) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => false,
+            CircuitState::Open => {
+                if self.retry_at.is_some_and(|at| now >= at) {
+                    self.state = CircuitState::HalfOpen;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Record a successful send. Returns true if this closed the circuit (recovery).
+    pub fn record_success(&mut self) -> bool {
+        let recovered = self.state != CircuitState::Closed;
+        self.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.backoff = INITIAL_BACKOFF;
+        self.retry_at = None;
+        recovered
+    }
+
+    /// Record a failed send. Returns true if this opened a closed circuit, starting
+    /// an outage. A failed probe reopens the circuit for twice as long.
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        self.consecutive_failures += 1;
+        match self.state {
+            CircuitState::Closed if self.consecutive_failures >= FAILURE_THRESHOLD => self.trip(now),
+            CircuitState::HalfOpen => {
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                self.open(now);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Open the circuit immediately, e.g. when the client reports every broker down.
+    /// Returns true if this started an outage.
+    pub fn trip(&mut self, now: Instant) -> bool {
+        if self.state != CircuitState::Closed {
+            return false;
+        }
+        self.open(now);
+        self.trips += 1;
+        true
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.retry_at = Some(now + self.backoff);
+    }
+}
+
+/// Rate limiter for repeated error messages: at most one line per window, with a
+/// count of what was suppressed in between.
+#[derive(Debug)]
+pub struct LogLimiter {
+    window: Duration,
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl LogLimiter {
+    pub fn new(window: Duration) -> Self {
+        LogLimiter {
+            window,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Print `message` to stderr unless one was printed within the window.
+    pub fn log(&mut self, now: Instant, message: &str) {
+        if self.last.is_some_and(|last| now.duration_since(last) < self.window) {
+            self.suppressed += 1;
+            return;
+        }
+        if self.suppressed > 0 {
+            eprintln!("{} ({} similar errors suppressed)", message, self.suppressed);
+        } else {
+            eprintln!("{}", message);
+        }
+        self.last = Some(now);
+        self.suppressed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold_and_backs_off() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            assert!(!breaker.record_failure(start));
+        }
+        assert!(breaker.admit(start));
+        assert!(breaker.record_failure(start));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.admit(start + Duration::from_millis(999)));
+
+        // One probe after the backoff; its failure doubles the pause.
+        assert!(breaker.admit(start + INITIAL_BACKOFF));
+        assert!(!breaker.admit(start + INITIAL_BACKOFF), "only one probe while half-open");
+        let probe_failed_at = start + INITIAL_BACKOFF;
+        assert!(!breaker.record_failure(probe_failed_at), "a failed probe is the same outage");
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.admit(probe_failed_at + INITIAL_BACKOFF));
+        assert!(breaker.admit(probe_failed_at + INITIAL_BACKOFF * 2));
+
+        assert!(breaker.record_success());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.trips(), 1);
+    }
+
+    #[test]
+    fn test_success_resets_failure_streak() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+        for _ in 0..10 {
+            breaker.record_failure(now);
+            breaker.record_failure(now);
+            assert!(!breaker.record_success());
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_log_limiter_suppresses_within_window() {
+        let now = Instant::now();
+        let mut limiter = LogLimiter::new(Duration::from_secs(5));
+        limiter.log(now, "boom");
+        limiter.log(now + Duration::from_secs(1), "boom");
+        limiter.log(now + Duration::from_secs(2), "boom");
+        assert_eq!(limiter.suppressed, 2);
+        limiter.log(now + Duration::from_secs(5), "boom");
+        assert_eq!(limiter.suppressed, 0);
+    }
+}
@@ -0,0 +1,124 @@
+use rand::Rng;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of message timestamps.
+#[derive(Debug, Clone)]
+pub enum Clock {
+    /// Wall-clock time.
+    System,
+    /// Simulated time: starts at `now_ms` and advances `step_ms` on every reading,
+    /// so output timestamps are independent of how fast the loop runs.
+    Stepped { now_ms: u64, step_ms: u64 },
+}
+
+impl Clock {
+    /// Current time in milliseconds since the Unix epoch.
+    pub fn now_millis(&mut self) -> u64 {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            Clock::Stepped { now_ms, step_ms } => {
+                let now = *now_ms;
+                *now_ms += *step_ms;
+                now
+            }
+        }
+    }
+
+    /// The time the next `now_millis` reading would report, without advancing a
+    /// simulated clock.
+    pub fn peek_millis(&self) -> u64 {
+        match self {
+            Clock::System => Clock::System.now_millis(),
+            Clock::Stepped { now_ms, .. } => *now_ms,
+        }
+    }
+
+    /// Whether `target_ms` has been reached. A simulated clock has no reason to
+    /// sit idle, so it jumps straight to the target; the system clock only
+    /// reports whether the target has passed.
+    pub fn reach(&mut self, target_ms: u64) -> bool {
+        match self {
+            Clock::System => self.now_millis() >= target_ms,
+            Clock::Stepped { now_ms, .. } => {
+                *now_ms = (*now_ms).max(target_ms);
+                true
+            }
+        }
+    }
+}
+
+/// Draw a fixed clock offset (ms) uniformly from `-max_ms..=max_ms`, simulating an
+/// entity whose clock is not synchronized with the others.
+pub fn draw_skew<R: Rng + ?Sized>(rng: &mut R, max_ms: u64) -> i64 {
+    let max_ms = max_ms.min(i64::MAX as u64) as i64;
+    rng.random_range(-max_ms..=max_ms)
+}
+
+/// Apply an entity's clock offset to a timestamp in milliseconds, saturating at the epoch.
+pub fn skewed(now_ms: u64, skew_ms: i64) -> u64 {
+    now_ms.saturating_add_signed(skew_ms)
+}
+
+/// Parse a positive duration such as `30s`, `5m`, `1.5h` or `250ms`; a bare number is seconds.
+pub fn parse_duration(spec: &str) -> Result<Duration, String> {
+    let spec = spec.trim();
+    let split = spec
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(spec.len());
+    let (number, unit) = spec.split_at(split);
+    let scale = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        other => return Err(format!("unknown unit '{}' in '{}' (use ms, s, m or h)", other, spec)),
+    };
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration such as 30s or 5m, got '{}'", spec))?;
+    let seconds = value * scale;
+    if !(seconds.is_finite() && seconds > 0.0) {
+        return Err(format!("duration must be greater than 0, got '{}'", spec));
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration '{}' is too long", spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stepped_clock_advances_per_reading() {
+        let mut clock = Clock::Stepped {
+            now_ms: 1_000,
+            step_ms: 250,
+        };
+        assert_eq!(clock.now_millis(), 1_000);
+        assert_eq!(clock.now_millis(), 1_250);
+        assert!(clock.reach(10_000));
+        assert_eq!(clock.now_millis(), 10_000);
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        let huge = format!("1{}h", "0".repeat(30));
+        for bad in ["0s", "", "10d", "s", "-5s", "1.2.3m", &huge] {
+            assert!(parse_duration(bad).is_err(), "accepted '{}'", bad);
+        }
+    }
+
+    #[test]
+    fn test_system_clock_reach() {
+        let mut clock = Clock::System;
+        assert!(clock.reach(0));
+        assert!(!clock.reach(u64::MAX));
+    }
+}
@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+use std::io::{self, IsTerminal};
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const LITERAL: &str = "\x1b[35m";
+
+/// When to colorize JSON written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// Colorize only when stdout is a terminal and `NO_COLOR` is unset.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Resolve the mode against the current stdout.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// Wrap JSON tokens in ANSI color codes: keys, strings, numbers and literals
+/// (`true`/`false`/`null`) each get their own color. Punctuation and whitespace
+/// are passed through untouched, so stripping the codes yields the input.
+pub fn colorize_json(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut out = String::with_capacity(json.len() * 2);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            let token: String = chars[start..i].iter().collect();
+            let is_key = chars[i..]
+                .iter()
+                .find(|c| !c.is_whitespace())
+                .is_some_and(|&c| c == ':');
+            push_colored(&mut out, if is_key { KEY } else { STRING }, &token);
+        } else if c == '-' || c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && matches!(chars[i], '0'..='9' | '-' | '+' | '.' | 'e' | 'E') {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            push_colored(&mut out, NUMBER, &token);
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            push_colored(&mut out, LITERAL, &token);
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn push_colored(out: &mut String, color: &str, token: &str) {
+    out.push_str(color);
+    out.push_str(token);
+    out.push_str(RESET);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_ansi(s: &str) -> String {
+        let mut out = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                for c in chars.by_ref() {
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_colorize_round_trips_when_stripped() {
+        let json = r#"{"a":"x \"quoted\" : y","b":-1.5e3,"c":[true,null],"d":{"e":false}}"#;
+        let colored = colorize_json(json);
+        assert_ne!(colored, json);
+        assert_eq!(strip_ansi(&colored), json);
+    }
+
+    #[test]
+    fn test_colorize_handles_escapes_at_string_end() {
+        // A string ending in an escaped backslash must not swallow the next token.
+        let json = serde_json::json!({"a": "ends with \\", "b": "\"", "c": "\u{0001}ü"}).to_string();
+        let colored = colorize_json(&json);
+        assert_eq!(strip_ansi(&colored), json);
+        assert!(colored.contains(&format!("{}\"b\"{}", KEY, RESET)));
+    }
+
+    #[test]
+    fn test_keys_and_values_get_distinct_colors() {
+        let colored = colorize_json(r#"{"price":1.5,"id":"STK0"}"#);
+        assert!(colored.contains(&format!("{}\"price\"{}", KEY, RESET)));
+        assert!(colored.contains(&format!("{}1.5{}", NUMBER, RESET)));
+        assert!(colored.contains(&format!("{}\"STK0\"{}", STRING, RESET)));
+    }
+
+    #[test]
+    fn test_never_mode_is_disabled() {
+        assert!(!ColorMode::Never.enabled());
+        assert!(ColorMode::Always.enabled());
+    }
+}
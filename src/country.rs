@@ -0,0 +1,246 @@
+use crate::weights;
+use fake::faker::address::en::{CityName, StateAbbr};
+use fake::Fake;
+use rand::Rng;
+
+/// A country stores can be located in, with its currency and a locale table of
+/// (town, state/region) pairs in the country's own naming.
+#[derive(Debug, PartialEq)]
+pub struct Country {
+    pub name: &'static str,
+    pub currency: &'static str,
+    /// Locale codes (`language_COUNTRY`) that select the country.
+    locales: &'static [&'static str],
+    /// Local towns and their state or region. Empty means US fake address data is used.
+    towns: &'static [(&'static str, &'static str)],
+}
+
+pub static COUNTRIES: &[Country] = &[
+    Country { name: "USA", currency: "USD", locales: &["en_US"], towns: &[] },
+    Country {
+        name: "Canada",
+        currency: "CAD",
+        locales: &["en_CA", "fr_CA"],
+        towns: &[
+            ("Toronto", "ON"),
+            ("Montréal", "QC"),
+            ("Vancouver", "BC"),
+            ("Calgary", "AB"),
+            ("Ottawa", "ON"),
+            ("Québec", "QC"),
+        ],
+    },
+    Country {
+        name: "UK",
+        currency: "GBP",
+        locales: &["en_GB"],
+        towns: &[
+            ("London", "England"),
+            ("Manchester", "England"),
+            ("Birmingham", "England"),
+            ("Glasgow", "Scotland"),
+            ("Edinburgh", "Scotland"),
+            ("Cardiff", "Wales"),
+            ("Belfast", "Northern Ireland"),
+        ],
+    },
+    Country {
+        name: "Germany",
+        currency: "EUR",
+        locales: &["de_DE"],
+        towns: &[
+            ("Berlin", "Berlin"),
+            ("München", "Bayern"),
+            ("Hamburg", "Hamburg"),
+            ("Köln", "Nordrhein-Westfalen"),
+            ("Frankfurt am Main", "Hessen"),
+            ("Stuttgart", "Baden-Württemberg"),
+        ],
+    },
+    Country {
+        name: "France",
+        currency: "EUR",
+        locales: &["fr_FR"],
+        towns: &[
+            ("Paris", "Île-de-France"),
+            ("Marseille", "Provence-Alpes-Côte d'Azur"),
+            ("Lyon", "Auvergne-Rhône-Alpes"),
+            ("Toulouse", "Occitanie"),
+            ("Nantes", "Pays de la Loire"),
+            ("Lille", "Hauts-de-France"),
+        ],
+    },
+    Country {
+        name: "Japan",
+        currency: "JPY",
+        locales: &["ja_JP"],
+        towns: &[
+            ("東京", "東京都"),
+            ("大阪", "大阪府"),
+            ("横浜", "神奈川県"),
+            ("名古屋", "愛知県"),
+            ("札幌", "北海道"),
+            ("福岡", "福岡県"),
+        ],
+    },
+    Country {
+        name: "Australia",
+        currency: "AUD",
+        locales: &["en_AU"],
+        towns: &[
+            ("Sydney", "NSW"),
+            ("Melbourne", "VIC"),
+            ("Brisbane", "QLD"),
+            ("Perth", "WA"),
+            ("Adelaide", "SA"),
+            ("Hobart", "TAS"),
+        ],
+    },
+    Country {
+        name: "Switzerland",
+        currency: "CHF",
+        locales: &["de_CH", "fr_CH", "it_CH"],
+        towns: &[
+            ("Zürich", "ZH"),
+            ("Genève", "GE"),
+            ("Basel", "BS"),
+            ("Bern", "BE"),
+            ("Lausanne", "VD"),
+        ],
+    },
+    Country {
+        name: "Singapore",
+        currency: "SGD",
+        locales: &["en_SG"],
+        towns: &[
+            ("Orchard", "Central"),
+            ("Jurong East", "West"),
+            ("Tampines", "East"),
+            ("Woodlands", "North"),
+            ("Punggol", "North-East"),
+        ],
+    },
+];
+
+/// Countries whose locales differ by language, narrowed to the towns where that language
+/// is spoken. A locale found here takes precedence over its entry in `COUNTRIES`.
+static LANGUAGE_REGIONS: &[Country] = &[
+    Country {
+        name: "Canada",
+        currency: "CAD",
+        locales: &["en_CA"],
+        towns: &[("Toronto", "ON"), ("Vancouver", "BC"), ("Calgary", "AB"), ("Ottawa", "ON")],
+    },
+    Country {
+        name: "Canada",
+        currency: "CAD",
+        locales: &["fr_CA"],
+        towns: &[("Montréal", "QC"), ("Québec", "QC"), ("Gatineau", "QC"), ("Sherbrooke", "QC")],
+    },
+    Country {
+        name: "Switzerland",
+        currency: "CHF",
+        locales: &["de_CH"],
+        towns: &[("Zürich", "ZH"), ("Basel", "BS"), ("Bern", "BE"), ("Luzern", "LU")],
+    },
+    Country {
+        name: "Switzerland",
+        currency: "CHF",
+        locales: &["fr_CH"],
+        towns: &[("Genève", "GE"), ("Lausanne", "VD"), ("Neuchâtel", "NE")],
+    },
+    Country {
+        name: "Switzerland",
+        currency: "CHF",
+        locales: &["it_CH"],
+        towns: &[("Lugano", "TI"), ("Bellinzona", "TI"), ("Locarno", "TI")],
+    },
+];
+
+impl Country {
+    /// Generate a (town, state) address in this country.
+    pub fn address<R: Rng + ?Sized>(&self, rng: &mut R) -> (String, String) {
+        if self.towns.is_empty() {
+            return (CityName().fake_with_rng(rng), StateAbbr().fake_with_rng(rng));
+        }
+        let (town, state) = self.towns[rng.random_range(0..self.towns.len())];
+        (town.to_string(), state.to_string())
+    }
+}
+
+/// Parse a weighted country mix such as `USA=0.6,UK=0.3,Germany=0.1`.
+pub fn parse_country_mix(spec: &str) -> Result<Vec<(&'static Country, f64)>, String> {
+    weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(name, weight)| {
+            COUNTRIES
+                .iter()
+                .find(|country| country.name.eq_ignore_ascii_case(&name))
+                .map(|country| (country, weight))
+                .ok_or_else(|| {
+                    let known: Vec<&str> = COUNTRIES.iter().map(|c| c.name).collect();
+                    format!("unknown country '{}' (supported: {})", name, known.join(", "))
+                })
+        })
+        .collect()
+}
+
+/// The country of a locale code such as `en_GB` or `de-DE`, limited to the towns that
+/// speak its language where the country has several.
+pub fn parse_locale(code: &str) -> Result<&'static Country, String> {
+    let code = code.trim().replace('-', "_");
+    LANGUAGE_REGIONS
+        .iter()
+        .chain(COUNTRIES)
+        .find(|country| country.locales.iter().any(|l| l.eq_ignore_ascii_case(&code)))
+        .ok_or_else(|| {
+            let known: Vec<&str> = COUNTRIES.iter().flat_map(|c| c.locales.iter().copied()).collect();
+            format!("unknown locale '{}' (supported: {})", code, known.join(", "))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_country_mix() {
+        let mix = parse_country_mix("usa=3,Germany=1").unwrap();
+        assert_eq!(mix[0].0.name, "USA");
+        assert_eq!(mix[1], (&COUNTRIES[3], 1.0));
+        assert!(parse_country_mix("Atlantis=1").is_err());
+    }
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!(parse_locale("en_GB").unwrap().name, "UK");
+        assert_eq!(parse_locale("de-de").unwrap().name, "Germany");
+        assert_eq!(parse_locale("fr_CA").unwrap().currency, "CAD");
+        assert!(parse_locale("xx_XX").is_err());
+    }
+
+    #[test]
+    fn test_locale_language_picks_the_towns() {
+        let towns = |code: &str| parse_locale(code).unwrap().towns;
+        assert_ne!(towns("en_CA"), towns("fr_CA"));
+        assert!(towns("fr_CA").iter().all(|&(_, province)| province == "QC"));
+        assert!(towns("it_CH").iter().all(|&(_, canton)| canton == "TI"));
+        assert_ne!(towns("de_CH"), towns("fr_CH"));
+        // Single-language countries keep their full table.
+        assert_eq!(parse_locale("en_GB").unwrap(), &COUNTRIES[2]);
+    }
+
+    #[test]
+    fn test_addresses_come_from_the_country_table() {
+        let mut rng = rand::rng();
+        let germany = &COUNTRIES[3];
+        for _ in 0..50 {
+            let address = germany.address(&mut rng);
+            assert!(germany
+                .towns
+                .iter()
+                .any(|&(town, state)| (town, state) == (address.0.as_str(), address.1.as_str())));
+        }
+        assert!(!COUNTRIES[0].address(&mut rng).0.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+
+/// Supported currencies: ISO code, starting units per US dollar, and minor-unit decimals.
+const CURRENCIES: &[(&str, f64, i32)] = &[
+    ("USD", 1.0, 2),
+    ("EUR", 0.92, 2),
+    ("GBP", 0.79, 2),
+    ("JPY", 150.0, 0),
+    ("CAD", 1.36, 2),
+    ("AUD", 1.52, 2),
+    ("CHF", 0.88, 2),
+    ("SGD", 1.34, 2),
+];
+
+/// The table entry of a supported currency.
+fn lookup(code: &str) -> (&'static str, f64, i32) {
+    *CURRENCIES
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .expect("currency validated by parse_currencies or taken from the country table")
+}
+
+/// The table's reference units of currency `code` per US dollar, before any drift.
+pub fn reference_rate(code: &str) -> f64 {
+    lookup(code).1
+}
+
+/// Round `amount` to the minor unit of currency `code`: the cent, or the whole yen.
+pub fn round_to_minor_unit(amount: f64, code: &str) -> f64 {
+    let factor = 10f64.powi(lookup(code).2);
+    (amount * factor).round() / factor
+}
+
+/// Parse a comma-separated currency set such as `USD,EUR,GBP`.
+pub fn parse_currencies(spec: &str) -> Result<Vec<&'static str>, String> {
+    let mut currencies = Vec::new();
+    for code in spec.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let Some(&(known, _, _)) = CURRENCIES.iter().find(|(c, _, _)| c.eq_ignore_ascii_case(code)) else {
+            let supported: Vec<&str> = CURRENCIES.iter().map(|(c, _, _)| *c).collect();
+            return Err(format!("unknown currency '{}' (supported: {})", code, supported.join(", ")));
+        };
+        if !currencies.contains(&known) {
+            currencies.push(known);
+        }
+    }
+    if currencies.is_empty() {
+        return Err("currency set is empty".to_string());
+    }
+    Ok(currencies)
+}
+
+/// Live FX rates for a currency set, drifting as a random walk so conversions vary
+/// over a run the way real rates do.
+#[derive(Debug, Clone)]
+pub struct FxRates {
+    currencies: Vec<&'static str>,
+    /// Units of each currency per US dollar.
+    per_usd: Vec<f64>,
+    /// Standard deviation of each step's log-return.
+    volatility: f64,
+}
+
+impl FxRates {
+    pub fn new(currencies: Vec<&'static str>, volatility: f64) -> Self {
+        let per_usd = currencies
+            .iter()
+            .map(|code| lookup(code).1)
+            .collect();
+        FxRates {
+            currencies,
+            per_usd,
+            volatility,
+        }
+    }
+
+    pub fn currencies(&self) -> &[&'static str] {
+        &self.currencies
+    }
+
+    /// Move every rate one random-walk step. The dollar is the anchor and never moves.
+    pub fn step<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if self.volatility <= 0.0 {
+            return;
+        }
+        for (code, rate) in self.currencies.iter().zip(&mut self.per_usd) {
+            let shock: f64 = StandardNormal.sample(rng);
+            if *code != "USD" {
+                *rate *= (self.volatility * shock).exp();
+            }
+        }
+    }
+
+    /// Current units of currency `i` (an index into `currencies()`) per US dollar.
+    pub fn per_usd(&self, i: usize) -> f64 {
+        self.per_usd[i]
+    }
+
+    /// Exchange rate converting one unit of currency `from` into currency `to`
+    /// (both indexes into `currencies()`).
+    pub fn rate(&self, from: usize, to: usize) -> f64 {
+        self.per_usd[to] / self.per_usd[from]
+    }
+
+    /// Convert an amount at `rate`, rounded to the target currency's minor unit.
+    pub fn convert(&self, amount: f64, rate: f64, to: usize) -> f64 {
+        round_to_minor_unit(amount * rate, self.currencies[to])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_currencies() {
+        assert_eq!(parse_currencies("usd, EUR,USD").unwrap(), vec!["USD", "EUR"]);
+        assert!(parse_currencies("USD,XYZ").is_err());
+        assert!(parse_currencies(" , ").is_err());
+    }
+
+    #[test]
+    fn test_conversion_math() {
+        let fx = FxRates::new(parse_currencies("USD,EUR,JPY").unwrap(), 0.0);
+        assert!((fx.rate(0, 1) - 0.92).abs() < 1e-12);
+        assert!((fx.rate(1, 0) * fx.rate(0, 1) - 1.0).abs() < 1e-12);
+        // EUR -> JPY goes through the dollar: 150 / 0.92.
+        assert!((fx.rate(1, 2) - 150.0 / 0.92).abs() < 1e-9);
+        assert_eq!(fx.convert(10.0, fx.rate(0, 1), 1), 9.2);
+        // Yen has no minor unit.
+        assert_eq!(fx.convert(10.0, fx.rate(0, 2), 2), 1500.0);
+        assert_eq!(fx.convert(12.34, fx.rate(1, 2), 2), (12.34f64 * 150.0 / 0.92).round());
+    }
+
+    #[test]
+    fn test_rates_drift_but_dollar_is_anchored() {
+        let mut fx = FxRates::new(parse_currencies("USD,GBP").unwrap(), 0.01);
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            fx.step(&mut rng);
+        }
+        assert_eq!(fx.per_usd[0], 1.0);
+        assert_ne!(fx.per_usd[1], 0.79);
+        assert!(fx.per_usd[1] > 0.0);
+    }
+}
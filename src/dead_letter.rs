@@ -0,0 +1,72 @@
+use chrono::Utc;
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Append-only file collecting messages that could not be delivered to a sink.
+///
+/// Each line is a JSON object holding the sink name, the failure reason, a
+/// timestamp and the original payload, so failed messages can be replayed later.
+/// The writer is behind a mutex because Kafka reports delivery failures from its
+/// producer context.
+pub struct DeadLetterFile {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl DeadLetterFile {
+    /// Open (or create) the dead-letter file in append mode.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(DeadLetterFile {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Record a payload that failed to reach `sink` with the given reason.
+    pub fn record(&self, sink: &str, reason: &str, payload: &str) {
+        let entry = json!({
+            "sink": sink,
+            "reason": reason,
+            "timestamp": Utc::now().timestamp(),
+            "payload": payload,
+        });
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{}", entry) {
+            eprintln!("Failed to write to dead-letter file: {}", e);
+        }
+    }
+
+    /// Flush buffered entries to disk.
+    pub fn flush(&self) {
+        if let Err(e) = self.writer.lock().unwrap().flush() {
+            eprintln!("Failed to flush dead-letter file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_record_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("fluxfakr-dlq-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dead_letter = DeadLetterFile::open(&path).unwrap();
+        dead_letter.record("kafka", "broker down", r#"{"a":1}"#);
+        dead_letter.record("stdout", "broken pipe", "second");
+        dead_letter.flush();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = contents.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["sink"], "kafka");
+        assert_eq!(lines[0]["reason"], "broker down");
+        assert_eq!(lines[0]["payload"], r#"{"a":1}"#);
+        assert_eq!(lines[1]["payload"], "second");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+//! Wire formats other than JSON that messages can be re-encoded into on their way out.
+
+use crate::avro::AvroEncoder;
+use crate::csv::CsvEncoder;
+use crate::generator::web_logs;
+use crate::line_protocol::LineProtocol;
+use crate::msgpack;
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// How message payloads are encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    /// Avro single-object encoding (Kafka records only)
+    Avro,
+    /// MessagePack (Kafka records and `--output` files; hex on stdout)
+    Msgpack,
+    /// CSV rows under a header (stdout and `--output` files only)
+    Csv,
+    /// InfluxDB line protocol (stdout and `--output` files only)
+    LineProtocol,
+    /// Apache Combined Log Format lines (`web_logs` module; stdout and `--output` files only)
+    CombinedLog,
+}
+
+/// Re-encodes JSON messages into another format, and decodes binary ones back for
+/// dead-lettering.
+#[derive(Debug)]
+pub enum PayloadEncoder {
+    Avro(AvroEncoder),
+    Msgpack,
+    Csv(CsvEncoder),
+    LineProtocol(LineProtocol),
+    CombinedLog,
+}
+
+impl PayloadEncoder {
+    pub fn encode(&self, message: &str) -> Result<Vec<u8>, String> {
+        match self {
+            PayloadEncoder::Avro(avro) => avro.encode(message),
+            PayloadEncoder::Msgpack => {
+                let value: Value = serde_json::from_str(message).map_err(|e| format!("invalid JSON: {}", e))?;
+                let mut out = Vec::with_capacity(message.len());
+                msgpack::encode(&value, &mut out);
+                Ok(out)
+            }
+            PayloadEncoder::Csv(csv) => csv.row(message).map(String::into_bytes),
+            PayloadEncoder::LineProtocol(lines) => lines.render(message).map(String::into_bytes),
+            PayloadEncoder::CombinedLog => web_logs::combined_log_line(message).map(String::into_bytes),
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Value, String> {
+        match self {
+            PayloadEncoder::Avro(avro) => avro.decode(data),
+            PayloadEncoder::Msgpack => msgpack::decode(data),
+            PayloadEncoder::Csv(_) | PayloadEncoder::LineProtocol(_) | PayloadEncoder::CombinedLog => {
+                Err("text rows are not decoded".to_string())
+            }
+        }
+    }
+
+    /// Whether heartbeats and end-of-stream markers are encoded too. Avro's schema, CSV's
+    /// columns, line protocol's mapping and access-log lines only describe data messages:
+    /// Kafka sends them as JSON instead, while files and stdout leave them out.
+    pub fn encodes_control(&self) -> bool {
+        matches!(self, PayloadEncoder::Msgpack)
+    }
+
+    /// Whether encoded messages are binary rather than lines of text.
+    pub fn is_binary(&self) -> bool {
+        matches!(self, PayloadEncoder::Avro(_) | PayloadEncoder::Msgpack)
+    }
+
+    /// A line to write before the first message, for formats that have one.
+    pub fn header(&self) -> Option<String> {
+        match self {
+            PayloadEncoder::Csv(csv) => Some(csv.header()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::clock::Clock;
+use crate::line_protocol::LineMapping;
+use crate::rng::{RngAlgorithm, SimRng};
+use rand::distr::Distribution;
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use rand_distr::Exp;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// A step of the shopping funnel, which is also the `event_type` of its events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Landing,
+    Browse,
+    AddToCart,
+    Checkout,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Landing, Stage::Browse, Stage::AddToCart, Stage::Checkout];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Landing => "landing",
+            Stage::Browse => "browse",
+            Stage::AddToCart => "add_to_cart",
+            Stage::Checkout => "checkout",
+        }
+    }
+
+    /// Where a session goes after this stage, with probabilities; `None` ends the session.
+    /// A checkout always ends it.
+    fn transitions(self) -> &'static [(Option<Stage>, f64)] {
+        match self {
+            Stage::Landing => &[(Some(Stage::Browse), 0.7), (None, 0.3)],
+            Stage::Browse => &[(Some(Stage::Browse), 0.45), (Some(Stage::AddToCart), 0.25), (None, 0.3)],
+            Stage::AddToCart => &[
+                (Some(Stage::Browse), 0.3),
+                (Some(Stage::AddToCart), 0.1),
+                (Some(Stage::Checkout), 0.35),
+                (None, 0.25),
+            ],
+            Stage::Checkout => &[(None, 1.0)],
+        }
+    }
+
+    /// Mean time (seconds) a user spends on this stage's page before the next event.
+    fn mean_dwell_secs(self) -> f64 {
+        match self {
+            Stage::Landing => 10.0,
+            Stage::Browse => 30.0,
+            Stage::AddToCart => 20.0,
+            Stage::Checkout => 45.0,
+        }
+    }
+
+    /// Draw the page an event at this stage is on.
+    fn page<R: Rng + ?Sized>(self, rng: &mut R) -> String {
+        match self {
+            Stage::Landing => LANDING_PAGES.choose(rng).expect("landing pages are listed").to_string(),
+            Stage::Browse if rng.random_bool(0.3) => {
+                format!("/category/{}", CATEGORIES.choose(rng).expect("categories are listed"))
+            }
+            Stage::Browse => format!("/products/{}", rng.random_range(1..=500)),
+            Stage::AddToCart => "/cart".to_string(),
+            Stage::Checkout => "/checkout".to_string(),
+        }
+    }
+}
+
+const LANDING_PAGES: &[&str] = &["/", "/landing/summer-sale", "/landing/newsletter", "/landing/new-arrivals"];
+
+const CATEGORIES: &[&str] = &["electronics", "home", "garden", "toys", "beauty", "sports"];
+
+/// Mean idle time (seconds) between a user's sessions.
+const MEAN_SESSION_GAP_SECS: f64 = 600.0;
+
+/// Users' first sessions start spread over this many milliseconds.
+const FIRST_ARRIVAL_SPREAD_MS: u64 = 30_000;
+
+/// One emitted event.
+#[derive(Serialize)]
+struct Event<'a> {
+    event_type: Stage,
+    page: &'a str,
+    seq: u64,
+    session_id: &'a str,
+    timestamp: u64,
+    user_id: &'a str,
+}
+
+/// A visit in progress: the stage its next event is at.
+#[derive(Debug)]
+struct Session {
+    session_id: String,
+    next_stage: Stage,
+    /// Number of events emitted in this session so far.
+    seq: u64,
+    /// Which stages the session has reached, indexed like `Stage::ALL`.
+    reached: [bool; 4],
+}
+
+/// A simulated user, returning in one session after another.
+#[derive(Debug)]
+pub struct User {
+    pub user_id: String,
+    session: Option<Session>,
+}
+
+/// Tunable parameters for the clickstream model.
+#[derive(Debug, Clone)]
+pub struct ClickstreamConfig {
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Source of message timestamps.
+    pub clock: Clock,
+}
+
+impl Default for ClickstreamConfig {
+    fn default() -> Self {
+        ClickstreamConfig {
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            clock: Clock::System,
+        }
+    }
+}
+
+/// A funnel clickstream generator: each user walks landing → browse → add to cart →
+/// checkout, one event per page, dwelling on each page for a while and dropping out
+/// along the way.
+pub struct ClickstreamGenerator {
+    users: Vec<User>,
+    /// When each user's next event is due (ms), earliest first.
+    schedule: BinaryHeap<Reverse<(u64, usize)>>,
+    /// Sessions that reached each stage, indexed like `Stage::ALL`.
+    funnel: [u64; 4],
+    rng: SimRng,
+    clock: Clock,
+    /// Index of the user behind the last event.
+    last_user: Option<usize>,
+}
+
+impl ClickstreamGenerator {
+    pub fn new(variants: usize, config: ClickstreamConfig) -> Self {
+        let mut rng = SimRng::new(config.rng, config.seed);
+        let mut clock = config.clock;
+        let start_ms = clock.now_millis();
+        let users = (0..variants)
+            .map(|i| User {
+                user_id: format!("USER-{:04}", i),
+                session: None,
+            })
+            .collect();
+        let schedule = (0..variants)
+            .map(|i| Reverse((start_ms + rng.random_range(0..FIRST_ARRIVAL_SPREAD_MS), i)))
+            .collect();
+        ClickstreamGenerator {
+            users,
+            schedule,
+            funnel: [0; 4],
+            rng,
+            clock,
+            last_user: None,
+        }
+    }
+
+    /// Draw an exponential wait (ms) with the given mean (seconds).
+    fn wait_ms(&mut self, mean_secs: f64) -> u64 {
+        let secs = Exp::new(1.0 / mean_secs).expect("waits have positive means").sample(&mut self.rng);
+        (secs * 1000.0) as u64
+    }
+
+    /// Emit the next event into `buf`. Unless `force` is set, returns false while no
+    /// user has an event due yet; also returns false if there are no users.
+    fn next_event(&mut self, buf: &mut String, force: bool) -> bool {
+        let Some(&Reverse((due_ms, idx))) = self.schedule.peek() else {
+            return false;
+        };
+        // Users are idle while they read a page; nothing is due until the clock gets there.
+        if !self.clock.reach(due_ms) && !force {
+            return false;
+        }
+        self.schedule.pop();
+        let timestamp_ms = self.clock.now_millis().max(due_ms);
+        self.last_user = Some(idx);
+
+        let rng = &mut self.rng;
+        let user = &mut self.users[idx];
+        let session = user.session.get_or_insert_with(|| Session {
+            session_id: format!("SESS-{:08x}", rng.random::<u32>()),
+            next_stage: Stage::Landing,
+            seq: 0,
+            reached: [false; 4],
+        });
+        let stage = session.next_stage;
+        let stage_index = Stage::ALL.iter().position(|s| *s == stage).expect("stages are listed");
+        if !session.reached[stage_index] {
+            session.reached[stage_index] = true;
+            self.funnel[stage_index] += 1;
+        }
+        session.seq += 1;
+        let page = stage.page(rng);
+        let event = Event {
+            event_type: stage,
+            page: &page,
+            seq: session.seq,
+            session_id: &session.session_id,
+            timestamp: timestamp_ms / 1000,
+            user_id: &user.user_id,
+        };
+        buf.clear();
+        super::write_json(buf, &event);
+
+        let transitions = stage.transitions();
+        let draw: f64 = rng.random();
+        let mut cumulative = 0.0;
+        let next = transitions
+            .iter()
+            .find(|(_, p)| {
+                cumulative += p;
+                draw < cumulative
+            })
+            .and_then(|(next, _)| *next);
+        let wait_ms = match next {
+            Some(next_stage) => {
+                session.next_stage = next_stage;
+                self.wait_ms(stage.mean_dwell_secs())
+            }
+            None => {
+                user.session = None;
+                self.wait_ms(stage.mean_dwell_secs()) + self.wait_ms(MEAN_SESSION_GAP_SECS)
+            }
+        };
+        self.schedule.push(Reverse((timestamp_ms + wait_ms, idx)));
+        true
+    }
+}
+
+impl crate::Generator for ClickstreamGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        let mut message = String::new();
+        self.next_event(&mut message, false).then_some(message)
+    }
+
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        self.next_event(buf, false)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Emits the next event even if it isn't due yet, stamped with its due time.
+    fn generate(&mut self) -> String {
+        let mut message = String::new();
+        if !self.next_event(&mut message, true) {
+            message.push_str("{}");
+        }
+        message
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["user_id", "session_id", "event_type", "page", "seq", "timestamp"]
+    }
+
+    fn schema(&self) -> Value {
+        let stages: Vec<&str> = Stage::ALL.iter().map(|s| s.name()).collect();
+        let fields = [
+            ("user_id", json!({"type": "string", "description": "User id, e.g. USER-0001"})),
+            ("session_id", json!({"type": "string"})),
+            ("event_type", json!({"type": "string", "enum": stages})),
+            ("page", json!({"type": "string", "description": "Path of the page the event is on"})),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Per-session sequence number"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        super::object_schema("fluxfakr clickstream event", &fields, &required)
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_user.map(|idx| self.users[idx].user_id.clone())
+    }
+
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["event_type"],
+            fields: vec!["user_id", "session_id", "page", "seq"],
+            timestamp: "timestamp",
+        })
+    }
+
+    /// The funnel so far: how many sessions reached each stage, and the share of them
+    /// that reached checkout.
+    fn dump(&self) -> String {
+        let mut csv = String::from("stage,sessions\n");
+        for (stage, sessions) in Stage::ALL.iter().zip(self.funnel) {
+            csv.push_str(&format!("{},{}\n", stage.name(), sessions));
+        }
+        let conversion = self.funnel[3] as f64 / self.funnel[0].max(1) as f64;
+        csv.push_str(&format!(
+            "{} of {} sessions reached checkout ({:.1}%)\n",
+            self.funnel[3],
+            self.funnel[0],
+            conversion * 100.0
+        ));
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+    use std::collections::HashMap;
+
+    fn generator(variants: usize, seed: u64) -> ClickstreamGenerator {
+        ClickstreamGenerator::new(
+            variants,
+            ClickstreamConfig {
+                seed,
+                clock: Clock::Stepped {
+                    now_ms: 1_700_000_000_000,
+                    step_ms: 0,
+                },
+                ..ClickstreamConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_sessions_walk_the_funnel() {
+        let mut generator = generator(20, 5);
+        // Per session: the last stage, seq and timestamp.
+        let mut sessions: HashMap<String, (String, u64, u64)> = HashMap::new();
+        let mut last_timestamp = 0;
+        for _ in 0..20_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let (stage, seq, timestamp) = (
+                v["event_type"].as_str().unwrap().to_string(),
+                v["seq"].as_u64().unwrap(),
+                v["timestamp"].as_u64().unwrap(),
+            );
+            assert!(timestamp >= last_timestamp, "events are emitted in time order");
+            last_timestamp = timestamp;
+            assert_eq!(generator.key().as_deref(), v["user_id"].as_str());
+            let session = v["session_id"].as_str().unwrap().to_string();
+            match sessions.get(&session) {
+                None => assert_eq!((stage.as_str(), seq), ("landing", 1), "sessions open on a landing page"),
+                Some((previous, last_seq, last_timestamp)) => {
+                    assert_eq!(seq, last_seq + 1);
+                    assert!(timestamp >= *last_timestamp);
+                    let allowed: &[&str] = match previous.as_str() {
+                        "landing" => &["browse"],
+                        "browse" => &["browse", "add_to_cart"],
+                        "add_to_cart" => &["browse", "add_to_cart", "checkout"],
+                        _ => &[],
+                    };
+                    assert!(allowed.contains(&stage.as_str()), "{} -> {}", previous, stage);
+                }
+            }
+            sessions.insert(session, (stage, seq, timestamp));
+        }
+        let funnel = generator.funnel;
+        assert!(funnel.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", funnel);
+        assert!(funnel[3] > 0, "some sessions convert: {:?}", funnel);
+        assert!(generator.dump().contains(&format!("{} of {} sessions reached checkout", funnel[3], funnel[0])));
+    }
+
+    #[test]
+    fn test_users_dwell_between_events() {
+        let mut generator = generator(1, 9);
+        let timestamps: Vec<u64> = (0..200)
+            .map(|_| serde_json::from_str::<Value>(&generator.generate()).unwrap()["timestamp"].as_u64().unwrap())
+            .collect();
+        let gaps: Vec<u64> = timestamps.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let mean = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+        // Page dwells average tens of seconds, and the gaps between sessions minutes.
+        assert!((20.0..300.0).contains(&mean), "mean gap {}s", mean);
+    }
+
+    #[test]
+    fn test_system_clock_idles_until_an_event_is_due() {
+        let mut generator = ClickstreamGenerator::new(3, ClickstreamConfig { seed: 1, ..ClickstreamConfig::default() });
+        generator.schedule = BinaryHeap::from([Reverse((u64::MAX, 0))]);
+        assert!(generator.try_generate().is_none());
+        assert!(!generator.is_exhausted());
+    }
+}
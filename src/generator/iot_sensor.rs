@@ -0,0 +1,280 @@
+use crate::clock::Clock;
+use crate::line_protocol::LineMapping;
+use crate::rng::{RngAlgorithm, SimRng};
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Operating range of the simulated temperature sensor (°C).
+pub const TEMPERATURE_RANGE: (f64, f64) = (-40.0, 85.0);
+/// Relative humidity is a percentage.
+pub const HUMIDITY_RANGE: (f64, f64) = (0.0, 100.0);
+/// Battery charge is a percentage.
+pub const BATTERY_RANGE: (f64, f64) = (0.0, 100.0);
+
+/// One emitted reading.
+#[derive(Serialize)]
+struct Reading<'a> {
+    battery: f64,
+    device_id: &'a str,
+    humidity: f64,
+    seq: u64,
+    temperature: f64,
+    timestamp: u64,
+}
+
+/// A simulated sensor and its latest values.
+#[derive(Debug)]
+pub struct Device {
+    pub id: String,
+    pub temperature: f64,
+    pub humidity: f64,
+    pub battery: f64,
+    /// Number of readings emitted by this device so far.
+    pub seq: u64,
+}
+
+/// Tunable parameters for the sensor model.
+#[derive(Debug, Clone)]
+pub struct IotConfig {
+    /// Standard deviation of each reading's temperature step (°C).
+    pub temperature_step: f64,
+    /// Standard deviation of each reading's humidity step (percentage points).
+    pub humidity_step: f64,
+    /// Largest battery drain per reading (percentage points); each drain is uniform below it.
+    pub battery_drain: f64,
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Source of message timestamps.
+    pub clock: Clock,
+}
+
+impl Default for IotConfig {
+    fn default() -> Self {
+        IotConfig {
+            temperature_step: 0.1,
+            humidity_step: 0.5,
+            battery_drain: 0.01,
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            clock: Clock::System,
+        }
+    }
+}
+
+/// Take a random-walk step from `value`, reflecting off the bounds so the walk stays in
+/// range without piling up at the edges the way clamping would.
+fn bounded_step(value: f64, step: f64, (min, max): (f64, f64)) -> f64 {
+    let mut next = value + step;
+    if next > max {
+        next = max - (next - max);
+    }
+    if next < min {
+        next = min + (min - next);
+    }
+    // A step wider than the whole range can still overshoot after one reflection.
+    next.clamp(min, max)
+}
+
+/// Sensors report two decimal places.
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// An IoT telemetry generator: devices report temperature, humidity and battery charge,
+/// each drifting smoothly within its physical bounds.
+pub struct IotSensorGenerator {
+    devices: Vec<Device>,
+    config: IotConfig,
+    rng: SimRng,
+    clock: Clock,
+    /// Index of the device that emitted the last reading.
+    last_updated: Option<usize>,
+}
+
+impl IotSensorGenerator {
+    pub fn new(variants: usize, config: IotConfig) -> Self {
+        let mut rng = SimRng::new(config.rng, config.seed);
+        let devices = (0..variants)
+            .map(|i| Device {
+                id: format!("DEV{}", i),
+                temperature: rng.random_range(15.0..25.0),
+                humidity: rng.random_range(30.0..60.0),
+                battery: rng.random_range(80.0..100.0),
+                seq: 0,
+            })
+            .collect();
+        IotSensorGenerator {
+            devices,
+            clock: config.clock.clone(),
+            config,
+            rng,
+            last_updated: None,
+        }
+    }
+
+    /// Advance one random device and render its reading into `buf`. Returns false if
+    /// there are no devices.
+    fn next_reading(&mut self, buf: &mut String) -> bool {
+        if self.devices.is_empty() {
+            return false;
+        }
+        let idx = self.rng.random_range(0..self.devices.len());
+        self.last_updated = Some(idx);
+        let rng = &mut self.rng;
+        let device = &mut self.devices[idx];
+
+        let temperature_step: f64 = StandardNormal.sample(rng);
+        device.temperature = bounded_step(
+            device.temperature,
+            temperature_step * self.config.temperature_step,
+            TEMPERATURE_RANGE,
+        );
+        let humidity_step: f64 = StandardNormal.sample(rng);
+        device.humidity = bounded_step(device.humidity, humidity_step * self.config.humidity_step, HUMIDITY_RANGE);
+        // Batteries only drain.
+        let drain = rng.random_range(0.0..=self.config.battery_drain);
+        device.battery = (device.battery - drain).max(BATTERY_RANGE.0);
+        device.seq += 1;
+
+        let reading = Reading {
+            battery: round2(device.battery),
+            device_id: &device.id,
+            humidity: round2(device.humidity),
+            seq: device.seq,
+            temperature: round2(device.temperature),
+            timestamp: self.clock.now_millis() / 1000,
+        };
+        buf.clear();
+        super::write_json(buf, &reading);
+        true
+    }
+}
+
+impl crate::Generator for IotSensorGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        let mut message = String::new();
+        self.next_reading(&mut message).then_some(message)
+    }
+
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        self.next_reading(buf)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    fn generate(&mut self) -> String {
+        self.try_generate().unwrap_or_else(|| "{}".to_string())
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["device_id", "temperature", "humidity", "battery", "seq", "timestamp"]
+    }
+
+    fn schema(&self) -> Value {
+        let bounded = |(min, max): (f64, f64), description: &str| {
+            json!({"type": "number", "minimum": min, "maximum": max, "description": description})
+        };
+        let fields = [
+            ("device_id", json!({"type": "string", "description": "Device id, e.g. DEV0"})),
+            ("temperature", bounded(TEMPERATURE_RANGE, "Degrees Celsius")),
+            ("humidity", bounded(HUMIDITY_RANGE, "Relative humidity, percent")),
+            ("battery", bounded(BATTERY_RANGE, "Battery charge, percent")),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Per-device sequence number"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        super::object_schema("fluxfakr iot reading", &fields, &required)
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_updated.map(|idx| self.devices[idx].id.clone())
+    }
+
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["device_id"],
+            fields: vec!["temperature", "humidity", "battery"],
+            timestamp: "timestamp",
+        })
+    }
+
+    /// The last reading of every device, as CSV.
+    fn dump(&self) -> String {
+        let mut csv = String::from("device_id,temperature,humidity,battery,seq\n");
+        for device in &self.devices {
+            csv.push_str(&format!(
+                "{},{:.2},{:.2},{:.2},{}\n",
+                device.id, device.temperature, device.humidity, device.battery, device.seq
+            ));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+
+    fn config(seed: u64) -> IotConfig {
+        IotConfig {
+            seed,
+            ..IotConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_values_stay_within_physical_bounds() {
+        // Exaggerated steps push the walks into the bounds again and again.
+        let mut generator = IotSensorGenerator::new(
+            4,
+            IotConfig {
+                temperature_step: 40.0,
+                humidity_step: 30.0,
+                battery_drain: 0.5,
+                ..config(3)
+            },
+        );
+        let within = |value: f64, (min, max): (f64, f64)| (min..=max).contains(&value);
+        for _ in 0..50_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert!(within(v["temperature"].as_f64().unwrap(), TEMPERATURE_RANGE), "{}", v);
+            assert!(within(v["humidity"].as_f64().unwrap(), HUMIDITY_RANGE), "{}", v);
+            assert!(within(v["battery"].as_f64().unwrap(), BATTERY_RANGE), "{}", v);
+        }
+        assert!(generator.devices.iter().all(|d| d.battery == 0.0), "batteries drain down to empty");
+    }
+
+    #[test]
+    fn test_readings_drift_smoothly_per_device() {
+        let mut generator = IotSensorGenerator::new(3, config(7));
+        let mut last: std::collections::HashMap<String, (f64, f64, u64)> = Default::default();
+        for _ in 0..5_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let device = v["device_id"].as_str().unwrap().to_string();
+            assert_eq!(generator.key().as_deref(), Some(device.as_str()));
+            let reading = (v["temperature"].as_f64().unwrap(), v["battery"].as_f64().unwrap(), v["seq"].as_u64().unwrap());
+            if let Some((temperature, battery, seq)) = last.get(&device) {
+                assert_eq!(reading.2, seq + 1);
+                assert!((reading.0 - temperature).abs() < 1.0, "temperature jumped: {} -> {}", temperature, reading.0);
+                assert!(reading.1 <= *battery);
+            }
+            last.insert(device, reading);
+        }
+        assert_eq!(last.len(), 3);
+        assert!(generator.dump().starts_with("device_id,temperature,humidity,battery,seq\nDEV0,"));
+    }
+
+    #[test]
+    fn test_bounded_step_reflects() {
+        assert_eq!(bounded_step(99.0, 3.0, (0.0, 100.0)), 98.0);
+        assert_eq!(bounded_step(1.0, -3.0, (0.0, 100.0)), 2.0);
+        assert_eq!(bounded_step(50.0, 500.0, (0.0, 100.0)), 100.0);
+    }
+}
@@ -0,0 +1,429 @@
+use crate::generator::Generator;
+use crate::line_protocol::LineMapping;
+use crate::transform;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How a rollup combines the events of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationKind {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregationKind {
+    fn name(self) -> &'static str {
+        match self {
+            AggregationKind::Count => "count",
+            AggregationKind::Sum => "sum",
+            AggregationKind::Avg => "avg",
+            AggregationKind::Min => "min",
+            AggregationKind::Max => "max",
+        }
+    }
+}
+
+/// One rollup computed per window, optionally per group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    pub kind: AggregationKind,
+    /// Numeric field aggregated; `None` for counts.
+    pub field: Option<String>,
+    /// Field whose values split the rollup into groups, if any.
+    pub group_by: Option<String>,
+}
+
+/// Parse an aggregation list such as `count,avg:price:instrument,sum:total_price:product.category`.
+/// Each entry is `count[:group_by]` or `sum|avg|min|max:field[:group_by]`.
+pub fn parse_aggregations(spec: &str) -> Result<Vec<Aggregation>, String> {
+    let mut aggregations = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut parts = entry.split(':').map(str::trim);
+        let kind = match parts.next().unwrap_or_default() {
+            "count" => AggregationKind::Count,
+            "sum" => AggregationKind::Sum,
+            "avg" => AggregationKind::Avg,
+            "min" => AggregationKind::Min,
+            "max" => AggregationKind::Max,
+            other => {
+                return Err(format!(
+                    "unknown aggregation '{}' (expected count, sum, avg, min or max)",
+                    other
+                ))
+            }
+        };
+        let field = match kind {
+            AggregationKind::Count => None,
+            _ => Some(
+                parts
+                    .next()
+                    .filter(|f| !f.is_empty())
+                    .ok_or_else(|| format!("'{}' needs a field, e.g. {}:price", entry, kind.name()))?
+                    .to_string(),
+            ),
+        };
+        let group_by = parts.next().filter(|g| !g.is_empty()).map(str::to_string);
+        if parts.next().is_some() {
+            return Err(format!("too many parts in aggregation '{}'", entry));
+        }
+        aggregations.push(Aggregation { kind, field, group_by });
+    }
+    if aggregations.is_empty() {
+        return Err("no aggregations given".to_string());
+    }
+    Ok(aggregations)
+}
+
+/// Running totals of one aggregation group within a window.
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Accumulator {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn value(&self, kind: AggregationKind) -> f64 {
+        match kind {
+            AggregationKind::Count => self.count as f64,
+            AggregationKind::Sum => self.sum,
+            AggregationKind::Avg => self.sum / self.count as f64,
+            AggregationKind::Min => self.min,
+            AggregationKind::Max => self.max,
+        }
+    }
+}
+
+/// Metrics mode: wraps another generator, buffers its raw events into tumbling
+/// windows keyed on their `timestamp` field (seconds), and emits one rollup message
+/// per aggregation and group each time a window closes. The raw events themselves
+/// are never emitted.
+pub struct MetricsGenerator {
+    inner: Box<dyn Generator>,
+    window_secs: u64,
+    aggregations: Vec<Aggregation>,
+    /// Start (seconds) of the window currently being filled.
+    window_start: Option<u64>,
+    /// Accumulators of the open window, by aggregation index and group.
+    groups: BTreeMap<(usize, Option<String>), Accumulator>,
+    /// Rollups of closed windows not yet emitted.
+    pending: VecDeque<(String, String)>,
+    /// Partition key of the last emitted rollup.
+    last_key: Option<String>,
+}
+
+impl MetricsGenerator {
+    pub fn new(inner: Box<dyn Generator>, window_secs: u64, aggregations: Vec<Aggregation>) -> Self {
+        MetricsGenerator {
+            inner,
+            window_secs,
+            aggregations,
+            window_start: None,
+            groups: BTreeMap::new(),
+            pending: VecDeque::new(),
+            last_key: None,
+        }
+    }
+
+    /// Fold one raw event into the open window, closing it first if the event
+    /// belongs to a later one.
+    fn ingest(&mut self, message: &str) {
+        let Ok(event) = serde_json::from_str::<Value>(message) else {
+            return;
+        };
+        let Some(timestamp) = event.get("timestamp").and_then(Value::as_u64) else {
+            return;
+        };
+        let start = timestamp - timestamp % self.window_secs;
+        match self.window_start {
+            Some(open) if start > open => {
+                self.close_window();
+                self.window_start = Some(start);
+            }
+            Some(_) => {}
+            None => self.window_start = Some(start),
+        }
+        for (i, aggregation) in self.aggregations.iter().enumerate() {
+            let value = match &aggregation.field {
+                None => 1.0,
+                Some(field) => match lookup(&event, field).and_then(Value::as_f64) {
+                    Some(value) => value,
+                    None => continue,
+                },
+            };
+            let group = match &aggregation.group_by {
+                None => None,
+                Some(field) => match lookup(&event, field) {
+                    Some(Value::String(group)) => Some(group.clone()),
+                    Some(Value::Null) | None => continue,
+                    Some(group) => Some(group.to_string()),
+                },
+            };
+            self.groups.entry((i, group)).or_default().add(value);
+        }
+    }
+
+    /// Turn the open window's accumulators into rollup messages.
+    fn close_window(&mut self) {
+        let Some(start) = self.window_start.take() else {
+            return;
+        };
+        for ((i, group), accumulator) in std::mem::take(&mut self.groups) {
+            let aggregation = &self.aggregations[i];
+            let rollup = json!({
+                "window_start": start,
+                "window_end": start + self.window_secs,
+                "metric": aggregation.kind.name(),
+                "field": aggregation.field,
+                "group_by": aggregation.group_by,
+                "group": group,
+                "value": accumulator.value(aggregation.kind),
+                "samples": accumulator.count,
+            });
+            let key = group.unwrap_or_else(|| aggregation.kind.name().to_string());
+            self.pending.push_back((rollup.to_string(), key));
+        }
+    }
+
+    fn pop(&mut self) -> Option<String> {
+        let (message, key) = self.pending.pop_front()?;
+        self.last_key = Some(key);
+        Some(message)
+    }
+}
+
+fn lookup<'a>(event: &'a Value, field: &str) -> Option<&'a Value> {
+    transform::lookup(event, &field.split('.').collect::<Vec<_>>())
+}
+
+impl Generator for MetricsGenerator {
+    /// Emits a pending rollup if there is one; otherwise feeds the wrapped generator
+    /// one event and stays idle until a window closes.
+    fn try_generate(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            if self.inner.is_exhausted() {
+                // Nothing more is coming, so the last, partial window is final.
+                self.close_window();
+            } else if let Some(message) = self.inner.try_generate() {
+                self.ingest(&message);
+            }
+        }
+        self.pop()
+    }
+
+    fn generate(&mut self) -> String {
+        while self.pending.is_empty() && !self.inner.is_exhausted() {
+            let message = self.inner.generate();
+            self.ingest(&message);
+        }
+        if self.pending.is_empty() {
+            self.close_window();
+        }
+        self.pop().unwrap_or_else(|| "{}".to_string())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.inner.is_exhausted() && self.window_start.is_none() && self.pending.is_empty()
+    }
+
+    fn dump(&self) -> String {
+        format!(
+            "Metrics: {}s windows, open window start: {:?}, {} groups open, {} rollups pending\n{}",
+            self.window_secs,
+            self.window_start,
+            self.groups.len(),
+            self.pending.len(),
+            self.inner.dump()
+        )
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec![
+            "window_start",
+            "window_end",
+            "metric",
+            "field",
+            "group_by",
+            "group",
+            "value",
+            "samples",
+        ]
+    }
+
+    fn schema(&self) -> Value {
+        let seconds = || json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"});
+        let nullable = || json!({"type": ["string", "null"]});
+        let fields = [
+            ("window_start", seconds()),
+            ("window_end", seconds()),
+            ("metric", json!({"enum": ["count", "sum", "avg", "min", "max"]})),
+            ("field", nullable()),
+            ("group_by", nullable()),
+            ("group", nullable()),
+            ("value", json!({"type": "number"})),
+            ("samples", json!({"type": "integer", "minimum": 1})),
+        ];
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        super::object_schema("fluxfakr metrics rollup", &fields, &required)
+    }
+
+    fn core_fields(&self) -> Vec<&'static str> {
+        vec!["window_start", "metric", "group", "value"]
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_key.clone()
+    }
+
+    /// Rollups are stamped with the end of their window.
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["metric", "field", "group_by", "group"],
+            fields: vec!["value", "samples"],
+            timestamp: "window_end",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+
+    /// Replays fixed events, then reports itself exhausted.
+    struct Replay(VecDeque<Value>);
+
+    impl Generator for Replay {
+        fn generate(&mut self) -> String {
+            self.0.pop_front().map_or_else(|| "{}".to_string(), |v| v.to_string())
+        }
+        fn is_exhausted(&self) -> bool {
+            self.0.is_empty()
+        }
+        fn dump(&self) -> String {
+            String::new()
+        }
+        fn fields(&self) -> Vec<&'static str> {
+            vec!["timestamp", "instrument", "price"]
+        }
+    }
+
+    fn rollups(generator: &mut MetricsGenerator) -> Vec<Value> {
+        let mut out = Vec::new();
+        while !generator.is_exhausted() {
+            if let Some(message) = generator.try_generate() {
+                out.push(serde_json::from_str(&message).unwrap());
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_aggregations() {
+        let aggregations = parse_aggregations("count, avg:price:instrument").unwrap();
+        assert_eq!(aggregations[0].kind, AggregationKind::Count);
+        assert_eq!(aggregations[1].field.as_deref(), Some("price"));
+        assert_eq!(aggregations[1].group_by.as_deref(), Some("instrument"));
+        assert!(parse_aggregations("median:price").is_err());
+        assert!(parse_aggregations("avg").is_err());
+        assert!(parse_aggregations("sum:a:b:c").is_err());
+        assert!(parse_aggregations("").is_err());
+    }
+
+    #[test]
+    fn test_rollups_per_window_and_group() {
+        let events = [(0, "A", 10.0), (30, "B", 20.0), (59, "A", 30.0), (60, "A", 5.0)];
+        let replay = Replay(
+            events
+                .iter()
+                .map(|&(ts, id, price)| json!({"timestamp": ts, "instrument": id, "price": price}))
+                .collect(),
+        );
+        let mut generator = MetricsGenerator::new(
+            Box::new(replay),
+            60,
+            parse_aggregations("count,avg:price:instrument").unwrap(),
+        );
+        let rollups = rollups(&mut generator);
+        let summary: Vec<(u64, &str, Option<&str>, f64)> = rollups
+            .iter()
+            .map(|r| {
+                (
+                    r["window_start"].as_u64().unwrap(),
+                    r["metric"].as_str().unwrap(),
+                    r["group"].as_str(),
+                    r["value"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "count", None, 3.0),
+                (0, "avg", Some("A"), 20.0),
+                (0, "avg", Some("B"), 20.0),
+                (60, "count", None, 1.0),
+                (60, "avg", Some("A"), 5.0),
+            ]
+        );
+        assert_eq!(rollups[0]["window_end"], 60);
+    }
+
+    #[test]
+    fn test_revenue_per_category_matches_raw_events() {
+        let config = || SalesConfig {
+            seed: 3,
+            clock: Clock::Stepped {
+                now_ms: 0,
+                step_ms: 1_000,
+            },
+            ..SalesConfig::default()
+        };
+        let mut raw = SalesGenerator::new(config());
+        let mut expected: BTreeMap<String, f64> = BTreeMap::new();
+        for _ in 0..120 {
+            let sale: Value = serde_json::from_str(&raw.generate()).unwrap();
+            if sale["timestamp"].as_u64().unwrap() < 60 {
+                *expected
+                    .entry(sale["product"]["category"].as_str().unwrap().to_string())
+                    .or_default() += sale["total_price"].as_f64().unwrap();
+            }
+        }
+
+        let mut generator = MetricsGenerator::new(
+            Box::new(SalesGenerator::new(config())),
+            60,
+            parse_aggregations("sum:total_price:product.category").unwrap(),
+        );
+        for _ in 0..expected.len() {
+            let rollup: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert_eq!(rollup["window_start"], 0);
+            let category = rollup["group"].as_str().unwrap();
+            assert!((rollup["value"].as_f64().unwrap() - expected[category]).abs() < 1e-6);
+            assert_eq!(generator.key().as_deref(), Some(category));
+        }
+    }
+}
@@ -1,10 +1,392 @@
+pub mod clickstream;
+pub mod iot_sensor;
+pub mod metrics;
+pub mod payments;
 pub mod stock;
 pub mod supermarket_sales;
+pub mod web_logs;
+
+use crate::line_protocol::LineMapping;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io;
+
+/// Lets `serde_json` serialize straight into a `String`. serde_json only ever writes
+/// complete UTF-8 sequences, so every chunk is valid on its own.
+struct StringWriter<'a>(&'a mut String);
+
+impl io::Write for StringWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let text = std::str::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.0.push_str(text);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Append `value` to `buf` as compact JSON, without an intermediate `String` or `Value`.
+/// Fields serialize in declaration order, so message structs declare theirs in
+/// alphabetical order: the order a `Value` writes its keys in, which the golden files lock.
+pub fn write_json(buf: &mut String, value: &impl Serialize) {
+    serde_json::to_writer(StringWriter(buf), value).expect("message types serialize to JSON");
+}
 
 pub trait Generator {
     /// Generate a JSON data message
     fn generate(&mut self) -> String;
+    /// Generate a message, or `None` when the generator is idle this tick
+    /// (e.g. a closed market). The default always produces a message.
+    fn try_generate(&mut self) -> Option<String> {
+        Some(self.generate())
+    }
+    /// Refill `batch` with up to `n` messages, each with its partition key (what `key`
+    /// returns right after it, empty if none). Stops early at an idle tick or once the
+    /// generator is exhausted. The default calls `generate_into` in a loop, reusing the
+    /// batch's buffers; generators with a cheaper bulk path can override it.
+    fn generate_batch(&mut self, n: usize, batch: &mut Batch) {
+        batch.clear();
+        while batch.len() < n && !self.is_exhausted() {
+            if !self.generate_into(batch.next_buffer()) {
+                break;
+            }
+            batch.commit(self.key().unwrap_or_default());
+        }
+    }
+    /// Write the next message into `buf`, replacing its contents, so callers can reuse one
+    /// buffer across messages. Returns false, like `try_generate` returning `None`, when
+    /// the generator is idle this tick. The default delegates to `try_generate`;
+    /// generators that serialize directly into the buffer override it.
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        match self.try_generate() {
+            Some(message) => {
+                *buf = message;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Whether the generator has nothing left to produce and the run should stop
+    fn is_exhausted(&self) -> bool {
+        false
+    }
     /// Dump the internal state
     fn dump(&self) -> String;
+    /// Dotted paths of every field the generator's messages can contain
+    fn fields(&self) -> Vec<&'static str>;
+    /// JSON Schema document describing the generator's messages. The default lists the
+    /// `fields` without types; generators override it to describe the types too.
+    fn schema(&self) -> Value {
+        let fields = self.fields();
+        let typed: Vec<(&str, Value)> = fields
+            .iter()
+            .map(|field| {
+                let is_object = fields.iter().any(|f| f.starts_with(field) && f[field.len()..].starts_with('.'));
+                (*field, if is_object { json!({"type": "object"}) } else { json!({}) })
+            })
+            .collect();
+        object_schema("fluxfakr message", &typed, &[])
+    }
+    /// Top-level fields a minimal (`--size-mix` small) message keeps: its ids and
+    /// headline figures. Defaults to every top-level field.
+    fn core_fields(&self) -> Vec<&'static str> {
+        self.fields().into_iter().filter(|f| !f.contains('.')).collect()
+    }
+    /// Natural partition key of the most recently generated message, if any
+    fn key(&self) -> Option<String> {
+        None
+    }
+    /// Which fields become tags and which become fields under `--format line-protocol`,
+    /// or `None` if the generator has no mapping
+    fn line_mapping(&self) -> Option<LineMapping> {
+        None
+    }
+}
+
+/// Build a JSON Schema (draft 2020-12) for an object message from each field's dotted
+/// path and its own schema, plus the top-level fields every message carries. A nested
+/// field's parent object must come before it.
+pub fn object_schema(title: &str, fields: &[(&str, Value)], required: &[&str]) -> Value {
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "object",
+        "properties": {},
+    });
+    for (path, field_schema) in fields {
+        let mut parts: Vec<&str> = path.split('.').collect();
+        let name = parts.pop().unwrap_or_default();
+        let mut parent = &mut schema;
+        for part in parts {
+            parent = &mut parent["properties"][part];
+        }
+        if parent.get("properties").is_none() {
+            parent["properties"] = json!({});
+        }
+        parent["properties"][name] = field_schema.clone();
+    }
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+/// A batch of `(message, key)` pairs whose buffers are kept across batches, so steady-state
+/// generation doesn't allocate a fresh `String` per message.
+#[derive(Debug, Default)]
+pub struct Batch {
+    entries: Vec<(String, String)>,
+    len: usize,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch::default()
+    }
+
+    /// Empty the batch, keeping its buffers for reuse.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The buffer for the next message; it only joins the batch once `commit`ted.
+    pub fn next_buffer(&mut self) -> &mut String {
+        if self.entries.len() == self.len {
+            self.entries.push(Default::default());
+        }
+        &mut self.entries[self.len].0
+    }
+
+    /// Add the message just written to `next_buffer`, with its key.
+    pub fn commit(&mut self, key: String) {
+        self.entries[self.len].1 = key;
+        self.len += 1;
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries[..self.len]
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [(String, String)] {
+        &mut self.entries[..self.len]
+    }
 }
 
+/// Golden-file tests lock the wire format: a fixed seed and stepped clock make output
+/// byte-for-byte reproducible, so any diff against `tests/golden/` is a format change.
+/// Run with `BLESS=1` to regenerate the golden files after an intentional change.
+#[cfg(test)]
+mod golden {
+    use super::payments::{PaymentsConfig, PaymentsGenerator};
+    use super::clickstream::{ClickstreamConfig, ClickstreamGenerator};
+    use super::iot_sensor::{IotConfig, IotSensorGenerator};
+    use super::stock::{StockConfig, StockDataGenerator};
+    use super::supermarket_sales::{SalesConfig, SalesGenerator};
+    use super::web_logs::{WebLogsConfig, WebLogsGenerator};
+    use super::Generator;
+    use crate::clock::Clock;
+    use std::path::PathBuf;
+
+    const SEED: u64 = 42;
+    const MESSAGES: usize = 25;
+
+    fn clock() -> Clock {
+        Clock::Stepped {
+            now_ms: 1_700_000_000_000,
+            step_ms: 1_000,
+        }
+    }
+
+    fn check(name: &str, mut generator: impl Generator) {
+        let output: String = (0..MESSAGES)
+            .map(|_| generator.generate() + "\n")
+            .collect();
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.jsonl", name));
+        if std::env::var_os("BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &output).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("cannot read {}: {} (run with BLESS=1)", path.display(), e));
+        assert!(
+            output == expected,
+            "{} output differs from {}; if the format change is intentional, rerun with BLESS=1",
+            name,
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_stream() {
+        fn run(generator: &mut dyn Generator) -> Vec<String> {
+            (0..200).map(|_| generator.generate()).collect()
+        }
+        let stock = |seed| {
+            StockDataGenerator::new(
+                5,
+                StockConfig {
+                    seed,
+                    clock: clock(),
+                    emit_deltas: true,
+                    ..StockConfig::default()
+                },
+            )
+        };
+        let supermarket = |seed| {
+            SalesGenerator::new(SalesConfig {
+                seed,
+                clock: clock(),
+                abandon_rate: 0.1,
+                stockout_rate: 0.05,
+                coupon_rate: 0.2,
+                store_pool: Some(4),
+                ..SalesConfig::default()
+            })
+        };
+        assert_eq!(run(&mut stock(7)), run(&mut stock(7)));
+        assert_ne!(run(&mut stock(7)), run(&mut stock(8)));
+        assert_eq!(run(&mut supermarket(7)), run(&mut supermarket(7)));
+        assert_ne!(run(&mut supermarket(7)), run(&mut supermarket(8)));
+    }
+
+    #[test]
+    fn test_stock_golden() {
+        check(
+            "stock",
+            StockDataGenerator::new(
+                3,
+                StockConfig {
+                    seed: SEED,
+                    clock: clock(),
+                    ..StockConfig::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_supermarket_golden() {
+        check(
+            "supermarket",
+            SalesGenerator::new(SalesConfig {
+                seed: SEED,
+                clock: clock(),
+                ..SalesConfig::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn test_iot_golden() {
+        check(
+            "iot",
+            IotSensorGenerator::new(
+                3,
+                IotConfig {
+                    seed: SEED,
+                    clock: clock(),
+                    ..IotConfig::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_web_logs_golden() {
+        check(
+            "web_logs",
+            WebLogsGenerator::new(
+                3,
+                WebLogsConfig {
+                    seed: SEED,
+                    clock: clock(),
+                    ..WebLogsConfig::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_clickstream_golden() {
+        check(
+            "clickstream",
+            ClickstreamGenerator::new(
+                3,
+                ClickstreamConfig {
+                    seed: SEED,
+                    clock: clock(),
+                    ..ClickstreamConfig::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_payments_golden() {
+        check(
+            "payments",
+            PaymentsGenerator::new(
+                3,
+                PaymentsConfig {
+                    fraud_rate: 0.2,
+                    seed: SEED,
+                    clock: clock(),
+                    ..PaymentsConfig::default()
+                },
+            ),
+        );
+    }
+}
+
+/// Counts heap allocations made by the current thread, so tests can check the
+/// allocation cost of generating a message without interference from other tests.
+#[cfg(test)]
+pub mod alloc_count {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    }
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static COUNTING: Counting = Counting;
+
+    /// Allocations made by this thread while running `f`.
+    pub fn count(f: impl FnOnce()) -> u64 {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+}
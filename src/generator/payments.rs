@@ -0,0 +1,352 @@
+use crate::clock::Clock;
+use crate::line_protocol::LineMapping;
+use crate::rng::{RngAlgorithm, SimRng};
+use rand::distr::Distribution;
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use rand_distr::LogNormal;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Card home countries (ISO 3166-1 alpha-2), each with the region it is in. Impossible
+/// travel moves a card to another region.
+const COUNTRIES: &[(&str, u8)] = &[
+    ("US", 0),
+    ("CA", 0),
+    ("GB", 1),
+    ("DE", 1),
+    ("FR", 1),
+    ("JP", 2),
+    ("SG", 2),
+    ("AU", 3),
+    ("BR", 4),
+    ("NG", 5),
+];
+
+/// Merchants: name, merchant category code, and typical amount relative to the card's
+/// usual spend.
+const MERCHANTS: &[(&str, &str, f64)] = &[
+    ("FreshMart", "5411", 1.0),
+    ("QuickFuel", "5541", 0.8),
+    ("Bean There Coffee", "5814", 0.15),
+    ("Luigi's Trattoria", "5812", 0.9),
+    ("MegaStore Online", "5311", 1.6),
+    ("CityRail", "4111", 0.1),
+    ("PharmaPlus", "5912", 0.5),
+    ("StreamFlix", "4899", 0.3),
+    ("SkyHigh Airlines", "4511", 6.0),
+    ("Grand Plaza Hotel", "7011", 4.0),
+];
+
+/// Where amount spikes are spent: merchants selling easily resold goods.
+const SPIKE_MERCHANTS: &[(&str, &str)] = &[("ElectroWorld", "5732"), ("Goldsmith & Co", "5944"), ("GiftCard Hub", "5999")];
+
+/// The kinds of fraud that can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FraudType {
+    /// The card is used in another region moments after its previous transaction.
+    ImpossibleTravel,
+    /// An amount far above the card's usual spend, at a merchant of resellable goods.
+    AmountSpike,
+}
+
+/// One emitted transaction. The underscored ground-truth labels are left out when labels
+/// are off, and `_fraud_type` on legitimate transactions.
+#[derive(Serialize)]
+struct Transaction<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    _fraud_type: Option<FraudType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    _is_fraud: Option<bool>,
+    amount: f64,
+    card_hash: &'a str,
+    country: &'a str,
+    mcc: &'a str,
+    merchant: &'a str,
+    timestamp: u64,
+    txn_id: &'a str,
+}
+
+/// A simulated card and its spending habits.
+#[derive(Debug)]
+pub struct Card {
+    pub card_hash: String,
+    /// Index into `COUNTRIES` of the card's home country.
+    home: usize,
+    /// Median amount of the card's purchases.
+    pub usual_spend: f64,
+    pub transactions: u64,
+}
+
+/// Tunable parameters for the payments model.
+#[derive(Debug, Clone)]
+pub struct PaymentsConfig {
+    /// Probability (0.0–1.0) that a transaction is fraudulent.
+    pub fraud_rate: f64,
+    /// Emit the ground-truth `_is_fraud` and `_fraud_type` labels.
+    pub labels: bool,
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Source of message timestamps.
+    pub clock: Clock,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        PaymentsConfig {
+            fraud_rate: 0.0,
+            labels: true,
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            clock: Clock::System,
+        }
+    }
+}
+
+/// A card-transaction generator: cards spend at everyday merchants in their home
+/// country, with a tunable fraction of fraud injected.
+pub struct PaymentsGenerator {
+    cards: Vec<Card>,
+    config: PaymentsConfig,
+    /// Spread of amounts around a card's usual spend at a merchant.
+    noise: LogNormal<f64>,
+    rng: SimRng,
+    clock: Clock,
+    /// Index of the card behind the last transaction.
+    last_card: Option<usize>,
+    /// Fraudulent transactions emitted so far, by type.
+    fraud_counts: [u64; 2],
+}
+
+impl PaymentsGenerator {
+    pub fn new(variants: usize, config: PaymentsConfig) -> Self {
+        let mut rng = SimRng::new(config.rng, config.seed);
+        let cards = (0..variants)
+            .map(|_| Card {
+                card_hash: format!("{:016x}", rng.random::<u64>()),
+                home: rng.random_range(0..COUNTRIES.len()),
+                usual_spend: rng.random_range(15.0..80.0),
+                transactions: 0,
+            })
+            .collect();
+        PaymentsGenerator {
+            cards,
+            noise: LogNormal::new(0.0, 0.5).expect("amount noise parameters are valid"),
+            clock: config.clock.clone(),
+            config,
+            rng,
+            last_card: None,
+            fraud_counts: [0; 2],
+        }
+    }
+
+    /// Generate one transaction into `buf`. Returns false if there are no cards.
+    fn next_transaction(&mut self, buf: &mut String) -> bool {
+        if self.cards.is_empty() {
+            return false;
+        }
+        let rng = &mut self.rng;
+        let fraud = rng.random_bool(self.config.fraud_rate).then(|| {
+            // Impossible travel needs a previous transaction to contradict.
+            if self.last_card.is_some() && rng.random_bool(0.5) {
+                FraudType::ImpossibleTravel
+            } else {
+                FraudType::AmountSpike
+            }
+        });
+        let idx = match fraud {
+            Some(FraudType::ImpossibleTravel) => self.last_card.expect("travel follows a transaction"),
+            _ => rng.random_range(0..self.cards.len()),
+        };
+        self.last_card = Some(idx);
+        let card = &mut self.cards[idx];
+        card.transactions += 1;
+
+        let (home, home_region) = COUNTRIES[card.home];
+        let (mut merchant, mut mcc, scale) = *MERCHANTS.choose(rng).expect("merchants are listed");
+        let mut country = home;
+        let mut amount = card.usual_spend * scale * self.noise.sample(rng);
+        match fraud {
+            Some(FraudType::ImpossibleTravel) => {
+                let abroad: Vec<&str> = COUNTRIES
+                    .iter()
+                    .filter(|(_, region)| *region != home_region)
+                    .map(|(code, _)| *code)
+                    .collect();
+                country = abroad.choose(rng).expect("there is more than one region");
+                self.fraud_counts[0] += 1;
+            }
+            Some(FraudType::AmountSpike) => {
+                (merchant, mcc) = *SPIKE_MERCHANTS.choose(rng).expect("spike merchants are listed");
+                amount = card.usual_spend * rng.random_range(15.0..60.0);
+                self.fraud_counts[1] += 1;
+            }
+            None => {}
+        }
+
+        let txn_id = format!("PAY-{:012}", rng.random_range(0..1_000_000_000_000u64));
+        let labels = self.config.labels;
+        let transaction = Transaction {
+            _fraud_type: fraud.filter(|_| labels),
+            _is_fraud: labels.then_some(fraud.is_some()),
+            amount: ((amount * 100.0).round() / 100.0).max(0.01),
+            card_hash: &card.card_hash,
+            country,
+            mcc,
+            merchant,
+            timestamp: self.clock.now_millis() / 1000,
+            txn_id: &txn_id,
+        };
+        buf.clear();
+        super::write_json(buf, &transaction);
+        true
+    }
+}
+
+impl crate::Generator for PaymentsGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        let mut message = String::new();
+        self.next_transaction(&mut message).then_some(message)
+    }
+
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        self.next_transaction(buf)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    fn generate(&mut self) -> String {
+        self.try_generate().unwrap_or_else(|| "{}".to_string())
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        let mut fields = vec!["txn_id", "card_hash", "amount", "merchant", "mcc", "country", "timestamp"];
+        if self.config.labels {
+            fields.extend(["_is_fraud", "_fraud_type"]);
+        }
+        fields
+    }
+
+    fn schema(&self) -> Value {
+        let mut fields = vec![
+            ("txn_id", json!({"type": "string"})),
+            ("card_hash", json!({"type": "string", "description": "Opaque card identifier"})),
+            ("amount", json!({"type": "number", "minimum": 0.01})),
+            ("merchant", json!({"type": "string"})),
+            ("mcc", json!({"type": "string", "description": "ISO 18245 merchant category code"})),
+            ("country", json!({"type": "string", "description": "ISO 3166-1 alpha-2 code of the merchant"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        let mut required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        if self.config.labels {
+            fields.push(("_is_fraud", json!({"type": "boolean", "description": "Ground truth: whether the transaction is fraudulent"})));
+            fields.push((
+                "_fraud_type",
+                json!({"type": "string", "enum": ["impossible_travel", "amount_spike"], "description": "Ground truth: the kind of fraud, on fraudulent transactions only"}),
+            ));
+            required.push("_is_fraud");
+        }
+        super::object_schema("fluxfakr card transaction", &fields, &required)
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_card.map(|idx| self.cards[idx].card_hash.clone())
+    }
+
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["mcc", "country"],
+            fields: vec!["amount", "merchant", "card_hash", "txn_id"],
+            timestamp: "timestamp",
+        })
+    }
+
+    /// Transactions and injected fraud so far, as CSV.
+    fn dump(&self) -> String {
+        let transactions: u64 = self.cards.iter().map(|card| card.transactions).sum();
+        format!(
+            "transactions,impossible_travel,amount_spike\n{},{},{}\n",
+            transactions, self.fraud_counts[0], self.fraud_counts[1]
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+
+    fn generator(fraud_rate: f64, labels: bool) -> PaymentsGenerator {
+        PaymentsGenerator::new(
+            50,
+            PaymentsConfig {
+                fraud_rate,
+                labels,
+                seed: 21,
+                ..PaymentsConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_fraud_rate_within_tolerance() {
+        for rate in [0.0, 0.02, 0.1] {
+            let mut generator = generator(rate, true);
+            let total = 50_000;
+            let mut fraudulent = 0;
+            for _ in 0..total {
+                let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+                if v["_is_fraud"].as_bool().unwrap() {
+                    fraudulent += 1;
+                    assert!(v["_fraud_type"].is_string(), "{}", v);
+                } else {
+                    assert!(v.get("_fraud_type").is_none(), "{}", v);
+                }
+            }
+            let observed = fraudulent as f64 / total as f64;
+            assert!((observed - rate).abs() < 0.005, "fraud rate {} for requested {}", observed, rate);
+        }
+    }
+
+    #[test]
+    fn test_fraud_patterns() {
+        let mut generator = generator(0.2, true);
+        let mut previous: Option<Value> = None;
+        for _ in 0..20_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let card = &generator.cards[generator.last_card.unwrap()];
+            let home = COUNTRIES[card.home];
+            match v["_fraud_type"].as_str() {
+                Some("impossible_travel") => {
+                    let previous = previous.as_ref().unwrap();
+                    assert_eq!(previous["card_hash"], v["card_hash"], "travel follows the card's last use");
+                    let region = COUNTRIES.iter().find(|(code, _)| v["country"] == *code).unwrap().1;
+                    assert_ne!(region, home.1);
+                }
+                Some("amount_spike") => {
+                    assert!(v["amount"].as_f64().unwrap() >= card.usual_spend * 15.0 - 0.01);
+                    assert!(SPIKE_MERCHANTS.iter().any(|(name, _)| v["merchant"] == *name));
+                }
+                _ => assert_eq!(v["country"], home.0),
+            }
+            previous = Some(v);
+        }
+        assert!(generator.fraud_counts.iter().all(|count| *count > 0));
+    }
+
+    #[test]
+    fn test_labels_can_be_omitted() {
+        let mut generator = generator(0.5, false);
+        for _ in 0..100 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert!(v.get("_is_fraud").is_none() && v.get("_fraud_type").is_none(), "{}", v);
+        }
+        assert!(!generator.fields().contains(&"_is_fraud"));
+        assert!(generator.schema()["properties"].get("_is_fraud").is_none());
+    }
+}
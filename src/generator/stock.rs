@@ -1,70 +1,791 @@
+use crate::clock::{self, Clock};
+use crate::line_protocol::LineMapping;
+use crate::rng::{RngAlgorithm, SimRng};
+use clap::ValueEnum;
+use rand::distr::weighted::WeightedIndex;
 use rand::Rng;
-use rand_distr::{Distribution, StandardNormal};
-use serde_json::json;
-use std::time::{SystemTime, UNIX_EPOCH};
+use rand_distr::{Distribution, LogNormal, Pareto, StandardNormal};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+
+/// One emitted quote. Fields are declared in alphabetical order, the wire order the
+/// golden files lock; the delta fields are present only with `emit_deltas`, and
+/// `sector` only with `tickers`.
+#[derive(Serialize)]
+struct Quote<'a> {
+    ask: f64,
+    bid: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<f64>,
+    instrument: &'a str,
+    price: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price_after: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price_before: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sector: Option<&'a str>,
+    seq: u64,
+    timestamp: u64,
+    volume: u64,
+}
+
+/// A bid/ask update in trades-and-quotes mode, in alphabetical field order like `Quote`.
+#[derive(Serialize)]
+struct QuoteUpdate<'a> {
+    ask: f64,
+    bid: f64,
+    instrument: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sector: Option<&'a str>,
+    seq: u64,
+    timestamp: u64,
+    r#type: &'static str,
+}
+
+/// An executed trade in trades-and-quotes mode.
+#[derive(Serialize)]
+struct Trade<'a> {
+    instrument: &'a str,
+    price: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sector: Option<&'a str>,
+    seq: u64,
+    size: u64,
+    timestamp: u64,
+    r#type: &'static str,
+}
+
+/// One emitted OHLC bar, in alphabetical field order like `Quote`.
+#[derive(Serialize)]
+struct CandleMessage<'a> {
+    close: f64,
+    high: f64,
+    instrument: &'a str,
+    low: f64,
+    open: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sector: Option<&'a str>,
+    seq: u64,
+    timestamp: u64,
+    trades: u64,
+    volume: u64,
+}
+
+/// The emitted figures of one instrument update.
+struct Tick {
+    idx: usize,
+    /// Shared-clock reading (ms) the update was made at.
+    now_ms: u64,
+    /// Message timestamp (s), on the instrument's own clock.
+    timestamp: u64,
+    price: f64,
+    bid: f64,
+    ask: f64,
+    /// Price before the tick and the delta, with `emit_deltas`.
+    deltas: Option<(f64, f64)>,
+    /// Whether the update is a trade; in trades-and-quotes mode the rest are quotes.
+    trade: bool,
+    /// Volume traded, zero for a quote.
+    trade_volume: u64,
+}
+
+/// An open/high/low/close bar accumulating one instrument's ticks.
+#[derive(Debug, Clone, PartialEq)]
+struct Bar {
+    /// Start (ms) of the interval the bar covers.
+    start_ms: u64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    /// Volume traded within the bar.
+    volume: u64,
+    trades: u64,
+    /// Per-instrument bar number, assigned when the bar closes.
+    seq: u64,
+}
+
+/// Candle mode state: each instrument's open bar in the current interval, and finished
+/// bars waiting to be emitted one per call.
+#[derive(Debug)]
+struct Candles {
+    interval_ms: u64,
+    /// Start (ms) of the interval the open bars cover.
+    bucket_start_ms: Option<u64>,
+    /// Open bar per instrument index; `None` for instruments that haven't ticked in it.
+    bars: Vec<Option<Bar>>,
+    /// Bars closed per instrument index so far.
+    closed: Vec<u64>,
+    ready: VecDeque<(usize, Bar)>,
+}
+
+impl Candles {
+    fn new(interval_ms: u64) -> Self {
+        Candles {
+            interval_ms,
+            bucket_start_ms: None,
+            bars: Vec::new(),
+            closed: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Add a tick to its instrument's bar. Intervals are aligned to the epoch on the
+    /// shared clock; the first tick of a new interval closes every bar of the previous one.
+    fn record(&mut self, tick: &Tick) {
+        let start_ms = tick.now_ms - tick.now_ms % self.interval_ms;
+        if self.bucket_start_ms.is_some_and(|open| open != start_ms) {
+            for (idx, slot) in self.bars.iter_mut().enumerate() {
+                if let Some(mut bar) = slot.take() {
+                    self.closed[idx] += 1;
+                    bar.seq = self.closed[idx];
+                    self.ready.push_back((idx, bar));
+                }
+            }
+        }
+        self.bucket_start_ms = Some(start_ms);
+        if tick.idx >= self.bars.len() {
+            self.bars.resize(tick.idx + 1, None);
+            self.closed.resize(tick.idx + 1, 0);
+        }
+        let bar = self.bars[tick.idx].get_or_insert(Bar {
+            start_ms,
+            open: tick.price,
+            high: tick.price,
+            low: tick.price,
+            close: tick.price,
+            volume: 0,
+            trades: 0,
+            seq: 0,
+        });
+        bar.high = bar.high.max(tick.price);
+        bar.low = bar.low.min(tick.price);
+        bar.close = tick.price;
+        bar.volume = bar.volume.saturating_add(tick.trade_volume);
+        bar.trades += 1;
+    }
+}
 
 /// Represents a simulated stock instrument with realistic market data.
 #[derive(Debug)]
 pub struct Instrument {
     pub id: String,
+    /// Industry sector, assigned only when instruments are named from the ticker pool.
+    pub sector: Option<&'static str>,
     pub price: f64,
     pub bid: f64,
     pub ask: f64,
     pub volume: u64,
+    /// Number of messages emitted for this instrument so far.
+    pub seq: u64,
+    /// Clock reading (ms) of this instrument's last update, for per-entity throttling.
+    pub last_emitted_ms: Option<u64>,
+    /// Price reported by the previous message (the starting price before the first),
+    /// captured so `--emit-deltas` can report the before/after pair.
+    pub last_price: f64,
+    /// Long-term mean the OU model pulls this instrument's price towards.
+    pub mean: f64,
+    /// Level of the shared market factor when this instrument last updated.
+    pub market_seen: f64,
+    /// Day (since the epoch) the intraday volume model last accumulated volume on.
+    pub volume_day: Option<u64>,
+    /// Bid and ask of the last quote update published for this instrument, which its
+    /// trades execute against (trades-and-quotes mode only).
+    pub quoted: Option<(f64, f64)>,
+    /// Fixed offset (ms) applied to this instrument's timestamps, simulating an
+    /// unsynchronized producer; 0 unless clock skew is configured.
+    pub clock_skew_ms: i64,
+}
+
+/// What to do when the instrument book is empty (e.g. `--variants 0`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EmptyBookPolicy {
+    /// Produce nothing and report the generator as exhausted, ending the run gracefully.
+    Stop,
+    /// List a freshly generated instrument and keep streaming.
+    Reseed,
+}
+
+/// Default GBM drift (mu), per year.
+pub const DEFAULT_DRIFT: f64 = 0.0001;
+/// Default volatility (sigma), per square-root year.
+pub const DEFAULT_VOLATILITY: f64 = 0.01;
+
+/// Stochastic process driving instrument prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PriceModel {
+    /// Geometric Brownian Motion: prices drift and wander without bound.
+    Gbm,
+    /// Ornstein–Uhlenbeck: prices revert towards a long-term mean, staying range-bound.
+    Ou,
+}
+
+/// Which messages the stock generator emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StockMessages {
+    /// One message per tick with the price, bid, ask and cumulative volume.
+    Combined,
+    /// Each tick is either a quote (`type` `quote`: bid and ask) or a trade (`type`
+    /// `trade`: executed price and size), like a real feed.
+    TradesAndQuotes,
+}
+
+/// Share of ticks that are trades in trades-and-quotes mode; the rest are quotes.
+const TRADE_SHARE: f64 = 0.3;
+
+/// How an instrument's cumulative volume evolves over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VolumeModel {
+    /// Every tick trades a draw from the volume distribution; volume accumulates forever.
+    Linear,
+    /// Trades follow a U-shaped intraday curve, heavy at the open and close, and the
+    /// cumulative volume resets at each day boundary.
+    Intraday,
+}
+
+/// Multiplier the intraday curve applies to trade volume at `now_ms`: three times the
+/// midday level at the session open and close, and thin (a fifth) outside session hours.
+fn intraday_volume_factor(now_ms: u64) -> f64 {
+    let time_of_day = now_ms % DAY_MS;
+    if !(SESSION_OPEN_UTC_MS..SESSION_CLOSE_UTC_MS).contains(&time_of_day) {
+        return 0.2;
+    }
+    let progress = (time_of_day - SESSION_OPEN_UTC_MS) as f64 / (SESSION_CLOSE_UTC_MS - SESSION_OPEN_UTC_MS) as f64;
+    1.0 + 2.0 * (2.0 * progress - 1.0).powi(2)
+}
+
+/// Distribution that per-tick trade volume is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VolumeDistribution {
+    /// `scale + uniform(0..shape)`: a narrow band (default 1000–1500).
+    Uniform,
+    /// Log-normal with median `scale` and log-space sigma `shape` (default 1.0).
+    Lognormal,
+    /// Pareto with minimum `scale` and tail index `shape` (default 1.5); occasional
+    /// very large block trades.
+    Pareto,
+}
+
+impl VolumeDistribution {
+    /// Shape parameter used when none is configured.
+    fn default_shape(self) -> f64 {
+        match self {
+            VolumeDistribution::Uniform => 500.0,
+            VolumeDistribution::Lognormal => 1.0,
+            VolumeDistribution::Pareto => 1.5,
+        }
+    }
+
+    /// Draw one trade volume.
+    fn sample<R: Rng + ?Sized>(self, rng: &mut R, scale: f64, shape: Option<f64>) -> u64 {
+        let shape = shape.unwrap_or_else(|| self.default_shape());
+        let volume = match self {
+            VolumeDistribution::Uniform => {
+                // Sampled as 32-bit so seeded streams match the original fixed uniform band.
+                let width = (shape as u32).max(1);
+                return scale as u64 + rng.random_range(0..width) as u64;
+            }
+            VolumeDistribution::Lognormal => LogNormal::new(scale.ln(), shape)
+                .expect("lognormal volume parameters must be positive")
+                .sample(rng),
+            VolumeDistribution::Pareto => Pareto::new(scale, shape)
+                .expect("pareto volume parameters must be positive")
+                .sample(rng),
+        };
+        // Heavy tails can overflow; saturate rather than wrap.
+        volume.round().clamp(1.0, u32::MAX as f64) as u64
+    }
+}
+
+/// Tunable parameters for the stock price model.
+#[derive(Debug, Clone)]
+pub struct StockConfig {
+    /// Process the price update follows.
+    pub price_model: PriceModel,
+    /// Drift term (mu) of the Geometric Brownian Motion.
+    pub drift: f64,
+    /// Volatility (sigma) per time step; under OU, as a fraction of the mean price.
+    pub volatility: f64,
+    /// Long-term mean price (mu) the OU model reverts to; `None` reverts each instrument
+    /// to its own starting price.
+    pub mean: Option<f64>,
+    /// Speed (theta) at which the OU model reverts to the mean, per year.
+    pub reversion_speed: f64,
+    /// Start each OU instrument at a draw from the process's stationary distribution
+    /// instead of converging on it from its starting price.
+    pub steady_state_start: bool,
+    /// Correlation (rho, 0 to 1) of instrument returns through a shared market factor.
+    pub correlation: f64,
+    /// If set, emitted prices are snapped to multiples of this tick size.
+    pub tick_size: Option<f64>,
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Policy applied when no instruments are left.
+    pub empty_book: EmptyBookPolicy,
+    /// Source of message timestamps.
+    pub clock: Clock,
+    /// If set, no single instrument updates more often than this many times per second.
+    pub per_entity_max_rate: Option<f64>,
+    /// Report `price_before`, `price_after` and `delta` on every tick.
+    pub emit_deltas: bool,
+    /// Distribution of per-tick trade volume.
+    pub volume_dist: VolumeDistribution,
+    /// How trade volume varies through the day and accumulates.
+    pub volume_model: VolumeModel,
+    /// Scale of the volume distribution (uniform minimum, lognormal median, pareto minimum).
+    pub volume_scale: f64,
+    /// Shape of the volume distribution; `None` uses the distribution's default.
+    pub volume_shape: Option<f64>,
+    /// Range (min, max) initial instrument prices are drawn from.
+    pub price_range: PriceRange,
+    /// Weighted price tiers, each a (min, max) range; when non-empty, every instrument
+    /// draws a tier and its initial price from that tier instead of `price_range`.
+    pub price_tiers: Vec<(PriceRange, f64)>,
+    /// Largest clock offset (ms) an instrument may be given; each instrument draws a
+    /// fixed offset from `-max..=max`. 0 keeps every instrument on the shared clock.
+    pub max_clock_skew_ms: u64,
+    /// Name instruments from `TICKERS`, with sectors, instead of `STK0`, `STK1`, ...
+    pub tickers: bool,
+    /// If set, emit an OHLC bar per instrument every this many milliseconds instead of
+    /// every quote.
+    pub candle_interval_ms: Option<u64>,
+    /// Only trade during session hours, with an overnight price gap at each open.
+    pub market_hours: bool,
+    /// Emit combined ticks, or separate trade and quote messages.
+    pub messages: StockMessages,
+}
+
+impl Default for StockConfig {
+    fn default() -> Self {
+        StockConfig {
+            price_model: PriceModel::Gbm,
+            drift: DEFAULT_DRIFT,
+            volatility: DEFAULT_VOLATILITY,
+            mean: None,
+            reversion_speed: 5.0,
+            steady_state_start: false,
+            correlation: 0.0,
+            tick_size: None,
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            empty_book: EmptyBookPolicy::Stop,
+            clock: Clock::System,
+            per_entity_max_rate: None,
+            emit_deltas: false,
+            volume_dist: VolumeDistribution::Uniform,
+            volume_model: VolumeModel::Linear,
+            volume_scale: 1000.0,
+            volume_shape: None,
+            price_range: (100.0, 200.0),
+            price_tiers: Vec::new(),
+            max_clock_skew_ms: 0,
+            tickers: false,
+            candle_interval_ms: None,
+            market_hours: false,
+            messages: StockMessages::Combined,
+        }
+    }
+}
+
+/// Real-looking ticker symbols and their sectors, assigned to instruments in order when
+/// `tickers` is set. Instruments beyond the pool get generated symbols.
+pub const TICKERS: &[(&str, &str)] = &[
+    ("AAPL", "Technology"),
+    ("MSFT", "Technology"),
+    ("NVDA", "Technology"),
+    ("AMZN", "Consumer Discretionary"),
+    ("GOOGL", "Communication Services"),
+    ("META", "Communication Services"),
+    ("JPM", "Financials"),
+    ("V", "Financials"),
+    ("JNJ", "Health Care"),
+    ("UNH", "Health Care"),
+    ("XOM", "Energy"),
+    ("CVX", "Energy"),
+    ("PG", "Consumer Staples"),
+    ("KO", "Consumer Staples"),
+    ("HD", "Consumer Discretionary"),
+    ("TSLA", "Consumer Discretionary"),
+    ("BAC", "Financials"),
+    ("PFE", "Health Care"),
+    ("CAT", "Industrials"),
+    ("BA", "Industrials"),
+    ("NEE", "Utilities"),
+    ("DUK", "Utilities"),
+    ("LIN", "Materials"),
+    ("NEM", "Materials"),
+    ("PLD", "Real Estate"),
+    ("AMT", "Real Estate"),
+    ("NFLX", "Communication Services"),
+    ("WMT", "Consumer Staples"),
+    ("ORCL", "Technology"),
+    ("GE", "Industrials"),
+];
+
+/// Sectors cycled through by the generated symbols beyond `TICKERS`.
+const SECTORS: &[&str] = &[
+    "Technology",
+    "Financials",
+    "Health Care",
+    "Consumer Discretionary",
+    "Communication Services",
+    "Industrials",
+    "Consumer Staples",
+    "Energy",
+    "Utilities",
+    "Materials",
+    "Real Estate",
+];
+
+/// Symbol and sector of the `index`th instrument: from `TICKERS` while the pool lasts
+/// if `tickers` is set, then `STK<index>`. Sectors are only assigned with `tickers`.
+fn instrument_name(index: usize, tickers: bool) -> (String, Option<&'static str>) {
+    match (tickers, TICKERS.get(index)) {
+        (true, Some((symbol, sector))) => (symbol.to_string(), Some(sector)),
+        (true, None) => (format!("STK{}", index), Some(SECTORS[index % SECTORS.len()])),
+        (false, _) => (format!("STK{}", index), None),
+    }
+}
+
+/// A (min, max) range of prices.
+pub type PriceRange = (f64, f64);
+
+/// Parse a price range such as `100..200`, requiring 0 < min < max.
+pub fn parse_price_range(spec: &str) -> Result<PriceRange, String> {
+    let (min, max) = spec
+        .split_once("..")
+        .ok_or_else(|| format!("expected min..max, got '{}'", spec))?;
+    let parse = |bound: &str| {
+        bound
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("invalid price '{}' in '{}'", bound.trim(), spec))
+    };
+    validate_price_range(parse(min)?, parse(max)?)
+}
+
+/// Check that a price range is finite and ordered with a positive minimum.
+pub fn validate_price_range(min: f64, max: f64) -> Result<PriceRange, String> {
+    if !(min.is_finite() && max.is_finite() && min > 0.0) {
+        return Err(format!("prices must be positive numbers, got {}..{}", min, max));
+    }
+    if min >= max {
+        return Err(format!("price minimum {} must be below maximum {}", min, max));
+    }
+    Ok((min, max))
+}
+
+/// Parse weighted price tiers such as `0.5..5=0.3,100..200=0.6,1000..3000=0.1`.
+pub fn parse_price_tiers(spec: &str) -> Result<Vec<(PriceRange, f64)>, String> {
+    crate::weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(range, weight)| Ok((parse_price_range(&range)?, weight)))
+        .collect()
+}
+
+/// Direction used when snapping a price to the tick grid.
+#[derive(Clone, Copy)]
+enum Snap {
+    Nearest,
+    Down,
+    Up,
+}
+
+/// Number of decimal places needed to represent the tick size exactly.
+fn tick_decimals(tick: f64) -> i32 {
+    (0..10)
+        .find(|&d| {
+            let scaled = tick * 10f64.powi(d);
+            (scaled - scaled.round()).abs() < 1e-9
+        })
+        .unwrap_or(10)
+}
+
+/// Snap a price onto the tick grid, rounding away the binary-float residue so the
+/// emitted number is the exact decimal multiple (e.g. 101.37 rather than 101.37000000000001).
+fn snap_to_tick(value: f64, tick: f64, snap: Snap) -> f64 {
+    let steps = value / tick;
+    // Tolerate float noise so a value already on the grid is not pushed a tick away.
+    let steps = match snap {
+        Snap::Nearest => steps.round(),
+        Snap::Down => (steps + 1e-9).floor(),
+        Snap::Up => (steps - 1e-9).ceil(),
+    };
+    let factor = 10f64.powi(tick_decimals(tick));
+    (steps * tick * factor).round() / factor
 }
 
 /// A Market Data Generator that simulates realistic stock price updates.
 pub struct StockDataGenerator {
     instruments: Vec<Instrument>,
+    config: StockConfig,
+    rng: SimRng,
+    /// Source of message timestamps.
+    clock: Clock,
+    /// Index of the instrument updated by the last `generate` call.
+    last_updated: Option<usize>,
+    /// Numeric suffix for the next instrument id, so reseeded ids never repeat.
+    next_id: usize,
+    /// Sampler over `config.price_tiers`, if tiers are configured.
+    price_tiers: Option<WeightedIndex<f64>>,
+    /// Bar state, in candle mode.
+    candles: Option<Candles>,
+    /// Cumulative market factor that correlated returns share.
+    market: f64,
+    /// With market hours: the day of the last session traded, and whether the market
+    /// was open at the last update (`None` before the first).
+    session_day: Option<u64>,
+    market_open: Option<bool>,
+}
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+/// Trading session hours, 09:30–16:00 UTC on weekdays, as offsets (ms) into the day.
+/// Sessions are kept in UTC rather than an exchange's local time, so they don't shift
+/// with daylight saving.
+const SESSION_OPEN_UTC_MS: u64 = (9 * 60 + 30) * 60 * 1000;
+const SESSION_CLOSE_UTC_MS: u64 = 16 * 60 * 60 * 1000;
+/// Standard deviation of the log price gap applied at each session open.
+const OPENING_GAP_SIGMA: f64 = 0.02;
+
+/// Whether the UTC day number `day` falls on a Saturday or Sunday; day 0, 1970-01-01,
+/// was a Thursday.
+fn is_weekend(day: u64) -> bool {
+    (day + 3) % 7 >= 5
+}
+
+/// The day number of the session open at `now_ms`, or `None` outside session hours.
+fn session_day(now_ms: u64) -> Option<u64> {
+    let day = now_ms / DAY_MS;
+    let in_session = (SESSION_OPEN_UTC_MS..SESSION_CLOSE_UTC_MS).contains(&(now_ms % DAY_MS));
+    (in_session && !is_weekend(day)).then_some(day)
+}
+
+/// When the next session opens, at or after `now_ms`, skipping weekends.
+fn next_open_ms(now_ms: u64) -> u64 {
+    let mut open = now_ms - now_ms % DAY_MS + SESSION_OPEN_UTC_MS;
+    if now_ms > open {
+        open += DAY_MS;
+    }
+    while is_weekend(open / DAY_MS) {
+        open += DAY_MS;
+    }
+    open
+}
+
+/// Lowest bid a quote may show.
+const MIN_BID: f64 = 0.01;
+/// Lowest price an instrument may fall to: far enough above `MIN_BID` that even the
+/// narrowest spread keeps the bid strictly below the price.
+const MIN_PRICE: f64 = 2.0 * MIN_BID;
+
+/// Bid and ask `spread_fraction` of `price` either side of it. The bid is floored at
+/// `MIN_BID`, so for a price of at least `MIN_PRICE` and a positive spread
+/// `0 < bid < price < ask` always holds. `None` if the ask overflows.
+fn bid_ask(price: f64, spread_fraction: f64) -> Option<(f64, f64)> {
+    let spread = finite(price * spread_fraction)?;
+    let ask = finite(price + spread)?;
+    Some(((price - spread).max(MIN_BID), ask))
+}
+
+/// Returns the value if it is finite, otherwise `None`.
+fn finite(value: f64) -> Option<f64> {
+    value.is_finite().then_some(value)
 }
 
 impl StockDataGenerator {
-    /// Creates a new MarketDataGenerator with the specified number of variants.
-    pub fn new(variants: usize) -> Self {
-        let mut rng = rand::rng();
+    /// Creates a new MarketDataGenerator with the specified number of variants and model parameters.
+    pub fn new(variants: usize, config: StockConfig) -> Self {
+        let mut rng = SimRng::new(config.rng, config.seed);
+        let price_tiers = (!config.price_tiers.is_empty()).then(|| {
+            WeightedIndex::new(config.price_tiers.iter().map(|(_, weight)| *weight))
+                .expect("price tier weights must contain a positive weight")
+        });
         let instruments = (0..variants)
-            .map(|i| {
-                let price = rng.random_range(100.0..200.0);
-                // Initialize bid/ask as a small spread around the price.
-                let spread = price * rng.random_range(0.001..0.002);
-                Instrument {
-                    id: format!("STK{}", i),
-                    price,
-                    bid: price - spread,
-                    ask: price + spread,
-                    volume: 0,
-                }
-            })
+            .map(|i| Self::new_instrument(&mut rng, i, &config, price_tiers.as_ref()))
             .collect();
-        StockDataGenerator { instruments }
+        StockDataGenerator {
+            instruments,
+            candles: config.candle_interval_ms.map(Candles::new),
+            clock: config.clock.clone(),
+            config,
+            rng,
+            last_updated: None,
+            next_id: variants,
+            price_tiers,
+            market: 0.0,
+            session_day: None,
+            market_open: None,
+        }
     }
-}
 
-impl crate::Generator for StockDataGenerator {
+    /// Create an instrument with a random starting price, from a drawn price tier if
+    /// tiers are configured and from the configured price range otherwise.
+    fn new_instrument(
+        rng: &mut SimRng,
+        index: usize,
+        config: &StockConfig,
+        tiers: Option<&WeightedIndex<f64>>,
+    ) -> Instrument {
+        let (min, max) = match tiers {
+            Some(tiers) => config.price_tiers[tiers.sample(rng)].0,
+            None => config.price_range,
+        };
+        let mut price = rng.random_range(min..max).max(MIN_PRICE);
+        let mean = config.mean.unwrap_or(price);
+        if config.price_model == PriceModel::Ou && config.steady_state_start {
+            // The stationary distribution is normal around the mean, with standard
+            // deviation sigma * m / sqrt(2 * theta) (volatility scales with the mean).
+            let spread = config.volatility * mean / (2.0 * config.reversion_speed).sqrt();
+            let z: f64 = StandardNormal.sample(rng);
+            price = (mean + spread * z).max(MIN_PRICE);
+        }
+        // Initialize bid/ask as a small spread around the price.
+        let (bid, ask) = bid_ask(price, rng.random_range(0.001..0.002)).unwrap_or((price, price));
+        let clock_skew_ms = match config.max_clock_skew_ms {
+            0 => 0,
+            max => clock::draw_skew(rng, max),
+        };
+        let (id, sector) = instrument_name(index, config.tickers);
+        Instrument {
+            id,
+            sector,
+            price,
+            bid,
+            ask,
+            volume: 0,
+            seq: 0,
+            last_emitted_ms: None,
+            last_price: price,
+            mean,
+            market_seen: 0.0,
+            volume_day: None,
+            quoted: None,
+            clock_skew_ms,
+        }
+    }
 
-    fn generate(&mut self) -> String {
-        let mut rng = rand::rng();
+    /// Whether the market is open at `now_ms`, applying the opening gap when a new session
+    /// starts. Only sessions that follow a close (or a day change) gap: a run started
+    /// mid-session trades on from the starting prices. A simulated clock skips the
+    /// overnight hours instead of idling through them.
+    fn trading_session(&mut self, now_ms: u64) -> bool {
+        let Some(day) = session_day(now_ms) else {
+            self.market_open = Some(false);
+            self.clock.reach(next_open_ms(now_ms));
+            return false;
+        };
+        let reopening = self.market_open == Some(false) || self.session_day.is_some_and(|last| last != day);
+        if reopening {
+            for instrument in &mut self.instruments {
+                let gap: f64 = StandardNormal.sample(&mut self.rng);
+                let spread_fraction = (instrument.ask - instrument.bid) / (2.0 * instrument.price);
+                let opened = finite((instrument.price * (OPENING_GAP_SIGMA * gap).exp()).max(MIN_PRICE))
+                    .and_then(|price| Some((price, bid_ask(price, spread_fraction)?)));
+                if let Some((price, (bid, ask))) = opened {
+                    instrument.price = price;
+                    instrument.bid = bid;
+                    instrument.ask = ask;
+                }
+            }
+        }
+        self.market_open = Some(true);
+        self.session_day = Some(day);
+        true
+    }
+
+    /// Pick the instrument to update at `now_ms`, skipping any that updated more
+    /// recently than the per-entity cap allows. `None` if every instrument is throttled.
+    fn pick_instrument(&mut self, now_ms: u64) -> Option<usize> {
+        let Some(rate) = self.config.per_entity_max_rate else {
+            return Some(self.rng.random_range(0..self.instruments.len()));
+        };
+        let min_interval_ms = (1000.0 / rate) as u64;
+        let eligible: Vec<usize> = self
+            .instruments
+            .iter()
+            .enumerate()
+            .filter(|(_, instrument)| {
+                instrument
+                    .last_emitted_ms
+                    .is_none_or(|last| now_ms.saturating_sub(last) >= min_interval_ms)
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        if eligible.is_empty() {
+            return None;
+        }
+        Some(eligible[self.rng.random_range(0..eligible.len())])
+    }
 
+    /// Update one instrument, returning the emitted figures of the tick. `None` if the
+    /// book is empty or every instrument is throttled.
+    fn step(&mut self) -> Option<Tick> {
         if self.instruments.is_empty() {
-            return "{}".to_string();
+            return None;
+        }
+        let now_ms = self.clock.now_millis();
+        if self.config.market_hours && !self.trading_session(now_ms) {
+            return None;
         }
 
         // Randomly select an instrument to update.
-        let idx = rng.random_range(0..self.instruments.len());
+        let idx = self.pick_instrument(now_ms)?;
+        self.last_updated = Some(idx);
+        let listed = self.instruments.len() as f64;
+        let rng = &mut self.rng;
         let instrument = &mut self.instruments[idx];
+        instrument.last_emitted_ms = Some(now_ms);
 
-        // --- Price Update using Geometric Brownian Motion ---
+        // --- Price Update ---
         //
         // Geometric Brownian Motion:
         //   S(t+dt) = S(t) * exp((mu - 0.5 * sigma^2)*dt + sigma * sqrt(dt) * epsilon)
         //
         // We'll use a small time increment dt, a slight drift (mu) and volatility (sigma).
+        //
+        // Ornstein–Uhlenbeck, stepped exactly so any dt and theta stay stable:
+        //   S(t+dt) = m + (S(t) - m) * e^(-theta*dt) + sigma * m * sqrt((1 - e^(-2*theta*dt)) / (2*theta)) * epsilon
+        //
+        // with volatility scaled by the mean m, so one sigma suits any price level.
         let dt: f64 = 1.0 / 252.0; // assume one trading day step (or one iteration) in yearly terms
-        let mu = 0.0001;      // drift term (very small positive drift)
-        let sigma = 0.01;     // volatility (1% per time step)
-        let epsilon: f64 = StandardNormal.sample(&mut rng);
-        let change_factor = ((mu - 0.5 * sigma * sigma) * dt + sigma * dt.sqrt() * epsilon).exp();
-        instrument.price = (instrument.price * change_factor).max(0.01);
+        let sigma = self.config.volatility;
+        let noise: f64 = StandardNormal.sample(rng);
+
+        // --- Market Factor ---
+        //
+        // With a correlation rho, returns share a market factor: a Brownian path that
+        // advances a 1/n-variance step per update, i.e. one unit per round of the n
+        // instruments. Each instrument takes the market's move since its own last update,
+        // blended with idiosyncratic noise, so any two instruments' returns over the same
+        // stretch of time correlate by rho. Without correlation nothing extra is drawn.
+        let rho = self.config.correlation;
+        let epsilon = if rho > 0.0 {
+            let market_step: f64 = StandardNormal.sample(rng);
+            self.market += market_step / listed.sqrt();
+            let market_move = self.market - instrument.market_seen;
+            instrument.market_seen = self.market;
+            rho.sqrt() * market_move + (1.0 - rho).sqrt() * noise
+        } else {
+            noise
+        };
+        let next_price = match self.config.price_model {
+            PriceModel::Gbm => {
+                let mu = self.config.drift;
+                let change_factor = ((mu - 0.5 * sigma * sigma) * dt + sigma * dt.sqrt() * epsilon).exp();
+                finite(change_factor).map(|factor| instrument.price * factor)
+            }
+            PriceModel::Ou => {
+                let theta = self.config.reversion_speed;
+                let mean = instrument.mean;
+                let decay = (-theta * dt).exp();
+                let noise = sigma * mean * ((1.0 - decay * decay) / (2.0 * theta)).sqrt();
+                Some(mean + (instrument.price - mean) * decay + noise * epsilon)
+            }
+        };
 
         // --- Bid/Ask Spread Update ---
         //
@@ -73,38 +794,382 @@ impl crate::Generator for StockDataGenerator {
         let base_spread_fraction = 0.001; // base 0.1%
         let extra_spread: f64 = rng.random_range(0.0..0.001); // additional randomness up to 0.1%
         let spread_fraction = base_spread_fraction + extra_spread;
-        let spread = instrument.price * spread_fraction;
-        instrument.bid = instrument.price - spread;
-        instrument.ask = instrument.price + spread;
+
+        // --- Non-finite Guard ---
+        //
+        // Extreme parameters can overflow `exp()` to infinity (or produce NaN from inf * 0).
+        // Such values are not valid JSON numbers, so the whole update is computed first and
+        // only committed if every value is finite; otherwise the tick keeps the previous quote.
+        let update = next_price
+            .and_then(|price| finite(price.max(MIN_PRICE)))
+            .and_then(|price| {
+                let (bid, ask) = bid_ask(price, spread_fraction)?;
+                Some((price, bid, ask))
+            });
+        match update {
+            Some((price, bid, ask)) => {
+                instrument.price = price;
+                instrument.bid = bid;
+                instrument.ask = ask;
+            }
+            None => eprintln!(
+                "Warning: skipped non-finite price update for {} (next price {:?})",
+                instrument.id, next_price
+            ),
+        }
 
         // --- Volume Update ---
         //
-        // We simulate trade volume as a base volume plus some random fluctuation.
-        let base_volume = 1000;
-        let volume_variation = rng.random_range(0..500);
-        let trade_volume = base_volume + volume_variation;
-        instrument.volume += trade_volume as u64;
+        // Trade volume is drawn from the configured distribution; the default is a base
+        // volume plus a narrow uniform fluctuation, heavy-tailed modes add block trades.
+        // The intraday model shapes each draw by the time of day and starts every day's
+        // cumulative volume from zero.
+        let mut trade_volume = self.config.volume_dist.sample(
+            rng,
+            self.config.volume_scale,
+            self.config.volume_shape,
+        );
+        if self.config.volume_model == VolumeModel::Intraday {
+            let day = now_ms / DAY_MS;
+            if instrument.volume_day != Some(day) {
+                instrument.volume_day = Some(day);
+                instrument.volume = 0;
+            }
+            trade_volume = ((trade_volume as f64 * intraday_volume_factor(now_ms)).round() as u64).max(1);
+        }
+        // In trades-and-quotes mode only the trades count toward the cumulative volume. An
+        // instrument's first update there is always a quote, so its trades have one to hit.
+        let trade = self.config.messages == StockMessages::Combined
+            || (instrument.quoted.is_some() && rng.random_bool(TRADE_SHARE));
+        if !trade {
+            trade_volume = 0;
+        }
+        instrument.volume = instrument.volume.saturating_add(trade_volume);
+
+        // --- Sequence Number ---
+        //
+        // Monotonic per instrument so consumers can detect gaps and reordering.
+        instrument.seq += 1;
 
         // --- Timestamp ---
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        // Build a JSON message containing the updated instrument data.
-        let message = json!({
-            "instrument": instrument.id,
-            "price": instrument.price,
-            "bid": instrument.bid,
-            "ask": instrument.ask,
-            "volume": instrument.volume,
-            "timestamp": now,
+        //
+        // Reported on the instrument's own (possibly skewed) clock; throttling above
+        // works on the shared clock.
+        let now = clock::skewed(now_ms, instrument.clock_skew_ms) / 1000;
+
+        // --- Tick Snapping ---
+        //
+        // The model stays continuous; only the emitted quote is snapped to the tick grid.
+        // Bids round down and asks round up, and the spread is kept at two ticks or more so
+        // the price can snap to a grid point strictly between them.
+        let (price, bid, ask) = match self.config.tick_size {
+            Some(tick) => {
+                let bid = snap_to_tick(instrument.bid, tick, Snap::Down).max(tick);
+                let ask = snap_to_tick(instrument.ask.max(bid + 2.0 * tick), tick, Snap::Up);
+                let lowest = snap_to_tick(bid + tick, tick, Snap::Nearest);
+                let highest = snap_to_tick(ask - tick, tick, Snap::Nearest);
+                let price = snap_to_tick(instrument.price, tick, Snap::Nearest).clamp(lowest, highest);
+                (price, bid, ask)
+            }
+            None => (instrument.price, instrument.bid, instrument.ask),
+        };
+
+        // --- Change Data Capture ---
+        //
+        // Deltas are computed on the emitted (snapped) prices, so consumers can check
+        // that applying `delta` to `price_before` reproduces `price_after` exactly.
+        let deltas = self.config.emit_deltas.then(|| match self.config.tick_size {
+            Some(tick) => {
+                let before = snap_to_tick(instrument.last_price, tick, Snap::Nearest);
+                let factor = 10f64.powi(tick_decimals(tick));
+                (before, ((price - before) * factor).round() / factor)
+            }
+            None => (instrument.last_price, price - instrument.last_price),
         });
-        message.to_string()
+        instrument.last_price = price;
+
+        Some(Tick {
+            idx,
+            now_ms,
+            timestamp: now,
+            price,
+            bid,
+            ask,
+            deltas,
+            trade,
+            trade_volume,
+        })
+    }
+
+    /// Update one instrument and render its message into `buf`, replacing its contents:
+    /// the quote, a trade or quote update in trades-and-quotes mode, or with candles the
+    /// next finished bar. Returns false, leaving `buf` alone, if the book is empty, every
+    /// instrument is throttled, or no bar is ready.
+    fn next_update(&mut self, buf: &mut String) -> bool {
+        if self.candles.is_some() {
+            return self.next_candle(buf);
+        }
+        let Some(tick) = self.step() else {
+            return false;
+        };
+        if self.config.messages == StockMessages::TradesAndQuotes {
+            self.write_trade_or_quote(&tick, buf);
+            return true;
+        }
+        let instrument = &self.instruments[tick.idx];
+        // Serialize the updated instrument data straight into the caller's buffer.
+        let quote = Quote {
+            ask: tick.ask,
+            bid: tick.bid,
+            delta: tick.deltas.map(|(_, delta)| delta),
+            instrument: &instrument.id,
+            price: tick.price,
+            price_after: tick.deltas.map(|_| tick.price),
+            price_before: tick.deltas.map(|(before, _)| before),
+            sector: instrument.sector,
+            seq: instrument.seq,
+            timestamp: tick.timestamp,
+            volume: instrument.volume,
+        };
+        buf.clear();
+        super::write_json(buf, &quote);
+        true
+    }
+
+    /// Render `tick` as either a trade or a quote update. Trades cross the spread of the
+    /// last published quote: a buy lifts its ask and a sell hits its bid, so every trade
+    /// prints within the quote consumers last saw, not one they were never sent.
+    fn write_trade_or_quote(&mut self, tick: &Tick, buf: &mut String) {
+        let buy = tick.trade && self.rng.random_bool(0.5);
+        let instrument = &mut self.instruments[tick.idx];
+        buf.clear();
+        if tick.trade {
+            let (bid, ask) = instrument.quoted.expect("trades follow a published quote");
+            let trade = Trade {
+                instrument: &instrument.id,
+                price: if buy { ask } else { bid },
+                sector: instrument.sector,
+                seq: instrument.seq,
+                size: tick.trade_volume,
+                timestamp: tick.timestamp,
+                r#type: "trade",
+            };
+            super::write_json(buf, &trade);
+        } else {
+            instrument.quoted = Some((tick.bid, tick.ask));
+            let quote = QuoteUpdate {
+                ask: tick.ask,
+                bid: tick.bid,
+                instrument: &instrument.id,
+                sector: instrument.sector,
+                seq: instrument.seq,
+                timestamp: tick.timestamp,
+                r#type: "quote",
+            };
+            super::write_json(buf, &quote);
+        }
+    }
+
+    /// JSON Schema of trades-and-quotes messages.
+    fn trades_and_quotes_schema(&self) -> Value {
+        let mut fields = vec![
+            ("instrument", json!({"type": "string", "description": "Instrument id, e.g. STK0 or AAPL"})),
+            ("type", json!({"type": "string", "enum": ["quote", "trade"]})),
+            ("bid", json!({"type": "number", "description": "Quotes only"})),
+            ("ask", json!({"type": "number", "description": "Quotes only"})),
+            ("price", json!({"type": "number", "description": "Trades only: the last quote's bid or ask"})),
+            ("size", json!({"type": "integer", "minimum": 0, "description": "Trades only: executed volume"})),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Per-instrument sequence number"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        if self.config.tickers {
+            fields.push(("sector", json!({"type": "string", "description": "Industry sector, e.g. Technology"})));
+        }
+        let mut required = vec!["instrument", "type", "seq", "timestamp"];
+        if self.config.tickers {
+            required.push("sector");
+        }
+        super::object_schema("fluxfakr stock trade or quote", &fields, &required)
+    }
+
+    /// JSON Schema of candle-mode messages.
+    fn candle_schema(&self) -> Value {
+        let price = |description: &str| json!({"type": "number", "description": description});
+        let mut fields = vec![
+            ("instrument", json!({"type": "string", "description": "Instrument id, e.g. STK0 or AAPL"})),
+            ("open", price("First price in the interval")),
+            ("high", price("Highest price in the interval")),
+            ("low", price("Lowest price in the interval")),
+            ("close", price("Last price in the interval")),
+            ("volume", json!({"type": "integer", "minimum": 0, "description": "Volume traded in the interval"})),
+            ("trades", json!({"type": "integer", "minimum": 1, "description": "Ticks in the interval"})),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Per-instrument bar number"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Interval start, Unix time in seconds"})),
+        ];
+        if self.config.tickers {
+            fields.push(("sector", json!({"type": "string", "description": "Industry sector, e.g. Technology"})));
+        }
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        super::object_schema("fluxfakr stock candle", &fields, &required)
+    }
+
+    /// Take one tick into the candles and render the next finished bar into `buf`, if any.
+    fn next_candle(&mut self, buf: &mut String) -> bool {
+        if self.candles.as_ref().is_some_and(|candles| candles.ready.is_empty()) {
+            let Some(tick) = self.step() else {
+                return false;
+            };
+            self.candles.as_mut().expect("candle mode").record(&tick);
+        }
+        let Some((idx, bar)) = self.candles.as_mut().and_then(|candles| candles.ready.pop_front()) else {
+            return false;
+        };
+        self.last_updated = Some(idx);
+        let instrument = &self.instruments[idx];
+        let candle = CandleMessage {
+            close: bar.close,
+            high: bar.high,
+            instrument: &instrument.id,
+            low: bar.low,
+            open: bar.open,
+            sector: instrument.sector,
+            seq: bar.seq,
+            timestamp: bar.start_ms / 1000,
+            trades: bar.trades,
+            volume: bar.volume,
+        };
+        buf.clear();
+        super::write_json(buf, &candle);
+        true
+    }
+}
+
+impl crate::Generator for StockDataGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        let mut message = String::new();
+        self.generate_into(&mut message).then_some(message)
+    }
+
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        if self.instruments.is_empty() && self.config.empty_book == EmptyBookPolicy::Reseed {
+            let mut instrument = Self::new_instrument(
+                &mut self.rng,
+                self.next_id,
+                &self.config,
+                self.price_tiers.as_ref(),
+            );
+            // Listed now, it has seen none of the market's past moves.
+            instrument.market_seen = self.market;
+            self.next_id += 1;
+            self.instruments.push(instrument);
+        }
+        self.next_update(buf)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.instruments.is_empty() && self.config.empty_book == EmptyBookPolicy::Stop
+    }
+
+    fn generate(&mut self) -> String {
+        let mut message = String::new();
+        if !self.next_update(&mut message) {
+            message.push_str("{}");
+        }
+        message
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        if self.candles.is_some() {
+            let mut fields = vec!["instrument", "open", "high", "low", "close", "volume", "trades", "seq", "timestamp"];
+            if self.config.tickers {
+                fields.push("sector");
+            }
+            return fields;
+        }
+        if self.config.messages == StockMessages::TradesAndQuotes {
+            let mut fields = vec!["instrument", "type", "bid", "ask", "price", "size", "seq", "timestamp"];
+            if self.config.tickers {
+                fields.push("sector");
+            }
+            return fields;
+        }
+        let mut fields = vec!["instrument", "price", "bid", "ask", "volume", "seq", "timestamp"];
+        if self.config.emit_deltas {
+            fields.extend(["price_before", "price_after", "delta"]);
+        }
+        if self.config.tickers {
+            fields.push("sector");
+        }
+        fields
+    }
+
+    fn schema(&self) -> Value {
+        let number = || json!({"type": "number"});
+        if self.candles.is_some() {
+            return self.candle_schema();
+        }
+        if self.config.messages == StockMessages::TradesAndQuotes {
+            return self.trades_and_quotes_schema();
+        }
+        let mut fields = vec![
+            ("instrument", json!({"type": "string", "description": "Instrument id, e.g. STK0 or AAPL"})),
+            ("price", number()),
+            ("bid", number()),
+            ("ask", number()),
+            ("volume", json!({"type": "integer", "minimum": 0, "description": "Cumulative traded volume"})),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Per-instrument sequence number"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        if self.config.emit_deltas {
+            fields.extend([("price_before", number()), ("price_after", number()), ("delta", number())]);
+        }
+        if self.config.tickers {
+            fields.push(("sector", json!({"type": "string", "description": "Industry sector, e.g. Technology"})));
+        }
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        super::object_schema("fluxfakr stock tick", &fields, &required)
+    }
+
+    fn core_fields(&self) -> Vec<&'static str> {
+        if self.candles.is_some() {
+            return vec!["instrument", "close", "seq", "timestamp"];
+        }
+        if self.config.messages == StockMessages::TradesAndQuotes {
+            return vec!["instrument", "type", "seq", "timestamp"];
+        }
+        vec!["instrument", "price", "seq", "timestamp"]
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_updated.map(|idx| self.instruments[idx].id.clone())
+    }
+
+    fn line_mapping(&self) -> Option<LineMapping> {
+        let trades_and_quotes = self.candles.is_none() && self.config.messages == StockMessages::TradesAndQuotes;
+        let mut fields = match self.candles {
+            Some(_) => vec!["open", "high", "low", "close", "volume", "trades"],
+            None if trades_and_quotes => vec!["bid", "ask", "price", "size"],
+            None => vec!["price", "bid", "ask", "volume"],
+        };
+        if self.config.emit_deltas && self.candles.is_none() {
+            fields.extend(["price_before", "price_after", "delta"]);
+        }
+        let mut tags = vec!["instrument"];
+        if trades_and_quotes {
+            tags.push("type");
+        }
+        if self.config.tickers {
+            tags.push("sector");
+        }
+        Some(LineMapping {
+            tags,
+            fields,
+            timestamp: "timestamp",
+        })
     }
 
     fn dump(&self) -> String {
-        // Build a CSV header with the relevant fields.
+        // Build a CSV header with the relevant fields; the market state follows the rows.
         let mut csv = String::from("id,price,bid,ask,volume\n");
         for instrument in &self.instruments {
             csv.push_str(&format!(
@@ -116,6 +1181,13 @@ impl crate::Generator for StockDataGenerator {
                 instrument.volume
             ));
         }
+        if self.config.market_hours {
+            csv.push_str(match self.market_open {
+                Some(true) => "market: open\n",
+                Some(false) => "market: closed\n",
+                None => "market: not yet traded\n",
+            });
+        }
         csv
     }
 }
@@ -123,12 +1195,13 @@ impl crate::Generator for StockDataGenerator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generator::{alloc_count, Batch};
     use crate::Generator;
     use serde_json::Value;
 
     #[test]
     fn test_generate_market_data() {
-        let mut generator = StockDataGenerator::new(5);
+        let mut generator = StockDataGenerator::new(5, StockConfig::default());
         let msg_str = generator.generate();
         // Parse the JSON message to ensure it is valid.
         let msg: Value = serde_json::from_str(&msg_str).unwrap();
@@ -138,11 +1211,698 @@ mod tests {
         assert!(msg.get("ask").is_some());
         assert!(msg.get("volume").is_some());
         assert!(msg.get("timestamp").is_some());
+        assert_eq!(msg["seq"], 1);
+    }
+
+    #[test]
+    fn test_seq_increments_per_instrument() {
+        let mut generator = StockDataGenerator::new(4, StockConfig::default());
+        let mut last_seq = std::collections::HashMap::new();
+        for _ in 0..200 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let id = msg["instrument"].as_str().unwrap().to_string();
+            let seq = msg["seq"].as_u64().unwrap();
+            let previous = last_seq.insert(id, seq).unwrap_or(0);
+            assert_eq!(seq, previous + 1, "seq must increase by one per instrument");
+        }
+    }
+
+    #[test]
+    fn test_key_matches_instrument_and_orders_per_key() {
+        let mut generator = StockDataGenerator::new(3, StockConfig::default());
+        let mut last_volume = std::collections::HashMap::new();
+        for _ in 0..300 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let key = generator.key().expect("stock messages are keyed");
+            assert_eq!(msg["instrument"], key.as_str());
+            // Volume accumulates per instrument, so per-key output must be strictly increasing.
+            let volume = msg["volume"].as_u64().unwrap();
+            if let Some(previous) = last_volume.insert(key, volume) {
+                assert!(volume > previous, "Per-key ordering violated");
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_batch_matches_single_messages() {
+        let config = || StockConfig {
+            seed: 3,
+            clock: Clock::Stepped {
+                now_ms: 1_700_000_000_000,
+                step_ms: 1_000,
+            },
+            ..StockConfig::default()
+        };
+        let mut batched = StockDataGenerator::new(3, config());
+        let mut single = StockDataGenerator::new(3, config());
+        let mut batch = Batch::new();
+        for _ in 0..2 {
+            batched.generate_batch(50, &mut batch);
+            assert_eq!(batch.len(), 50);
+            for (message, key) in batch.entries() {
+                assert_eq!(Some(message.clone()), single.try_generate());
+                assert_eq!(Some(key.clone()), single.key());
+            }
+        }
+        // An empty book stops the batch early instead of padding it.
+        StockDataGenerator::new(0, StockConfig::default()).generate_batch(10, &mut batch);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_generate_into_reuses_its_buffer() {
+        let mut generator = StockDataGenerator::new(10, StockConfig { emit_deltas: true, ..StockConfig::default() });
+        // Comfortably longer than any quote, so the buffer never has to grow.
+        let mut buf = String::with_capacity(512);
+        for _ in 0..100 {
+            assert!(generator.generate_into(&mut buf));
+        }
+        // With the buffer reused, a message costs no allocations at all; building it
+        // through `json!` cost 18.
+        let allocations = alloc_count::count(|| {
+            for _ in 0..1_000 {
+                generator.generate_into(&mut buf);
+            }
+        });
+        assert_eq!(allocations, 0);
+        let value: Value = serde_json::from_str(&buf).unwrap();
+        assert_eq!(value["price_after"], value["price"]);
+    }
+
+    #[test]
+    fn test_empty_book_stop_policy() {
+        let mut generator = StockDataGenerator::new(0, StockConfig::default());
+        assert!(generator.try_generate().is_none());
+        assert!(generator.is_exhausted());
+        // A direct generate() call must not panic on an empty book.
+        assert_eq!(generator.generate(), "{}");
+        assert_eq!(generator.key(), None);
+    }
+
+    #[test]
+    fn test_empty_book_reseed_policy() {
+        let config = StockConfig {
+            empty_book: EmptyBookPolicy::Reseed,
+            ..StockConfig::default()
+        };
+        let mut generator = StockDataGenerator::new(0, config);
+        assert!(!generator.is_exhausted());
+        let msg: Value = serde_json::from_str(&generator.try_generate().unwrap()).unwrap();
+        assert_eq!(msg["instrument"], "STK0");
+        assert_eq!(generator.instruments.len(), 1);
+    }
+
+    #[test]
+    fn test_pathological_parameters_stay_finite() {
+        let config = StockConfig {
+            drift: 1.0e6,
+            volatility: 1.0e4,
+            ..StockConfig::default()
+        };
+        let mut generator = StockDataGenerator::new(2, config);
+        for _ in 0..1000 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            for field in ["price", "bid", "ask"] {
+                let value = msg[field].as_f64().expect("price fields must be JSON numbers");
+                assert!(value.is_finite(), "{} should be finite, got {}", field, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bid_and_ask_never_cross_or_go_negative() {
+        // A strong negative drift pins prices to the floor, where the spread is tightest
+        // relative to the minimum bid; tiny starting prices begin there.
+        for (drift, price_range) in [(-1.0e4, (100.0, 200.0)), (0.0001, (0.001, 0.03))] {
+            let mut generator = StockDataGenerator::new(
+                4,
+                StockConfig {
+                    drift,
+                    volatility: 2.0,
+                    price_range,
+                    ..StockConfig::default()
+                },
+            );
+            for _ in 0..5_000 {
+                let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+                let (price, bid, ask) = (
+                    msg["price"].as_f64().unwrap(),
+                    msg["bid"].as_f64().unwrap(),
+                    msg["ask"].as_f64().unwrap(),
+                );
+                assert!(MIN_BID <= bid && bid < price && price < ask, "invalid quote {}", msg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prices_snap_to_tick_size() {
+        for tick in [0.01, 0.05, 0.25] {
+            let config = StockConfig {
+                tick_size: Some(tick),
+                ..StockConfig::default()
+            };
+            let mut generator = StockDataGenerator::new(3, config);
+            for _ in 0..500 {
+                let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+                let (price, bid, ask) = (
+                    msg["price"].as_f64().unwrap(),
+                    msg["bid"].as_f64().unwrap(),
+                    msg["ask"].as_f64().unwrap(),
+                );
+                for value in [price, bid, ask] {
+                    let steps = value / tick;
+                    assert!((steps - steps.round()).abs() < 1e-6, "{} is not a multiple of {}", value, tick);
+                }
+                assert!(bid < ask, "snapped bid {} must stay below ask {}", bid, ask);
+                assert!(bid < price && price < ask, "price {} must fall strictly inside {}..{}", price, bid, ask);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adversarial_instrument_ids_round_trip() {
+        let mut generator = StockDataGenerator::new(1, StockConfig::default());
+        for id in ["QUOTE\"D", "back\\slash", "tab\tnew\nline\u{0007}", "ünïcødé 株式 📈", "</script>"] {
+            generator.instruments[0].id = id.to_string();
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert_eq!(msg["instrument"], id);
+        }
+    }
+
+    #[test]
+    fn test_per_entity_max_rate_caps_single_instrument() {
+        let mut generator = StockDataGenerator::new(
+            1,
+            StockConfig {
+                clock: Clock::Stepped {
+                    now_ms: 1_700_000_000_000,
+                    step_ms: 100,
+                },
+                per_entity_max_rate: Some(1.0),
+                ..StockConfig::default()
+            },
+        );
+        // 100 ticks of 100ms cover 10 simulated seconds: at most one update per second.
+        let timestamps: Vec<u64> = (0..100)
+            .filter_map(|_| generator.try_generate())
+            .map(|msg| serde_json::from_str::<Value>(&msg).unwrap()["timestamp"].as_u64().unwrap())
+            .collect();
+        assert_eq!(timestamps.len(), 10);
+        assert!(timestamps.windows(2).all(|w| w[1] - w[0] >= 1));
+    }
+
+    #[test]
+    fn test_clock_skew_offsets_instrument_timestamps() {
+        let mut generator = StockDataGenerator::new(
+            2,
+            StockConfig {
+                clock: Clock::Stepped {
+                    now_ms: 1_700_000_000_000,
+                    step_ms: 0,
+                },
+                max_clock_skew_ms: 5_000,
+                ..StockConfig::default()
+            },
+        );
+        assert!(generator.instruments.iter().all(|i| i.clock_skew_ms.abs() <= 5_000));
+        generator.instruments[0].clock_skew_ms = 3_000;
+        generator.instruments[1].clock_skew_ms = -2_000;
+        let mut seen = std::collections::HashMap::new();
+        for _ in 0..50 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            seen.insert(generator.key().unwrap(), msg["timestamp"].as_u64().unwrap());
+        }
+        // The shared clock stands still, so each instrument reports a constant offset.
+        assert_eq!(seen["STK0"], 1_700_000_003);
+        assert_eq!(seen["STK1"], 1_699_999_998);
+    }
+
+    #[test]
+    fn test_per_entity_max_rate_spreads_updates_across_instruments() {
+        let mut generator = StockDataGenerator::new(
+            3,
+            StockConfig {
+                clock: Clock::Stepped {
+                    now_ms: 0,
+                    step_ms: 100,
+                },
+                per_entity_max_rate: Some(1.0),
+                ..StockConfig::default()
+            },
+        );
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..100 {
+            if generator.try_generate().is_some() {
+                *counts.entry(generator.key().unwrap()).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(counts.len(), 3);
+        assert!(counts.values().all(|&n| n <= 10));
+    }
+
+    #[test]
+    fn test_emit_deltas_reports_consistent_before_and_after() {
+        for tick_size in [None, Some(0.01)] {
+            let mut generator = StockDataGenerator::new(
+                2,
+                StockConfig {
+                    emit_deltas: true,
+                    tick_size,
+                    ..StockConfig::default()
+                },
+            );
+            let mut last_price = std::collections::HashMap::new();
+            for _ in 0..50 {
+                let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+                let before = msg["price_before"].as_f64().unwrap();
+                let after = msg["price_after"].as_f64().unwrap();
+                let delta = msg["delta"].as_f64().unwrap();
+                assert_eq!(after, msg["price"].as_f64().unwrap());
+                assert!((after - before - delta).abs() < 1e-9, "{} - {} != {}", after, before, delta);
+                // Each tick's "before" is the previous tick's "after" for that instrument.
+                if let Some(prev) = last_price.insert(msg["instrument"].to_string(), after) {
+                    assert_eq!(before, prev);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lognormal_volume_has_heavier_tail_than_uniform() {
+        let mut rng = SimRng::new(RngAlgorithm::Chacha8, 1);
+        // Ratio of the 99.9th percentile to the median: a heavy tail stretches it far out.
+        let mut tail_ratio = |dist: VolumeDistribution| {
+            let mut samples: Vec<u64> = (0..20_000).map(|_| dist.sample(&mut rng, 1000.0, None)).collect();
+            samples.sort_unstable();
+            samples[samples.len() * 999 / 1000] as f64 / samples[samples.len() / 2] as f64
+        };
+        let uniform = tail_ratio(VolumeDistribution::Uniform);
+        let lognormal = tail_ratio(VolumeDistribution::Lognormal);
+        let pareto = tail_ratio(VolumeDistribution::Pareto);
+        assert!(uniform < 1.3, "uniform tail ratio {}", uniform);
+        assert!(lognormal > 10.0, "lognormal tail ratio {}", lognormal);
+        assert!(pareto > 10.0, "pareto tail ratio {}", pareto);
+    }
+
+    #[test]
+    fn test_initial_prices_fall_within_configured_range() {
+        let generator = StockDataGenerator::new(
+            200,
+            StockConfig {
+                price_range: parse_price_range("0.5..5").unwrap(),
+                ..StockConfig::default()
+            },
+        );
+        assert!(generator.instruments.iter().all(|i| (0.5..5.0).contains(&i.price)));
+        assert!(parse_price_range("5..5").is_err());
+        assert!(parse_price_range("10..1").is_err());
+        assert!(parse_price_range("-1..1").is_err());
+    }
+
+    #[test]
+    fn test_price_tiers_mix_ranges() {
+        let generator = StockDataGenerator::new(
+            500,
+            StockConfig {
+                price_tiers: parse_price_tiers("1..5=0.5,1000..3000=0.5").unwrap(),
+                ..StockConfig::default()
+            },
+        );
+        let penny = generator.instruments.iter().filter(|i| (1.0..5.0).contains(&i.price)).count();
+        let high = generator.instruments.iter().filter(|i| (1000.0..3000.0).contains(&i.price)).count();
+        assert_eq!(penny + high, 500);
+        assert!((150..350).contains(&penny), "{} penny stocks", penny);
+    }
+
+    #[test]
+    fn test_tickers_name_instruments_with_sectors() {
+        let config = |tickers| StockConfig {
+            seed: 8,
+            tickers,
+            ..StockConfig::default()
+        };
+        let variants = TICKERS.len() + 2;
+        let mut named = StockDataGenerator::new(variants, config(true));
+        let mut plain = StockDataGenerator::new(variants, config(false));
+        assert_eq!(named.instruments[0].id, "AAPL");
+        assert_eq!(named.instruments[0].sector, Some("Technology"));
+        assert_eq!(named.instruments[TICKERS.len()].id, format!("STK{}", TICKERS.len()));
+        assert!(named.instruments.iter().all(|i| i.sector.is_some()));
+        for _ in 0..100 {
+            let a: Value = serde_json::from_str(&named.generate()).unwrap();
+            let b: Value = serde_json::from_str(&plain.generate()).unwrap();
+            let ticker = TICKERS.iter().find(|(symbol, _)| a["instrument"] == *symbol);
+            if let Some((_, sector)) = ticker {
+                assert_eq!(a["sector"], *sector);
+            }
+            assert!(b.get("sector").is_none());
+            // Names draw nothing from the random stream, so the seeded quotes are unchanged.
+            assert_eq!(a["price"], b["price"]);
+        }
+        assert!(named.fields().contains(&"sector"));
+        assert!(named.line_mapping().unwrap().tags.contains(&"sector"));
+    }
+
+    #[test]
+    fn test_candles_aggregate_ticks() {
+        let config = |candle_interval_ms| StockConfig {
+            seed: 12,
+            clock: Clock::Stepped {
+                now_ms: 1_700_000_000_000,
+                step_ms: 1_000,
+            },
+            candle_interval_ms,
+            ..StockConfig::default()
+        };
+        let mut candles = StockDataGenerator::new(3, config(Some(10_000)));
+        let mut ticks = StockDataGenerator::new(3, config(None));
+        // Candles draw the same random stream, so the same seed sees the same ticks.
+        let mut volume: Vec<u64> = ticks.instruments.iter().map(|i| i.volume).collect();
+        let quotes: Vec<(Value, u64)> = (0..400)
+            .map(|_| {
+                let quote: Value = serde_json::from_str(&ticks.generate()).unwrap();
+                let idx = ticks.last_updated.unwrap();
+                let total = quote["volume"].as_u64().unwrap();
+                let traded = total - volume[idx];
+                volume[idx] = total;
+                (quote, traded)
+            })
+            .collect();
+        let bars: Vec<Value> = (0..300)
+            .filter_map(|_| candles.try_generate())
+            .map(|bar| serde_json::from_str(&bar).unwrap())
+            .collect();
+        assert!(bars.len() > 50, "only {} bars", bars.len());
+        let mut seqs = std::collections::HashMap::new();
+        for bar in &bars {
+            let start = bar["timestamp"].as_u64().unwrap();
+            assert_eq!(start % 10, 0, "bars align to the interval: {}", bar);
+            let in_bar: Vec<&(Value, u64)> = quotes
+                .iter()
+                .filter(|(q, _)| q["instrument"] == bar["instrument"] && (start..start + 10).contains(&q["timestamp"].as_u64().unwrap()))
+                .collect();
+            let prices: Vec<f64> = in_bar.iter().map(|(q, _)| q["price"].as_f64().unwrap()).collect();
+            let (open, high, low, close) = (
+                bar["open"].as_f64().unwrap(),
+                bar["high"].as_f64().unwrap(),
+                bar["low"].as_f64().unwrap(),
+                bar["close"].as_f64().unwrap(),
+            );
+            assert!(high >= open.max(close) && low <= open.min(close), "{}", bar);
+            assert_eq!(open, prices[0]);
+            assert_eq!(close, prices[prices.len() - 1]);
+            assert_eq!(high, prices.iter().cloned().fold(f64::MIN, f64::max));
+            assert_eq!(low, prices.iter().cloned().fold(f64::MAX, f64::min));
+            assert_eq!(bar["trades"], prices.len());
+            assert_eq!(bar["volume"], in_bar.iter().map(|(_, traded)| traded).sum::<u64>());
+            let seq = seqs.entry(bar["instrument"].to_string()).or_insert(0);
+            *seq += 1;
+            assert_eq!(bar["seq"], *seq);
+        }
+        assert_eq!(candles.fields()[..5], ["instrument", "open", "high", "low", "close"]);
+        assert!(candles.line_mapping().unwrap().fields.contains(&"trades"));
+    }
+
+    #[test]
+    fn test_ou_prices_stay_near_the_mean() {
+        let config = |mean| StockConfig {
+            seed: 21,
+            price_model: PriceModel::Ou,
+            volatility: 0.05,
+            mean,
+            ..StockConfig::default()
+        };
+        let mut generator = StockDataGenerator::new(1, config(Some(150.0)));
+        let prices: Vec<f64> = (0..20_000)
+            .map(|_| serde_json::from_str::<Value>(&generator.generate()).unwrap()["price"].as_f64().unwrap())
+            .collect();
+        // Past the start-up transient the price hovers around the mean: the stationary
+        // standard deviation is sigma * mean / sqrt(2 * theta), about 2.4 here.
+        let settled = &prices[10_000..];
+        let average = settled.iter().sum::<f64>() / settled.len() as f64;
+        assert!((average - 150.0).abs() < 1.0, "average {} strayed from the mean", average);
+        assert!(settled.iter().all(|price| (price - 150.0).abs() < 15.0));
+
+        // Without a configured mean, each instrument reverts to its own starting price.
+        let mut generator = StockDataGenerator::new(3, config(None));
+        let starts: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+        for _ in 0..20_000 {
+            generator.generate();
+        }
+        for (instrument, start) in generator.instruments.iter().zip(starts) {
+            assert!((instrument.price - start).abs() < start * 0.1, "{} wandered from {}", instrument.price, start);
+        }
+    }
+
+    #[test]
+    fn test_ou_steady_state_start_matches_the_stationary_spread() {
+        let config = |steady_state_start| StockConfig {
+            seed: 4,
+            price_model: PriceModel::Ou,
+            volatility: 0.05,
+            mean: Some(150.0),
+            steady_state_start,
+            ..StockConfig::default()
+        };
+        let spread = |prices: &[f64]| {
+            let mean = prices.iter().sum::<f64>() / prices.len() as f64;
+            (mean, (prices.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / prices.len() as f64).sqrt())
+        };
+        // Across instruments, starting prices and prices long after agree: centred on
+        // the mean with standard deviation sigma * mean / sqrt(2 * theta), about 2.37.
+        let mut generator = StockDataGenerator::new(2_000, config(true));
+        let starts: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+        for _ in 0..200_000 {
+            generator.generate();
+        }
+        let settled: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+        for (mean, sd) in [spread(&starts), spread(&settled)] {
+            assert!((mean - 150.0).abs() < 0.3, "mean {}", mean);
+            assert!((sd - 2.37).abs() < 0.2, "standard deviation {}", sd);
+        }
+        // Without it, instruments start wherever the price range put them.
+        let generator = StockDataGenerator::new(2_000, config(false));
+        let starts: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+        assert!(spread(&starts).1 > 20.0);
+    }
+
+    #[test]
+    fn test_correlation_links_instrument_returns() {
+        // Sample correlation of two instruments' log returns over windows of 100 updates;
+        // long windows keep the market moves not yet taken at a window's end negligible.
+        fn sample_correlation(rho: f64) -> f64 {
+            let mut generator = StockDataGenerator::new(
+                2,
+                StockConfig {
+                    seed: 5,
+                    volatility: 0.3,
+                    correlation: rho,
+                    ..StockConfig::default()
+                },
+            );
+            let mut last: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+            let mut returns = Vec::new();
+            for _ in 0..3_000 {
+                for _ in 0..100 {
+                    generator.generate();
+                }
+                let prices: Vec<f64> = generator.instruments.iter().map(|i| i.price).collect();
+                returns.push(((prices[0] / last[0]).ln(), (prices[1] / last[1]).ln()));
+                last = prices;
+            }
+            let n = returns.len() as f64;
+            let (ma, mb) = returns.iter().fold((0.0, 0.0), |(a, b), (x, y)| (a + x / n, b + y / n));
+            let (cov, va, vb) = returns.iter().fold((0.0, 0.0, 0.0), |(c, va, vb), (x, y)| {
+                (c + (x - ma) * (y - mb), va + (x - ma).powi(2), vb + (y - mb).powi(2))
+            });
+            cov / (va * vb).sqrt()
+        }
+        for rho in [0.0, 0.8] {
+            let measured = sample_correlation(rho);
+            assert!((measured - rho).abs() < 0.08, "rho {} measured as {}", rho, measured);
+        }
+    }
+
+    #[test]
+    fn test_market_hours_gap_at_each_open() {
+        // 15:00 UTC; one-minute steps span two day boundaries.
+        let start_ms = 1_700_006_400_000 - 9 * 60 * 60 * 1000;
+        let mut generator = StockDataGenerator::new(
+            2,
+            StockConfig {
+                drift: 0.0,
+                volatility: 0.0,
+                clock: Clock::Stepped {
+                    now_ms: start_ms,
+                    step_ms: 60_000,
+                },
+                market_hours: true,
+                ..StockConfig::default()
+            },
+        );
+        let mut prices = Vec::new();
+        let mut days = std::collections::BTreeSet::new();
+        for _ in 0..800 {
+            let Some(msg) = generator.try_generate() else {
+                assert!(generator.dump().ends_with("market: closed\n"));
+                continue;
+            };
+            let msg: Value = serde_json::from_str(&msg).unwrap();
+            let now_ms = msg["timestamp"].as_u64().unwrap() * 1000;
+            assert!(session_day(now_ms).is_some(), "traded outside session hours: {}", msg);
+            days.insert(now_ms / DAY_MS);
+            if msg["instrument"] == "STK0" {
+                prices.push(msg["price"].as_f64().unwrap());
+            }
+        }
+        assert_eq!(days.len(), 3, "the run should span three sessions");
+        // Without drift or volatility, prices only move at each open's gap.
+        prices.dedup();
+        assert_eq!(prices.len(), 3, "{:?}", prices);
+        assert!(prices.windows(2).all(|w| (w[1] / w[0]).ln().abs() < 0.1));
+        assert!(generator.dump().ends_with("market: open\n"));
+    }
+
+    #[test]
+    fn test_market_hours_skip_weekends() {
+        // Friday 2023-11-17, 15:00 UTC; the next session is Monday's.
+        let (friday, monday) = (19_678, 19_681);
+        let mut generator = StockDataGenerator::new(
+            2,
+            StockConfig {
+                clock: Clock::Stepped {
+                    now_ms: friday * DAY_MS + 15 * 60 * 60 * 1000,
+                    step_ms: 60_000,
+                },
+                market_hours: true,
+                ..StockConfig::default()
+            },
+        );
+        let mut days = std::collections::BTreeSet::new();
+        for _ in 0..200 {
+            if let Some(msg) = generator.try_generate() {
+                let msg: Value = serde_json::from_str(&msg).unwrap();
+                days.insert(msg["timestamp"].as_u64().unwrap() * 1000 / DAY_MS);
+            }
+        }
+        assert_eq!(days.into_iter().collect::<Vec<_>>(), [friday, monday]);
+        assert_eq!(next_open_ms(friday * DAY_MS + SESSION_CLOSE_UTC_MS), monday * DAY_MS + SESSION_OPEN_UTC_MS);
+    }
+
+    #[test]
+    fn test_intraday_volume_is_u_shaped_and_resets_daily() {
+        // 09:30 UTC; one-minute steps cover two full days.
+        let start_ms = 1_700_006_400_000 + SESSION_OPEN_UTC_MS;
+        let mut generator = StockDataGenerator::new(
+            1,
+            StockConfig {
+                clock: Clock::Stepped {
+                    now_ms: start_ms,
+                    step_ms: 60_000,
+                },
+                volume_model: VolumeModel::Intraday,
+                ..StockConfig::default()
+            },
+        );
+        let mut last = (0, 0);
+        let mut resets = 0;
+        // Trade volume sums by half hour of the first session.
+        let mut half_hours = [0u64; 13];
+        for minute in 0..2 * 24 * 60 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let (volume, day) = (msg["volume"].as_u64().unwrap(), msg["timestamp"].as_u64().unwrap() / 86_400);
+            if day != last.1 && minute > 0 {
+                resets += 1;
+                assert!(volume < last.0, "volume must restart at the day boundary");
+            } else if minute < 390 {
+                half_hours[minute / 30] += volume - last.0;
+            }
+            last = (volume, day);
+        }
+        assert_eq!(resets, 2);
+        let midday = half_hours[6];
+        assert!(half_hours[0] > 2 * midday && half_hours[12] > 2 * midday, "{:?}", half_hours);
+        assert!(half_hours[..6].windows(2).all(|w| w[0] > w[1]), "{:?}", half_hours);
+    }
+
+    #[test]
+    fn test_trades_and_quotes_are_separate_messages() {
+        let mut generator = StockDataGenerator::new(
+            3,
+            StockConfig {
+                messages: StockMessages::TradesAndQuotes,
+                ..StockConfig::default()
+            },
+        );
+        let (mut trades, mut quotes, mut traded) = (0, 0, 0);
+        for _ in 0..2_000 {
+            let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let instrument = &generator.instruments[generator.last_updated.unwrap()];
+            match msg["type"].as_str().unwrap() {
+                "trade" => {
+                    trades += 1;
+                    traded += msg["size"].as_u64().unwrap();
+                    let price = msg["price"].as_f64().unwrap();
+                    let (bid, ask) = instrument.quoted.unwrap();
+                    // JSON parsing may be an ulp out, so the band's edges are given a hair of slack.
+                    let slack = price * 1e-12;
+                    assert!(
+                        bid - slack <= price && price <= ask + slack,
+                        "trade outside the band: {}",
+                        msg
+                    );
+                    assert!(msg["size"].as_u64().unwrap() > 0);
+                    assert!(msg.get("bid").is_none());
+                }
+                "quote" => {
+                    quotes += 1;
+                    assert!(msg["bid"].as_f64().unwrap() < msg["ask"].as_f64().unwrap());
+                    assert!(msg.get("price").is_none() && msg.get("size").is_none());
+                }
+                other => panic!("unexpected message type {}", other),
+            }
+        }
+        assert!(trades > 400 && quotes > 1_000, "{} trades, {} quotes", trades, quotes);
+        // Quotes trade nothing, so the cumulative volume is the trades' sizes alone.
+        assert_eq!(generator.instruments.iter().map(|i| i.volume).sum::<u64>(), traded);
+        assert!(generator.line_mapping().unwrap().tags.contains(&"type"));
+    }
+
+    #[test]
+    fn test_trades_print_within_the_previously_published_quote() {
+        for tick_size in [None, Some(0.01)] {
+            let mut generator = StockDataGenerator::new(
+                5,
+                StockConfig {
+                    messages: StockMessages::TradesAndQuotes,
+                    tick_size,
+                    seed: 9,
+                    ..StockConfig::default()
+                },
+            );
+            // Replay the stream as a consumer would, remembering each instrument's last quote.
+            let mut quotes = std::collections::HashMap::new();
+            for _ in 0..5_000 {
+                let msg: Value = serde_json::from_str(&generator.generate()).unwrap();
+                let id = msg["instrument"].as_str().unwrap().to_string();
+                if msg["type"] == "quote" {
+                    quotes.insert(id, (msg["bid"].as_f64().unwrap(), msg["ask"].as_f64().unwrap()));
+                    continue;
+                }
+                let Some(&(bid, ask)) = quotes.get(&id) else {
+                    panic!("trade before any quote: {}", msg);
+                };
+                // The same f64 serializes to the same text, so the prices compare exactly.
+                let price = msg["price"].as_f64().unwrap();
+                assert!(price == bid || price == ask, "trade away from the last quote ({}, {}): {}", bid, ask, msg);
+            }
+        }
     }
 
     #[test]
     fn test_dump_market_data() {
-        let generator = StockDataGenerator::new(3);
+        let generator = StockDataGenerator::new(3, StockConfig::default());
         let csv = generator.dump();
         let lines: Vec<&str> = csv.lines().collect();
         // Expect a header plus one line per instrument.
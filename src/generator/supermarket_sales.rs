@@ -1,11 +1,20 @@
-use chrono::Utc;
-use fake::faker::address::en::{CityName, StateAbbr};
-use fake::Fake;
-use once_cell::sync::Lazy;
+use crate::clock::{self, Clock};
+use crate::country::{Country, COUNTRIES};
+use crate::currency::{self, FxRates};
+use crate::line_protocol::LineMapping;
+use crate::region::Region;
+use crate::rng::{RngAlgorithm, SimRng};
+use clap::ValueEnum;
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::Distribution;
 use rand::Rng;
-use serde::Serialize;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use rand_distr::{Exp, Gamma, Geometric, LogNormal, Poisson};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::str::FromStr;
 
 /// Represents a product in a hierarchical catalog.
 #[derive(Debug, Serialize, Clone)]
@@ -13,40 +22,338 @@ pub struct Product {
     pub product_name: String,
     pub category: String,
     pub subcategory: String,
+    pub brand: String,
+    pub supplier_id: String,
     pub unit_price: f64,
 }
 
+/// The format (size tier) of a store, which drives catalog breadth and basket size.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreFormat {
+    Express,
+    Standard,
+    Superstore,
+}
+
+impl StoreFormat {
+    /// Whether products of the given category are stocked in this format.
+    /// Express stores carry a convenience range; only superstores sell clothing.
+    fn stocks(self, category: &str) -> bool {
+        match self {
+            StoreFormat::Express => matches!(category, "Food" | "Beauty" | "Healthcare"),
+            StoreFormat::Standard => category != "Clothing",
+            StoreFormat::Superstore => true,
+        }
+    }
+
+    /// Typical range of items per basket for this format.
+    fn basket_size_range(self) -> Range<u32> {
+        match self {
+            StoreFormat::Express => 1..6,
+            StoreFormat::Standard => 5..16,
+            StoreFormat::Superstore => 10..31,
+        }
+    }
+}
+
+/// Upper bound on a sampled basket size, so heavy tails stay plausible.
+const MAX_BASKET_SIZE: u32 = 200;
+
+/// How busy the stores are, and how full the baskets, at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Season {
+    /// Multiplier on the basket arrival rate.
+    traffic: f64,
+    /// Multiplier on the basket size.
+    basket_size: f64,
+}
+
+/// Seasonal pattern at `now_ms` (UTC): quiet nights, a lunch rush of small baskets, an
+/// after-work peak, and busier weekends with bigger weekly shops.
+fn season(now_ms: u64) -> Season {
+    let hour = now_ms / 3_600_000 % 24;
+    // The epoch fell on a Thursday; 0 is Monday.
+    let weekday = (now_ms / 86_400_000 + 3) % 7;
+    let weekend = weekday >= 5;
+    let (traffic, basket_size) = match hour {
+        0..=6 | 22..=23 => (0.2, 0.8),
+        12..=13 => (1.8, 0.7),
+        17..=19 => (1.5, 1.2),
+        _ => (1.0, 1.0),
+    };
+    let (day_traffic, day_size) = match weekday {
+        4 => (1.1, 1.1),
+        _ if weekend => (1.3, 1.4),
+        _ => (1.0, 1.0),
+    };
+    Season {
+        traffic: traffic * day_traffic,
+        basket_size: basket_size * day_size,
+    }
+}
+
+/// Distribution that the number of items per basket is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BasketSizeDistribution {
+    /// Uniform over the store format's typical range (e.g. 5–15 for standard stores).
+    Uniform,
+    /// One plus a geometric count: many small baskets and an exponential tail.
+    Geometric,
+    /// One plus a negative-binomial count: right-skewed like geometric, with a
+    /// dispersion parameter controlling how heavy the tail is.
+    NegativeBinomial,
+}
+
+impl BasketSizeDistribution {
+    /// Draw a basket size for a store format. `mean` defaults to the midpoint of the
+    /// format's range; `dispersion` is the negative binomial's shape `r` (smaller is
+    /// more skewed). Sizes are at least 1 and at most `MAX_BASKET_SIZE`.
+    pub fn sample<R: Rng + ?Sized>(
+        self,
+        rng: &mut R,
+        format: StoreFormat,
+        mean: Option<f64>,
+        dispersion: f64,
+    ) -> u32 {
+        let range = format.basket_size_range();
+        let mean = mean.unwrap_or((range.start + range.end - 1) as f64 / 2.0);
+        // Both skewed modes draw the items beyond the first, so the mean excess is mean - 1.
+        let excess = (mean - 1.0).max(0.0);
+        let extra = match self {
+            BasketSizeDistribution::Uniform => return rng.random_range(range),
+            BasketSizeDistribution::Geometric => Geometric::new(1.0 / (excess + 1.0))
+                .expect("basket size mean must be at least 1")
+                .sample(rng),
+            // A mean of one item leaves no excess, and a zero-scale Gamma is invalid.
+            BasketSizeDistribution::NegativeBinomial if excess == 0.0 => 0,
+            BasketSizeDistribution::NegativeBinomial => {
+                // Gamma-Poisson mixture: a negative binomial with mean `excess` and shape `dispersion`.
+                let rate = Gamma::new(dispersion, excess / dispersion)
+                    .expect("basket size dispersion must be positive")
+                    .sample(rng);
+                if rate > 0.0 {
+                    Poisson::new(rate).expect("poisson rate is positive").sample(rng) as u64
+                } else {
+                    0
+                }
+            }
+        };
+        (1 + extra).min(MAX_BASKET_SIZE as u64) as u32
+    }
+}
+
+impl FromStr for StoreFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "express" => Ok(StoreFormat::Express),
+            "standard" => Ok(StoreFormat::Standard),
+            "superstore" => Ok(StoreFormat::Superstore),
+            _ => Err(format!(
+                "unknown store format '{}' (expected express, standard or superstore)",
+                s
+            )),
+        }
+    }
+}
+
+/// Parse a store format distribution such as `express=0.3,standard=0.5,superstore=0.2`.
+pub fn parse_store_format_mix(spec: &str) -> Result<Vec<(StoreFormat, f64)>, String> {
+    crate::weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(name, weight)| Ok((name.parse()?, weight)))
+        .collect()
+}
+
+/// Which entity supermarket messages are keyed by, for Kafka partitioning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SalesKey {
+    /// The basket id: each basket's line items stay in order.
+    Basket,
+    /// The store id: everything sold at a store stays in order, across baskets.
+    Store,
+}
+
 /// Represents store details.
 #[derive(Debug, Serialize, Clone)]
 pub struct Store {
+    /// Stable id within the store pool, present only when a pool is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
     pub town: String,
     pub state: String,
     pub country: String,
+    pub store_format: StoreFormat,
+    /// Local currency, present only when a store country mix is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<&'static str>,
+    /// Store location, present only when a region preset is selected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// Fixed offset (ms) applied to the store's timestamps, simulating an
+    /// unsynchronized till; 0 unless clock skew is configured.
+    #[serde(skip)]
+    pub clock_skew_ms: i64,
 }
 
 /// Represents customer demographic data.
 #[derive(Debug, Serialize, Clone)]
 pub struct Customer {
+    /// Stable id within the customer pool, present only when a pool is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer_id: Option<String>,
     pub age: u8,
     pub income_band: String,
+    /// Account home currency, present only when a currency set is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub home_currency: Option<&'static str>,
+    /// Number of people in the household (rich demographics only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub household_size: Option<u8>,
+    /// Occupation (rich demographics only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occupation: Option<&'static str>,
 }
 
 /// Represents a sale for a single product.
 #[derive(Debug, Serialize)]
 pub struct SaleMessage {
+    /// `Some("stockout")` when the product was unavailable; absent for ordinary sales.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<&'static str>,
+    /// `sale` or `return` (return runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<&'static str>,
     pub transaction_id: String,
+    /// Transaction the returned product was sold in (returns only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_transaction_id: Option<String>,
     pub basket_id: String,
+    pub seq: u64,
     pub timestamp: u64,
     pub store: Store,
     pub customer: Customer,
+    /// How the basket was paid: `cash`, `card` or `mobile`.
+    pub payment_method: &'static str,
+    /// Where the basket was bought: `in_store` or `online`.
+    pub channel: &'static str,
     pub product: Product,
-    pub quantity: u32,
+    /// Items sold; negative for returns.
+    pub quantity: i32,
+    /// Line total in the basket's price currency (the customer's home currency under a
+    /// currency set), after any promotional discount.
     pub total_price: f64,
+    /// Unit price before the discount (promotion runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_price: Option<f64>,
+    /// Fraction taken off the unit price, 0 when the line isn't on promotion (promotion
+    /// runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discount_rate: Option<f64>,
+    /// Sales tax, VAT or GST rate of the store's country and state (tax runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<f64>,
+    /// Tax on the amount charged, in `currency` and rounded to its minor unit: on top of
+    /// it, or included in it with `TaxBasis::Inclusive` (tax runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_amount: Option<f64>,
+    /// Currency the transaction was charged in (multi-currency and tax runs only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<&'static str>,
+    /// Amount charged, in `currency`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    /// Home-to-transaction currency rate, for foreign-currency transactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange_rate: Option<f64>,
+    /// Checkout lane the basket went through (with `lanes_per_store` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lane_id: Option<u32>,
+    /// Cashier who served the basket, from the store's cashier pool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cashier_id: Option<String>,
+    /// Time the whole basket took at the checkout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_duration_seconds: Option<u32>,
+}
+
+/// A subcategory entry: (Subcategory, list of Product Names).
+type SubcategoryEntry = (&'static str, &'static [&'static str]);
+
+/// A category entry: (Category, list of subcategories).
+type CategoryEntry = (&'static str, &'static [SubcategoryEntry]);
+
+/// Emitted when a basket is abandoned before all of its items were generated.
+#[derive(Debug, Serialize)]
+pub struct BasketAbandonedEvent {
+    pub event_type: &'static str,
+    pub transaction_id: String,
+    pub basket_id: String,
+    pub seq: u64,
+    pub timestamp: u64,
+    pub store: Store,
+    pub customer: Customer,
+    pub items_generated: usize,
+    pub total_items: usize,
+}
+
+/// Emitted after a completed basket's last item, totalling its lines (basket summary
+/// runs only).
+#[derive(Debug, Serialize)]
+pub struct BasketSummaryEvent {
+    pub event_type: &'static str,
+    pub transaction_id: String,
+    pub basket_id: String,
+    pub seq: u64,
+    pub timestamp: u64,
+    pub store: Store,
+    pub customer: Customer,
+    pub payment_method: &'static str,
+    pub channel: &'static str,
+    /// Number of line items the basket emitted.
+    pub item_count: usize,
+    /// Sum of the lines' `total_price`, rounded to the cent.
+    pub basket_total: f64,
+}
+
+/// Emitted after a completed basket when the customer redeems a coupon against it.
+#[derive(Debug, Serialize)]
+pub struct CouponRedeemedEvent {
+    pub event_type: &'static str,
+    pub transaction_id: String,
+    pub basket_id: String,
+    pub seq: u64,
+    pub timestamp: u64,
+    pub coupon_code: &'static str,
+    pub discount_amount: f64,
+}
+
+/// How a coupon discounts a basket.
+#[derive(Debug, Clone, Copy)]
+enum Discount {
+    /// A fraction of the basket subtotal.
+    Percent(f64),
+    /// A fixed amount, capped at the subtotal.
+    Fixed(f64),
 }
 
+/// Coupons customers redeem, by code.
+static COUPONS: &[(&str, Discount)] = &[
+    ("SAVE5", Discount::Percent(0.05)),
+    ("SAVE10", Discount::Percent(0.10)),
+    ("SAVE20", Discount::Percent(0.20)),
+    ("WELCOME3", Discount::Fixed(3.0)),
+    ("TAKE5OFF", Discount::Fixed(5.0)),
+    ("BIGSHOP10", Discount::Fixed(10.0)),
+];
+
 /// A product hierarchy that simulates a supermarket product catalog.
 /// Each tuple is (Category, list of (Subcategory, list of Product Names)).
-static PRODUCT_HIERARCHY: &[(&str, &[(&str, &[&str])])] = &[
+static PRODUCT_HIERARCHY: &[CategoryEntry] = &[
     (
         "Food",
         &[
@@ -141,23 +448,447 @@ static PRODUCT_HIERARCHY: &[(&str, &[(&str, &[&str])])] = &[
     ),
 ];
 
-/// A global cache for product prices keyed by (category, product_name).
-static PRICE_CACHE: Lazy<Mutex<HashMap<(String, String), f64>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+/// The product catalog sales are drawn from: categories → subcategories → product names,
+/// with a unit price range per category. Defaults to the built-in `PRODUCT_HIERARCHY`;
+/// `Catalog::load` reads one from a JSON file such as
+/// `{"categories": [{"name": "Food", "price_range": [1, 10], "subcategories":
+/// [{"name": "Bakery", "products": ["Bread", {"name": "Bagel", "weight": 0.5}]}]}]}`.
+/// Categories, subcategories and products may carry a popularity `weight`; items without
+/// one are weighted by the generator's `Popularity`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Catalog {
+    categories: Vec<CatalogCategory>,
+    /// Products bought together, which `--basket-affinity` baskets follow.
+    #[serde(default)]
+    affinities: Vec<Affinity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogCategory {
+    name: String,
+    /// `[min, max]` unit price in dollars; defaults to the built-in range for the name.
+    #[serde(default)]
+    price_range: Option<(f64, f64)>,
+    #[serde(default)]
+    weight: Option<f64>,
+    subcategories: Vec<CatalogSubcategory>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogSubcategory {
+    name: String,
+    #[serde(default)]
+    weight: Option<f64>,
+    products: Vec<CatalogProduct>,
+}
+
+/// Two products bought together, e.g. `{"products": ["Frozen Pizza", "Ice Cream"],
+/// "rate": 0.3}`: once a basket holds either, each later line is the other with
+/// probability `rate`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Affinity {
+    products: (String, String),
+    rate: f64,
+}
+
+/// Co-purchase pairs of the built-in catalog, with their rates.
+static AFFINITIES: &[(&str, &str, f64)] = &[
+    ("Frozen Pizza", "Ice Cream", 0.3),
+    ("Bread", "Cheese", 0.2),
+    ("Bread", "Ham", 0.15),
+    ("Tomato Soup", "Bread", 0.15),
+    ("Shampoo", "Conditioner", 0.3),
+    ("Detergent", "Fabric Softener", 0.3),
+    ("Cough Syrup", "Pain Reliever", 0.2),
+    ("Dog Food", "Chew Toy", 0.25),
+    ("Cat Food", "Catnip Toy", 0.25),
+    ("Jeans", "T-Shirt", 0.2),
+    ("Dress", "Heels", 0.2),
+];
+
+/// A catalog product, written in the file as its bare name or as `{"name", "weight"}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "ProductEntry")]
+struct CatalogProduct {
+    name: String,
+    weight: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum ProductEntry {
+    Name(String),
+    Weighted {
+        name: String,
+        #[serde(default)]
+        weight: Option<f64>,
+    },
+}
+
+impl From<ProductEntry> for CatalogProduct {
+    fn from(entry: ProductEntry) -> Self {
+        match entry {
+            ProductEntry::Name(name) => CatalogProduct { name, weight: None },
+            ProductEntry::Weighted { name, weight } => CatalogProduct { name, weight },
+        }
+    }
+}
+
+/// Reject a popularity weight that is not a positive number.
+fn check_weight(weight: Option<f64>, item: &str) -> Result<(), String> {
+    match weight {
+        Some(weight) if !(weight.is_finite() && weight > 0.0) => {
+            Err(format!("'{}' has weight {}; expected a positive number", item, weight))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Catalog {
+    /// The built-in catalog, `PRODUCT_HIERARCHY` with its default price ranges.
+    pub fn builtin() -> Self {
+        let categories = PRODUCT_HIERARCHY
+            .iter()
+            .map(|(category, subcategories)| CatalogCategory {
+                name: category.to_string(),
+                price_range: None,
+                weight: None,
+                subcategories: subcategories
+                    .iter()
+                    .map(|(subcategory, products)| CatalogSubcategory {
+                        name: subcategory.to_string(),
+                        weight: None,
+                        products: products
+                            .iter()
+                            .map(|p| CatalogProduct {
+                                name: p.to_string(),
+                                weight: None,
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let affinities = AFFINITIES
+            .iter()
+            .map(|(a, b, rate)| Affinity {
+                products: (a.to_string(), b.to_string()),
+                rate: *rate,
+            })
+            .collect();
+        Catalog {
+            categories,
+            affinities,
+        }
+    }
+
+    /// Read and validate a JSON catalog file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Catalog::parse(&json)
+    }
+
+    /// Parse a JSON catalog, rejecting empty or duplicate entries, bad price ranges, bad
+    /// weights and affinities between unknown products.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let catalog: Catalog = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if catalog.categories.is_empty() {
+            return Err("the catalog has no categories".to_string());
+        }
+        let mut categories = HashSet::new();
+        for category in &catalog.categories {
+            if category.name.is_empty() || !categories.insert(category.name.to_lowercase()) {
+                return Err(format!("category '{}' is empty or listed twice", category.name));
+            }
+            if let Some((min, max)) = category.price_range {
+                if !(min.is_finite() && max.is_finite() && 0.0 < min && min <= max) {
+                    return Err(format!(
+                        "category '{}' has price range [{}, {}]; expected 0 < min <= max",
+                        category.name, min, max
+                    ));
+                }
+            }
+            check_weight(category.weight, &category.name)?;
+            if category.subcategories.is_empty() {
+                return Err(format!("category '{}' has no subcategories", category.name));
+            }
+            let mut products = HashSet::new();
+            for subcategory in &category.subcategories {
+                check_weight(subcategory.weight, &subcategory.name)?;
+                if subcategory.products.is_empty() {
+                    return Err(format!(
+                        "subcategory '{}' of '{}' has no products",
+                        subcategory.name, category.name
+                    ));
+                }
+                for product in &subcategory.products {
+                    check_weight(product.weight, &product.name)?;
+                    if !products.insert(product.name.as_str()) {
+                        return Err(format!(
+                            "product '{}' is listed twice in '{}'",
+                            product.name, category.name
+                        ));
+                    }
+                }
+            }
+        }
+        for Affinity { products: (a, b), rate } in &catalog.affinities {
+            if let Some(unknown) = [a, b].into_iter().find(|p| catalog.locate(p).is_none()) {
+                return Err(format!("affinity names unknown product '{}'", unknown));
+            }
+            if a == b || !(0.0 < *rate && *rate <= 1.0) {
+                return Err(format!(
+                    "affinity between '{}' and '{}' needs two products and a rate in (0, 1]",
+                    a, b
+                ));
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Where the first product of the given name is, in catalog order.
+    fn locate(&self, name: &str) -> Option<ProductRef> {
+        self.categories.iter().enumerate().find_map(|(c, category)| {
+            category.subcategories.iter().enumerate().find_map(|(s, subcategory)| {
+                let p = subcategory.products.iter().position(|p| p.name == name)?;
+                Some((c, s, p))
+            })
+        })
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.categories.iter().position(|category| category.name == name)
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog::builtin()
+    }
+}
+
+impl CatalogCategory {
+    fn price_range(&self) -> (f64, f64) {
+        self.price_range.unwrap_or_else(|| default_price_range(&self.name))
+    }
+}
+
+/// How popular catalog items without an explicit `weight` are, relative to the other
+/// entries of their list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Popularity {
+    /// Every entry is as likely as any other.
+    Uniform,
+    /// Zipf-like: the k-th entry of a list is 1/k as likely as the first, so the
+    /// staples listed first dominate.
+    Zipf,
+}
+
+impl Popularity {
+    /// Weights of a list's entries, given their explicit weights in list order.
+    fn weights(self, explicit: impl Iterator<Item = Option<f64>>) -> Vec<f64> {
+        explicit
+            .enumerate()
+            .map(|(rank, weight)| {
+                weight.unwrap_or(match self {
+                    Popularity::Uniform => 1.0,
+                    Popularity::Zipf => 1.0 / (rank + 1) as f64,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A product's position in a catalog: its category, subcategory and product indexes.
+type ProductRef = (usize, usize, usize);
+
+/// Weighted draws over a catalog: its categories (for each store format), and the
+/// subcategories and products within each.
+#[derive(Debug, Clone)]
+struct CatalogSampler {
+    /// Category draw per store format, indexed by `StoreFormat as usize`.
+    categories: Vec<WeightedIndex<f64>>,
+    /// Subcategory draw per category.
+    subcategories: Vec<WeightedIndex<f64>>,
+    /// Product draw per subcategory per category.
+    products: Vec<Vec<WeightedIndex<f64>>>,
+    /// Each product's co-purchase partners and rates, both ways round; empty unless
+    /// affinities are followed.
+    affinities: HashMap<ProductRef, Vec<(ProductRef, f64)>>,
+}
+
+impl CatalogSampler {
+    fn new(catalog: &Catalog, popularity: Popularity, affinity: bool) -> Self {
+        let index = |weights: Vec<f64>| WeightedIndex::new(weights).expect("catalog weights are positive");
+        let weights = popularity.weights(catalog.categories.iter().map(|c| c.weight));
+        let categories = [StoreFormat::Express, StoreFormat::Standard, StoreFormat::Superstore]
+            .into_iter()
+            .map(|format| {
+                let stocked: Vec<f64> = catalog
+                    .categories
+                    .iter()
+                    .zip(&weights)
+                    .map(|(category, weight)| if format.stocks(&category.name) { *weight } else { 0.0 })
+                    .collect();
+                // A format that stocks none of the catalog's categories sells from all of them.
+                WeightedIndex::new(stocked).unwrap_or_else(|_| index(weights.clone()))
+            })
+            .collect();
+        let subcategories = catalog
+            .categories
+            .iter()
+            .map(|category| index(popularity.weights(category.subcategories.iter().map(|s| s.weight))))
+            .collect();
+        let products = catalog
+            .categories
+            .iter()
+            .map(|category| {
+                category
+                    .subcategories
+                    .iter()
+                    .map(|subcategory| index(popularity.weights(subcategory.products.iter().map(|p| p.weight))))
+                    .collect()
+            })
+            .collect();
+        let mut affinities: HashMap<ProductRef, Vec<(ProductRef, f64)>> = HashMap::new();
+        for Affinity { products: (a, b), rate } in catalog.affinities.iter().filter(|_| affinity) {
+            if let (Some(a), Some(b)) = (catalog.locate(a), catalog.locate(b)) {
+                affinities.entry(a).or_default().push((b, *rate));
+                affinities.entry(b).or_default().push((a, *rate));
+            }
+        }
+        CatalogSampler {
+            categories,
+            subcategories,
+            products,
+            affinities,
+        }
+    }
+
+    /// A co-purchase partner of one of the basket's `picked` products, if the next line
+    /// follows an affinity: each partner a store of `format` stocks is taken with its
+    /// pair's rate, in the order the basket picked its products.
+    fn follow_affinity<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        catalog: &Catalog,
+        format: StoreFormat,
+        picked: &[ProductRef],
+    ) -> Option<ProductRef> {
+        let partners = || {
+            picked
+                .iter()
+                .filter_map(|product| self.affinities.get(product))
+                .flatten()
+                .filter(|((c, _, _), _)| format.stocks(&catalog.categories[*c].name))
+        };
+        partners().next()?;
+        let mut draw: f64 = rng.random();
+        for (partner, rate) in partners() {
+            if draw < *rate {
+                return Some(*partner);
+            }
+            draw -= rate;
+        }
+        None
+    }
+
+    /// Share of draws that go to entry `i` of `index`.
+    fn share(index: &WeightedIndex<f64>, i: usize) -> f64 {
+        index.weight(i).unwrap_or(0.0) / index.total_weight()
+    }
+}
+
+/// Brands stocked in each category, with the id of the supplier each brand comes from.
+/// Some suppliers carry brands across several categories.
+static BRANDS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Food",
+        &[
+            ("Harvest Table", "SUP-001"),
+            ("Golden Acre", "SUP-001"),
+            ("Baker's Choice", "SUP-002"),
+            ("Coastline Foods", "SUP-003"),
+            ("Value Basket", "SUP-010"),
+        ],
+    ),
+    (
+        "Beauty",
+        &[
+            ("Lumière", "SUP-004"),
+            ("Pure Bloom", "SUP-004"),
+            ("Velvet & Co", "SUP-005"),
+            ("Value Basket", "SUP-010"),
+        ],
+    ),
+    (
+        "Healthcare",
+        &[
+            ("WellSpring", "SUP-006"),
+            ("CareFirst", "SUP-006"),
+            ("MediPlus", "SUP-007"),
+            ("Value Basket", "SUP-010"),
+        ],
+    ),
+    (
+        "Cleaning Products",
+        &[
+            ("Sparkle", "SUP-008"),
+            ("FreshHome", "SUP-008"),
+            ("Value Basket", "SUP-010"),
+        ],
+    ),
+    (
+        "Pets",
+        &[
+            ("Happy Paws", "SUP-009"),
+            ("Whisker Farm", "SUP-009"),
+            ("Value Basket", "SUP-010"),
+        ],
+    ),
+    (
+        "Clothing",
+        &[
+            ("Northbound", "SUP-011"),
+            ("Urban Thread", "SUP-011"),
+            ("Little Steps", "SUP-012"),
+        ],
+    ),
+];
+
+/// The (brand, supplier id) of a product. Like its price, the brand is a pure function of
+/// the product's name and category, so it is sticky within a run and across runs.
+fn get_product_brand(category: &str, product_name: &str) -> (&'static str, &'static str) {
+    let brands = BRANDS
+        .iter()
+        .find(|(c, _)| *c == category)
+        .map_or(&[("Value Basket", "SUP-010")][..], |(_, brands)| brands);
+    // FNV-1a, so brands do not correlate with the byte-sum hash behind prices.
+    let hash = product_name.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    brands[hash as usize % brands.len()]
+}
+
+/// A generator's product prices keyed by (category, product_name). Only catalog products
+/// are ever priced, so a cache holds at most one entry per catalog product.
+type PriceCache = HashMap<(String, String), f64>;
 
-/// Compute a deterministic raw price (in dollars) for a given product name and category.
-/// The price range is determined by the category:
+/// The unit price range (in dollars) of a category a catalog gives no range for:
 /// - Food: $1–$10
 /// - Beauty: $5–$30
 /// - Healthcare: $3–$25
 /// - Cleaning Products: $2–$15
 /// - Pets: $3–$20
 /// - Clothing: $5–$50
-fn compute_price(category: &str, product_name: &str) -> f64 {
-    let hash: u32 = product_name
-        .bytes()
-        .fold(0, |acc, b| acc.wrapping_add(b as u32));
-    let (min, max) = match category {
+/// - anything else: $1–$20
+fn default_price_range(category: &str) -> (f64, f64) {
+    match category {
         "Food" => (1.0, 10.0),
         "Beauty" => (5.0, 30.0),
         "Healthcare" => (3.0, 25.0),
@@ -165,7 +896,15 @@ fn compute_price(category: &str, product_name: &str) -> f64 {
         "Pets" => (3.0, 20.0),
         "Clothing" => (5.0, 50.0),
         _ => (1.0, 20.0),
-    };
+    }
+}
+
+/// Compute a deterministic raw price (in dollars) for a given product name within its
+/// category's price range.
+fn compute_price((min, max): (f64, f64), product_name: &str) -> f64 {
+    let hash: u32 = product_name
+        .bytes()
+        .fold(0, |acc, b| acc.wrapping_add(b as u32));
     let range = max - min;
     let scaled = (hash % 1000) as f64 / 1000.0;
     min + scaled * range
@@ -185,83 +924,533 @@ fn round_price(price: f64) -> f64 {
 }
 
 /// Retrieve the product price from cache (or compute, round, and cache it if not already computed).
-fn get_product_price(category: &str, product_name: &str) -> f64 {
-    let key = (category.to_string(), product_name.to_string());
-    {
-        let cache = PRICE_CACHE.lock().unwrap();
-        if let Some(&price) = cache.get(&key) {
-            return price;
-        }
+fn get_product_price(cache: &mut PriceCache, category: &CatalogCategory, product_name: &str) -> f64 {
+    if let Some(&price) = cache.get(&(category.name.clone(), product_name.to_string())) {
+        return price;
     }
-    let raw_price = compute_price(category, product_name);
+    let raw_price = compute_price(category.price_range(), product_name);
     let final_price = round_price(raw_price);
-    let mut cache = PRICE_CACHE.lock().unwrap();
-    cache.insert(key, final_price);
+    cache.insert((category.name.clone(), product_name.to_string()), final_price);
     final_price
 }
 
-/// Generate a product using the product hierarchy.
+/// Price every catalog product up front, so no message pays a cache miss.
+/// Returns the number of products priced.
+fn precompute_prices(cache: &mut PriceCache, catalog: &Catalog) -> usize {
+    let mut priced = 0;
+    for category in &catalog.categories {
+        for product in category.subcategories.iter().flat_map(|s| &s.products) {
+            get_product_price(cache, category, &product.name);
+            priced += 1;
+        }
+    }
+    priced
+}
+
+/// Parse per-category revenue targets such as `Food=0.6,Clothing=0.2`. Shares are
+/// fractions of total revenue; categories left out split whatever share remains.
+pub fn parse_category_targets(spec: &str, catalog: &Catalog) -> Result<Vec<(String, f64)>, String> {
+    let targets = crate::weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(name, share)| {
+            catalog
+                .categories
+                .iter()
+                .find(|category| category.name.eq_ignore_ascii_case(&name))
+                .map(|category| (category.name.clone(), share))
+                .ok_or_else(|| {
+                    let known: Vec<&str> = catalog.categories.iter().map(|c| c.name.as_str()).collect();
+                    format!("unknown category '{}' (supported: {})", name, known.join(", "))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let total: f64 = targets.iter().map(|(_, share)| share).sum();
+    if total > 1.0 + 1e-9 {
+        return Err(format!("category shares add up to {}, more than 1", total));
+    }
+    Ok(targets)
+}
+
+/// Steers category selection so cumulative revenue converges on target shares.
+/// Each category is picked in proportion to its target share divided by its
+/// expected revenue per sale (so cheap categories are picked more often), scaled by
+/// how far its realised share lags or leads the target.
+#[derive(Debug, Clone)]
+struct RevenueShaper {
+    /// Target revenue share of each catalog category.
+    targets: Vec<f64>,
+    /// Mean unit price of each category, drawing subcategory and product by popularity.
+    expected: Vec<f64>,
+    /// Revenue realised so far per category.
+    revenue: Vec<f64>,
+}
+
+impl RevenueShaper {
+    /// Bounds on the feedback factor, so one lagging category cannot starve the rest.
+    const MAX_NUDGE: f64 = 5.0;
+
+    fn new(
+        catalog: &Catalog,
+        sampler: &CatalogSampler,
+        targets: &[(String, f64)],
+        prices: &mut PriceCache,
+    ) -> Self {
+        let listed: f64 = targets.iter().map(|(_, share)| share).sum();
+        let unlisted = catalog.categories.len() - targets.len();
+        let remainder = if unlisted > 0 { (1.0 - listed).max(0.0) / unlisted as f64 } else { 0.0 };
+        let targets = catalog
+            .categories
+            .iter()
+            .map(|category| {
+                targets
+                    .iter()
+                    .find(|(c, _)| *c == category.name)
+                    .map_or(remainder, |(_, share)| *share)
+            })
+            .collect();
+        let expected = catalog
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(c, category)| {
+                let subcategories = &sampler.subcategories[c];
+                category
+                    .subcategories
+                    .iter()
+                    .enumerate()
+                    .map(|(s, subcategory)| {
+                        let products = &sampler.products[c][s];
+                        let mean: f64 = subcategory
+                            .products
+                            .iter()
+                            .enumerate()
+                            .map(|(p, product)| {
+                                CatalogSampler::share(products, p)
+                                    * get_product_price(prices, category, &product.name)
+                            })
+                            .sum();
+                        CatalogSampler::share(subcategories, s) * mean
+                    })
+                    .sum()
+            })
+            .collect();
+        RevenueShaper {
+            targets,
+            expected,
+            revenue: vec![0.0; catalog.categories.len()],
+        }
+    }
+
+    /// Selection weight of each category for a store of the given format.
+    fn weights(&self, catalog: &Catalog, format: StoreFormat) -> Vec<f64> {
+        let total: f64 = self.revenue.iter().sum();
+        catalog
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(i, category)| {
+                let target = self.targets[i];
+                if !format.stocks(&category.name) || target <= 0.0 {
+                    return 0.0;
+                }
+                let nudge = if total > 0.0 {
+                    (target * total / self.revenue[i]).clamp(1.0 / Self::MAX_NUDGE, Self::MAX_NUDGE)
+                } else {
+                    1.0
+                };
+                target / self.expected[i] * nudge
+            })
+            .collect()
+    }
+
+    fn record(&mut self, catalog: &Catalog, category: &str, revenue: f64) {
+        if let Some(i) = catalog.position(category) {
+            self.revenue[i] += revenue;
+        }
+    }
+}
+
+/// Pick the basket's next product from the catalog, limited to the categories the store
+/// format stocks (or any category, if the format stocks none of the catalog's). A line
+/// may follow an affinity with one of the basket's `picked` products; otherwise, with a
+/// revenue shaper the category is drawn from its weights, or else by popularity, as are
+/// the subcategory and product.
 /// The unit price is computed deterministically and then rounded, using the cache.
-fn generate_product() -> Product {
-    let mut rng = rand::rng();
-    let (category, subcategories) = PRODUCT_HIERARCHY[rng.random_range(0..PRODUCT_HIERARCHY.len())];
-    let (subcategory, products) = subcategories[rng.random_range(0..subcategories.len())];
-    let product_name = products[rng.random_range(0..products.len())];
-    let unit_price = get_product_price(category, product_name);
+fn generate_product<R: Rng + ?Sized>(
+    rng: &mut R,
+    prices: &mut PriceCache,
+    catalog: &Catalog,
+    sampler: &CatalogSampler,
+    format: StoreFormat,
+    shaper: Option<&RevenueShaper>,
+    picked: &[ProductRef],
+) -> (ProductRef, Product) {
+    let picks = sampler.follow_affinity(rng, catalog, format, picked).unwrap_or_else(|| {
+        let c = shaper
+            .and_then(|shaper| WeightedIndex::new(shaper.weights(catalog, format)).ok())
+            .map(|index| index.sample(rng))
+            .unwrap_or_else(|| sampler.categories[format as usize].sample(rng));
+        let s = sampler.subcategories[c].sample(rng);
+        (c, s, sampler.products[c][s].sample(rng))
+    });
+    (picks, catalog_product(prices, catalog, picks))
+}
+
+/// The catalog product at `picks`, at its (dollar) catalog price.
+fn catalog_product(prices: &mut PriceCache, catalog: &Catalog, (c, s, p): ProductRef) -> Product {
+    let category = &catalog.categories[c];
+    let subcategory = &category.subcategories[s];
+    let product_name = &subcategory.products[p].name;
+    let unit_price = get_product_price(prices, category, product_name);
+    let (brand, supplier_id) = get_product_brand(&category.name, product_name);
     Product {
-        product_name: product_name.to_string(),
-        category: category.to_string(),
-        subcategory: subcategory.to_string(),
+        product_name: product_name.clone(),
+        category: category.name.clone(),
+        subcategory: subcategory.name.clone(),
+        brand: brand.to_string(),
+        supplier_id: supplier_id.to_string(),
         unit_price,
     }
 }
 
-/// Generate store details using fake data, limited to America.
-fn generate_store() -> Store {
-    let town: String = CityName().fake();
-    let state: String = StateAbbr().fake();
-    let country = "USA".to_string();
+/// Generate store details. With a region, the store is placed around one of the
+/// region's towns; otherwise its address comes from the given country (or, without
+/// one, from fake data limited to America).
+fn generate_store<R: Rng + ?Sized>(
+    rng: &mut R,
+    store_format: StoreFormat,
+    region: Option<Region>,
+    country: Option<&'static Country>,
+) -> Store {
+    if let Some(region) = region {
+        let (town, lat, lon) = region.locate(rng);
+        return Store {
+            store_id: None,
+            town: town.name.to_string(),
+            state: town.state.to_string(),
+            country: town.country.to_string(),
+            store_format,
+            currency: None,
+            latitude: Some(lat),
+            longitude: Some(lon),
+            clock_skew_ms: 0,
+        };
+    }
+    let (town, state) = country.unwrap_or(&COUNTRIES[0]).address(rng);
     Store {
+        store_id: None,
         town,
         state,
-        country,
+        country: country.map_or("USA", |c| c.name).to_string(),
+        store_format,
+        currency: country.map(|c| c.currency),
+        latitude: None,
+        longitude: None,
+        clock_skew_ms: 0,
+    }
+}
+
+/// Relative frequency of household sizes 1 to 6, roughly following census data.
+const HOUSEHOLD_SIZE_WEIGHTS: [f64; 6] = [0.28, 0.35, 0.15, 0.13, 0.06, 0.03];
+
+/// Occupations drawn for rich demographics.
+static OCCUPATIONS: &[&str] = &[
+    "Student",
+    "Retired",
+    "Teacher",
+    "Nurse",
+    "Engineer",
+    "Retail Worker",
+    "Office Administrator",
+    "Tradesperson",
+    "Driver",
+    "Manager",
+    "Self-Employed",
+    "Healthcare Professional",
+    "Unemployed",
+];
+
+/// Customer demographics model.
+#[derive(Debug, Clone, Default)]
+struct Demographics {
+    /// Custom income band labels and their sampler; `None` draws Low/Medium/High uniformly.
+    income_bands: Option<(Vec<String>, WeightedIndex<f64>)>,
+    /// Household size sampler, set when rich demographics are enabled.
+    household_sizes: Option<WeightedIndex<f64>>,
+}
+
+impl Demographics {
+    fn new(income_bands: &[(String, f64)], rich: bool) -> Self {
+        Demographics {
+            income_bands: (!income_bands.is_empty()).then(|| {
+                let labels = income_bands.iter().map(|(label, _)| label.clone()).collect();
+                let index = WeightedIndex::new(income_bands.iter().map(|(_, weight)| *weight))
+                    .expect("income band weights must contain a positive weight");
+                (labels, index)
+            }),
+            household_sizes: rich.then(|| {
+                WeightedIndex::new(HOUSEHOLD_SIZE_WEIGHTS).expect("household size weights are valid")
+            }),
+        }
     }
 }
 
 /// Generate customer demographic data using fake data.
-fn generate_customer() -> Customer {
-    let mut rng = rand::rng();
+fn generate_customer<R: Rng + ?Sized>(rng: &mut R, demographics: &Demographics) -> Customer {
     let age = rng.random_range(18..80);
-    let income_bands = ["Low", "Medium", "High"];
-    let income_band = income_bands[rng.random_range(0..income_bands.len())].to_string();
+    let income_band = match &demographics.income_bands {
+        Some((bands, index)) => bands[index.sample(rng)].clone(),
+        None => {
+            let income_bands = ["Low", "Medium", "High"];
+            income_bands[rng.random_range(0..income_bands.len())].to_string()
+        }
+    };
+    let (household_size, occupation) = match &demographics.household_sizes {
+        Some(sizes) => (
+            Some(sizes.sample(rng) as u8 + 1),
+            Some(OCCUPATIONS[rng.random_range(0..OCCUPATIONS.len())]),
+        ),
+        None => (None, None),
+    };
     Customer {
+        customer_id: None,
         age,
         income_band,
+        home_currency: None,
+        household_size,
+        occupation,
     }
 }
 
-/// Generate a sale message for a single product sale.
+/// Generate a sale message for the next item of a basket, priced in the basket's
+/// currency, remembering its product when affinities are followed. Returns the catalog
+/// entry sold along with the message.
 /// Each sale message includes details of the store, customer, and product sold.
-fn generate_sale_message(
-    transaction_id: &str,
-    basket_id: &str,
-    store: &Store,
-    customer: &Customer,
-) -> SaleMessage {
-    let product = generate_product();
-    let mut rng = rand::rng();
+fn generate_sale_message<R: Rng + ?Sized>(
+    rng: &mut R,
+    prices: &mut PriceCache,
+    catalog: &Catalog,
+    sampler: &CatalogSampler,
+    basket: &mut Basket,
+    timestamp: u64,
+    shaper: Option<&RevenueShaper>,
+) -> (ProductRef, SaleMessage) {
+    let format = basket.store.store_format;
+    let (picked, mut product) =
+        generate_product(rng, prices, catalog, sampler, format, shaper, &basket.picked);
+    if !sampler.affinities.is_empty() {
+        basket.picked.push(picked);
+    }
+    product.unit_price = basket.pricing.convert(product.unit_price);
     let quantity = rng.random_range(1..5); // Quantity between 1 and 4.
-    let total_price = product.unit_price * quantity as f64;
-    let timestamp: u64 = Utc::now().timestamp() as u64;
-    SaleMessage {
-        transaction_id: transaction_id.to_string(),
-        basket_id: basket_id.to_string(),
+    let total_price = basket.pricing.round(product.unit_price * quantity as f64);
+    let sale = SaleMessage {
+        event_type: None,
+        transaction_type: None,
+        transaction_id: basket.transaction_id.clone(),
+        original_transaction_id: None,
+        basket_id: basket.basket_id.clone(),
+        seq: basket.seq,
         timestamp,
-        store: store.clone(),
-        customer: customer.clone(),
+        store: basket.store.clone(),
+        customer: basket.customer.clone(),
+        payment_method: basket.payment_method,
+        channel: basket.channel,
         product,
         quantity,
         total_price,
+        original_price: None,
+        discount_rate: None,
+        tax_rate: None,
+        tax_amount: None,
+        currency: None,
+        amount: None,
+        exchange_rate: None,
+        lane_id: basket.checkout.as_ref().map(|checkout| checkout.lane_id),
+        cashier_id: basket.checkout.as_ref().map(|checkout| checkout.cashier_id.clone()),
+        transaction_duration_seconds: basket.checkout.as_ref().map(|checkout| checkout.duration_secs),
+    };
+    (picked, sale)
+}
+
+/// Range of promotional discounts, as fractions of the unit price.
+const DISCOUNT_RANGE: RangeInclusive<f64> = 0.10..=0.30;
+
+/// Put `sale` on promotion with probability `rate`, reusing the discount the basket
+/// already has for the product, and reprice its total. Lines not on promotion report a
+/// discount rate of 0, so every line carries both fields.
+fn apply_promotion<R: Rng + ?Sized>(rng: &mut R, basket: &mut Basket, sale: &mut SaleMessage, rate: f64) {
+    let unit_price = sale.product.unit_price;
+    let discount = *basket
+        .discounts
+        .entry(sale.product.product_name.clone())
+        .or_insert_with(|| {
+            if rng.random_bool(rate) {
+                (rng.random_range(DISCOUNT_RANGE) * 100.0).round() / 100.0
+            } else {
+                0.0
+            }
+        });
+    sale.original_price = Some(unit_price);
+    sale.discount_rate = Some(discount);
+    sale.total_price = basket.pricing.round(unit_price * (1.0 - discount) * sale.quantity as f64);
+}
+
+/// Number of recent sale lines kept for returns to draw from.
+const RECENT_SALES: usize = 256;
+
+/// A sale line that a later return can refund.
+#[derive(Debug)]
+struct RecentSale {
+    transaction_id: String,
+    product: ProductRef,
+}
+
+/// Turn `sale` into a return of one of `recent_sales` (which must not be empty), with
+/// negative quantity and total, referencing the transaction it was sold in. The product
+/// is refunded at its current catalog price, in `pricing`'s currency.
+fn make_return<R: Rng + ?Sized>(
+    rng: &mut R,
+    prices: &mut PriceCache,
+    catalog: &Catalog,
+    recent_sales: &VecDeque<RecentSale>,
+    pricing: Pricing,
+    sale: &mut SaleMessage,
+) {
+    let original = &recent_sales[rng.random_range(0..recent_sales.len())];
+    sale.product = catalog_product(prices, catalog, original.product);
+    sale.product.unit_price = pricing.convert(sale.product.unit_price);
+    sale.original_transaction_id = Some(original.transaction_id.clone());
+    sale.transaction_type = Some("return");
+    sale.quantity = -sale.quantity;
+    sale.total_price = pricing.round(sale.product.unit_price * sale.quantity as f64);
+}
+
+/// Tax rates of the states or provinces of a country.
+type StateTaxRates = &'static [(&'static str, f64)];
+
+/// Sales tax rate of each country, with the rates of states or provinces that differ from
+/// it. US rates are state rates, without local add-ons; elsewhere it is the standard VAT
+/// or GST rate.
+static TAX_RATES: &[(&str, f64, StateTaxRates)] = &[
+    (
+        "USA",
+        0.0,
+        &[
+            ("AL", 0.04), ("AZ", 0.056), ("AR", 0.065), ("CA", 0.0725), ("CO", 0.029),
+            ("CT", 0.0635), ("DC", 0.06), ("FL", 0.06), ("GA", 0.04), ("HI", 0.04),
+            ("ID", 0.06), ("IL", 0.0625), ("IN", 0.07), ("IA", 0.06), ("KS", 0.065),
+            ("KY", 0.06), ("LA", 0.0445), ("ME", 0.055), ("MD", 0.06), ("MA", 0.0625),
+            ("MI", 0.06), ("MN", 0.06875), ("MS", 0.07), ("MO", 0.04225), ("NE", 0.055),
+            ("NV", 0.0685), ("NJ", 0.06625), ("NM", 0.04875), ("NY", 0.04), ("NC", 0.0475),
+            ("ND", 0.05), ("OH", 0.0575), ("OK", 0.045), ("PA", 0.06), ("RI", 0.07),
+            ("SC", 0.06), ("SD", 0.042), ("TN", 0.07), ("TX", 0.0625), ("UT", 0.061),
+            ("VT", 0.06), ("VA", 0.053), ("WA", 0.065), ("WV", 0.06), ("WI", 0.05),
+            ("WY", 0.04),
+        ],
+    ),
+    ("Canada", 0.05, &[("ON", 0.13), ("QC", 0.14975), ("BC", 0.12)]),
+    ("UK", 0.20, &[]),
+    ("Ireland", 0.23, &[]),
+    ("Germany", 0.19, &[]),
+    ("France", 0.20, &[]),
+    ("Netherlands", 0.21, &[]),
+    ("Switzerland", 0.081, &[]),
+    ("Japan", 0.10, &[]),
+    ("Australia", 0.10, &[]),
+    ("Singapore", 0.09, &[]),
+    ("Malaysia", 0.08, &[]),
+    ("Indonesia", 0.11, &[]),
+    ("Thailand", 0.07, &[]),
+    ("Vietnam", 0.10, &[]),
+    ("Philippines", 0.12, &[]),
+];
+
+/// Sales tax rate at a store in `country` and `state`; 0 where the table has none.
+fn tax_rate(country: &str, state: &str) -> f64 {
+    TAX_RATES
+        .iter()
+        .find(|(c, _, _)| *c == country)
+        .map_or(0.0, |(_, rate, states)| {
+            states.iter().find(|(s, _)| *s == state).map_or(*rate, |(_, rate)| *rate)
+        })
+}
+
+/// Whether sale prices include sales tax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaxBasis {
+    /// Prices are net, and tax is charged on top of `total_price`.
+    Exclusive,
+    /// Prices are gross, as on shelves in VAT countries, and `total_price` includes the tax.
+    Inclusive,
+}
+
+impl TaxBasis {
+    /// Tax on a line totalling `total` at `rate`, rounded to the minor unit of `currency`.
+    fn tax(self, total: f64, rate: f64, currency: &str) -> f64 {
+        let tax = match self {
+            TaxBasis::Exclusive => total * rate,
+            TaxBasis::Inclusive => total * rate / (1.0 + rate),
+        };
+        currency::round_to_minor_unit(tax, currency)
+    }
+}
+
+/// How long (simulated seconds) a product stays out of stock at a store once it runs out.
+const STOCKOUT_DURATION_SECS: u64 = 600;
+
+/// Whether `product` is out of stock at `store` at time `now`. A product already out of
+/// stock stays so until its restock time; otherwise it runs out with probability `rate`.
+fn check_stockout<R: Rng + ?Sized>(
+    stockouts: &mut HashMap<(String, String), u64>,
+    rng: &mut R,
+    rate: f64,
+    store: &Store,
+    product: &str,
+    now: u64,
+) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    let key = (format!("{}|{}", store.town, store.state), product.to_string());
+    if stockouts.get(&key).is_some_and(|&restock_at| now < restock_at) {
+        return true;
+    }
+    if !rng.random_bool(rate) {
+        stockouts.remove(&key);
+        return false;
+    }
+    // Drop restocked entries now and then so the table does not grow without bound.
+    if stockouts.len() >= 4096 {
+        stockouts.retain(|_, restock_at| now < *restock_at);
+    }
+    stockouts.insert(key, now + STOCKOUT_DURATION_SECS);
+    true
+}
+
+/// The currency a basket's prices are quoted in, with its units per US dollar, the
+/// catalog's currency.
+#[derive(Debug, Clone, Copy)]
+struct Pricing {
+    currency: &'static str,
+    per_usd: f64,
+}
+
+impl Pricing {
+    /// The catalog's own dollar prices.
+    const USD: Pricing = Pricing {
+        currency: "USD",
+        per_usd: 1.0,
+    };
+
+    /// A catalog (dollar) amount in this currency, rounded to its minor unit.
+    fn convert(self, usd: f64) -> f64 {
+        self.round(usd * self.per_usd)
+    }
+
+    /// Round an amount in this currency to its minor unit.
+    fn round(self, amount: f64) -> f64 {
+        currency::round_to_minor_unit(amount, self.currency)
     }
 }
 
@@ -272,72 +1461,847 @@ struct Basket {
     basket_id: String,
     store: Store,
     customer: Customer,
+    payment_method: &'static str,
+    channel: &'static str,
     total_items: usize,
     items_generated: usize,
+    /// If set, the basket is abandoned once this many items have been generated.
+    abandon_after: Option<usize>,
+    /// Number of messages emitted for this basket so far.
+    seq: u64,
+    /// Home and transaction currency indexes into the FX currency set, if configured.
+    currency: Option<(usize, usize)>,
+    /// Currency the basket's prices are quoted in.
+    pricing: Pricing,
+    /// Checkout details, if lanes are configured.
+    checkout: Option<Checkout>,
+    /// Total price of the items sold so far.
+    subtotal: f64,
+    /// Whether a basket summary follows the basket's last item.
+    summary_due: bool,
+    /// Whether a coupon redemption follows the basket's last item (and its summary).
+    coupon_due: bool,
+    /// Discount drawn for each product the basket has bought, so repeat lines of a
+    /// product get the same one (promotion runs only).
+    discounts: HashMap<String, f64>,
+    /// Products picked for the basket so far, which later lines' affinities follow
+    /// (affinity runs only).
+    picked: Vec<ProductRef>,
 }
 
-/// SalesGenerator is our generator for FluxFakr. It produces one sale message per call.
-/// When a basket is exhausted, it automatically creates a new basket.
-pub struct SalesGenerator {
-    current_basket: Option<Basket>,
+/// Weighted payment methods of in-store baskets; online baskets can't pay cash.
+const IN_STORE_PAYMENT_METHODS: &[(&str, f64)] = &[("card", 0.55), ("cash", 0.25), ("mobile", 0.2)];
+const ONLINE_PAYMENT_METHODS: &[(&str, f64)] = &[("card", 0.8), ("mobile", 0.2)];
+
+/// Draw a basket's channel, online with probability `online_rate`, and a payment
+/// method suited to it.
+fn draw_payment<R: Rng + ?Sized>(rng: &mut R, online_rate: f64) -> (&'static str, &'static str) {
+    let (channel, methods) = if rng.random_bool(online_rate) {
+        ("online", ONLINE_PAYMENT_METHODS)
+    } else {
+        ("in_store", IN_STORE_PAYMENT_METHODS)
+    };
+    let mut draw = rng.random_range(0.0..methods.iter().map(|(_, weight)| weight).sum::<f64>());
+    let method = methods
+        .iter()
+        .find(|(_, weight)| {
+            draw -= weight;
+            draw < 0.0
+        })
+        .map_or(methods[0].0, |(method, _)| method);
+    (method, channel)
 }
 
-impl SalesGenerator {
-    pub fn new() -> Self {
-        SalesGenerator {
-            current_basket: None,
-        }
+/// Partition key of a message about `basket` (see `SalesKey`).
+fn basket_key(key_by: SalesKey, basket: &Basket) -> String {
+    match key_by {
+        SalesKey::Basket => basket.basket_id.clone(),
+        // Without a pool, stores have no id; their address identifies them instead.
+        SalesKey::Store => basket.store.store_id.clone().unwrap_or_else(|| {
+            format!("{}|{}|{}", basket.store.country, basket.store.state, basket.store.town)
+        }),
     }
+}
 
-    /// Initialize a new basket with the given number of items.
-    pub fn init_basket(&mut self, basket_size: u32) {
-        let mut rng = rand::rng();
-        let transaction_id = format!("TXN-{:08}", rng.random_range(0..100000000));
-        let basket_id = format!("BASKET-{:04}", rng.random_range(0..10000));
-        let store = generate_store();
-        let customer = generate_customer();
-        self.current_basket = Some(Basket {
+/// How a basket was checked out.
+#[derive(Debug, Clone)]
+struct Checkout {
+    lane_id: u32,
+    cashier_id: String,
+    duration_secs: u32,
+}
+
+/// Cashiers on each store's payroll per checkout lane, covering shifts.
+const CASHIERS_PER_LANE: u32 = 2;
+/// Most checkout lanes a store may have; well beyond any real store.
+pub const MAX_LANES_PER_STORE: u32 = 1_000;
+
+/// Draw a checkout at one of `lanes` lanes of `store`. Cashier ids are prefixed with the
+/// store id when the store has one, so each store has its own cashier pool. The
+/// duration is a fixed overhead plus a few seconds per item, with log-normal noise.
+fn generate_checkout<R: Rng + ?Sized>(rng: &mut R, store: &Store, lanes: u32, items: u32) -> Checkout {
+    let lane_id = rng.random_range(1..=lanes);
+    let cashier = rng.random_range(1..=lanes.saturating_mul(CASHIERS_PER_LANE));
+    let cashier_id = match &store.store_id {
+        Some(store_id) => format!("{}-C{:03}", store_id, cashier),
+        None => format!("C{:03}", cashier),
+    };
+    let noise: f64 = LogNormal::new(0.0, 0.25).expect("valid lognormal").sample(rng);
+    let duration_secs = ((30.0 + 4.0 * items as f64) * noise).round() as u32;
+    Checkout {
+        lane_id,
+        cashier_id,
+        duration_secs,
+    }
+}
+
+/// Tunable parameters for the supermarket sales generator.
+#[derive(Debug, Clone)]
+pub struct SalesConfig {
+    /// Probability (0.0–1.0) that a basket is abandoned before completion.
+    pub abandon_rate: f64,
+    /// Relative weights of the store formats baskets are drawn from.
+    pub store_formats: Vec<(StoreFormat, f64)>,
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Source of message timestamps.
+    pub clock: Clock,
+    /// Mean idle gap between baskets in seconds (exponentially distributed); 0 disables gaps.
+    pub inter_basket_gap: f64,
+    /// Region preset stores are located in; `None` scatters them across America.
+    pub region: Option<Region>,
+    /// Relative weights of the countries stores are located in; empty keeps every store in
+    /// the USA. Ignored when a region is set.
+    pub countries: Vec<(&'static Country, f64)>,
+    /// Probability (0.0–1.0) that a selected product runs out of stock at the store.
+    pub stockout_rate: f64,
+    /// Currency set and FX model; `None` emits no currency fields.
+    pub fx: Option<FxRates>,
+    /// Probability (0.0–1.0) that a basket is paid in a currency other than the home currency.
+    pub foreign_txn_rate: f64,
+    /// Products sales are drawn from.
+    pub catalog: Catalog,
+    /// Popularity of catalog entries that carry no weight of their own.
+    pub popularity: Popularity,
+    /// Bias each basket toward the co-purchase partners of the products already in it,
+    /// following the catalog's affinities.
+    pub affinity: bool,
+    /// Target revenue share per catalog category; empty picks categories uniformly.
+    pub category_targets: Vec<(String, f64)>,
+    /// Number of stores generated up front and revisited by baskets; `None` opens a
+    /// new store for every basket.
+    pub store_pool: Option<usize>,
+    /// Number of repeat customers generated up front and revisiting; `None` brings a new
+    /// customer with every basket.
+    pub customer_pool: Option<usize>,
+    /// Entity messages are keyed by.
+    pub key_by: SalesKey,
+    /// Weighted custom income band labels; empty keeps Low/Medium/High, equally likely.
+    pub income_bands: Vec<(String, f64)>,
+    /// Add household size and occupation to customers.
+    pub rich_demographics: bool,
+    /// Distribution of items per basket.
+    pub basket_size_dist: BasketSizeDistribution,
+    /// Mean basket size for the skewed distributions; `None` uses each format's typical size.
+    pub basket_size_mean: Option<f64>,
+    /// Negative-binomial dispersion (shape); smaller values give a heavier tail.
+    pub basket_size_dispersion: f64,
+    /// Checkout lanes per store; `None` emits no lane, cashier or duration fields.
+    pub lanes_per_store: Option<u32>,
+    /// Largest clock offset (ms) a store may be given; each store draws a fixed offset
+    /// from `-max..=max`. 0 keeps every store on the shared clock.
+    pub max_clock_skew_ms: u64,
+    /// Fill the price cache for the whole catalog when the generator is created.
+    pub precompute_prices: bool,
+    /// Probability (0.0–1.0) that a completed basket is followed by a coupon redemption.
+    pub coupon_rate: f64,
+    /// Follow each completed basket with a `basket_summary` event.
+    pub basket_summary: bool,
+    /// Vary basket sizes and, with inter-basket gaps, basket frequency by the hour and
+    /// weekday of the clock. Off here; the CLI turns it on unless `--no-seasonality`.
+    pub seasonality: bool,
+    /// Probability (0.0–1.0) that a product is on promotion in a basket, at a 10–30% discount.
+    pub promo_rate: f64,
+    /// Probability (0.0–1.0) that a sale line is a return of a recently sold product.
+    pub return_rate: f64,
+    /// Probability (0.0–1.0) that a basket is bought online rather than in store.
+    pub online_rate: f64,
+    /// Add `tax_rate`, `tax_amount` and `currency` to sales, with prices on this basis;
+    /// `None` leaves tax out.
+    pub tax: Option<TaxBasis>,
+}
+
+impl Default for SalesConfig {
+    fn default() -> Self {
+        SalesConfig {
+            abandon_rate: 0.0,
+            store_formats: vec![(StoreFormat::Standard, 1.0)],
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            clock: Clock::System,
+            inter_basket_gap: 0.0,
+            region: None,
+            countries: Vec::new(),
+            stockout_rate: 0.0,
+            fx: None,
+            foreign_txn_rate: 0.1,
+            catalog: Catalog::builtin(),
+            popularity: Popularity::Uniform,
+            affinity: false,
+            category_targets: Vec::new(),
+            store_pool: None,
+            customer_pool: None,
+            key_by: SalesKey::Basket,
+            income_bands: Vec::new(),
+            rich_demographics: false,
+            basket_size_dist: BasketSizeDistribution::Uniform,
+            basket_size_mean: None,
+            basket_size_dispersion: 2.0,
+            lanes_per_store: None,
+            max_clock_skew_ms: 0,
+            precompute_prices: false,
+            coupon_rate: 0.0,
+            basket_summary: false,
+            seasonality: false,
+            promo_rate: 0.0,
+            return_rate: 0.0,
+            online_rate: 0.1,
+            tax: None,
+        }
+    }
+}
+
+/// Size of the transaction and basket id spaces: ids are unique for this many baskets.
+const ID_SPACE: u64 = 100_000_000;
+
+/// Maps a generator's `n`th basket to an id below `ID_SPACE`. The map is affine modulo
+/// `ID_SPACE` with a multiplier coprime to it, so it is a bijection: ids never repeat
+/// within `ID_SPACE` baskets, yet the offset taken from the seed keeps them looking random.
+fn basket_number(n: u64, multiplier: u64, seed: u64) -> u64 {
+    ((n % ID_SPACE) * multiplier + seed % ID_SPACE) % ID_SPACE
+}
+
+/// SalesGenerator is our generator for FluxFakr. It produces one sale message per call.
+/// When a basket is exhausted, it automatically creates a new basket.
+pub struct SalesGenerator {
+    current_basket: Option<Basket>,
+    config: SalesConfig,
+    rng: SimRng,
+    store_format_index: WeightedIndex<f64>,
+    /// Sampler over `config.countries`, if a country mix is configured.
+    country_index: Option<WeightedIndex<f64>>,
+    /// Partition key of the last emitted message (see `SalesKey`).
+    last_key: Option<String>,
+    /// Stores that baskets are drawn from, if a pool is configured.
+    store_pool: Vec<Store>,
+    /// Customers that baskets are drawn from, if a pool is configured.
+    customer_pool: Vec<Customer>,
+    /// Customer demographics model.
+    demographics: Demographics,
+    /// Source of message timestamps.
+    clock: Clock,
+    /// Last clock reading (ms), so timestamps never run backwards.
+    last_timestamp_ms: u64,
+    /// Time (ms) before which no new basket starts, while the store is idle between baskets.
+    next_basket_at_ms: Option<u64>,
+    /// Restock time (seconds) of each out-of-stock (store, product) pair.
+    stockouts: HashMap<(String, String), u64>,
+    /// Baskets started so far, which transaction and basket ids are derived from.
+    baskets_started: u64,
+    /// Live FX rates, drifting from basket to basket.
+    fx: Option<FxRates>,
+    /// Weighted category, subcategory and product draws over the catalog.
+    sampler: CatalogSampler,
+    /// Category selection steering, if revenue targets are configured.
+    shaper: Option<RevenueShaper>,
+    /// Product prices computed so far by this generator.
+    prices: PriceCache,
+    /// Ring buffer of the last `RECENT_SALES` sale lines, which returns are drawn from
+    /// (return runs only).
+    recent_sales: VecDeque<RecentSale>,
+}
+
+impl SalesGenerator {
+    pub fn new(config: SalesConfig) -> Self {
+        let store_format_index =
+            WeightedIndex::new(config.store_formats.iter().map(|(_, weight)| *weight))
+                .expect("store format weights must contain a positive weight");
+        let country_index = (!config.countries.is_empty()).then(|| {
+            WeightedIndex::new(config.countries.iter().map(|(_, weight)| *weight))
+                .expect("country weights must contain a positive weight")
+        });
+        let sampler = CatalogSampler::new(&config.catalog, config.popularity, config.affinity);
+        let mut prices = PriceCache::new();
+        if config.precompute_prices {
+            precompute_prices(&mut prices, &config.catalog);
+        }
+        let mut generator = SalesGenerator {
+            current_basket: None,
+            rng: SimRng::new(config.rng, config.seed),
+            clock: config.clock.clone(),
+            last_timestamp_ms: 0,
+            next_basket_at_ms: None,
+            stockouts: HashMap::new(),
+            baskets_started: 0,
+            recent_sales: VecDeque::new(),
+            fx: config.fx.clone(),
+            demographics: Demographics::new(&config.income_bands, config.rich_demographics),
+            shaper: (!config.category_targets.is_empty()).then(|| {
+                RevenueShaper::new(&config.catalog, &sampler, &config.category_targets, &mut prices)
+            }),
+            sampler,
+            prices,
+            config,
+            store_format_index,
+            country_index,
+            last_key: None,
+            store_pool: Vec::new(),
+            customer_pool: Vec::new(),
+        };
+        for i in 0..generator.config.store_pool.unwrap_or(0) {
+            let format = generator.choose_store_format();
+            let mut store = generator.open_store(format);
+            store.store_id = Some(format!("STORE-{:04}", i + 1));
+            generator.store_pool.push(store);
+        }
+        for i in 0..generator.config.customer_pool.unwrap_or(0) {
+            let mut customer = generate_customer(&mut generator.rng, &generator.demographics);
+            customer.customer_id = Some(format!("CUST-{:05}", i + 1));
+            generator.customer_pool.push(customer);
+        }
+        generator
+    }
+
+    /// Current time in milliseconds, clamped so it never runs backwards.
+    fn timestamp_ms(&mut self) -> u64 {
+        self.last_timestamp_ms = self.last_timestamp_ms.max(self.clock.now_millis());
+        self.last_timestamp_ms
+    }
+
+    /// Whether the just-completed basket still has a coupon redemption to emit.
+    fn coupon_due(&self) -> bool {
+        self.current_basket.as_ref().is_some_and(|basket| basket.coupon_due)
+    }
+
+    /// Whether the just-completed basket still has its summary to emit.
+    fn summary_due(&self) -> bool {
+        self.current_basket.as_ref().is_some_and(|basket| basket.summary_due)
+    }
+
+    /// Emit the summary due on the just-completed basket, if any. Like a coupon
+    /// redemption, it joins to the basket's sales and continues its `seq`.
+    fn summarize_basket(&mut self) -> Option<String> {
+        if !self.summary_due() {
+            return None;
+        }
+        let now_ms = self.timestamp_ms();
+        let basket = self.current_basket.as_mut()?;
+        basket.summary_due = false;
+        basket.seq += 1;
+        self.last_key = Some(basket_key(self.config.key_by, basket));
+        let event = BasketSummaryEvent {
+            event_type: "basket_summary",
+            transaction_id: basket.transaction_id.clone(),
+            basket_id: basket.basket_id.clone(),
+            seq: basket.seq,
+            timestamp: clock::skewed(now_ms, basket.store.clock_skew_ms) / 1000,
+            store: basket.store.clone(),
+            customer: basket.customer.clone(),
+            payment_method: basket.payment_method,
+            channel: basket.channel,
+            item_count: basket.items_generated,
+            basket_total: basket.pricing.round(basket.subtotal),
+        };
+        Some(serde_json::to_string(&event).unwrap_or_else(|_| "{}".to_string()))
+    }
+
+    /// Emit the coupon redemption due on the just-completed basket, if any. It joins
+    /// to the basket's sales by `transaction_id` and `basket_id`, and continues its `seq`.
+    fn redeem_coupon(&mut self) -> Option<String> {
+        if !self.coupon_due() {
+            return None;
+        }
+        let now_ms = self.timestamp_ms();
+        let basket = self.current_basket.as_mut()?;
+        basket.coupon_due = false;
+        basket.seq += 1;
+        let (coupon_code, discount) = COUPONS[self.rng.random_range(0..COUPONS.len())];
+        let amount = match discount {
+            Discount::Percent(fraction) => basket.subtotal * fraction,
+            Discount::Fixed(amount) => basket.pricing.convert(amount).min(basket.subtotal),
+        };
+        self.last_key = Some(basket_key(self.config.key_by, basket));
+        let event = CouponRedeemedEvent {
+            event_type: "coupon_redeemed",
+            transaction_id: basket.transaction_id.clone(),
+            basket_id: basket.basket_id.clone(),
+            seq: basket.seq,
+            timestamp: clock::skewed(now_ms, basket.store.clock_skew_ms) / 1000,
+            coupon_code,
+            discount_amount: basket.pricing.round(amount).max(basket.pricing.convert(0.01)),
+        };
+        Some(serde_json::to_string(&event).unwrap_or_else(|_| "{}".to_string()))
+    }
+
+    /// Whether a new basket is needed before the next message.
+    fn needs_new_basket(&self) -> bool {
+        self.current_basket
+            .as_ref()
+            .is_none_or(|basket| basket.items_generated >= basket.total_items)
+    }
+
+    /// Schedule an idle gap after a basket finishes, if gaps are configured.
+    fn schedule_gap(&mut self) {
+        if self.config.inter_basket_gap > 0.0 {
+            let mut gap = Exp::new(1.0 / self.config.inter_basket_gap)
+                .expect("inter-basket gap must be positive")
+                .sample(&mut self.rng);
+            let now_ms = self.clock.now_millis();
+            if self.config.seasonality {
+                // Busier times mean shorter gaps between baskets.
+                gap /= season(now_ms).traffic;
+            }
+            self.next_basket_at_ms = Some(now_ms + (gap * 1000.0) as u64);
+        }
+    }
+
+    /// Draw a store format according to the configured distribution.
+    fn choose_store_format(&mut self) -> StoreFormat {
+        self.config.store_formats[self.store_format_index.sample(&mut self.rng)].0
+    }
+
+    /// Generate a new store of the given format in the configured region or country mix.
+    fn open_store(&mut self, store_format: StoreFormat) -> Store {
+        let rng = &mut self.rng;
+        let country = self
+            .country_index
+            .as_ref()
+            .map(|index| self.config.countries[index.sample(rng)].0);
+        let mut store = generate_store(rng, store_format, self.config.region, country);
+        if self.config.max_clock_skew_ms > 0 {
+            store.clock_skew_ms = clock::draw_skew(rng, self.config.max_clock_skew_ms);
+        }
+        store
+    }
+
+    /// Initialize a new basket with the given number of items, at a new store of the given format.
+    pub fn init_basket(&mut self, store_format: StoreFormat, basket_size: u32) {
+        self.start_basket(None, store_format, basket_size);
+    }
+
+    /// Initialize a new basket at the given pool store, or at a new store of the given format.
+    fn start_basket(&mut self, pooled: Option<usize>, store_format: StoreFormat, basket_size: u32) {
+        let n = self.baskets_started;
+        self.baskets_started += 1;
+        let seed = self.config.seed;
+        let transaction_id = format!("TXN-{:08}", basket_number(n, 61_803_399, seed));
+        let basket_id = format!("BASKET-{:08}", basket_number(n, 41_421_357, seed >> 32));
+        let store = match pooled {
+            Some(i) => self.store_pool[i].clone(),
+            None => self.open_store(store_format),
+        };
+        let rng = &mut self.rng;
+        let mut customer = match self.customer_pool.len() {
+            0 => generate_customer(rng, &self.demographics),
+            n => self.customer_pool[rng.random_range(0..n)].clone(),
+        };
+        // An abandoned basket always stops partway, so it needs at least two items.
+        let abandon_after = (basket_size > 1 && rng.random_bool(self.config.abandon_rate))
+            .then(|| rng.random_range(1..basket_size as usize));
+        let currency = self.fx.as_mut().map(|fx| {
+            fx.step(rng);
+            let n = fx.currencies().len();
+            let home = rng.random_range(0..n);
+            customer.home_currency = Some(fx.currencies()[home]);
+            // Occasionally the basket is paid in some other currency of the set.
+            let txn = if n > 1 && rng.random_bool(self.config.foreign_txn_rate) {
+                let other = rng.random_range(0..n - 1);
+                if other >= home { other + 1 } else { other }
+            } else {
+                home
+            };
+            (home, txn)
+        });
+        // Prices are quoted in the customer's home currency, at the basket's rate, or
+        // else in the store's local currency at the table's reference rate.
+        let pricing = match (&self.fx, currency, store.currency) {
+            (Some(fx), Some((home, _)), _) => Pricing {
+                currency: fx.currencies()[home],
+                per_usd: fx.per_usd(home),
+            },
+            (_, _, Some(local)) => Pricing {
+                currency: local,
+                per_usd: currency::reference_rate(local),
+            },
+            _ => Pricing::USD,
+        };
+        let checkout = self
+            .config
+            .lanes_per_store
+            .map(|lanes| generate_checkout(rng, &store, lanes, basket_size));
+        let (payment_method, channel) = draw_payment(rng, self.config.online_rate);
+        self.current_basket = Some(Basket {
             transaction_id,
             basket_id,
             store,
             customer,
+            payment_method,
+            channel,
             total_items: basket_size as usize,
             items_generated: 0,
+            abandon_after,
+            seq: 0,
+            currency,
+            pricing,
+            checkout,
+            subtotal: 0.0,
+            summary_due: false,
+            coupon_due: false,
+            discounts: HashMap::new(),
+            picked: Vec::new(),
         });
     }
 }
 
 impl crate::Generator for SalesGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        // Between baskets the store is idle: emit nothing until the gap has elapsed.
+        // A basket summary or coupon redemption still goes out straight after its basket.
+        if self.needs_new_basket() && !self.summary_due() && !self.coupon_due() {
+            if let Some(at) = self.next_basket_at_ms {
+                if !self.clock.reach(at) {
+                    return None;
+                }
+            }
+        }
+        Some(self.generate())
+    }
+
     fn generate(&mut self) -> String {
+        if let Some(summary) = self.summarize_basket() {
+            return summary;
+        }
+        if let Some(coupon) = self.redeem_coupon() {
+            return coupon;
+        }
         // If there is no basket or if the current basket is exhausted, initialize a new basket.
-        if self.current_basket.is_none()
-            || self.current_basket.as_ref().unwrap().items_generated
-                >= self.current_basket.as_ref().unwrap().total_items
-        {
-            let mut rng = rand::rng();
-            let basket_size = rng.random_range(5..16);
-            self.init_basket(basket_size);
+        if self.needs_new_basket() {
+            if let Some(at) = self.next_basket_at_ms.take() {
+                self.clock.reach(at);
+            }
+            // A pooled store brings its own format and is revisited by later baskets.
+            let pooled = (!self.store_pool.is_empty())
+                .then(|| self.rng.random_range(0..self.store_pool.len()));
+            let format = match pooled {
+                Some(i) => self.store_pool[i].store_format,
+                None => self.choose_store_format(),
+            };
+            let mut basket_size = self.config.basket_size_dist.sample(
+                &mut self.rng,
+                format,
+                self.config.basket_size_mean,
+                self.config.basket_size_dispersion,
+            );
+            if self.config.seasonality {
+                let now_ms = self.clock.peek_millis().max(self.last_timestamp_ms);
+                let scaled = (basket_size as f64 * season(now_ms).basket_size).round() as u32;
+                basket_size = scaled.clamp(1, MAX_BASKET_SIZE);
+            }
+            match pooled {
+                Some(i) => self.start_basket(Some(i), format, basket_size),
+                None => self.init_basket(format, basket_size),
+            }
         }
 
+        let now_ms = self.timestamp_ms();
         if let Some(ref mut basket) = self.current_basket {
+            // Messages carry the store's own (possibly skewed) clock; stock tracking
+            // stays on the shared clock.
+            let timestamp = clock::skewed(now_ms, basket.store.clock_skew_ms) / 1000;
+            self.last_key = Some(basket_key(self.config.key_by, basket));
+            basket.seq += 1;
+            if basket.abandon_after == Some(basket.items_generated) {
+                let basket = self.current_basket.take().unwrap();
+                let event = BasketAbandonedEvent {
+                    event_type: "basket_abandoned",
+                    transaction_id: basket.transaction_id,
+                    basket_id: basket.basket_id,
+                    seq: basket.seq,
+                    timestamp,
+                    store: basket.store,
+                    customer: basket.customer,
+                    items_generated: basket.items_generated,
+                    total_items: basket.total_items,
+                };
+                self.schedule_gap();
+                return serde_json::to_string(&event).unwrap_or_else(|_| "{}".to_string());
+            }
             basket.items_generated += 1;
-            let sale = generate_sale_message(
-                &basket.transaction_id,
-                &basket.basket_id,
-                &basket.store,
-                &basket.customer,
+            let (picked, mut sale) = generate_sale_message(
+                &mut self.rng,
+                &mut self.prices,
+                &self.config.catalog,
+                &self.sampler,
+                basket,
+                timestamp,
+                self.shaper.as_ref(),
             );
+            if check_stockout(
+                &mut self.stockouts,
+                &mut self.rng,
+                self.config.stockout_rate,
+                &basket.store,
+                &sale.product.product_name,
+                now_ms / 1000,
+            ) {
+                sale.event_type = Some("stockout");
+                sale.quantity = 0;
+                sale.total_price = 0.0;
+            } else if !self.recent_sales.is_empty() && self.rng.random_bool(self.config.return_rate) {
+                make_return(
+                    &mut self.rng,
+                    &mut self.prices,
+                    &self.config.catalog,
+                    &self.recent_sales,
+                    basket.pricing,
+                    &mut sale,
+                );
+                // Returning one product doesn't draw the customer to its partners.
+                basket.picked.pop();
+                if self.config.promo_rate > 0.0 {
+                    // Refunded at the full unit price.
+                    sale.original_price = Some(sale.product.unit_price);
+                    sale.discount_rate = Some(0.0);
+                }
+            } else if self.config.promo_rate > 0.0 {
+                apply_promotion(&mut self.rng, basket, &mut sale, self.config.promo_rate);
+            }
+            // Stockouts sold nothing, so they are neither sales nor returns.
+            let sold = sale.transaction_type.is_none() && sale.event_type.is_none();
+            if self.config.return_rate > 0.0 && sold {
+                sale.transaction_type = Some("sale");
+                if self.recent_sales.len() == RECENT_SALES {
+                    self.recent_sales.pop_front();
+                }
+                self.recent_sales.push_back(RecentSale {
+                    transaction_id: sale.transaction_id.clone(),
+                    product: picked,
+                });
+            }
+            if let Some(shaper) = &mut self.shaper {
+                // Targets are revenue shares, so revenue is counted in catalog dollars.
+                let revenue = sale.total_price / basket.pricing.per_usd;
+                shaper.record(&self.config.catalog, &sale.product.category, revenue);
+            }
+            if let (Some(fx), Some((home, txn))) = (&self.fx, basket.currency) {
+                sale.currency = Some(fx.currencies()[txn]);
+                if txn == home {
+                    sale.amount = Some(sale.total_price);
+                } else {
+                    let rate = fx.rate(home, txn);
+                    sale.exchange_rate = Some(rate);
+                    sale.amount = Some(fx.convert(sale.total_price, rate, txn));
+                }
+            }
+            if let Some(basis) = self.config.tax {
+                let store = &basket.store;
+                let rate = tax_rate(&store.country, &store.state);
+                // Tax is levied on what was charged, in the currency it was charged in.
+                let currency = sale.currency.unwrap_or(basket.pricing.currency);
+                let charged = sale.amount.unwrap_or(sale.total_price);
+                sale.tax_rate = Some(rate);
+                sale.tax_amount = Some(basis.tax(charged, rate, currency));
+                sale.currency = Some(currency);
+            }
+            basket.subtotal += sale.total_price;
+            if basket.items_generated == basket.total_items {
+                basket.summary_due = self.config.basket_summary;
+                basket.coupon_due = self.config.coupon_rate > 0.0
+                    && basket.subtotal > 0.0
+                    && self.rng.random_bool(self.config.coupon_rate);
+                self.schedule_gap();
+            }
             serde_json::to_string(&sale).unwrap_or_else(|_| "{}".to_string())
         } else {
             "{}".to_string()
         }
     }
 
+    fn fields(&self) -> Vec<&'static str> {
+        vec![
+            "event_type",
+            "transaction_type",
+            "transaction_id",
+            "original_transaction_id",
+            "basket_id",
+            "seq",
+            "timestamp",
+            "store",
+            "store.store_id",
+            "store.town",
+            "store.state",
+            "store.country",
+            "store.store_format",
+            "store.currency",
+            "store.latitude",
+            "store.longitude",
+            "customer",
+            "customer.customer_id",
+            "customer.age",
+            "customer.income_band",
+            "customer.home_currency",
+            "customer.household_size",
+            "customer.occupation",
+            "payment_method",
+            "channel",
+            "product",
+            "product.product_name",
+            "product.category",
+            "product.subcategory",
+            "product.brand",
+            "product.supplier_id",
+            "product.unit_price",
+            "quantity",
+            "total_price",
+            "original_price",
+            "discount_rate",
+            "tax_rate",
+            "tax_amount",
+            "currency",
+            "amount",
+            "exchange_rate",
+            "lane_id",
+            "cashier_id",
+            "transaction_duration_seconds",
+            "items_generated",
+            "total_items",
+            "coupon_code",
+            "discount_amount",
+            "item_count",
+            "basket_total",
+        ]
+    }
+
+    /// One schema covers every event type: sale lines (`SaleMessage`, `event_type` absent
+    /// or `stockout`), abandoned baskets and coupon redemptions. Only the fields they all
+    /// share are required.
+    fn schema(&self) -> Value {
+        let string = || json!({"type": "string"});
+        let number = || json!({"type": "number"});
+        let count = || json!({"type": "integer", "minimum": 0});
+        let fields = [
+            (
+                "event_type",
+                json!({
+                    "enum": ["stockout", "basket_abandoned", "coupon_redeemed", "basket_summary"],
+                    "description": "Absent on ordinary sale lines",
+                }),
+            ),
+            ("transaction_type", json!({"enum": ["sale", "return"]})),
+            ("transaction_id", string()),
+            ("original_transaction_id", json!({"type": "string", "description": "Sale a return refunds"})),
+            ("basket_id", string()),
+            ("seq", json!({"type": "integer", "minimum": 1, "description": "Position within the basket"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+            ("store", json!({"type": "object", "required": ["town", "state", "country", "store_format"]})),
+            ("store.store_id", string()),
+            ("store.town", string()),
+            ("store.state", string()),
+            ("store.country", string()),
+            ("store.store_format", json!({"enum": ["express", "standard", "superstore"]})),
+            ("store.currency", string()),
+            ("store.latitude", number()),
+            ("store.longitude", number()),
+            ("customer", json!({"type": "object", "required": ["age", "income_band"]})),
+            ("customer.customer_id", string()),
+            ("customer.age", count()),
+            ("customer.income_band", string()),
+            ("customer.home_currency", string()),
+            ("customer.household_size", json!({"type": "integer", "minimum": 1})),
+            ("customer.occupation", string()),
+            ("payment_method", json!({"enum": ["cash", "card", "mobile"]})),
+            ("channel", json!({"enum": ["in_store", "online"]})),
+            (
+                "product",
+                json!({
+                    "type": "object",
+                    "required": ["product_name", "category", "subcategory", "brand", "supplier_id", "unit_price"],
+                }),
+            ),
+            ("product.product_name", string()),
+            ("product.category", string()),
+            ("product.subcategory", string()),
+            ("product.brand", string()),
+            ("product.supplier_id", string()),
+            ("product.unit_price", number()),
+            ("quantity", json!({"type": "integer", "description": "Negative on returns"})),
+            ("total_price", json!({"type": "number", "description": "Negative on returns"})),
+            ("original_price", number()),
+            ("discount_rate", json!({"type": "number", "minimum": 0, "maximum": 1})),
+            ("tax_rate", json!({"type": "number", "minimum": 0, "maximum": 1})),
+            ("tax_amount", number()),
+            ("currency", string()),
+            ("amount", number()),
+            ("exchange_rate", number()),
+            ("lane_id", json!({"type": "integer", "minimum": 1})),
+            ("cashier_id", string()),
+            ("transaction_duration_seconds", count()),
+            ("items_generated", count()),
+            ("total_items", count()),
+            ("coupon_code", string()),
+            ("discount_amount", number()),
+            ("item_count", count()),
+            ("basket_total", number()),
+        ];
+        super::object_schema(
+            "fluxfakr supermarket event",
+            &fields,
+            &["transaction_id", "basket_id", "seq", "timestamp"],
+        )
+    }
+
+    fn core_fields(&self) -> Vec<&'static str> {
+        vec!["event_type", "transaction_id", "basket_id", "seq", "timestamp", "total_price"]
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_key.clone()
+    }
+
+    /// Every event type carries at least one of the fields: sales and stockouts their
+    /// totals, coupons the discount and abandoned baskets their item counts.
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["event_type", "transaction_type", "store.state", "store.country", "store.store_format", "product.category", "payment_method", "channel"],
+            fields: vec![
+                "total_price",
+                "quantity",
+                "product.unit_price",
+                "discount_rate",
+                "tax_amount",
+                "amount",
+                "discount_amount",
+                "items_generated",
+                "total_items",
+                "item_count",
+                "basket_total",
+            ],
+            timestamp: "timestamp",
+        })
+    }
+
     fn dump(&self) -> String {
         if let Some(ref basket) = self.current_basket {
             format!(
-                "Basket Summary: transaction_id: {}, basket_id: {}, items_generated: {}, total_items: {}",
-                basket.transaction_id, basket.basket_id, basket.items_generated, basket.total_items
+                "Basket Summary: transaction_id: {}, basket_id: {}, items_generated: {}, total_items: {}, payment_method: {}, channel: {}",
+                basket.transaction_id,
+                basket.basket_id,
+                basket.items_generated,
+                basket.total_items,
+                basket.payment_method,
+                basket.channel
             )
         } else {
             "No basket data available.".to_string()
@@ -351,11 +2315,12 @@ mod tests {
     use super::*;
     use serde_json::Value;
     use crate::generator::Generator;
+    use std::collections::HashSet;
 
     #[test]
     fn test_compute_price_deterministic() {
-        let price1 = compute_price("Food", "Bread");
-        let price2 = compute_price("Food", "Bread");
+        let price1 = compute_price(default_price_range("Food"), "Bread");
+        let price2 = compute_price(default_price_range("Food"), "Bread");
         assert_eq!(price1, price2, "compute_price should be deterministic");
     }
 
@@ -375,12 +2340,15 @@ mod tests {
     #[test]
     fn test_get_product_price_cache() {
         // Calling get_product_price twice for the same product should yield the same result.
-        let price1 = get_product_price("Food", "Bread");
-        let price2 = get_product_price("Food", "Bread");
+        let catalog = Catalog::builtin();
+        let food = &catalog.categories[0];
+        let mut cache = PriceCache::new();
+        let price1 = get_product_price(&mut cache, food, "Bread");
+        let price2 = get_product_price(&mut cache, food, "Bread");
         assert_eq!(price1, price2, "Price cache should return consistent prices");
 
         // Ensure the computed price is one of the rounded candidates.
-        let raw_price = compute_price("Food", "Bread");
+        let raw_price = compute_price(default_price_range("Food"), "Bread");
         let base = raw_price.floor();
         let candidate1 = base + 0.49;
         let candidate2 = base + 0.99;
@@ -390,39 +2358,329 @@ mod tests {
         assert!((price1 - expected).abs() < 0.001, "Rounded price does not match expected candidate");
     }
 
+    #[test]
+    fn test_precompute_prices_caches_whole_catalog() {
+        let mut cache = PriceCache::new();
+        let priced = precompute_prices(&mut cache, &Catalog::builtin());
+        let catalog: usize = PRODUCT_HIERARCHY
+            .iter()
+            .flat_map(|(_, subcategories)| subcategories.iter())
+            .map(|(_, products)| products.len())
+            .sum();
+        assert_eq!(priced, catalog);
+        assert_eq!(cache.len(), catalog);
+        for (category, subcategories) in PRODUCT_HIERARCHY {
+            for product in subcategories.iter().flat_map(|(_, products)| products.iter()) {
+                let key = (category.to_string(), product.to_string());
+                assert!(cache.contains_key(&key), "{} / {} not cached", category, product);
+            }
+        }
+    }
+
+    #[test]
+    fn test_price_caches_are_per_generator() {
+        let mut precomputed = SalesGenerator::new(SalesConfig {
+            precompute_prices: true,
+            ..SalesConfig::default()
+        });
+        let mut lazy = SalesGenerator::new(SalesConfig::default());
+        let catalog = precomputed.prices.len();
+        assert!(lazy.prices.is_empty(), "a new generator starts with an empty cache");
+        for _ in 0..50 {
+            lazy.generate();
+        }
+        let priced = lazy.prices.len();
+        assert!(priced > 0 && priced < catalog, "{} of {} products priced", priced, catalog);
+        for _ in 0..50 {
+            precomputed.generate();
+        }
+        assert_eq!(precomputed.prices.len(), catalog, "the catalog bounds the cache");
+        assert_eq!(lazy.prices.len(), priced, "one generator's sales never fill another's cache");
+    }
+
     #[test]
     fn test_generate_product() {
-        let product = generate_product();
+        let catalog = Catalog::builtin();
+        let mut cache = PriceCache::new();
+        let sampler = CatalogSampler::new(&catalog, Popularity::Uniform, false);
+        let (_, product) = generate_product(
+            &mut rand::rng(),
+            &mut cache,
+            &catalog,
+            &sampler,
+            StoreFormat::Superstore,
+            None,
+            &[],
+        );
         assert!(!product.product_name.is_empty(), "Product name should not be empty");
         assert!(!product.category.is_empty(), "Category should not be empty");
         assert!(!product.subcategory.is_empty(), "Subcategory should not be empty");
-        let expected_price = get_product_price(&product.category, &product.product_name);
+        let category = catalog.categories.iter().find(|c| c.name == product.category).unwrap();
+        let expected_price = get_product_price(&mut cache, category, &product.product_name);
         assert!((product.unit_price - expected_price).abs() < 0.001,
                 "Product unit price should match cached price");
     }
 
+    #[test]
+    fn test_brand_is_sticky_per_product() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_formats: vec![(StoreFormat::Superstore, 1.0)],
+            ..SalesConfig::default()
+        });
+        let mut brands: HashMap<(String, String), (String, String)> = HashMap::new();
+        for _ in 0..2000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let product = &v["product"];
+            let key = (
+                product["category"].as_str().unwrap().to_string(),
+                product["product_name"].as_str().unwrap().to_string(),
+            );
+            let brand = (
+                product["brand"].as_str().unwrap().to_string(),
+                product["supplier_id"].as_str().unwrap().to_string(),
+            );
+            assert_eq!(brands.entry(key).or_insert_with(|| brand.clone()), &brand);
+        }
+        // Every brand comes from its category's list.
+        for ((category, _), (brand, supplier)) in &brands {
+            let (_, listed) = BRANDS.iter().find(|(c, _)| c == category).unwrap();
+            assert!(listed.contains(&(brand.as_str(), supplier.as_str())));
+        }
+        let distinct: std::collections::HashSet<_> = brands.values().collect();
+        assert!(distinct.len() > 5, "products spread over brands");
+    }
+
     #[test]
     fn test_generate_store() {
-        let store = generate_store();
+        let store = generate_store(&mut rand::rng(), StoreFormat::Standard, None, None);
+        assert_eq!(store.store_format, StoreFormat::Standard);
         assert_eq!(store.country, "USA", "Store country should be USA");
         assert!(!store.town.is_empty(), "Store town should not be empty");
         assert!(!store.state.is_empty(), "Store state should not be empty");
+        assert!(store.latitude.is_none() && store.longitude.is_none());
+    }
+
+    #[test]
+    fn test_locale_places_stores_in_its_country() {
+        let uk = crate::country::parse_locale("en_GB").unwrap();
+        let mut generator = SalesGenerator::new(SalesConfig {
+            countries: vec![(uk, 1.0)],
+            ..SalesConfig::default()
+        });
+        for _ in 0..100 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert_eq!(v["store"]["country"], "UK");
+            assert_eq!(v["store"]["currency"], "GBP");
+            assert!(!v["store"]["town"].as_str().unwrap().is_empty());
+            assert!(!v["store"]["state"].as_str().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_locale_prices_in_its_currency() {
+        let mean_price = |locale: &str| {
+            let mut generator = SalesGenerator::new(SalesConfig {
+                countries: vec![(crate::country::parse_locale(locale).unwrap(), 1.0)],
+                seed: 3,
+                ..SalesConfig::default()
+            });
+            let mut sum = 0.0;
+            for _ in 0..500 {
+                let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+                let currency = v["store"]["currency"].as_str().unwrap();
+                for price in [&v["product"]["unit_price"], &v["total_price"]] {
+                    let price = price.as_f64().unwrap();
+                    assert_eq!(price, crate::currency::round_to_minor_unit(price, currency));
+                }
+                sum += v["product"]["unit_price"].as_f64().unwrap();
+            }
+            sum / 500.0
+        };
+        // Yen prices are whole and about 150 times the dollar ones.
+        let ratio = mean_price("ja_JP") / mean_price("en_US");
+        assert!((100.0..220.0).contains(&ratio), "JPY/USD price ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_sales_tax_follows_the_store_state() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            region: Some(Region::UsWest),
+            tax: Some(TaxBasis::Exclusive),
+            ..SalesConfig::default()
+        });
+        let mut states = HashSet::new();
+        for _ in 0..300 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let state = v["store"]["state"].as_str().unwrap();
+            let expected = match state {
+                "CA" => 0.0725,
+                "WA" => 0.065,
+                "OR" => 0.0,
+                other => panic!("unexpected us-west state {}", other),
+            };
+            assert_eq!(v["tax_rate"], expected);
+            let total = v["total_price"].as_f64().unwrap();
+            let tax = v["tax_amount"].as_f64().unwrap();
+            assert!((tax - (total * expected * 100.0).round() / 100.0).abs() < 1e-9, "{}", v);
+            assert_eq!(v["currency"], "USD");
+            states.insert(state.to_string());
+        }
+        assert!(states.len() > 1, "stores spread over states");
+    }
+
+    #[test]
+    fn test_tax_inclusive_prices_contain_the_tax() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            countries: vec![(crate::country::parse_locale("en_GB").unwrap(), 1.0)],
+            tax: Some(TaxBasis::Inclusive),
+            ..SalesConfig::default()
+        });
+        for _ in 0..100 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert_eq!(v["tax_rate"], 0.2);
+            assert_eq!(v["currency"], "GBP");
+            let total = v["total_price"].as_f64().unwrap();
+            let tax = v["tax_amount"].as_f64().unwrap();
+            assert!((tax - (total * 0.2 / 1.2 * 100.0).round() / 100.0).abs() < 1e-9, "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_tax_is_charged_in_the_transaction_currency() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            countries: vec![(crate::country::parse_locale("de_DE").unwrap(), 1.0)],
+            fx: Some(FxRates::new(crate::currency::parse_currencies("EUR,JPY").unwrap(), 0.001)),
+            foreign_txn_rate: 0.5,
+            tax: Some(TaxBasis::Exclusive),
+            seed: 4,
+            ..SalesConfig::default()
+        });
+        let mut yen = 0;
+        for _ in 0..500 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let currency = v["currency"].as_str().unwrap();
+            let amount = v["amount"].as_f64().unwrap();
+            let tax = v["tax_amount"].as_f64().unwrap();
+            let expected = crate::currency::round_to_minor_unit(amount * 0.19, currency);
+            assert!((tax - expected).abs() < 1e-9, "{}", v);
+            if currency == "JPY" {
+                yen += 1;
+                assert_eq!(tax, tax.round(), "yen tax is whole: {}", v);
+            }
+        }
+        assert!(yen > 0, "expected some baskets charged in yen");
+    }
+
+    #[test]
+    fn test_country_distribution_matches_weights() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            countries: crate::country::parse_country_mix("USA=0.5,UK=0.3,Japan=0.2").unwrap(),
+            ..SalesConfig::default()
+        });
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let baskets = 4000;
+        for _ in 0..baskets {
+            generator.init_basket(StoreFormat::Standard, 1);
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let country = v["store"]["country"].as_str().unwrap().to_string();
+            let currency = v["store"]["currency"].as_str().unwrap();
+            assert_eq!(currency, COUNTRIES.iter().find(|c| c.name == country).unwrap().currency);
+            *counts.entry(country).or_insert(0) += 1;
+        }
+        for (country, weight) in [("USA", 0.5), ("UK", 0.3), ("Japan", 0.2)] {
+            let share = counts[country] as f64 / baskets as f64;
+            assert!((share - weight).abs() < 0.03, "{} share {}", country, share);
+        }
+    }
+
+    #[test]
+    fn test_region_clusters_stores() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            region: Some(Region::EuWest),
+            ..SalesConfig::default()
+        });
+        for _ in 0..50 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let town = Region::EuWest
+                .towns()
+                .iter()
+                .find(|t| v["store"]["town"] == t.name)
+                .expect("store town should come from the region preset");
+            assert_eq!(v["store"]["country"], town.country);
+            assert!((v["store"]["latitude"].as_f64().unwrap() - town.lat).abs() < 0.1);
+            assert!((v["store"]["longitude"].as_f64().unwrap() - town.lon).abs() < 0.1);
+        }
     }
 
     #[test]
     fn test_generate_customer() {
-        let customer = generate_customer();
+        let customer = generate_customer(&mut rand::rng(), &Demographics::default());
         assert!(customer.age >= 18 && customer.age < 80, "Customer age out of range");
         let valid_income = ["Low", "Medium", "High"];
         assert!(valid_income.contains(&customer.income_band.as_str()),
                 "Customer income band is invalid");
     }
 
+    fn test_basket(store: Store, customer: Customer) -> Basket {
+        Basket {
+            transaction_id: "TXN123456".to_string(),
+            basket_id: "BASKET1234".to_string(),
+            store,
+            customer,
+            payment_method: "card",
+            channel: "in_store",
+            total_items: 1,
+            items_generated: 0,
+            abandon_after: None,
+            seq: 1,
+            currency: None,
+            pricing: Pricing::USD,
+            checkout: None,
+            subtotal: 0.0,
+            summary_due: false,
+            coupon_due: false,
+            discounts: HashMap::new(),
+            picked: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_custom_income_bands_follow_weights() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            income_bands: crate::weights::parse_weights("Q1=1,Q2=1,Q3=2,Q4=2,Q5=4").unwrap(),
+            rich_demographics: true,
+            ..SalesConfig::default()
+        });
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let total = 5000;
+        for _ in 0..total {
+            generator.init_basket(StoreFormat::Standard, 1);
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let customer = &v["customer"];
+            *counts.entry(customer["income_band"].as_str().unwrap().to_string()).or_default() += 1;
+            assert!((1..=6).contains(&customer["household_size"].as_u64().unwrap()));
+            assert!(OCCUPATIONS.contains(&customer["occupation"].as_str().unwrap()));
+        }
+        assert_eq!(counts.len(), 5);
+        for (band, weight) in [("Q1", 0.1), ("Q3", 0.2), ("Q5", 0.4)] {
+            let share = counts[band] as f64 / total as f64;
+            assert!((share - weight).abs() < 0.03, "{} share {}", band, share);
+        }
+    }
+
     #[test]
     fn test_generate_sale_message() {
-        let store = generate_store();
-        let customer = generate_customer();
-        let sale = generate_sale_message("TXN123456", "BASKET1234", &store, &customer);
+        let store = generate_store(&mut rand::rng(), StoreFormat::Standard, None, None);
+        let customer = generate_customer(&mut rand::rng(), &Demographics::default());
+        let (_, sale) = generate_sale_message(
+            &mut rand::rng(),
+            &mut PriceCache::new(),
+            &Catalog::builtin(),
+            &CatalogSampler::new(&Catalog::builtin(), Popularity::Uniform, false),
+            &mut test_basket(store, customer),
+            0,
+            None,
+        );
         // Validate total_price equals product.unit_price * quantity.
         let expected_total = sale.product.unit_price * sale.quantity as f64;
         assert!((sale.total_price - expected_total).abs() < 0.001,
@@ -430,32 +2688,827 @@ mod tests {
                 expected_total, sale.total_price);
     }
 
+    #[test]
+    fn test_basket_summary_totals_its_lines() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            basket_summary: true,
+            coupon_rate: 0.5,
+            return_rate: 0.1,
+            promo_rate: 0.3,
+            ..SalesConfig::default()
+        });
+        let (mut lines, mut total, mut summaries) = (0, 0.0, 0);
+        for _ in 0..2000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            match v["event_type"].as_str() {
+                None => {
+                    lines += 1;
+                    total += v["total_price"].as_f64().unwrap();
+                }
+                Some("basket_summary") => {
+                    assert_eq!(v["item_count"], lines, "{}", v);
+                    let basket_total = v["basket_total"].as_f64().unwrap();
+                    assert!((basket_total - total).abs() < 0.005 + 1e-9, "{} vs {}", basket_total, total);
+                    assert!(v["store"]["town"].is_string() && v["customer"]["age"].is_u64());
+                    (lines, total) = (0, 0.0);
+                    summaries += 1;
+                }
+                // A coupon follows the summary, before the next basket's first line.
+                Some("coupon_redeemed") => assert_eq!(lines, 0),
+                Some(other) => panic!("unexpected event {}", other),
+            }
+        }
+        assert!(summaries > 50, "{} baskets summarized", summaries);
+    }
+
+    #[test]
+    fn test_adversarial_strings_serialize_to_valid_json() {
+        let nasty = "O'Fallon \"The \\ City\"\n\t\u{0000}\u{001f} Zürich 東京 🛒";
+        let store = Store {
+            store_id: None,
+            town: nasty.to_string(),
+            state: "\u{2028}\u{2029}".to_string(),
+            country: "USA".to_string(),
+            store_format: StoreFormat::Standard,
+            currency: None,
+            latitude: None,
+            longitude: None,
+            clock_skew_ms: 0,
+        };
+        let customer = generate_customer(&mut rand::rng(), &Demographics::default());
+        let mut basket = test_basket(store, customer);
+        basket.transaction_id = "TXN\"1".to_string();
+        basket.basket_id = "B\\1".to_string();
+        let (_, mut sale) = generate_sale_message(
+            &mut rand::rng(),
+            &mut PriceCache::new(),
+            &Catalog::builtin(),
+            &CatalogSampler::new(&Catalog::builtin(), Popularity::Uniform, false),
+            &mut basket,
+            0,
+            None,
+        );
+        sale.product.product_name = nasty.to_string();
+
+        let json = serde_json::to_string(&sale).unwrap();
+        assert!(!json.contains('\n'), "Output must stay on one line");
+        let v: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v["store"]["town"], nasty);
+        assert_eq!(v["store"]["state"], "\u{2028}\u{2029}");
+        assert_eq!(v["product"]["product_name"], nasty);
+        assert_eq!(v["transaction_id"], "TXN\"1");
+        assert_eq!(v["basket_id"], "B\\1");
+    }
+
     #[test]
     fn test_sales_generator_basket_reset() {
-        let mut generator = SalesGenerator::new();
+        let mut generator = SalesGenerator::new(SalesConfig::default());
         // Initialize a basket with exactly 3 items.
-        generator.init_basket(3);
+        generator.init_basket(StoreFormat::Standard, 3);
         let mut txn_ids = Vec::new();
         // Generate three sale messages and record their transaction IDs.
-        for _ in 0..3 {
+        for expected_seq in 1..=3 {
             let sale_json = generator.generate();
             let v: Value = serde_json::from_str(&sale_json).unwrap();
+            assert_eq!(v["seq"], expected_seq, "seq should increase by one per basket message");
             let txn_id = v["transaction_id"].as_str().unwrap().to_string();
             txn_ids.push(txn_id);
         }
         // The basket should now be exhausted; next call should create a new basket.
         let sale_json = generator.generate();
         let v: Value = serde_json::from_str(&sale_json).unwrap();
+        assert_eq!(generator.key().as_deref(), v["basket_id"].as_str());
         let new_txn_id = v["transaction_id"].as_str().unwrap().to_string();
         assert!(!txn_ids.contains(&new_txn_id),
                 "Transaction id should change when basket resets");
     }
 
+    #[test]
+    fn test_basket_ids_never_repeat() {
+        let mut generator = SalesGenerator::new(SalesConfig { seed: 5, ..SalesConfig::default() });
+        let mut txn_ids = HashSet::new();
+        let mut basket_ids = HashSet::new();
+        for _ in 0..20_000 {
+            generator.init_basket(StoreFormat::Standard, 1);
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert!(txn_ids.insert(v["transaction_id"].as_str().unwrap().to_string()));
+            assert!(basket_ids.insert(v["basket_id"].as_str().unwrap().to_string()));
+        }
+    }
+
+    #[test]
+    fn test_coupons_reference_completed_baskets() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            coupon_rate: 0.5,
+            abandon_rate: 0.2,
+            stockout_rate: 0.1,
+            ..SalesConfig::default()
+        });
+        let mut subtotals: HashMap<String, f64> = HashMap::new();
+        let mut last_sale: Option<Value> = None;
+        let mut coupons = 0;
+        for _ in 0..3000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            if v["event_type"] == "coupon_redeemed" {
+                coupons += 1;
+                let sale = last_sale.as_ref().expect("a coupon follows a sale");
+                // The coupon applies to the basket that just completed, and joins to it.
+                assert_eq!(v["basket_id"], sale["basket_id"]);
+                assert_eq!(v["transaction_id"], sale["transaction_id"]);
+                assert_eq!(v["seq"].as_u64(), sale["seq"].as_u64().map(|seq| seq + 1));
+                let discount = v["discount_amount"].as_f64().unwrap();
+                let subtotal = subtotals[sale["transaction_id"].as_str().unwrap()];
+                assert!(discount > 0.0 && discount <= subtotal + 0.01, "{} of {}", discount, subtotal);
+                assert!(COUPONS.iter().any(|(code, _)| v["coupon_code"] == *code));
+                assert_eq!(generator.key().unwrap(), v["basket_id"].as_str().unwrap());
+                last_sale = None;
+            } else if v["event_type"] == "basket_abandoned" {
+                last_sale = None;
+            } else {
+                *subtotals.entry(v["transaction_id"].as_str().unwrap().to_string()).or_default() +=
+                    v["total_price"].as_f64().unwrap();
+                last_sale = Some(v);
+            }
+        }
+        assert!(coupons > 20, "only {} coupons", coupons);
+    }
+
+    #[test]
+    fn test_basket_abandonment_rate() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            abandon_rate: 0.3,
+            ..SalesConfig::default()
+        });
+        let baskets = 2000;
+        let mut abandoned = 0;
+        for _ in 0..baskets {
+            generator.init_basket(StoreFormat::Standard, 10);
+            loop {
+                let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+                if v["event_type"] == "basket_abandoned" {
+                    let generated = v["items_generated"].as_u64().unwrap();
+                    let total = v["total_items"].as_u64().unwrap();
+                    assert!(generated < total, "Abandoned basket should be incomplete");
+                    abandoned += 1;
+                    break;
+                }
+                let basket = generator.current_basket.as_ref().unwrap();
+                if basket.items_generated == basket.total_items {
+                    break;
+                }
+            }
+        }
+        let rate = abandoned as f64 / baskets as f64;
+        assert!((rate - 0.3).abs() < 0.05, "Abandonment rate {} too far from 0.3", rate);
+    }
+
+    #[test]
+    fn test_store_formats_shape_catalog_and_baskets() {
+        let mix = parse_store_format_mix("express=1,superstore=1").unwrap();
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_formats: mix,
+            ..SalesConfig::default()
+        });
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..500 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let format = v["store"]["store_format"].as_str().unwrap().to_string();
+            let category = v["product"]["category"].as_str().unwrap();
+            if format == "express" {
+                assert!(StoreFormat::Express.stocks(category), "{} sold in express", category);
+                let size = generator.current_basket.as_ref().unwrap().total_items as u32;
+                assert!(StoreFormat::Express.basket_size_range().contains(&size));
+            }
+            assert_ne!(format, "standard", "standard stores have zero weight");
+            seen.insert(format);
+        }
+        assert_eq!(seen.len(), 2, "Both configured formats should appear");
+    }
+
+    #[test]
+    fn test_geometric_basket_sizes_are_right_skewed() {
+        let mut rng = SimRng::new(RngAlgorithm::Chacha8, 5);
+        let mut sizes: Vec<u32> = (0..20_000)
+            .map(|_| BasketSizeDistribution::Geometric.sample(&mut rng, StoreFormat::Standard, None, 2.0))
+            .collect();
+        sizes.sort_unstable();
+        let mean = sizes.iter().map(|&s| s as f64).sum::<f64>() / sizes.len() as f64;
+        let median = sizes[sizes.len() / 2] as f64;
+        assert!((mean - 10.0).abs() < 0.5, "mean {}", mean);
+        // Right skew: the mean sits above the median and the tail runs far past the uniform range.
+        assert!(mean > median, "mean {} median {}", mean, median);
+        assert!(sizes[0] >= 1 && *sizes.last().unwrap() > 30);
+
+        let uniform: Vec<u32> = (0..1000)
+            .map(|_| BasketSizeDistribution::Uniform.sample(&mut rng, StoreFormat::Standard, None, 2.0))
+            .collect();
+        assert!(uniform.iter().all(|size| (5..16).contains(size)));
+    }
+
+    #[test]
+    fn test_negative_binomial_basket_sizes_match_mean() {
+        let mut rng = SimRng::new(RngAlgorithm::Chacha8, 6);
+        let sizes: Vec<u32> = (0..20_000)
+            .map(|_| {
+                BasketSizeDistribution::NegativeBinomial.sample(&mut rng, StoreFormat::Express, Some(6.0), 1.5)
+            })
+            .collect();
+        let mean = sizes.iter().map(|&s| s as f64).sum::<f64>() / sizes.len() as f64;
+        assert!((mean - 6.0).abs() < 0.3, "mean {}", mean);
+        assert!(sizes.iter().all(|&size| (1..=MAX_BASKET_SIZE).contains(&size)));
+    }
+
+    #[test]
+    fn test_skewed_basket_sizes_with_mean_one_are_single_items() {
+        let mut rng = SimRng::new(RngAlgorithm::Chacha8, 6);
+        for distribution in [BasketSizeDistribution::NegativeBinomial, BasketSizeDistribution::Geometric] {
+            for _ in 0..100 {
+                assert_eq!(distribution.sample(&mut rng, StoreFormat::Express, Some(1.0), 1.5), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_store_format_mix_rejects_unknown() {
+        assert!(parse_store_format_mix("hypermarket=1").is_err());
+    }
+
+    #[test]
+    fn test_fields_cover_sale_messages() {
+        let mut generator = SalesGenerator::new(SalesConfig::default());
+        let fields = generator.fields();
+        let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+        for (key, value) in v.as_object().unwrap() {
+            assert!(fields.contains(&key.as_str()), "{} missing from fields()", key);
+            if let Some(nested) = value.as_object() {
+                for nested_key in nested.keys() {
+                    let path = format!("{}.{}", key, nested_key);
+                    assert!(fields.contains(&path.as_str()), "{} missing from fields()", path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stockouts_occur_at_configured_rate() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            stockout_rate: 0.2,
+            ..SalesConfig::default()
+        });
+        let total = 5000;
+        let mut stockouts = 0;
+        for _ in 0..total {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            if v["event_type"] == "stockout" {
+                stockouts += 1;
+                assert_eq!(v["quantity"], 0);
+                assert_eq!(v["total_price"], 0.0);
+            } else {
+                assert!(v.get("event_type").is_none());
+                assert!(v["quantity"].as_u64().unwrap() > 0);
+            }
+        }
+        // Sticky stockouts nudge the observed rate slightly above the configured one.
+        let rate = stockouts as f64 / total as f64;
+        assert!((0.17..0.26).contains(&rate), "stockout rate {}", rate);
+    }
+
+    #[test]
+    fn test_category_revenue_converges_to_targets() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_formats: vec![(StoreFormat::Superstore, 1.0)],
+            category_targets: parse_category_targets("Food=0.6,Clothing=0.2", &Catalog::builtin()).unwrap(),
+            ..SalesConfig::default()
+        });
+        let mut revenue: HashMap<String, f64> = HashMap::new();
+        for _ in 0..20_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            *revenue
+                .entry(v["product"]["category"].as_str().unwrap().to_string())
+                .or_default() += v["total_price"].as_f64().unwrap();
+        }
+        let total: f64 = revenue.values().sum();
+        let share = |category: &str| revenue.get(category).copied().unwrap_or(0.0) / total;
+        assert!((share("Food") - 0.6).abs() < 0.02, "Food share {}", share("Food"));
+        assert!((share("Clothing") - 0.2).abs() < 0.02, "Clothing share {}", share("Clothing"));
+        // The other four categories split the remaining 20%.
+        assert!((share("Pets") - 0.05).abs() < 0.02, "Pets share {}", share("Pets"));
+    }
+
+    #[test]
+    fn test_parse_category_targets() {
+        let catalog = Catalog::builtin();
+        assert_eq!(
+            parse_category_targets("food=0.5", &catalog).unwrap(),
+            vec![("Food".to_string(), 0.5)]
+        );
+        assert!(parse_category_targets("Toys=0.5", &catalog).is_err());
+        assert!(parse_category_targets("Food=0.7,Pets=0.4", &catalog).is_err());
+    }
+
+    const TOY_CATALOG: &str = r#"{"categories": [
+        {"name": "Toys", "price_range": [40, 60], "subcategories": [
+            {"name": "Games", "products": ["Chess", "Go"]},
+            {"name": "Puzzles", "products": ["Jigsaw"]}
+        ]},
+        {"name": "Food", "subcategories": [{"name": "Bakery", "products": ["Bread"]}]}
+    ]}"#;
+
+    #[test]
+    fn test_custom_catalog_replaces_the_builtin_one() {
+        let catalog = Catalog::parse(TOY_CATALOG).unwrap();
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_formats: vec![(StoreFormat::Superstore, 1.0)],
+            category_targets: parse_category_targets("toys=0.5", &catalog).unwrap(),
+            catalog,
+            ..SalesConfig::default()
+        });
+        let mut products = HashSet::new();
+        for _ in 0..500 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let product = &v["product"];
+            let price = product["unit_price"].as_f64().unwrap();
+            match product["category"].as_str().unwrap() {
+                "Toys" => assert!((40.0..60.5).contains(&price), "toy at {}", price),
+                // No range given, so Food keeps its built-in one.
+                "Food" => assert!((1.0..10.5).contains(&price), "food at {}", price),
+                other => panic!("category {} is not in the catalog", other),
+            }
+            products.insert(product["product_name"].as_str().unwrap().to_string());
+        }
+        let expected: HashSet<String> =
+            ["Chess", "Go", "Jigsaw", "Bread"].iter().map(|p| p.to_string()).collect();
+        assert_eq!(products, expected);
+    }
+
+    #[test]
+    fn test_express_stores_fall_back_to_any_catalog_category() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_formats: vec![(StoreFormat::Express, 1.0)],
+            catalog: Catalog::parse(
+                r#"{"categories": [{"name": "Clothing", "subcategories": [{"name": "Men", "products": ["Jeans"]}]}]}"#,
+            )
+            .unwrap(),
+            ..SalesConfig::default()
+        });
+        let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+        assert_eq!(v["product"]["product_name"], "Jeans");
+    }
+
+    #[test]
+    fn test_weighted_products_dominate() {
+        let catalog = Catalog::parse(
+            r#"{"categories": [{"name": "Food", "subcategories": [{"name": "Bakery",
+                "products": [{"name": "Bread", "weight": 10}, {"name": "Brioche", "weight": 1}]}]}]}"#,
+        )
+        .unwrap();
+        let sampler = CatalogSampler::new(&catalog, Popularity::Uniform, false);
+        let mut rng = rand::rng();
+        let mut cache = PriceCache::new();
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for _ in 0..11_000 {
+            let (_, product) =
+                generate_product(&mut rng, &mut cache, &catalog, &sampler, StoreFormat::Standard, None, &[]);
+            *counts.entry(product.product_name).or_default() += 1;
+        }
+        let ratio = counts["Bread"] as f64 / counts["Brioche"] as f64;
+        assert!((8.0..12.5).contains(&ratio), "Bread sold {} times as often", ratio);
+    }
+
+    #[test]
+    fn test_zipf_popularity_favours_the_first_listed() {
+        let catalog = Catalog::builtin();
+        let sampler = CatalogSampler::new(&catalog, Popularity::Zipf, false);
+        let mut rng = rand::rng();
+        let mut cache = PriceCache::new();
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for _ in 0..20_000 {
+            let (_, product) =
+                generate_product(&mut rng, &mut cache, &catalog, &sampler, StoreFormat::Superstore, None, &[]);
+            *counts.entry(product.category).or_default() += 1;
+        }
+        // Food is listed first and Clothing sixth, so Food sells about six times as often.
+        let ratio = counts["Food"] as f64 / counts["Clothing"] as f64;
+        assert!((5.0..7.2).contains(&ratio), "Food sold {} times as often", ratio);
+    }
+
+    #[test]
+    fn test_affinity_lifts_co_purchases() {
+        // Share of superstore baskets holding Frozen Pizza that also hold Ice Cream.
+        let ice_cream_with_pizza = |affinity| {
+            let mut generator = SalesGenerator::new(SalesConfig {
+                store_formats: vec![(StoreFormat::Superstore, 1.0)],
+                affinity,
+                basket_summary: true,
+                ..SalesConfig::default()
+            });
+            let (mut basket, mut with_pizza, mut with_both) = (HashSet::new(), 0, 0);
+            for _ in 0..20_000 {
+                let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+                if v["event_type"] == "basket_summary" {
+                    if basket.contains("Frozen Pizza") {
+                        with_pizza += 1;
+                        with_both += basket.contains("Ice Cream") as u32;
+                    }
+                    basket.clear();
+                } else {
+                    basket.insert(v["product"]["product_name"].as_str().unwrap().to_string());
+                }
+            }
+            with_both as f64 / with_pizza as f64
+        };
+        let (random, lifted) = (ice_cream_with_pizza(false), ice_cream_with_pizza(true));
+        assert!(lifted > random * 2.0, "{} of pizza baskets had ice cream, {} without affinity", lifted, random);
+    }
+
+    #[test]
+    fn test_malformed_catalogs_are_rejected() {
+        for (json, reason) in [
+            ("\"toys\"", "invalid type"),
+            (r#"{"categories": []}"#, "no categories"),
+            (r#"{"categories": [{"name": "Toys"}]}"#, "missing field `subcategories`"),
+            (r#"{"categories": [{"name": "Toys", "subcategories": []}]}"#, "no subcategories"),
+            (
+                r#"{"categories": [{"name": "Toys", "subcategories": [{"name": "Games", "products": []}]}]}"#,
+                "no products",
+            ),
+            (
+                r#"{"categories": [{"name": "Toys", "price_range": [5, 1], "subcategories": [{"name": "Games", "products": ["Go"]}]}]}"#,
+                "price range",
+            ),
+            (
+                r#"{"categories": [{"name": "Toys", "subcategories": [{"name": "Games", "products": ["Go", "Go"]}]}]}"#,
+                "listed twice",
+            ),
+            (
+                r#"{"categories": [{"name": "Toys", "subcategories": [{"name": "Games", "products": [{"name": "Go", "weight": 0}]}]}]}"#,
+                "positive number",
+            ),
+            (
+                r#"{"categories": [{"name": "Toys", "subcategories": [{"name": "Games", "products": ["Go"]}]}],
+                    "affinities": [{"products": ["Go", "Chess"], "rate": 0.5}]}"#,
+                "unknown product 'Chess'",
+            ),
+            (
+                r#"{"categories": [{"name": "Toys", "colour": "red", "subcategories": [{"name": "Games", "products": ["Go"]}]}]}"#,
+                "unknown field",
+            ),
+        ] {
+            let err = Catalog::parse(json).unwrap_err();
+            assert!(err.contains(reason), "{}: {}", json, err);
+        }
+    }
+
+    #[test]
+    fn test_stockouts_are_sticky_per_store_and_product() {
+        let mut stockouts = HashMap::new();
+        let mut rng = rand::rng();
+        let store = generate_store(&mut rng, StoreFormat::Standard, None, None);
+        assert!(check_stockout(&mut stockouts, &mut rng, 1.0, &store, "Bread", 100));
+        // Even at a zero-ish rate the product stays unavailable until restocked...
+        assert!(check_stockout(&mut stockouts, &mut rng, 1e-12, &store, "Bread", 100 + STOCKOUT_DURATION_SECS - 1));
+        // ...while other products at the store are unaffected.
+        assert!(!check_stockout(&mut stockouts, &mut rng, 1e-12, &store, "Bagel", 100));
+        assert!(!check_stockout(&mut stockouts, &mut rng, 1e-12, &store, "Bread", 100 + STOCKOUT_DURATION_SECS));
+    }
+
+    #[test]
+    fn test_multi_currency_conversion() {
+        let currencies = crate::currency::parse_currencies("USD,EUR,JPY").unwrap();
+        let mut generator = SalesGenerator::new(SalesConfig {
+            fx: Some(FxRates::new(currencies.clone(), 0.001)),
+            foreign_txn_rate: 0.5,
+            seed: 11,
+            ..SalesConfig::default()
+        });
+        let mut foreign = 0;
+        let mut unit_prices: HashMap<String, Vec<f64>> = HashMap::new();
+        for _ in 0..500 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let home = v["customer"]["home_currency"].as_str().unwrap();
+            let currency = v["currency"].as_str().unwrap();
+            assert!(currencies.contains(&home) && currencies.contains(&currency));
+            let total = v["total_price"].as_f64().unwrap();
+            let amount = v["amount"].as_f64().unwrap();
+            // Prices are quoted in the home currency, at its minor unit.
+            let unit_price = v["product"]["unit_price"].as_f64().unwrap();
+            for price in [unit_price, total] {
+                assert_eq!(price, crate::currency::round_to_minor_unit(price, home), "{}", v);
+            }
+            unit_prices.entry(home.to_string()).or_default().push(unit_price);
+            if currency == home {
+                assert_eq!(amount, total);
+                assert!(v.get("exchange_rate").is_none());
+            } else {
+                foreign += 1;
+                let rate = v["exchange_rate"].as_f64().unwrap();
+                let decimals = if currency == "JPY" { 0 } else { 2 };
+                let factor = 10f64.powi(decimals);
+                assert_eq!(amount, (total * rate * factor).round() / factor);
+            }
+        }
+        assert!(foreign > 0, "expected some foreign-currency transactions");
+        // Catalog dollars are converted, not relabelled: yen prices run about 150x higher.
+        let mean = |code: &str| {
+            let prices = &unit_prices[code];
+            prices.iter().sum::<f64>() / prices.len() as f64
+        };
+        assert!(mean("JPY") > 50.0 * mean("USD"), "JPY {} USD {}", mean("JPY"), mean("USD"));
+    }
+
+    #[test]
+    fn test_store_key_is_shared_by_basket_items_and_repeat_visits() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_pool: Some(3),
+            key_by: SalesKey::Store,
+            ..SalesConfig::default()
+        });
+        let mut visits: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut previous: Option<(String, String)> = None;
+        for _ in 0..500 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let key = generator.key().unwrap();
+            assert_eq!(v["store"]["store_id"], key.as_str());
+            let transaction_id = v["transaction_id"].as_str().unwrap().to_string();
+            // Every line item of a basket carries the same key.
+            if let Some((prev_txn, prev_key)) = &previous {
+                if *prev_txn == transaction_id {
+                    assert_eq!(*prev_key, key);
+                }
+            }
+            visits.entry(key.clone()).or_default().insert(transaction_id.clone());
+            previous = Some((transaction_id, key));
+        }
+        assert_eq!(visits.len(), 3);
+        assert!(visits.values().all(|baskets| baskets.len() > 1), "stores are revisited");
+    }
+
+    #[test]
+    fn test_clock_skew_offsets_store_timestamps() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_pool: Some(2),
+            clock: Clock::Stepped {
+                now_ms: 1_700_000_000_000,
+                step_ms: 0,
+            },
+            max_clock_skew_ms: 5_000,
+            ..SalesConfig::default()
+        });
+        assert!(generator.store_pool.iter().all(|s| s.clock_skew_ms.abs() <= 5_000));
+        generator.store_pool[0].clock_skew_ms = 4_000;
+        generator.store_pool[1].clock_skew_ms = -1_000;
+        let mut seen: HashMap<String, HashSet<u64>> = HashMap::new();
+        for _ in 0..200 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            assert!(v["store"].get("clock_skew_ms").is_none());
+            seen.entry(v["store"]["store_id"].as_str().unwrap().to_string())
+                .or_default()
+                .insert(v["timestamp"].as_u64().unwrap());
+        }
+        assert_eq!(seen["STORE-0001"], HashSet::from([1_700_000_004]));
+        assert_eq!(seen["STORE-0002"], HashSet::from([1_699_999_999]));
+    }
+
+    #[test]
+    fn test_checkout_lanes_cashiers_and_duration() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            store_pool: Some(2),
+            lanes_per_store: Some(4),
+            store_formats: vec![(StoreFormat::Superstore, 1.0)],
+            ..SalesConfig::default()
+        });
+        let mut durations: HashMap<u64, Vec<f64>> = HashMap::new();
+        let mut last_txn = None;
+        for _ in 0..3000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let lane = v["lane_id"].as_u64().unwrap();
+            assert!((1..=4).contains(&lane));
+            let store_id = v["store"]["store_id"].as_str().unwrap();
+            let cashier = v["cashier_id"].as_str().unwrap();
+            let number: u32 = cashier
+                .strip_prefix(&format!("{}-C", store_id))
+                .expect("cashier belongs to the store's pool")
+                .parse()
+                .unwrap();
+            assert!((1..=4 * CASHIERS_PER_LANE).contains(&number));
+            let txn = v["transaction_id"].as_str().unwrap().to_string();
+            if last_txn.as_ref() != Some(&txn) {
+                let items = generator.current_basket.as_ref().unwrap().total_items as u64;
+                durations
+                    .entry(items)
+                    .or_default()
+                    .push(v["transaction_duration_seconds"].as_f64().unwrap());
+                last_txn = Some(txn);
+            }
+        }
+        let mean = |range: std::ops::Range<u64>| {
+            let samples: Vec<f64> = range.filter_map(|n| durations.get(&n)).flatten().copied().collect();
+            samples.iter().sum::<f64>() / samples.len() as f64
+        };
+        // Expected means are about 78s for 10-14 items and 140s for 25-30.
+        let small = mean(10..15);
+        let large = mean(25..31);
+        assert!(large > 1.4 * small, "small baskets {}s, large baskets {}s", small, large);
+    }
+
+    #[test]
+    fn test_promotions_discount_totals_consistently_per_basket() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            seed: 6,
+            promo_rate: 0.3,
+            // Express stores have a small range, so baskets often repeat a product.
+            store_formats: vec![(StoreFormat::Express, 1.0)],
+            ..SalesConfig::default()
+        });
+        let mut discounts: HashMap<(String, String), f64> = HashMap::new();
+        let mut promoted = 0;
+        for _ in 0..5_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let (original, discount) = (v["original_price"].as_f64().unwrap(), v["discount_rate"].as_f64().unwrap());
+            let quantity = v["quantity"].as_f64().unwrap();
+            assert_eq!(original, v["product"]["unit_price"].as_f64().unwrap());
+            let expected = (original * (1.0 - discount) * quantity * 100.0).round() / 100.0;
+            assert!((v["total_price"].as_f64().unwrap() - expected).abs() < 1e-9, "{}", v);
+            if discount > 0.0 {
+                promoted += 1;
+                assert!((0.1..=0.3).contains(&discount));
+            }
+            let key = (v["basket_id"].as_str().unwrap().to_string() + v["transaction_id"].as_str().unwrap(), v["product"]["product_name"].as_str().unwrap().to_string());
+            assert_eq!(*discounts.entry(key).or_insert(discount), discount, "a product's discount must hold across its basket");
+        }
+        assert!((1_000..2_000).contains(&promoted), "{} promoted lines", promoted);
+    }
+
+    #[test]
+    fn test_returns_have_negative_totals_at_the_return_rate() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            seed: 9,
+            return_rate: 0.1,
+            stockout_rate: 0.05,
+            ..SalesConfig::default()
+        });
+        let mut sold = HashMap::new();
+        let (mut returns, mut stockouts) = (0, 0);
+        for _ in 0..10_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            if v["event_type"] == "stockout" {
+                stockouts += 1;
+                assert!(v.get("transaction_type").is_none(), "stockout tagged as a sale: {}", v);
+                continue;
+            }
+            let (quantity, total) = (v["quantity"].as_i64().unwrap(), v["total_price"].as_f64().unwrap());
+            let product = v["product"]["product_name"].as_str().unwrap().to_string();
+            match v["transaction_type"].as_str().unwrap() {
+                "return" => {
+                    returns += 1;
+                    assert!(quantity < 0 && total < 0.0, "{}", v);
+                    let expected = v["product"]["unit_price"].as_f64().unwrap() * quantity as f64;
+                    assert!((total - expected).abs() < 1e-9);
+                    // The return joins to a real earlier sale of the same product.
+                    let original = v["original_transaction_id"].as_str().unwrap();
+                    let sold_in: &HashSet<String> = sold.get(&product).expect("returned product was never sold");
+                    assert!(sold_in.contains(original), "{} never sold {}", original, product);
+                }
+                "sale" => {
+                    assert!(quantity > 0 && total > 0.0);
+                    assert!(v.get("original_transaction_id").is_none());
+                    let txn = v["transaction_id"].as_str().unwrap().to_string();
+                    sold.entry(product).or_insert_with(HashSet::new).insert(txn);
+                }
+                other => panic!("unexpected transaction type {}", other),
+            }
+        }
+        assert!(stockouts > 0);
+        assert!((850..1_150).contains(&returns), "{} returns", returns);
+    }
+
+    #[test]
+    fn test_payment_method_and_channel_are_fixed_per_basket() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            seed: 10,
+            online_rate: 0.3,
+            ..SalesConfig::default()
+        });
+        let mut baskets: HashMap<String, (String, String)> = HashMap::new();
+        for _ in 0..5_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let (method, channel) = (v["payment_method"].as_str().unwrap(), v["channel"].as_str().unwrap());
+            assert!(["cash", "card", "mobile"].contains(&method));
+            assert!(!(channel == "online" && method == "cash"), "online baskets can't pay cash");
+            let basket = baskets
+                .entry(v["transaction_id"].as_str().unwrap().to_string())
+                .or_insert((method.to_string(), channel.to_string()));
+            assert_eq!(*basket, (method.to_string(), channel.to_string()), "items of one basket must share payment");
+        }
+        let online = baskets.values().filter(|(_, channel)| channel == "online").count() as f64;
+        assert!((online / baskets.len() as f64 - 0.3).abs() < 0.05);
+        assert!(generator.dump().contains("payment_method: "));
+    }
+
+    #[test]
+    fn test_store_and_customer_pools_bound_the_universe() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            seed: 11,
+            store_pool: Some(5),
+            customer_pool: Some(20),
+            ..SalesConfig::default()
+        });
+        let mut towns = std::collections::HashSet::new();
+        let mut customers: HashMap<String, (u64, String)> = HashMap::new();
+        for _ in 0..5_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            towns.insert(v["store"]["town"].as_str().unwrap().to_string());
+            let customer = &v["customer"];
+            let profile = (customer["age"].as_u64().unwrap(), customer["income_band"].as_str().unwrap().to_string());
+            // A repeat customer keeps their profile from basket to basket.
+            let id = customer["customer_id"].as_str().unwrap().to_string();
+            assert_eq!(*customers.entry(id).or_insert(profile.clone()), profile);
+        }
+        assert!(towns.len() <= 5, "{} towns from a pool of 5 stores", towns.len());
+        assert_eq!(customers.len(), 20, "5,000 lines should visit every pooled customer");
+    }
+
+    #[test]
+    fn test_seasonality_fills_weekend_baskets() {
+        // Average basket size over many baskets started around 10:00 UTC on a given day.
+        fn average_basket_size(start_ms: u64, seasonality: bool) -> f64 {
+            let mut generator = SalesGenerator::new(SalesConfig {
+                seed: 4,
+                clock: Clock::Stepped { now_ms: start_ms, step_ms: 100 },
+                seasonality,
+                ..SalesConfig::default()
+            });
+            let mut sizes = Vec::new();
+            for _ in 0..10_000 {
+                generator.generate();
+                let basket = generator.current_basket.as_ref().unwrap();
+                if basket.items_generated == 1 {
+                    sizes.push(basket.total_items as f64);
+                }
+            }
+            sizes.iter().sum::<f64>() / sizes.len() as f64
+        }
+        // Saturday 2023-11-18 and Tuesday 2023-11-14, both 10:00 UTC.
+        let saturday = 1_700_301_600_000;
+        let tuesday = saturday - 4 * 86_400_000;
+        assert_eq!(season(saturday).basket_size, 1.4);
+        let (weekend, weekday) = (average_basket_size(saturday, true), average_basket_size(tuesday, true));
+        assert!(weekend > weekday * 1.3, "weekend {} vs weekday {}", weekend, weekday);
+        // Without seasonality the day makes no difference.
+        assert_eq!(average_basket_size(saturday, false), average_basket_size(tuesday, false));
+        // Nights are quiet and lunchtimes busy.
+        assert!(season(tuesday - 6 * 3_600_000).traffic < 1.0 && season(tuesday + 2 * 3_600_000).traffic > 1.0);
+    }
+
+    #[test]
+    fn test_inter_basket_gaps_advance_simulated_clock() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            clock: Clock::Stepped {
+                now_ms: 1_700_000_000_000,
+                step_ms: 1_000,
+            },
+            inter_basket_gap: 600.0,
+            ..SalesConfig::default()
+        });
+        let mut last_timestamp = 0;
+        let mut basket_starts = Vec::new();
+        let mut previous_txn = String::new();
+        for _ in 0..300 {
+            let v: Value = serde_json::from_str(&generator.try_generate().unwrap()).unwrap();
+            let timestamp = v["timestamp"].as_u64().unwrap();
+            assert!(timestamp >= last_timestamp, "timestamps must be monotonic");
+            last_timestamp = timestamp;
+            let txn = v["transaction_id"].as_str().unwrap().to_string();
+            if txn != previous_txn {
+                basket_starts.push(timestamp);
+                previous_txn = txn;
+            }
+        }
+        // Baskets are at most 15 items (15s at 1s per message) apart without gaps;
+        // with a 10-minute mean gap the average spacing must be far larger.
+        let spans: Vec<u64> = basket_starts.windows(2).map(|w| w[1] - w[0]).collect();
+        let mean_span = spans.iter().sum::<u64>() as f64 / spans.len() as f64;
+        assert!(mean_span > 200.0, "mean basket spacing {}s shows no gaps", mean_span);
+    }
+
+    #[test]
+    fn test_inter_basket_gap_idles_on_system_clock() {
+        let mut generator = SalesGenerator::new(SalesConfig {
+            inter_basket_gap: 3600.0,
+            ..SalesConfig::default()
+        });
+        generator.init_basket(StoreFormat::Standard, 1);
+        assert!(generator.try_generate().is_some());
+        // The basket is complete; the next one is, on average, an hour away.
+        generator.next_basket_at_ms = Some(u64::MAX);
+        assert!(generator.try_generate().is_none());
+    }
+
     #[test]
     fn test_dump() {
-        let mut generator = SalesGenerator::new();
+        let mut generator = SalesGenerator::new(SalesConfig::default());
         // Initialize a basket with 5 items.
-        generator.init_basket(5);
+        generator.init_basket(StoreFormat::Standard, 5);
         let dump_str = generator.dump();
         assert!(dump_str.contains("Basket Summary"),
                 "Dump should contain 'Basket Summary'");
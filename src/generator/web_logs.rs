@@ -0,0 +1,369 @@
+use crate::clock::Clock;
+use crate::line_protocol::LineMapping;
+use crate::rng::{RngAlgorithm, SimRng};
+use chrono::DateTime;
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::Distribution;
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Status code weights used unless `--status-weights` says otherwise.
+pub const DEFAULT_STATUS_WEIGHTS: &str = "200=0.9,304=0.04,404=0.05,500=0.01";
+
+/// Host the `referrer` URLs point at.
+const SITE: &str = "https://shop.example.com";
+
+/// The site map: each path template with its share of requests, the methods it is hit
+/// with, and the typical size (bytes) of a successful response. `{id}` and `{query}` are
+/// filled in per request.
+const PAGES: &[(&str, f64, &[&str], u64)] = &[
+    ("/", 0.18, &["GET"], 18_000),
+    ("/products", 0.12, &["GET"], 42_000),
+    ("/products/{id}", 0.22, &["GET"], 27_000),
+    ("/search?q={query}", 0.1, &["GET"], 35_000),
+    ("/cart", 0.07, &["GET", "POST"], 9_000),
+    ("/checkout", 0.03, &["GET", "POST"], 12_000),
+    ("/login", 0.04, &["GET", "POST"], 6_000),
+    ("/api/orders", 0.04, &["GET", "POST"], 1_500),
+    ("/static/app.js", 0.12, &["GET"], 210_000),
+    ("/static/style.css", 0.08, &["GET"], 48_000),
+];
+
+const SEARCH_TERMS: &[&str] = &["shoes", "laptop", "coffee", "headphones", "gift+ideas", "garden", "toys"];
+
+const USER_AGENTS: &[&str] = &[
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_5) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Safari/605.1.15",
+    "Mozilla/5.0 (X11; Linux x86_64; rv:127.0) Gecko/20100101 Firefox/127.0",
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1",
+    "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Mobile Safari/537.36",
+    "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+    "curl/8.6.0",
+];
+
+/// Parse `--status-weights`, e.g. `200=0.9,404=0.08,500=0.02`.
+pub fn parse_status_weights(spec: &str) -> Result<Vec<(u16, f64)>, String> {
+    crate::weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(code, weight)| match code.parse::<u16>() {
+            Ok(status @ 100..=599) => Ok((status, weight)),
+            _ => Err(format!("'{}' is not an HTTP status code", code)),
+        })
+        .collect()
+}
+
+/// One access-log entry; `referrer` is absent on a session's first request.
+#[derive(Serialize)]
+struct Request<'a> {
+    bytes: u64,
+    ip: &'a str,
+    method: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referrer: Option<&'a str>,
+    status: u16,
+    timestamp: u64,
+    user_agent: &'a str,
+}
+
+/// A client session: one IP and browser, browsing from page to page.
+#[derive(Debug)]
+pub struct Client {
+    pub ip: String,
+    pub user_agent: &'static str,
+    /// URL of the last page this client loaded, sent as the next request's referrer.
+    pub last_page: Option<String>,
+    pub requests: u64,
+}
+
+/// Tunable parameters for the access-log model.
+#[derive(Debug, Clone)]
+pub struct WebLogsConfig {
+    /// Relative weight of each response status code.
+    pub status_weights: Vec<(u16, f64)>,
+    /// Seed for the generator's random number stream.
+    pub seed: u64,
+    /// Pinned algorithm backing the random number stream.
+    pub rng: RngAlgorithm,
+    /// Source of message timestamps.
+    pub clock: Clock,
+}
+
+impl Default for WebLogsConfig {
+    fn default() -> Self {
+        WebLogsConfig {
+            status_weights: parse_status_weights(DEFAULT_STATUS_WEIGHTS).expect("default status weights parse"),
+            seed: rand::random(),
+            rng: RngAlgorithm::Chacha8,
+            clock: Clock::System,
+        }
+    }
+}
+
+/// An HTTP access-log generator: client sessions request pages from a small site map,
+/// with weighted response statuses.
+pub struct WebLogsGenerator {
+    clients: Vec<Client>,
+    statuses: Vec<u16>,
+    status_index: WeightedIndex<f64>,
+    page_index: WeightedIndex<f64>,
+    rng: SimRng,
+    clock: Clock,
+    /// Index of the client that made the last request.
+    last_client: Option<usize>,
+}
+
+impl WebLogsGenerator {
+    pub fn new(variants: usize, config: WebLogsConfig) -> Self {
+        let mut rng = SimRng::new(config.rng, config.seed);
+        let clients = (0..variants)
+            .map(|_| Client {
+                ip: format!(
+                    "{}.{}.{}.{}",
+                    rng.random_range(1..=223),
+                    rng.random_range(0..=255),
+                    rng.random_range(0..=255),
+                    rng.random_range(1..=254)
+                ),
+                user_agent: USER_AGENTS.choose(&mut rng).expect("user agents are listed"),
+                last_page: None,
+                requests: 0,
+            })
+            .collect();
+        let (statuses, weights): (Vec<u16>, Vec<f64>) = config.status_weights.iter().copied().unzip();
+        WebLogsGenerator {
+            clients,
+            statuses,
+            status_index: WeightedIndex::new(weights).expect("status weights are validated"),
+            page_index: WeightedIndex::new(PAGES.iter().map(|page| page.1)).expect("page weights are positive"),
+            rng,
+            clock: config.clock,
+            last_client: None,
+        }
+    }
+
+    /// Make one request from a random client and render it into `buf`. Returns false if
+    /// there are no clients.
+    fn next_request(&mut self, buf: &mut String) -> bool {
+        if self.clients.is_empty() {
+            return false;
+        }
+        let idx = self.rng.random_range(0..self.clients.len());
+        self.last_client = Some(idx);
+        let rng = &mut self.rng;
+
+        let (template, _, methods, size) = PAGES[self.page_index.sample(rng)];
+        let path = if template.contains("{id}") {
+            template.replace("{id}", &rng.random_range(1..=500).to_string())
+        } else if template.contains("{query}") {
+            template.replace("{query}", SEARCH_TERMS.choose(rng).expect("search terms are listed"))
+        } else {
+            template.to_string()
+        };
+        let method = *methods.choose(rng).expect("pages have methods");
+        let status = self.statuses[self.status_index.sample(rng)];
+        let bytes = match status {
+            204 | 304 => 0,
+            200..=299 => rng.random_range(size / 2..=size * 3 / 2),
+            400..=499 => rng.random_range(150..=600),
+            500..=599 => rng.random_range(200..=900),
+            _ => rng.random_range(0..=300),
+        };
+
+        let client = &mut self.clients[idx];
+        client.requests += 1;
+        let request = Request {
+            bytes,
+            ip: &client.ip,
+            method,
+            path: &path,
+            referrer: client.last_page.as_deref(),
+            status,
+            timestamp: self.clock.now_millis() / 1000,
+            user_agent: client.user_agent,
+        };
+        buf.clear();
+        super::write_json(buf, &request);
+        // Page views, not assets or API calls, are what later requests are referred from.
+        if method == "GET" && status == 200 && !path.starts_with("/static/") && !path.starts_with("/api/") {
+            client.last_page = Some(format!("{}{}", SITE, path));
+        }
+        true
+    }
+}
+
+impl crate::Generator for WebLogsGenerator {
+    fn try_generate(&mut self) -> Option<String> {
+        let mut message = String::new();
+        self.next_request(&mut message).then_some(message)
+    }
+
+    fn generate_into(&mut self, buf: &mut String) -> bool {
+        self.next_request(buf)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    fn generate(&mut self) -> String {
+        self.try_generate().unwrap_or_else(|| "{}".to_string())
+    }
+
+    fn fields(&self) -> Vec<&'static str> {
+        vec!["ip", "method", "path", "status", "bytes", "referrer", "user_agent", "timestamp"]
+    }
+
+    fn schema(&self) -> Value {
+        let fields = [
+            ("ip", json!({"type": "string", "description": "Client IPv4 address"})),
+            ("method", json!({"type": "string", "enum": ["GET", "POST"]})),
+            ("path", json!({"type": "string", "description": "Request path, with any query string"})),
+            ("status", json!({"type": "integer", "minimum": 100, "maximum": 599})),
+            ("bytes", json!({"type": "integer", "minimum": 0, "description": "Response body size"})),
+            ("referrer", json!({"type": "string", "description": "Previous page of the session, absent on its first request"})),
+            ("user_agent", json!({"type": "string"})),
+            ("timestamp", json!({"type": "integer", "minimum": 0, "description": "Unix time in seconds"})),
+        ];
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).filter(|name| *name != "referrer").collect();
+        super::object_schema("fluxfakr web access log", &fields, &required)
+    }
+
+    fn key(&self) -> Option<String> {
+        self.last_client.map(|idx| self.clients[idx].ip.clone())
+    }
+
+    fn line_mapping(&self) -> Option<LineMapping> {
+        Some(LineMapping {
+            tags: vec!["method", "status"],
+            fields: vec!["bytes", "path"],
+            timestamp: "timestamp",
+        })
+    }
+
+    /// Every client session and its request count, as CSV.
+    fn dump(&self) -> String {
+        let mut csv = String::from("ip,requests,user_agent\n");
+        for client in &self.clients {
+            csv.push_str(&format!("{},{},\"{}\"\n", client.ip, client.requests, client.user_agent));
+        }
+        csv
+    }
+}
+
+/// Append `s` as a quoted Combined Log Format field, escaping quotes and backslashes the
+/// way Apache does.
+fn push_quoted(line: &mut String, s: &str) {
+    line.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            line.push('\\');
+        }
+        line.push(c);
+    }
+    line.push('"');
+}
+
+/// Render one access-log message as a Combined Log Format line:
+/// `ip - - [10/Oct/2024:13:55:36 +0000] "GET /path HTTP/1.1" 200 2326 "referrer" "agent"`.
+/// Missing fields are written as `-`.
+pub fn combined_log_line(message: &str) -> Result<String, String> {
+    let value: Value = serde_json::from_str(message).map_err(|e| format!("invalid JSON: {}", e))?;
+    let text = |field: &str| value.get(field).and_then(Value::as_str).unwrap_or("-");
+    let number = |field: &str| value.get(field).and_then(Value::as_u64);
+    let mut line = String::with_capacity(message.len());
+    line.push_str(text("ip"));
+    line.push_str(" - - [");
+    match number("timestamp").and_then(|seconds| DateTime::from_timestamp(seconds as i64, 0)) {
+        Some(time) => line.push_str(&time.format("%d/%b/%Y:%H:%M:%S %z").to_string()),
+        None => line.push('-'),
+    }
+    line.push_str("] ");
+    push_quoted(&mut line, &format!("{} {} HTTP/1.1", text("method"), text("path")));
+    line.push(' ');
+    line.push_str(&number("status").map_or("-".to_string(), |status| status.to_string()));
+    line.push(' ');
+    // Like Apache's `%b`, an empty body is `-` rather than 0.
+    line.push_str(&number("bytes").filter(|bytes| *bytes > 0).map_or("-".to_string(), |bytes| bytes.to_string()));
+    line.push(' ');
+    push_quoted(&mut line, text("referrer"));
+    line.push(' ');
+    push_quoted(&mut line, text("user_agent"));
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Generator;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn test_status_distribution_follows_weights() {
+        let weights = vec![(200, 0.6), (404, 0.3), (500, 0.1)];
+        let mut generator = WebLogsGenerator::new(
+            20,
+            WebLogsConfig {
+                status_weights: weights.clone(),
+                seed: 11,
+                ..WebLogsConfig::default()
+            },
+        );
+        let total = 20_000;
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for _ in 0..total {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            *counts.entry(v["status"].as_u64().unwrap()).or_default() += 1;
+        }
+        assert_eq!(counts.len(), weights.len());
+        for (status, weight) in weights {
+            let share = counts[&(status as u64)] as f64 / total as f64;
+            assert!((share - weight).abs() < 0.02, "status {} share {} vs weight {}", status, share, weight);
+        }
+    }
+
+    #[test]
+    fn test_sessions_keep_their_client_and_referrer() {
+        let mut generator = WebLogsGenerator::new(5, WebLogsConfig { seed: 3, ..WebLogsConfig::default() });
+        let mut agents: HashMap<String, String> = HashMap::new();
+        let mut paths = HashSet::new();
+        for _ in 0..2_000 {
+            let v: Value = serde_json::from_str(&generator.generate()).unwrap();
+            let ip = v["ip"].as_str().unwrap().to_string();
+            assert_eq!(generator.key().as_deref(), Some(ip.as_str()));
+            let agent = agents.entry(ip).or_insert_with(|| v["user_agent"].as_str().unwrap().to_string());
+            assert_eq!(v["user_agent"], *agent);
+            if let Some(referrer) = v["referrer"].as_str() {
+                assert!(referrer.starts_with(SITE), "{}", referrer);
+            }
+            if v["status"] == 304 {
+                assert_eq!(v["bytes"], 0);
+            }
+            paths.insert(v["path"].as_str().unwrap().split(['/', '?']).nth(1).unwrap().to_string());
+        }
+        assert_eq!(agents.len(), 5);
+        assert!(paths.len() >= 6, "{:?}", paths);
+    }
+
+    #[test]
+    fn test_combined_log_line() {
+        let message = r#"{"bytes":2326,"ip":"203.0.113.9","method":"GET","path":"/search?q=\"x\"","referrer":"https://shop.example.com/","status":200,"timestamp":1700000000,"user_agent":"curl/8.6.0"}"#;
+        assert_eq!(
+            combined_log_line(message).unwrap(),
+            r#"203.0.113.9 - - [14/Nov/2023:22:13:20 +0000] "GET /search?q=\"x\" HTTP/1.1" 200 2326 "https://shop.example.com/" "curl/8.6.0""#
+        );
+        let first = r#"{"bytes":0,"ip":"203.0.113.9","method":"GET","path":"/","status":304,"timestamp":1700000000,"user_agent":"curl/8.6.0"}"#;
+        assert!(combined_log_line(first).unwrap().ends_with(r#"304 - "-" "curl/8.6.0""#));
+        assert!(combined_log_line("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_weights() {
+        assert_eq!(parse_status_weights("200=0.9, 404=0.1").unwrap(), vec![(200, 0.9), (404, 0.1)]);
+        assert!(parse_status_weights("ok=1").is_err());
+        assert!(parse_status_weights("700=1").is_err());
+        assert!(parse_status_weights("200=0").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::stats::RunStats;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a probe connection may take to send its request line.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Health of the run as reported to probes: an HTTP status and a one-line reason.
+fn status(running: &AtomicBool, stats: &RunStats) -> (u16, &'static str) {
+    if !running.load(Ordering::SeqCst) {
+        (503, "shutting down")
+    } else if stats.all_sinks_failing() {
+        (503, "all sinks failing")
+    } else {
+        (200, "ok")
+    }
+}
+
+/// Answer one probe: `GET /healthz` gets the run's status, anything else a 404.
+fn respond(stream: TcpStream, running: &AtomicBool, stats: &RunStats) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (code, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/healthz")) => status(running, stats),
+        _ => (404, "not found"),
+    };
+    let reason = match code {
+        200 => "OK",
+        503 => "Service Unavailable",
+        _ => "Not Found",
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+        code,
+        reason,
+        body.len() + 1,
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serve `GET /healthz` on `port` from a background thread. Returns 200 while the main
+/// loop runs and at least one sink is healthy, and 503 once shutdown begins or while
+/// every sink is failing. Binding happens up front so a taken port fails at startup.
+pub fn serve(port: u16, running: Arc<AtomicBool>, stats: Arc<RunStats>) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Probes are tiny and infrequent; answer them one at a time.
+            let _ = respond(stream, &running, &stats);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_healthz_reflects_run_and_sink_state() {
+        let running = Arc::new(AtomicBool::new(true));
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let port = free_port();
+        serve(port, running.clone(), stats.clone()).unwrap();
+
+        assert!(get(port, "/healthz").starts_with("HTTP/1.1 200 OK"));
+        assert!(get(port, "/other").starts_with("HTTP/1.1 404"));
+
+        stats.sink("kafka").set_available(false);
+        let response = get(port, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.ends_with("all sinks failing\n"));
+
+        stats.sink("kafka").set_available(true);
+        running.store(false, Ordering::SeqCst);
+        assert!(get(port, "/healthz").ends_with("shutting down\n"));
+    }
+}
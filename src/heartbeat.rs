@@ -0,0 +1,71 @@
+use chrono::Utc;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Tracks output activity and signals when an idle heartbeat is due.
+pub struct Heartbeat {
+    interval: Duration,
+    last_activity: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Heartbeat {
+            interval,
+            last_activity: now,
+        }
+    }
+
+    /// Note that a real message was emitted, postponing the next heartbeat.
+    pub fn record_activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    /// Returns true if the stream has been idle for at least the interval.
+    /// A due heartbeat counts as activity, so heartbeats repeat once per interval.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last_activity) >= self.interval {
+            self.last_activity = now;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The heartbeat payload. The `_heartbeat` key marks it so consumers can filter it out.
+pub fn heartbeat_message(module: &str) -> String {
+    json!({
+        "_heartbeat": true,
+        "module": module,
+        "timestamp": Utc::now().timestamp(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_heartbeat_due_only_after_idle_interval() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new(Duration::from_secs(5), start);
+        assert!(!heartbeat.poll(start + Duration::from_secs(4)));
+        heartbeat.record_activity(start + Duration::from_secs(4));
+        assert!(!heartbeat.poll(start + Duration::from_secs(8)));
+        assert!(heartbeat.poll(start + Duration::from_secs(9)));
+        // The next one is due a full interval after the previous heartbeat.
+        assert!(!heartbeat.poll(start + Duration::from_secs(13)));
+        assert!(heartbeat.poll(start + Duration::from_secs(14)));
+    }
+
+    #[test]
+    fn test_heartbeat_message_is_marked() {
+        let msg: Value = serde_json::from_str(&heartbeat_message("stock")).unwrap();
+        assert_eq!(msg["_heartbeat"], true);
+        assert_eq!(msg["module"], "stock");
+        assert!(msg["timestamp"].is_i64());
+    }
+}
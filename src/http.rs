@@ -0,0 +1,209 @@
+//! HTTP webhook output: each message is POSTed as its own request, over plain HTTP.
+
+use crate::sink::{Sink, SinkError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Further attempts after a 5xx response or connection failure, before giving up.
+const MAX_RETRIES: u32 = 2;
+/// Delay before the first retry, doubling after each further one.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+/// After a message fails every attempt, later messages fail fast for this long, so a
+/// dead endpoint doesn't stall the stream by a timeout per message.
+const COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Where an `http://` URL points.
+#[derive(Debug, PartialEq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+/// Split an `http://host[:port][/path]` URL. HTTPS is not supported.
+pub fn parse_url(url: &str) -> Result<Endpoint, String> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None if url.starts_with("https://") => {
+            return Err("https is not supported; use a plain http:// URL or a TLS-terminating proxy".to_string())
+        }
+        None => return Err(format!("'{}' is not an http:// URL", url)),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port.parse().map_err(|_| format!("invalid port '{}'", port))?;
+            (host, port)
+        }
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("'{}' has no host", url));
+    }
+    Ok(Endpoint {
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+/// POSTs each message to a webhook with `Content-Type: application/json`, retrying
+/// 5xx responses and connection failures.
+pub struct HttpSink {
+    endpoint: Endpoint,
+    addr: SocketAddr,
+    timeout: Duration,
+    retry_after: Instant,
+}
+
+impl HttpSink {
+    /// Parse the URL and resolve its host, so a bad endpoint fails before streaming.
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, String> {
+        let endpoint = parse_url(url)?;
+        let addr = (endpoint.host.as_str(), endpoint.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("cannot resolve {}: {}", endpoint.host, e))?
+            .next()
+            .ok_or_else(|| format!("cannot resolve {}", endpoint.host))?;
+        Ok(HttpSink {
+            endpoint,
+            addr,
+            timeout,
+            retry_after: Instant::now(),
+        })
+    }
+
+    /// Make one request, returning the response's status code.
+    fn post(&self, payload: &str) -> io::Result<u16> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.endpoint.path,
+            self.endpoint.host,
+            self.endpoint.port,
+            payload.len(),
+            payload
+        )?;
+        stream.flush()?;
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line)?;
+        status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad status line '{}'", status_line.trim_end())))
+    }
+}
+
+impl Sink for HttpSink {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        if Instant::now() < self.retry_after {
+            return Err(SinkError::from(io::Error::new(io::ErrorKind::NotConnected, "endpoint failing, waiting to retry")));
+        }
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let error = match self.post(payload) {
+                Ok(code) if (200..300).contains(&code) => return Ok(()),
+                // Client errors won't go away on a retry.
+                Ok(code) if code < 500 => return Err(SinkError::from(io::Error::other(format!("HTTP {}", code)))),
+                Ok(code) => io::Error::other(format!("HTTP {}", code)),
+                Err(e) => e,
+            };
+            if attempt == MAX_RETRIES {
+                self.retry_after = Instant::now() + COOLDOWN;
+                return Err(error.into());
+            }
+            attempt += 1;
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    fn flush(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("http://localhost:8080/ingest/events").unwrap(),
+            Endpoint {
+                host: "localhost".to_string(),
+                port: 8080,
+                path: "/ingest/events".to_string(),
+            }
+        );
+        let bare = parse_url("http://example.com").unwrap();
+        assert_eq!((bare.port, bare.path.as_str()), (80, "/"));
+        assert!(parse_url("https://example.com").unwrap_err().contains("https"));
+        assert!(parse_url("example.com").is_err());
+        assert!(parse_url("http://host:port/").is_err());
+        assert!(parse_url("http://:80/").is_err());
+    }
+
+    /// Answer each connection with the next status code, returning the request bodies.
+    fn serve(listener: TcpListener, codes: Vec<u16>) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for code in codes {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                write!(stream, "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n", code).unwrap();
+            }
+            bodies
+        })
+    }
+
+    #[test]
+    fn test_retries_server_errors_then_fails_fast() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = serve(listener, vec![503, 200, 500, 500, 500, 400]);
+        let mut sink = HttpSink::new(&url, Duration::from_secs(2)).unwrap();
+
+        // A 5xx is retried until it succeeds.
+        sink.send(r#"{"a":1}"#, "").unwrap();
+        // Every attempt failing gives up, then later sends fail without a request.
+        let error = sink.send(r#"{"a":2}"#, "").unwrap_err();
+        assert!(error.to_string().contains("500"), "{}", error);
+        assert!(sink.send(r#"{"a":3}"#, "").is_err());
+        // Once the cooldown is over, requests resume; a 4xx is not retried.
+        sink.retry_after = Instant::now();
+        assert!(sink.send(r#"{"a":4}"#, "").unwrap_err().to_string().contains("400"));
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies, vec![r#"{"a":1}"#, r#"{"a":1}"#, r#"{"a":2}"#, r#"{"a":2}"#, r#"{"a":2}"#, r#"{"a":4}"#]);
+    }
+}
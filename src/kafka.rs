@@ -0,0 +1,832 @@
+use crate::backoff::{CircuitBreaker, CircuitState, LogLimiter};
+use crate::dead_letter::DeadLetterFile;
+use crate::format::PayloadEncoder;
+use crate::sink::{Sink, SinkError};
+use crate::stats::RunStats;
+use clap::ValueEnum;
+use rdkafka::config::{ClientConfig, FromClientConfigAndContext};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{DeliveryResult, Header, Message, OwnedHeaders, ToBytes};
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::ClientContext;
+use std::collections::VecDeque;
+use std::net::ToSocketAddrs;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most messages held back while the broker is down under the `buffer` policy;
+/// beyond this the oldest are dead-lettered.
+const MAX_BUFFERED: usize = 100_000;
+/// Repeated Kafka errors are logged at most once per window.
+const ERROR_LOG_WINDOW: Duration = Duration::from_secs(5);
+/// How long a send waits for deliveries to free up librdkafka's queue before retrying.
+const QUEUE_FULL_WAIT: Duration = Duration::from_millis(10);
+
+/// Delivery ordering guarantee requested for Kafka output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OrderingMode {
+    /// All messages sharing a key land on the same partition, in production order,
+    /// even when librdkafka retries or pipelines requests.
+    PerKey,
+    /// No ordering guarantee across retries; records are still keyed, so each key
+    /// maps to one partition.
+    None,
+}
+
+/// What happens to messages while the Kafka circuit breaker is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutagePolicy {
+    /// Hold messages in memory and replay them in order once the broker recovers.
+    Buffer,
+    /// Drop messages (dead-lettering them, if configured) until the broker recovers.
+    Drop,
+}
+
+/// Explicit partition targeting for Kafka records, overriding key-based partitioning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionSpec {
+    /// Every record goes to this partition.
+    Fixed(i32),
+    /// Records cycle through the topic's partitions in order.
+    RoundRobin,
+}
+
+impl FromStr for PartitionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "round-robin" {
+            return Ok(PartitionSpec::RoundRobin);
+        }
+        match s.parse::<i32>() {
+            Ok(partition) if partition >= 0 => Ok(PartitionSpec::Fixed(partition)),
+            _ => Err(format!(
+                "expected a partition number or 'round-robin', got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+/// Picks the partition of each record according to a `PartitionSpec`.
+#[derive(Debug)]
+struct Partitioner {
+    spec: PartitionSpec,
+    /// Partition count of the topic, once known from metadata (0 until then).
+    count: AtomicI32,
+    /// Records assigned so far, driving the round-robin cycle.
+    assigned: AtomicU64,
+}
+
+impl Partitioner {
+    fn new(spec: PartitionSpec) -> Self {
+        Partitioner {
+            spec,
+            count: AtomicI32::new(0),
+            assigned: AtomicU64::new(0),
+        }
+    }
+
+    /// Partition for the next record; `None` leaves the choice to librdkafka.
+    fn next(&self) -> Option<i32> {
+        match self.spec {
+            PartitionSpec::Fixed(partition) => Some(partition),
+            PartitionSpec::RoundRobin => {
+                let count = self.count.load(Ordering::Relaxed);
+                if count <= 0 {
+                    return None;
+                }
+                let n = self.assigned.fetch_add(1, Ordering::Relaxed);
+                Some((n % count as u64) as i32)
+            }
+        }
+    }
+}
+
+/// Build the producer configuration for the given broker and ordering mode.
+///
+/// In `per-key` mode the idempotent producer is enabled, which makes librdkafka
+/// preserve per-partition order across retries and in-flight batches. Either way
+/// librdkafka's default partitioner hashes the keys, so every key maps to one fixed
+/// partition.
+fn producer_config(broker: &str, ordering: OrderingMode) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", broker);
+    if ordering == OrderingMode::PerKey {
+        config.set("enable.idempotence", "true");
+    }
+    config
+}
+
+/// Check that at least one bootstrap server in a comma-separated `broker` list resolves,
+/// so a typo'd or unresolvable address fails at startup rather than as a stream of
+/// librdkafka errors. Entries may carry a `protocol://` prefix; the port defaults to 9092.
+fn check_bootstrap_servers(broker: &str) -> Result<(), String> {
+    let servers: Vec<&str> = broker.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+    if servers.is_empty() {
+        return Err("no bootstrap servers given".to_string());
+    }
+    let mut errors = Vec::new();
+    for server in &servers {
+        let address = server.rsplit_once("://").map_or(*server, |(_, address)| address);
+        let resolved = if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            address.to_socket_addrs()
+        } else {
+            (address, 9092).to_socket_addrs()
+        };
+        match resolved.map(|mut addrs| addrs.next().is_some()) {
+            Ok(true) => return Ok(()),
+            Ok(false) => errors.push(format!("{}: no addresses", server)),
+            Err(e) => errors.push(format!("{}: {}", server, e)),
+        }
+    }
+    Err(format!("cannot resolve any bootstrap server ({})", errors.join("; ")))
+}
+
+/// The librdkafka producers the Kafka sink can send through. `BaseProducer` serves
+/// delivery callbacks only when polled, which `send` does on the generating thread after
+/// every message. `ThreadedProducer` (`--async-kafka`) polls on a background thread of its
+/// own, so callbacks are handled concurrently with sending and `send` never polls.
+pub trait KafkaProducer: Producer<DeliveryContext> + FromClientConfigAndContext<DeliveryContext> {
+    /// Hand a record to librdkafka, handing it back with the error if it was rejected.
+    #[allow(clippy::result_large_err)]
+    fn send_record<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized>(
+        &self,
+        record: BaseRecord<'a, K, P>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P>)>;
+    /// Serve any delivery callbacks that are ready, without blocking.
+    fn serve(&self);
+    /// Serve delivery callbacks for up to `timeout`, e.g. while waiting for the queue to drain.
+    fn wait(&self, timeout: Duration);
+}
+
+impl KafkaProducer for BaseProducer<DeliveryContext> {
+    fn send_record<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized>(
+        &self,
+        record: BaseRecord<'a, K, P>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P>)> {
+        self.send(record)
+    }
+
+    fn serve(&self) {
+        self.poll(Duration::from_millis(0));
+    }
+
+    fn wait(&self, timeout: Duration) {
+        self.poll(timeout);
+    }
+}
+
+impl KafkaProducer for ThreadedProducer<DeliveryContext> {
+    fn send_record<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized>(
+        &self,
+        record: BaseRecord<'a, K, P>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P>)> {
+        self.send(record)
+    }
+
+    /// The background thread serves callbacks as they arrive.
+    fn serve(&self) {}
+
+    fn wait(&self, timeout: Duration) {
+        self.poll(timeout);
+    }
+}
+
+/// Create the producer for `broker`, reporting bad bootstrap servers or configuration
+/// as an error instead of panicking.
+pub fn create_producer<P: KafkaProducer>(
+    broker: &str,
+    ordering: OrderingMode,
+    context: DeliveryContext,
+) -> Result<P, String> {
+    check_bootstrap_servers(broker)?;
+    producer_config(broker, ordering)
+        .create_with_context(context)
+        .map_err(|e| e.to_string())
+}
+
+/// Connect the Kafka sink: create the producer for `broker`, `ThreadedProducer` if
+/// `threaded` (`--async-kafka`) and `BaseProducer` otherwise, and check its partitioning
+/// against `topic`.
+pub fn connect(
+    broker: &str,
+    topic: String,
+    ordering: OrderingMode,
+    context: DeliveryContext,
+    threaded: bool,
+) -> Result<Box<dyn Sink>, String> {
+    fn open<P: KafkaProducer + 'static>(
+        broker: &str,
+        topic: String,
+        ordering: OrderingMode,
+        context: DeliveryContext,
+    ) -> Result<Box<dyn Sink>, String> {
+        let producer: P = create_producer(broker, ordering, context)
+            .map_err(|e| format!("cannot create Kafka producer for {}: {}", broker, e))?;
+        configure_partitions(&producer, &topic).map_err(|e| format!("--kafka-partition: {}", e))?;
+        Ok(Box::new(KafkaSink::new(producer, topic)))
+    }
+    if threaded {
+        open::<ThreadedProducer<DeliveryContext>>(broker, topic, ordering, context)
+    } else {
+        open::<BaseProducer<DeliveryContext>>(broker, topic, ordering, context)
+    }
+}
+
+/// A message held back while the broker is down.
+struct Pending {
+    payload: String,
+    key: String,
+    headers: Option<OwnedHeaders>,
+}
+
+/// Broker health as seen by the producer: circuit breaker, error log limiter and
+/// the backlog buffered during an outage.
+struct Health {
+    breaker: CircuitBreaker,
+    errors: LogLimiter,
+    backlog: VecDeque<Pending>,
+}
+
+/// Producer context that counts delivery outcomes, reports failed deliveries and
+/// routes them to the dead-letter file when one is configured. It also tracks broker
+/// health: repeated failures open a circuit breaker that pauses sending, with
+/// exponential backoff between recovery probes and rate-limited error logging.
+pub struct DeliveryContext {
+    dead_letter: Option<Arc<DeadLetterFile>>,
+    stats: Arc<RunStats>,
+    policy: OutagePolicy,
+    health: Mutex<Health>,
+    partitioner: Option<Partitioner>,
+    encoder: Option<PayloadEncoder>,
+    /// Cleared when the run is interrupted, so a send stops waiting on a full queue.
+    running: Arc<AtomicBool>,
+}
+
+impl DeliveryContext {
+    /// `stats` must have a `kafka` sink registered.
+    pub fn new(dead_letter: Option<Arc<DeadLetterFile>>, stats: Arc<RunStats>, policy: OutagePolicy) -> Self {
+        DeliveryContext {
+            dead_letter,
+            stats,
+            policy,
+            health: Mutex::new(Health {
+                breaker: CircuitBreaker::default(),
+                errors: LogLimiter::new(ERROR_LOG_WINDOW),
+                backlog: VecDeque::new(),
+            }),
+            partitioner: None,
+            encoder: None,
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Target partitions explicitly instead of by key; see `configure_partitions`.
+    pub fn with_partitioning(mut self, spec: Option<PartitionSpec>) -> Self {
+        self.partitioner = spec.map(Partitioner::new);
+        self
+    }
+
+    /// Send records in a binary format instead of as JSON (see `--format`).
+    pub fn with_encoder(mut self, encoder: Option<PayloadEncoder>) -> Self {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Stop waiting for room in a full queue once `running` is cleared.
+    pub fn with_shutdown(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = running;
+        self
+    }
+
+    /// Count a message as failed and dead-letter it.
+    fn fail(&self, reason: &str, payload: &str) {
+        self.stats.sink("kafka").record_failed();
+        if let Some(dead_letter) = &self.dead_letter {
+            dead_letter.record("kafka", reason, payload);
+        }
+    }
+
+    /// Feed a failure into the breaker, logging (rate-limited) and reporting a trip.
+    fn record_failure(&self, message: &str) {
+        let now = Instant::now();
+        let mut health = self.health.lock().unwrap();
+        health.errors.log(now, message);
+        if health.breaker.record_failure(now) {
+            self.on_trip(&health.breaker);
+        }
+    }
+
+    fn on_trip(&self, breaker: &CircuitBreaker) {
+        let counters = self.stats.sink("kafka");
+        counters.record_trip();
+        counters.set_available(false);
+        let action = match self.policy {
+            OutagePolicy::Buffer => "buffering",
+            OutagePolicy::Drop => "dropping",
+        };
+        eprintln!(
+            "Kafka broker appears down; pausing sends and {} messages until it recovers (outage #{})",
+            action,
+            breaker.trips()
+        );
+    }
+}
+
+impl ClientContext for DeliveryContext {
+    fn error(&self, error: KafkaError, reason: &str) {
+        let now = Instant::now();
+        let mut health = self.health.lock().unwrap();
+        health.errors.log(now, &format!("Kafka client error: {}: {}", error, reason));
+        // Every broker unreachable: open the circuit now rather than waiting for
+        // per-message timeouts, and fail a recovery probe.
+        if error.rdkafka_error_code() == Some(RDKafkaErrorCode::AllBrokersDown) {
+            let opened = match health.breaker.state() {
+                CircuitState::HalfOpen => health.breaker.record_failure(now),
+                _ => health.breaker.trip(now),
+            };
+            if opened {
+                self.on_trip(&health.breaker);
+            }
+        }
+    }
+}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _opaque: Self::DeliveryOpaque) {
+        match result {
+            Ok(_) => {
+                self.stats.sink("kafka").record_sent();
+                if self.health.lock().unwrap().breaker.record_success() {
+                    self.stats.sink("kafka").set_available(true);
+                    eprintln!("Kafka broker recovered; resuming sends");
+                }
+            }
+            Err((e, message)) => {
+                // Binary records are dead-lettered as the JSON they were encoded from.
+                let decoded = self
+                    .encoder
+                    .as_ref()
+                    .zip(message.payload())
+                    .and_then(|(encoder, data)| encoder.decode(data).ok())
+                    .map(|value| value.to_string());
+                let payload = decoded
+                    .as_deref()
+                    .or_else(|| message.payload_view::<str>().and_then(Result::ok))
+                    .unwrap_or("");
+                self.fail(&e.to_string(), payload);
+                self.record_failure(&format!("Failed to deliver message to Kafka: {}", e));
+            }
+        }
+    }
+}
+
+/// Hand one record to librdkafka. While its queue is full this waits for deliveries to
+/// make room, so the sink pushes back on the loop instead of dropping messages, until the
+/// run is interrupted; those rejected for any other reason are reported and dead-lettered
+/// immediately.
+fn enqueue(producer: &impl KafkaProducer, topic: &str, pending: Pending) {
+    let context = producer.context();
+    let encoded;
+    let mut payload = pending.payload.as_bytes();
+    // Only control records carry headers.
+    let encoder = context.encoder.as_ref().filter(|e| pending.headers.is_none() || e.encodes_control());
+    if let Some(encoder) = encoder {
+        match encoder.encode(&pending.payload) {
+            Ok(data) => {
+                encoded = data;
+                payload = &encoded;
+            }
+            Err(e) => {
+                context.fail(&format!("cannot encode message: {}", e), &pending.payload);
+                context.health.lock().unwrap().errors.log(Instant::now(), &format!("Cannot encode message for Kafka: {}", e));
+                return;
+            }
+        }
+    }
+    let mut record = BaseRecord::to(topic).payload(payload).key(&pending.key);
+    if let Some(headers) = pending.headers {
+        record = record.headers(headers);
+    }
+    if let Some(partition) = producer.context().partitioner.as_ref().and_then(Partitioner::next) {
+        record = record.partition(partition);
+    }
+    loop {
+        match producer.send_record(record) {
+            Ok(()) => return,
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _))
+                if !context.running.load(Ordering::SeqCst) =>
+            {
+                context.fail("kafka queue full at shutdown", &pending.payload);
+                return;
+            }
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                record = rejected;
+                producer.wait(QUEUE_FULL_WAIT);
+            }
+            Err((e, _)) => {
+                context.fail(&e.to_string(), &pending.payload);
+                context.record_failure(&format!("Failed to send message to Kafka: {}", e));
+                return;
+            }
+        }
+    }
+}
+
+/// Send a record and serve delivery callbacks. While the circuit breaker is open the
+/// message is buffered or dropped according to the outage policy; once the broker
+/// recovers, any buffered backlog is replayed in order ahead of new messages.
+pub fn send(
+    producer: &impl KafkaProducer,
+    topic: &str,
+    payload: &str,
+    key: &str,
+    headers: Option<OwnedHeaders>,
+) {
+    let context = producer.context();
+    let pending = Pending {
+        payload: payload.to_string(),
+        key: key.to_string(),
+        headers,
+    };
+    // The lock is released before sending or polling: delivery callbacks take it too.
+    let mut health = context.health.lock().unwrap();
+    if !health.breaker.admit(Instant::now()) {
+        match context.policy {
+            OutagePolicy::Buffer => {
+                if health.backlog.len() >= MAX_BUFFERED {
+                    let oldest = health.backlog.pop_front().unwrap();
+                    context.fail("kafka outage buffer full", &oldest.payload);
+                }
+                health.backlog.push_back(pending);
+            }
+            OutagePolicy::Drop => context.fail("kafka circuit open", payload),
+        }
+        drop(health);
+        producer.serve();
+        return;
+    }
+    let backlog: Vec<Pending> = if health.breaker.state() == CircuitState::Closed {
+        health.backlog.drain(..).collect()
+    } else {
+        Vec::new()
+    };
+    drop(health);
+    for buffered in backlog {
+        enqueue(producer, topic, buffered);
+    }
+    enqueue(producer, topic, pending);
+    // Handle any delivery callbacks.
+    producer.serve();
+}
+
+/// Kafka headers flagging a control record (heartbeat or end-of-stream marker).
+fn control_headers(name: &str) -> OwnedHeaders {
+    OwnedHeaders::new().insert(Header {
+        key: name,
+        value: Some("true"),
+    })
+}
+
+/// Kafka output as a `Sink`, sending through `send`'s circuit breaker and outage
+/// backlog. Delivery outcomes arrive later, in `DeliveryContext`, which counts them.
+pub struct KafkaSink<P: KafkaProducer> {
+    producer: P,
+    topic: String,
+}
+
+impl<P: KafkaProducer> KafkaSink<P> {
+    pub fn new(producer: P, topic: String) -> Self {
+        KafkaSink { producer, topic }
+    }
+}
+
+impl<P: KafkaProducer> Sink for KafkaSink<P> {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn send(&mut self, payload: &str, key: &str) -> Result<(), SinkError> {
+        send(&self.producer, &self.topic, payload, key, None);
+        Ok(())
+    }
+
+    /// Control records are flagged with a `<control>: true` header.
+    fn send_control(&mut self, payload: &str, control: &str) -> Result<(), SinkError> {
+        send(&self.producer, &self.topic, payload, "", Some(control_headers(control)));
+        Ok(())
+    }
+
+    fn tracks_delivery(&self) -> bool {
+        true
+    }
+
+    /// Messages librdkafka has queued but not yet delivered.
+    fn backlog(&self) -> u64 {
+        self.producer.in_flight_count().max(0) as u64
+    }
+
+    /// Replay or dead-letter any outage backlog, then flush what librdkafka still holds.
+    fn flush(&mut self) {
+        drain_backlog(&self.producer, &self.topic);
+        if let Err(e) = self.producer.flush(Duration::from_secs(5)) {
+            eprintln!("Failed to flush Kafka messages before exit: {}", e);
+        }
+    }
+}
+
+/// Look up the topic's partition count for explicit partition targeting. A fixed
+/// partition is checked against it; round-robin needs it to know what to cycle
+/// through. If the metadata can't be fetched, a fixed partition is left for
+/// librdkafka to reject, but round-robin fails.
+pub fn configure_partitions(producer: &impl KafkaProducer, topic: &str) -> Result<(), String> {
+    let Some(partitioner) = &producer.context().partitioner else {
+        return Ok(());
+    };
+    let count = producer
+        .client()
+        .fetch_metadata(Some(topic), Duration::from_secs(5))
+        .map_err(|e| e.to_string())
+        .and_then(|metadata| match metadata.topics().first() {
+            Some(t) if t.error().is_none() => Ok(t.partitions().len() as i32),
+            _ => Err(format!("topic '{}' not found", topic)),
+        });
+    match (partitioner.spec, count) {
+        (PartitionSpec::Fixed(partition), Ok(count)) if partition >= count => Err(format!(
+            "partition {} does not exist; topic '{}' has {} partition(s)",
+            partition, topic, count
+        )),
+        (PartitionSpec::Fixed(_), Err(e)) => {
+            eprintln!("Warning: cannot validate kafka-partition ({}); sending anyway", e);
+            Ok(())
+        }
+        (_, Ok(count)) => {
+            partitioner.count.store(count, Ordering::Relaxed);
+            Ok(())
+        }
+        (PartitionSpec::RoundRobin, Err(e)) => {
+            Err(format!("round-robin needs the topic's partition count: {}", e))
+        }
+    }
+}
+
+/// At shutdown, replay the outage backlog if the broker is back, otherwise
+/// dead-letter it so buffered messages are never lost silently.
+pub fn drain_backlog(producer: &impl KafkaProducer, topic: &str) {
+    let context = producer.context();
+    let mut health = context.health.lock().unwrap();
+    let available = health.breaker.state() == CircuitState::Closed;
+    let backlog: Vec<Pending> = health.backlog.drain(..).collect();
+    drop(health);
+    for pending in backlog {
+        if available {
+            enqueue(producer, topic, pending);
+        } else {
+            context.fail("kafka broker unavailable at shutdown", &pending.payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn test_parse_partition_spec() {
+        assert_eq!("3".parse(), Ok(PartitionSpec::Fixed(3)));
+        assert_eq!("round-robin".parse(), Ok(PartitionSpec::RoundRobin));
+        assert!("-1".parse::<PartitionSpec>().is_err());
+        assert!("all".parse::<PartitionSpec>().is_err());
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_partitions() {
+        let partitioner = Partitioner::new(PartitionSpec::RoundRobin);
+        assert_eq!(partitioner.next(), None, "unknown partition count defers to librdkafka");
+        partitioner.count.store(3, Ordering::Relaxed);
+        let assigned: Vec<Option<i32>> = (0..7).map(|_| partitioner.next()).collect();
+        assert_eq!(assigned, [0, 1, 2, 0, 1, 2, 0].map(Some).to_vec());
+        let fixed = Partitioner::new(PartitionSpec::Fixed(2));
+        assert!((0..3).all(|_| fixed.next() == Some(2)));
+    }
+
+    #[test]
+    fn test_per_key_enables_idempotence() {
+        let config = producer_config("localhost:9092", OrderingMode::PerKey);
+        assert_eq!(config.get("enable.idempotence"), Some("true"));
+        // Keys are hashed by librdkafka's default partitioner in either mode.
+        assert_eq!(config.get("partitioner"), None);
+    }
+
+    #[test]
+    fn test_no_ordering_leaves_defaults() {
+        let config = producer_config("localhost:9092", OrderingMode::None);
+        assert_eq!(config.get("bootstrap.servers"), Some("localhost:9092"));
+        assert_eq!(config.get("enable.idempotence"), None);
+    }
+
+    #[test]
+    fn test_bad_bootstrap_servers_fail_without_panicking() {
+        assert!(check_bootstrap_servers("127.0.0.1:9092").is_ok());
+        assert!(check_bootstrap_servers("PLAINTEXT://127.0.0.1, 127.0.0.1:1").is_ok());
+        assert!(check_bootstrap_servers(" , ").is_err());
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let context = DeliveryContext::new(None, stats, OutagePolicy::Buffer);
+        let err = create_producer::<BaseProducer<_>>("no-such-host.invalid:9092", OrderingMode::None, context)
+            .err()
+            .expect("an unresolvable broker is an error");
+        assert!(err.contains("no-such-host.invalid"), "{}", err);
+    }
+
+    #[test]
+    fn test_failed_delivery_goes_to_dead_letter_file() {
+        let path = std::env::temp_dir().join(format!("fluxfakr-kafka-dlq-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dead_letter = Arc::new(DeadLetterFile::open(&path).unwrap());
+
+        // Nothing listens on port 1, so delivery fails once the message times out.
+        let mut config = producer_config("127.0.0.1:1", OrderingMode::None);
+        config.set("message.timeout.ms", "200");
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let producer: BaseProducer<DeliveryContext> = config
+            .create_with_context(DeliveryContext::new(
+                Some(dead_letter.clone()),
+                stats.clone(),
+                OutagePolicy::Buffer,
+            ))
+            .unwrap();
+        send(&producer, "test-topic", "lost message", "", None);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while producer.in_flight_count() > 0 && Instant::now() < deadline {
+            producer.poll(Duration::from_millis(50));
+        }
+        dead_letter.flush();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("lost message"), "Failed message should be dead-lettered");
+        assert!(contents.contains("\"sink\":\"kafka\""));
+        assert_eq!(stats.sink("kafka").failed(), 1);
+        assert_eq!(stats.sink("kafka").sent(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_full_queue_waits_instead_of_dropping() {
+        let path = std::env::temp_dir().join(format!("fluxfakr-kafka-full-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dead_letter = Arc::new(DeadLetterFile::open(&path).unwrap());
+
+        // Room for one message, which times out undelivered and so frees the queue.
+        let mut config = producer_config("127.0.0.1:1", OrderingMode::None);
+        config.set("message.timeout.ms", "200").set("queue.buffering.max.messages", "1");
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let producer: ThreadedProducer<DeliveryContext> = config
+            .create_with_context(DeliveryContext::new(
+                Some(dead_letter.clone()),
+                stats.clone(),
+                OutagePolicy::Buffer,
+            ))
+            .unwrap();
+        // Straight to `enqueue`, since a refused connection soon opens the circuit breaker.
+        let pending = |payload: &str| Pending {
+            payload: payload.to_string(),
+            key: String::new(),
+            headers: None,
+        };
+        enqueue(&producer, "test-topic", pending("first"));
+        let blocked = Instant::now();
+        enqueue(&producer, "test-topic", pending("second"));
+        assert!(blocked.elapsed() >= Duration::from_millis(100), "the second send waits for room");
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while producer.in_flight_count() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        dead_letter.flush();
+
+        // Both were queued and timed out; neither was rejected for a full queue.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("first") && contents.contains("second"));
+        assert!(!contents.contains("Queue full"), "{}", contents);
+        assert_eq!(stats.sink("kafka").failed(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_full_queue_stops_waiting_on_shutdown() {
+        // The one queued message won't time out for a minute, so only shutdown ends the wait.
+        let mut config = producer_config("127.0.0.1:1", OrderingMode::None);
+        config.set("message.timeout.ms", "60000").set("queue.buffering.max.messages", "1");
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let running = Arc::new(AtomicBool::new(true));
+        let producer: ThreadedProducer<DeliveryContext> = config
+            .create_with_context(
+                DeliveryContext::new(None, stats.clone(), OutagePolicy::Buffer).with_shutdown(running.clone()),
+            )
+            .unwrap();
+        let pending = |payload: &str| Pending {
+            payload: payload.to_string(),
+            key: String::new(),
+            headers: None,
+        };
+        enqueue(&producer, "test-topic", pending("first"));
+        let interrupt = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            running.store(false, Ordering::SeqCst);
+        });
+        let blocked = Instant::now();
+        enqueue(&producer, "test-topic", pending("second"));
+        assert!(blocked.elapsed() < Duration::from_secs(5), "the wait outlived the run");
+        interrupt.join().unwrap();
+        assert_eq!(stats.sink("kafka").failed(), 1);
+        // Drop the first message rather than wait out its timeout when the producer closes.
+        producer.purge(rdkafka::producer::PurgeConfig::default().queue());
+    }
+
+    /// Sustained throughput of both producers against a real broker, which this suite has
+    /// no other way to measure. Run with
+    /// `FLUXFAKR_BENCH_BROKER=localhost:9092 cargo test --release bench_producers -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_producers() {
+        fn run<P: KafkaProducer>(broker: &str, topic: &str, messages: u32) -> f64 {
+            let stats = Arc::new(RunStats::new(&["kafka"]));
+            let context = DeliveryContext::new(None, stats.clone(), OutagePolicy::Buffer);
+            let producer: P = create_producer(broker, OrderingMode::PerKey, context).unwrap();
+            if let Err(e) = producer.client().fetch_metadata(Some(topic), Duration::from_secs(5)) {
+                panic!("no broker to benchmark against at {}: {}", broker, e);
+            }
+            let mut sink = KafkaSink::new(producer, topic.to_string());
+            let payload = r#"{"symbol":"ACME","price":101.25,"volume":1200,"timestamp":1700000000000}"#;
+            let start = Instant::now();
+            for i in 0..messages {
+                Sink::send(&mut sink, payload, &format!("key-{}", i % 64)).unwrap();
+            }
+            // Wait out every delivery, however long the broker takes.
+            while sink.backlog() > 0 {
+                sink.producer.wait(Duration::from_millis(100));
+            }
+            let delivered = stats.sink("kafka").sent();
+            assert_eq!(delivered, messages as u64, "some deliveries failed");
+            delivered as f64 / start.elapsed().as_secs_f64()
+        }
+        let broker = std::env::var("FLUXFAKR_BENCH_BROKER").unwrap_or_else(|_| "localhost:9092".to_string());
+        let topic = std::env::var("FLUXFAKR_BENCH_TOPIC").unwrap_or_else(|_| "fluxfakr-bench".to_string());
+        let messages = 500_000;
+        println!("{} messages to {} on {}", messages, topic, broker);
+        println!("BaseProducer:     {:>10.0} msg/s", run::<BaseProducer<DeliveryContext>>(&broker, &topic, messages));
+        println!("ThreadedProducer: {:>10.0} msg/s", run::<ThreadedProducer<DeliveryContext>>(&broker, &topic, messages));
+    }
+
+    #[test]
+    fn test_open_circuit_buffers_then_dead_letters_at_shutdown() {
+        let path = std::env::temp_dir().join(format!("fluxfakr-kafka-outage-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dead_letter = Arc::new(DeadLetterFile::open(&path).unwrap());
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let producer: BaseProducer<DeliveryContext> = producer_config("127.0.0.1:1", OrderingMode::None)
+            .create_with_context(DeliveryContext::new(
+                Some(dead_letter.clone()),
+                stats.clone(),
+                OutagePolicy::Buffer,
+            ))
+            .unwrap();
+        // Simulate the broker going down.
+        {
+            let mut health = producer.context().health.lock().unwrap();
+            assert!(health.breaker.trip(Instant::now()));
+        }
+        for i in 0..3 {
+            send(&producer, "test-topic", &format!("held {}", i), "", None);
+        }
+        assert_eq!(producer.context().health.lock().unwrap().backlog.len(), 3);
+        assert_eq!(producer.in_flight_count(), 0, "nothing is sent while the circuit is open");
+
+        drain_backlog(&producer, "test-topic");
+        dead_letter.flush();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.matches("kafka broker unavailable at shutdown").count(), 3);
+        assert_eq!(stats.sink("kafka").failed(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_drop_policy_dead_letters_while_open() {
+        let stats = Arc::new(RunStats::new(&["kafka"]));
+        let producer: BaseProducer<DeliveryContext> = producer_config("127.0.0.1:1", OrderingMode::None)
+            .create_with_context(DeliveryContext::new(None, stats.clone(), OutagePolicy::Drop))
+            .unwrap();
+        producer.context().health.lock().unwrap().breaker.trip(Instant::now());
+        send(&producer, "test-topic", "dropped", "", None);
+        assert!(producer.context().health.lock().unwrap().backlog.is_empty());
+        assert_eq!(stats.sink("kafka").failed(), 1);
+    }
+}
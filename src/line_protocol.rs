@@ -0,0 +1,218 @@
+//! InfluxDB line protocol output: `measurement,tag=value field=value timestamp`.
+
+use serde_json::Value;
+
+/// Which message fields become line-protocol tags (low-cardinality labels) and which become
+/// fields (the measured values), plus the field holding the Unix timestamp in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMapping {
+    pub tags: Vec<&'static str>,
+    pub fields: Vec<&'static str>,
+    pub timestamp: &'static str,
+}
+
+/// Backslash-escape the characters in `chars`, as line protocol requires per element.
+fn push_escaped(line: &mut String, s: &str, chars: &[char]) {
+    for c in s.chars() {
+        if chars.contains(&c) {
+            line.push('\\');
+        }
+        line.push(c);
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |v, part| v.get(part)).filter(|v| !v.is_null())
+}
+
+/// Renders messages as one line-protocol point each.
+#[derive(Debug)]
+pub struct LineProtocol {
+    measurement: String,
+    mapping: LineMapping,
+}
+
+impl LineProtocol {
+    /// Tags are written sorted by key, as InfluxDB recommends.
+    pub fn new(measurement: &str, mut mapping: LineMapping) -> Self {
+        mapping.tags.sort_unstable();
+        LineProtocol {
+            measurement: measurement.to_string(),
+            mapping,
+        }
+    }
+
+    /// One message as a point. Missing, null or empty tags are left out, as are missing
+    /// fields; a message with none of the mapped fields can't be written and is an error.
+    pub fn render(&self, message: &str) -> Result<String, String> {
+        let value: Value = serde_json::from_str(message).map_err(|e| format!("invalid JSON: {}", e))?;
+        let mut line = String::with_capacity(message.len());
+        push_escaped(&mut line, &self.measurement, &[',', ' ']);
+        for tag in &self.mapping.tags {
+            let tag_value = match lookup(&value, tag) {
+                Some(Value::String(s)) if !s.is_empty() => s.clone(),
+                Some(Value::String(_)) | None => continue,
+                Some(other) => other.to_string(),
+            };
+            line.push(',');
+            push_escaped(&mut line, tag, &[',', '=', ' ']);
+            line.push('=');
+            push_escaped(&mut line, &tag_value, &[',', '=', ' ']);
+        }
+        let mut separator = ' ';
+        for field in &self.mapping.fields {
+            let Some(field_value) = lookup(&value, field) else {
+                continue;
+            };
+            line.push(separator);
+            separator = ',';
+            push_escaped(&mut line, field, &[',', '=', ' ']);
+            line.push('=');
+            match field_value {
+                Value::Number(n) if n.is_f64() => line.push_str(&n.to_string()),
+                Value::Number(n) => {
+                    line.push_str(&n.to_string());
+                    line.push('i');
+                }
+                Value::String(s) => {
+                    line.push('"');
+                    push_escaped(&mut line, s, &['"', '\\']);
+                    line.push('"');
+                }
+                other => line.push_str(&other.to_string()),
+            }
+        }
+        if separator == ' ' {
+            return Err("message has none of the line-protocol fields".to_string());
+        }
+        if let Some(seconds) = lookup(&value, self.mapping.timestamp).and_then(Value::as_u64) {
+            line.push(' ');
+            line.push_str(&(seconds as u128 * 1_000_000_000).to_string());
+        }
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::stock::{StockConfig, StockDataGenerator};
+    use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+    use crate::generator::Generator;
+    use std::collections::BTreeMap;
+
+    /// A parsed point: measurement, tags, fields and timestamp.
+    type Point = (String, BTreeMap<String, String>, BTreeMap<String, String>, Option<u128>);
+
+    /// Split `s` on `delimiter` outside quotes and backslash escapes.
+    fn split(s: &str, delimiter: char) -> Vec<String> {
+        let (mut parts, mut current, mut quoted, mut chars) = (Vec::new(), String::new(), false, s.chars());
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    current.push(c);
+                    current.extend(chars.next());
+                }
+                '"' => {
+                    quoted = !quoted;
+                    current.push(c);
+                }
+                c if c == delimiter && !quoted => parts.push(std::mem::take(&mut current)),
+                c => current.push(c),
+            }
+        }
+        parts.push(current);
+        parts
+    }
+
+    fn unescape(s: &str) -> String {
+        let mut out = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            out.extend(if c == '\\' { chars.next() } else { Some(c) });
+        }
+        out
+    }
+
+    fn parse(line: &str) -> Point {
+        let sections = split(line, ' ');
+        let series = split(&sections[0], ',');
+        let pairs = |items: &[String]| -> BTreeMap<String, String> {
+            items
+                .iter()
+                .map(|item| {
+                    let kv = split(item, '=');
+                    (unescape(&kv[0]), kv[1].clone())
+                })
+                .collect()
+        };
+        let tags = pairs(&series[1..]).into_iter().map(|(k, v)| (k, unescape(&v))).collect();
+        let fields = pairs(&split(&sections[1], ','));
+        (unescape(&series[0]), tags, fields, sections.get(2).map(|t| t.parse().unwrap()))
+    }
+
+    /// Check a point against the message it was rendered from.
+    fn check(point: &Point, message: &Value, mapping: &LineMapping) {
+        let (_, tags, fields, timestamp) = point;
+        for tag in &mapping.tags {
+            let expected = lookup(message, tag).map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string));
+            assert_eq!(tags.get(*tag).cloned(), expected.filter(|v| !v.is_empty()), "tag {}", tag);
+        }
+        for field in &mapping.fields {
+            let Some(expected) = lookup(message, field) else {
+                assert!(!fields.contains_key(*field));
+                continue;
+            };
+            let written = &fields[*field];
+            if let Some(integer) = written.strip_suffix('i') {
+                assert_eq!(integer.parse::<i64>().ok(), expected.as_i64(), "field {}", field);
+            } else {
+                assert_eq!(written.parse::<f64>().ok(), expected.as_f64(), "field {}", field);
+            }
+        }
+        let seconds = message["timestamp"].as_u64().unwrap() as u128;
+        assert_eq!(*timestamp, Some(seconds * 1_000_000_000));
+    }
+
+    #[test]
+    fn test_generated_points_round_trip() {
+        let mut stock = StockDataGenerator::new(3, StockConfig::default());
+        let mut supermarket = SalesGenerator::new(SalesConfig {
+            abandon_rate: 0.1,
+            stockout_rate: 0.05,
+            coupon_rate: 0.2,
+            ..SalesConfig::default()
+        });
+        let generators: [(&str, &mut dyn Generator); 2] = [("stock", &mut stock), ("supermarket", &mut supermarket)];
+        for (module, generator) in generators {
+            let mapping = generator.line_mapping().unwrap();
+            let renderer = LineProtocol::new(module, mapping.clone());
+            for _ in 0..300 {
+                let message = generator.generate();
+                let point = parse(&renderer.render(&message).unwrap());
+                assert_eq!(point.0, module);
+                check(&point, &serde_json::from_str(&message).unwrap(), &mapping);
+            }
+        }
+    }
+
+    #[test]
+    fn test_escaping_and_missing_values() {
+        let renderer = LineProtocol::new(
+            "my events",
+            LineMapping {
+                tags: vec!["town", "region", "empty"],
+                fields: vec!["price", "count", "note", "missing"],
+                timestamp: "ts",
+            },
+        );
+        let line = renderer
+            .render(r#"{"town":"Stoke on Trent","region":"a,b=c","empty":"","price":1.5,"count":3,"note":"say \"hi\"","ts":2}"#)
+            .unwrap();
+        assert_eq!(line, r#"my\ events,region=a\,b\=c,town=Stoke\ on\ Trent price=1.5,count=3i,note="say \"hi\"" 2000000000"#);
+        let point = parse(&line);
+        assert_eq!(point.1["town"], "Stoke on Trent");
+        assert_eq!(point.1["region"], "a,b=c");
+        assert!(renderer.render(r#"{"town":"x","ts":2}"#).is_err());
+    }
+}
@@ -1,35 +1,132 @@
+mod avro;
+mod backoff;
+mod clock;
+mod color;
+mod country;
+mod csv;
+mod currency;
+mod dead_letter;
+mod format;
 mod generator;
+mod health;
+mod heartbeat;
+mod http;
+mod kafka;
+mod line_protocol;
+mod mqtt;
+mod msgpack;
+mod pacer;
+mod region;
+mod registry;
+mod rng;
+mod schema;
+mod selftest;
+mod sink;
+mod stats;
+mod transform;
+mod template;
+mod tune;
+#[cfg(unix)]
+mod uds;
+mod weights;
+mod ws;
 
 use clap::Parser;
-use std::io::{self, Write};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
 use std::thread;
-use std::time::Duration;
-use rdkafka::config::ClientConfig;
-use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
-use crate::generator::Generator;
-use crate::generator::stock::StockDataGenerator;
-use crate::generator::supermarket_sales::SalesGenerator;
+use std::time::{Duration, Instant};
+use crate::avro::AvroEncoder;
+use crate::clock::Clock;
+use crate::color::ColorMode;
+use crate::csv::CsvEncoder;
+use crate::line_protocol::LineProtocol;
+use crate::format::{OutputFormat, PayloadEncoder};
+use crate::currency::FxRates;
+use crate::dead_letter::DeadLetterFile;
+use crate::generator::{Batch, Generator};
+use crate::generator::clickstream::{ClickstreamConfig, ClickstreamGenerator};
+use crate::generator::iot_sensor::{IotConfig, IotSensorGenerator};
+use crate::generator::metrics::{self, MetricsGenerator};
+use crate::generator::payments::{PaymentsConfig, PaymentsGenerator};
+use crate::generator::stock::{self, EmptyBookPolicy, PriceModel, StockConfig, StockDataGenerator, StockMessages, VolumeDistribution, VolumeModel};
+use crate::generator::supermarket_sales::{
+    self, BasketSizeDistribution, Catalog, Popularity, SalesConfig, SalesGenerator, SalesKey,
+    TaxBasis,
+};
+use crate::generator::web_logs::{self, WebLogsConfig, WebLogsGenerator};
+use crate::heartbeat::Heartbeat;
+use crate::pacer::{Jitter, Pacer, Ramp};
+use crate::http::HttpSink;
+use crate::kafka::{DeliveryContext, OrderingMode, OutagePolicy, PartitionSpec};
+use crate::mqtt::{MqttQos, MqttSink};
+use crate::region::Region;
+use crate::rng::{RngAlgorithm, SimRng, Stage};
+use crate::sink::{FileSink, Sinks, StdoutSink};
+use crate::stats::RunStats;
+use crate::template::PayloadTemplate;
+use crate::transform::{CloudEvents, KeyField, NullInjection, Padding, Pipeline, SizeMix};
+use crate::ws::WsSink;
+
+/// How long `--find-max-rate` holds each rate.
+const RAMP_STEP_SECS: u64 = 5;
+/// Kafka messages queued but undelivered beyond which `--find-max-rate` considers
+/// the broker saturated.
+const RAMP_MAX_BACKLOG: u64 = 50_000;
+/// The main loop generates a batch per tick of about this long, so high rates aren't
+/// capped by one wait and one stdout write per message.
+const BATCH_TICK: Duration = Duration::from_millis(10);
+/// Upper bound on messages per batch.
+const MAX_BATCH: usize = 1_000;
 
 /// FluxFakr: A modular data stream generator.
 #[derive(Parser, Debug)]
-#[command(name = "FluxFakr", about = "A modular data stream generator.")]
+#[command(
+    name = "FluxFakr",
+    about = "A modular data stream generator.",
+    after_help = "Run `fluxfakr selftest` to validate every module, or `fluxfakr schema --module <name>` \
+                  to print a module's JSON Schema; see `--help` on either."
+)]
 struct Cli {
-    /// Generator module to use (e.g., market, other)
+    /// Generator module to use: stock, supermarket, iot, web_logs, clickstream or payments
     #[arg(long)]
     module: String,
 
-    /// Messages per second (must be > 0)
-    #[arg(long)]
-    mps: u32,
+    /// Messages per second (at least one message a day, 1/86400; fractional rates such
+    /// as 0.2 trickle one message every 5 seconds)
+    #[arg(long, required_unless_present = "ramp", conflicts_with = "ramp")]
+    mps: Option<f64>,
+
+    /// Ramp the rate linearly from one rate to another over a duration and then hold it,
+    /// as `<start_mps>:<end_mps>:<duration>` (e.g. `10:1000:1m`), instead of `--mps`
+    #[arg(long, value_parser = pacer::parse_ramp, conflicts_with = "find_max_rate")]
+    ramp: Option<Ramp>,
 
     /// Number of unique simulated entities (variants)
     #[arg(long)]
     variants: u32,
 
+    /// Stop gracefully after emitting this many messages (default: run until interrupted)
+    #[arg(long)]
+    count: Option<u64>,
+
+    /// Stop gracefully after running this long, e.g. `30s`, `5m` or `1h` (whichever of
+    /// `--count` and `--duration` is reached first wins)
+    #[arg(long, value_parser = clock::parse_duration, conflicts_with = "find_max_rate")]
+    duration: Option<Duration>,
+
+    /// Ramp the rate up from `--mps` until the sinks can't keep up, report the maximum
+    /// sustainable rate on stderr, and exit
+    #[arg(long, conflicts_with = "count")]
+    find_max_rate: bool,
+
+    /// Randomize the spacing between messages by up to this fraction either way (0.0 to
+    /// 1.0), for bursty traffic that still averages `--mps`
+    #[arg(long, default_value_t = 0.0, conflicts_with = "find_max_rate")]
+    jitter: f64,
+
     /// Broker address (optional; e.g., Kafka broker)
     #[arg(long)]
     broker: Option<String>,
@@ -37,11 +134,512 @@ struct Cli {
     /// Output topic name (optional)
     #[arg(long)]
     topic: Option<String>,
+
+    /// Seed for the random number stream; identical seeds reproduce identical data
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Pinned RNG algorithm, so seeded runs stay identical across upgrades
+    #[arg(long, value_enum, default_value_t = RngAlgorithm::Chacha8)]
+    rng: RngAlgorithm,
+
+    /// Use a simulated clock starting at this Unix time (seconds) instead of wall-clock time
+    #[arg(long)]
+    start_time: Option<u64>,
+
+    /// Milliseconds the simulated clock advances per message (with `--start-time`)
+    #[arg(long, default_value_t = 1000, requires = "start_time")]
+    time_step_ms: u64,
+
+    /// Give each entity (instrument or store) a fixed clock offset of up to this many
+    /// milliseconds either way, simulating unsynchronized producers
+    #[arg(long, default_value_t = 0)]
+    max_clock_skew_ms: u64,
+
+    /// Kafka ordering guarantee: `per-key` keeps each entity's messages in order on one partition
+    #[arg(long, value_enum, default_value_t = OrderingMode::None)]
+    ordering: OrderingMode,
+
+    /// Pin every Kafka record to this partition, or `round-robin` to cycle through the
+    /// topic's partitions; overrides key-based partitioning (keys are still sent)
+    #[arg(long)]
+    kafka_partition: Option<PartitionSpec>,
+
+    /// While the Kafka broker is down: `buffer` holds messages and replays them on recovery,
+    /// `drop` discards them (to the dead-letter file, if set)
+    #[arg(long, value_enum, default_value_t = OutagePolicy::Buffer)]
+    kafka_outage_policy: OutagePolicy,
+
+    /// Send to Kafka through a producer that serves delivery reports on a background
+    /// thread, instead of polling for them after every message on the generating thread
+    #[arg(long)]
+    async_kafka: bool,
+
+    /// Append messages that fail to reach any sink to this file, with the failure reason
+    #[arg(long)]
+    dead_letter_file: Option<String>,
+
+    /// Emit a `{"_heartbeat":true}` message after this many idle seconds without output
+    #[arg(long)]
+    heartbeat_secs: Option<u64>,
+
+    /// Comma-separated allowlist of fields to emit, with dotted paths for nested
+    /// fields (e.g. `transaction_id,store.town`)
+    #[arg(long)]
+    fields: Option<String>,
+
+    /// Append a `_padding` field of this many filler bytes to every message, to simulate
+    /// large payloads
+    #[arg(long)]
+    pad_bytes: Option<usize>,
+
+    /// Weighted mix of message detail levels (e.g. `small=0.3,medium=0.5,large=0.2`); each
+    /// message is trimmed to a drawn level and tagged with it as `detail_level`
+    #[arg(long)]
+    size_mix: Option<String>,
+
+    /// Probability (0.0–1.0) that each `--nullable-fields` field is set to null in a message,
+    /// to simulate upstream data-quality issues
+    #[arg(long, default_value_t = 0.0, requires = "nullable_fields")]
+    null_rate: f64,
+
+    /// Comma-separated dotted paths of fields `--null-rate` may null (e.g. `customer.age,store.state`)
+    #[arg(long)]
+    nullable_fields: Option<String>,
+
+    /// Add a `_schema_fingerprint` field to every message: a stable hash of the module's
+    /// JSON Schema, so consumers can detect schema changes mid-stream
+    #[arg(long)]
+    schema_fingerprint: bool,
+
+    /// Emit every message with its JSON object keys in sorted order, for canonical,
+    /// byte-comparable output
+    #[arg(long)]
+    sort_keys: bool,
+
+    /// Wrap each message in a CloudEvents 1.0 JSON envelope, with the payload under `data`
+    #[arg(long)]
+    cloudevents: bool,
+
+    /// Wrap each message in a custom envelope: `{{payload}}` is replaced by the message and
+    /// `{{field.path}}` by one of its fields, e.g. `{"key":"{{instrument}}","body":{{payload}}}`
+    #[arg(long, conflicts_with = "cloudevents")]
+    payload_template: Option<String>,
+
+    /// Wire format: `avro` sends Kafka records in Avro single-object encoding against the
+    /// module's schema (see `fluxfakr schema --format avro`); `msgpack` sends Kafka records and
+    /// writes `--output` files as MessagePack, printing it hex-encoded on stdout; `csv` prints
+    /// and writes flattened rows under a header (see `fluxfakr schema --format csv`);
+    /// `line-protocol` prints and writes InfluxDB points; `combined-log` prints and writes
+    /// web_logs messages as Apache access-log lines. Other outputs stay JSON
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    format: OutputFormat,
+
+    /// Register the Avro schema with this Confluent Schema Registry (`http://host:port`) at
+    /// startup, under the `<topic>-value` subject, and frame `--format avro` records with
+    /// the schema id in the Confluent wire format
+    #[arg(long)]
+    schema_registry_url: Option<String>,
+
+    /// Serve an HTTP liveness/readiness probe at `GET /healthz` on this port
+    #[arg(long)]
+    health_port: Option<u16>,
+
+    /// Colorize JSON on stdout: `auto` only when stdout is a terminal
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Pretty-print JSON on stdout, one field per line, for eyeballing during development.
+    /// Ignored when any other output is active, so their newline-delimited framing holds
+    #[arg(long)]
+    pretty: bool,
+
+    /// Also write newline-delimited messages to this file, created or truncated at startup
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+
+    /// POST each message as JSON to this `http://` webhook URL
+    #[arg(long)]
+    http_endpoint: Option<String>,
+
+    /// Connect, send and response timeout for each `--http-endpoint` request
+    #[arg(long, default_value_t = 5000, requires = "http_endpoint")]
+    http_timeout_ms: u64,
+
+    /// Publish every message to this MQTT broker (`host:port`), on `--mqtt-topic`
+    #[arg(long, requires = "mqtt_topic")]
+    mqtt_broker: Option<String>,
+
+    /// MQTT topic to publish to
+    #[arg(long, requires = "mqtt_broker")]
+    mqtt_topic: Option<String>,
+
+    /// MQTT QoS: `0` drops messages while the broker is down, `1` holds each message
+    /// until its PUBACK and buffers messages for replay after a reconnect
+    #[arg(long, value_enum, default_value_t = MqttQos::AtMostOnce)]
+    mqtt_qos: MqttQos,
+
+    /// Serve a WebSocket endpoint on this address (e.g. `127.0.0.1:9001`) that broadcasts
+    /// every message to all connected clients
+    #[arg(long)]
+    ws_listen: Option<String>,
+
+    /// Don't print messages to stdout; use with `--output`, `--http-endpoint`, `--ws-listen`,
+    /// `--mqtt-broker`, `--uds` or Kafka
+    #[arg(long)]
+    quiet: bool,
+
+    /// Write newline-delimited messages to this Unix domain socket (Unix only)
+    #[cfg(unix)]
+    #[arg(long)]
+    uds: Option<std::path::PathBuf>,
+
+    /// Opt-in: emit a final end-of-stream marker to every output on graceful shutdown.
+    /// Defaults to `{"_eos":true}` when given without a value.
+    #[arg(long, num_args = 0..=1, default_missing_value = r#"{"_eos":true}"#)]
+    eos_marker: Option<String>,
+
+    /// Metrics mode: emit rollups over tumbling windows of this many seconds (by event
+    /// timestamp) instead of raw events
+    #[arg(long)]
+    metrics_window: Option<u64>,
+
+    /// Rollups computed in metrics mode, e.g. `count,avg:price:instrument`; each is
+    /// `count[:group_by]` or `sum|avg|min|max:field[:group_by]` (defaults per module)
+    #[arg(long, requires = "metrics_window")]
+    metrics: Option<String>,
+
+    /// Snap emitted stock prices, bids and asks to multiples of this tick size (e.g. 0.01),
+    /// keeping the price strictly between the bid and ask
+    #[arg(long)]
+    tick_size: Option<f64>,
+
+    /// Cap how often any single stock instrument updates, in updates per second
+    #[arg(long)]
+    per_entity_max_rate: Option<f64>,
+
+    /// Report each stock tick's `price_before`, `price_after` and `delta` (for CDC testing)
+    #[arg(long)]
+    emit_deltas: bool,
+
+    /// Name stock instruments after real-looking tickers (AAPL, MSFT, ...) and add each
+    /// one's `sector`; instruments beyond the pool keep generated symbols
+    #[arg(long)]
+    tickers: bool,
+
+    /// Emit an OHLC bar per stock instrument every interval, e.g. `1m`, instead of every
+    /// tick; bars align to the clock and close once a later interval starts
+    #[arg(long, value_parser = clock::parse_duration, conflicts_with = "emit_deltas")]
+    candles: Option<Duration>,
+
+    /// Only trade stocks during session hours (09:30–16:00 UTC, weekdays), gapping prices at each open
+    #[arg(long)]
+    market_hours: bool,
+
+    /// Stock messages to emit: `combined` ticks, or `trades-and-quotes` as separate
+    /// messages tagged with a `type`, like a real feed
+    #[arg(long, value_enum, default_value_t = StockMessages::Combined, conflicts_with_all = ["emit_deltas", "candles"])]
+    stock_messages: StockMessages,
+
+    /// Process driving stock prices: `gbm` wanders freely, `ou` reverts to a mean
+    #[arg(long, value_enum, default_value_t = PriceModel::Gbm)]
+    price_model: PriceModel,
+
+    /// Stock drift per year under `gbm`; the long-term mean price under `ou` (default:
+    /// each instrument's starting price)
+    #[arg(long)]
+    mu: Option<f64>,
+
+    /// Start `ou` prices at a draw from the process's stationary distribution around the
+    /// mean, instead of converging on it from the starting price
+    #[arg(long)]
+    ou_steady_state: bool,
+
+    /// Stock price volatility per square-root year; under `ou`, a fraction of the mean
+    #[arg(long, default_value_t = stock::DEFAULT_VOLATILITY)]
+    sigma: f64,
+
+    /// Speed at which `ou` prices revert to the mean, per year
+    #[arg(long, default_value_t = 5.0)]
+    theta: f64,
+
+    /// Correlation (0 to 1) of stock returns across instruments, through a shared market factor
+    #[arg(long, default_value_t = 0.0)]
+    correlation: f64,
+
+    /// Distribution of stock trade volume per tick
+    #[arg(long, value_enum, default_value_t = VolumeDistribution::Uniform)]
+    volume_dist: VolumeDistribution,
+
+    /// Stock volume over the day: `linear` accumulates forever, `intraday` follows a
+    /// U-shaped session curve and resets at each day boundary (UTC)
+    #[arg(long, value_enum, default_value_t = VolumeModel::Linear)]
+    volume_model: VolumeModel,
+
+    /// Volume scale: uniform minimum, lognormal median or pareto minimum trade size
+    #[arg(long, default_value_t = 1000.0)]
+    volume_scale: f64,
+
+    /// Minimum initial stock price
+    #[arg(long, default_value_t = 100.0)]
+    price_min: f64,
+
+    /// Maximum initial stock price
+    #[arg(long, default_value_t = 200.0)]
+    price_max: f64,
+
+    /// Weighted initial-price tiers overriding `--price-min/--price-max`, e.g.
+    /// `0.5..5=0.3,100..200=0.6,1000..3000=0.1` for a mix of penny and high-priced names
+    #[arg(long)]
+    price_tiers: Option<String>,
+
+    /// Volume shape: uniform width (default 500), lognormal sigma (1.0) or pareto tail index (1.5)
+    #[arg(long)]
+    volume_shape: Option<f64>,
+
+    /// What the stock generator does with an empty instrument book: `stop` ends the run
+    /// gracefully, `reseed` lists a new instrument
+    #[arg(long, value_enum, default_value_t = EmptyBookPolicy::Stop)]
+    empty_book: EmptyBookPolicy,
+
+    /// Probability (0.0–1.0) that a supermarket basket is abandoned partway
+    #[arg(long, default_value_t = 0.0)]
+    abandon_rate: f64,
+
+    /// Probability (0.0–1.0) that a completed supermarket basket is followed by a
+    /// `coupon_redeemed` event referencing it
+    #[arg(long, default_value_t = 0.0)]
+    coupon_rate: f64,
+
+    /// Follow each completed supermarket basket with a `basket_summary` event carrying its
+    /// `item_count`, `basket_total`, store and customer
+    #[arg(long)]
+    emit_basket_summary: bool,
+
+    /// Probability (0.0–1.0) that a product is on promotion in a supermarket basket, at a
+    /// 10–30% discount; sale lines then carry `original_price` and `discount_rate`
+    #[arg(long, default_value_t = 0.0)]
+    promo_rate: f64,
+
+    /// Probability (0.0–1.0) that a supermarket sale line is a return of a recently sold
+    /// product, with negative quantity and total; lines then carry a `transaction_type`
+    #[arg(long, default_value_t = 0.0)]
+    return_rate: f64,
+
+    /// Probability (0.0–1.0) that a supermarket basket is bought online rather than in store
+    #[arg(long, default_value_t = 0.1)]
+    online_rate: f64,
+
+    /// Add `tax_rate`, `tax_amount` and `currency` to supermarket sales, taxing each line at
+    /// its store's state or country rate on top of `total_price`
+    #[arg(long)]
+    sales_tax: bool,
+
+    /// Like `--sales-tax`, but prices include the tax, so `tax_amount` is part of `total_price`
+    #[arg(long)]
+    tax_inclusive: bool,
+
+    /// Probability (0.0–1.0) that a supermarket product runs out of stock at a store; out-of-stock
+    /// items are emitted as `stockout` events with quantity 0 and stay unavailable for a while
+    #[arg(long, default_value_t = 0.0)]
+    stockout_rate: f64,
+
+    /// Comma-separated currency set for supermarket transactions (e.g. `USD,EUR,GBP`); each
+    /// customer gets a home currency that prices are quoted in, and some baskets are paid
+    /// in a foreign one
+    #[arg(long)]
+    currencies: Option<String>,
+
+    /// Standard deviation of the per-basket FX rate random walk (log-return)
+    #[arg(long, default_value_t = 0.001)]
+    fx_volatility: f64,
+
+    /// Probability (0.0–1.0) that a basket is charged in a foreign currency (with `--currencies`)
+    #[arg(long, default_value_t = 0.1)]
+    foreign_txn_rate: f64,
+
+    /// Mean idle gap in seconds between supermarket baskets (exponentially distributed)
+    #[arg(long, default_value_t = 0.0)]
+    inter_basket_gap: f64,
+
+    /// Keep supermarket traffic flat instead of varying basket sizes (and, with
+    /// `--inter-basket-gap`, basket frequency) by hour and weekday
+    #[arg(long)]
+    no_seasonality: bool,
+
+    /// Distribution of items per supermarket basket
+    #[arg(long, value_enum, default_value_t = BasketSizeDistribution::Uniform)]
+    basket_size_dist: BasketSizeDistribution,
+
+    /// Mean items per basket for the geometric and negative-binomial distributions
+    /// (default: each store format's typical size)
+    #[arg(long)]
+    basket_size_mean: Option<f64>,
+
+    /// Negative-binomial dispersion; smaller values give more very large baskets
+    #[arg(long, default_value_t = 2.0)]
+    basket_size_dispersion: f64,
+
+    /// Supermarket store format weights, e.g. `express=0.3,standard=0.5,superstore=0.2`
+    #[arg(long, default_value = "standard=1")]
+    store_format_mix: String,
+
+    /// JSON file of supermarket categories, subcategories and products (with optional
+    /// per-category price ranges) to sell instead of the built-in catalog
+    #[arg(long)]
+    catalog: Option<std::path::PathBuf>,
+
+    /// Popularity of supermarket catalog entries without their own `weight`: `zipf` makes
+    /// the k-th entry of each list 1/k as likely as the first, `uniform` makes all equal
+    #[arg(long, value_enum, default_value_t = Popularity::Zipf)]
+    popularity: Popularity,
+
+    /// Bias supermarket baskets toward products bought together with those already in them
+    /// (e.g. ice cream with frozen pizza), following the catalog's `affinities`
+    #[arg(long)]
+    basket_affinity: bool,
+
+    /// Target share of supermarket revenue per category, e.g. `Food=0.6,Clothing=0.2`;
+    /// categories left out split the remainder
+    #[arg(long)]
+    category_revenue_targets: Option<String>,
+
+    /// Number of supermarket stores to generate up front and revisit (each gets a `store_id`);
+    /// without it every basket opens a new store
+    #[arg(long, visible_alias = "stores")]
+    store_pool: Option<usize>,
+
+    /// Number of repeat supermarket customers to generate up front and revisit (each gets
+    /// a `customer_id`); without it every basket brings a new customer
+    #[arg(long, visible_alias = "customers")]
+    customer_pool: Option<usize>,
+
+    /// Probability (0.0–1.0) that a payment is fraudulent (impossible travel or an amount spike)
+    #[arg(long, default_value_t = 0.0)]
+    fraud_rate: f64,
+
+    /// Leave the ground-truth `_is_fraud` and `_fraud_type` labels out of payments
+    #[arg(long)]
+    omit_fraud_labels: bool,
+
+    /// Weighted response status codes of web access logs, e.g. `200=0.9,404=0.08,500=0.02`
+    #[arg(long, default_value = web_logs::DEFAULT_STATUS_WEIGHTS)]
+    status_weights: String,
+
+    /// Dotted path of a field whose value keys every message (e.g. `instrument` or
+    /// `store.town`), overriding the module's natural key
+    #[arg(long)]
+    key_field: Option<String>,
+
+    /// Kafka key of supermarket messages: `basket` or `store`, so a store's sales share a
+    /// partition (use with `--ordering per-key`)
+    #[arg(long, value_enum, default_value_t = SalesKey::Basket)]
+    supermarket_key: SalesKey,
+
+    /// Weighted supermarket customer income bands, e.g. `Q1=1,Q2=1,Q3=1,Q4=1,Q5=1`
+    /// (default: Low, Medium and High, equally likely)
+    #[arg(long)]
+    income_bands: Option<String>,
+
+    /// Add `household_size` and `occupation` to supermarket customers
+    #[arg(long)]
+    rich_demographics: bool,
+
+    /// Checkout lanes per supermarket store; adds `lane_id`, `cashier_id` and
+    /// `transaction_duration_seconds` to every sale
+    #[arg(long)]
+    lanes_per_store: Option<u32>,
+
+    /// Price the whole supermarket catalog at startup instead of on first use
+    #[arg(long)]
+    precompute_prices: bool,
+
+    /// Geographic preset that supermarket stores are located in (adds store coordinates)
+    #[arg(long, value_enum)]
+    region: Option<Region>,
+
+    /// Weighted countries supermarket stores are located in, e.g. `USA=0.6,UK=0.3,Germany=0.1`;
+    /// addresses follow each country's locale and stores price in its currency
+    #[arg(long, conflicts_with = "region")]
+    store_countries: Option<String>,
+
+    /// Locale all supermarket stores are located in, e.g. `en_GB` or `fr_CA`: the locale's
+    /// country alone, in the towns that speak its language
+    #[arg(long, conflicts_with_all = ["region", "store_countries"])]
+    locale: Option<String>,
 }
 
 fn main() {
+    // The self-test needs none of the streaming flags, so it is dispatched before they
+    // are parsed (and required).
+    if std::env::args().nth(1).as_deref() == Some("selftest") {
+        let args = selftest::SelftestArgs::parse_from(std::env::args().skip(1));
+        std::process::exit(if selftest::run(&args) { 0 } else { 1 });
+    }
+    if std::env::args().nth(1).as_deref() == Some("schema") {
+        let args = schema::SchemaArgs::parse_from(std::env::args().skip(1));
+        let Some(schema) = schema::module_schema(&args) else {
+            eprintln!("Unknown module: {}", args.module);
+            std::process::exit(1);
+        };
+        println!("{}", schema);
+        return;
+    }
     let cli = Cli::parse();
 
+    // Kafka needs both a broker and a topic; one without the other is a mistake, not
+    // a request for stdout-only output.
+    match (&cli.broker, &cli.topic) {
+        (Some(_), None) => {
+            eprintln!("Error: --broker requires --topic");
+            std::process::exit(1);
+        }
+        (None, Some(_)) => {
+            eprintln!("Error: --topic requires --broker");
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+    // Avro records must match the module's schema, so options that reshape messages can't
+    // be combined with it.
+    if cli.format == OutputFormat::Avro {
+        if cli.broker.is_none() {
+            eprintln!("Error: --format avro applies to Kafka output and requires --broker/--topic");
+            std::process::exit(1);
+        }
+        let reshaping = [
+            ("--fields", cli.fields.is_some()),
+            ("--pad-bytes", cli.pad_bytes.is_some()),
+            ("--size-mix", cli.size_mix.is_some()),
+            ("--null-rate", cli.null_rate > 0.0),
+            ("--schema-fingerprint", cli.schema_fingerprint),
+            ("--cloudevents", cli.cloudevents),
+            ("--payload-template", cli.payload_template.is_some()),
+        ];
+        if let Some((flag, _)) = reshaping.iter().find(|(_, set)| *set) {
+            eprintln!("Error: --format avro cannot be combined with {}", flag);
+            std::process::exit(1);
+        }
+    }
+    if cli.schema_registry_url.is_some() && cli.format != OutputFormat::Avro {
+        eprintln!("Error: --schema-registry-url registers the Avro schema and requires --format avro");
+        std::process::exit(1);
+    }
+    if cli.format == OutputFormat::Msgpack && cli.payload_template.is_some() {
+        eprintln!("Error: --format msgpack needs JSON messages and cannot be combined with --payload-template");
+        std::process::exit(1);
+    }
+    if matches!(cli.format, OutputFormat::Csv | OutputFormat::LineProtocol | OutputFormat::CombinedLog)
+        && (cli.payload_template.is_some() || cli.cloudevents)
+    {
+        eprintln!("Error: --format csv, line-protocol and combined-log flatten the messages themselves and cannot be combined with an envelope");
+        std::process::exit(1);
+    }
+    if cli.format == OutputFormat::CombinedLog && (cli.module != "web_logs" || cli.metrics_window.is_some()) {
+        eprintln!("Error: --format combined-log applies to raw web_logs messages only");
+        std::process::exit(1);
+    }
+
     // Display optional settings.
     if let Some(broker) = &cli.broker {
         println!("Broker set to: {}", broker);
@@ -50,38 +648,675 @@ fn main() {
         println!("Topic set to: {}", topic);
     }
 
-    // Validate messages-per-second.
-    if cli.mps == 0 {
-        eprintln!("Error: mps (messages per second) must be greater than 0");
+    // Validate messages-per-second. A ramp's rates are checked as it's parsed, and the
+    // rate it ends up holding is what the run summary reports as configured.
+    let mps = match &cli.ramp {
+        Some(ramp) => ramp.end_mps,
+        None => cli.mps.expect("clap requires --mps without --ramp"),
+    };
+    if !(mps.is_finite() && mps >= pacer::MIN_MPS) {
+        eprintln!("Error: mps (messages per second) must be at least one message a day (1/86400)");
+        std::process::exit(1);
+    }
+    if cli.count == Some(0) {
+        eprintln!("Error: count must be greater than 0");
+        std::process::exit(1);
+    }
+
+    if cli.candles.is_some_and(|interval| interval.as_millis() == 0) {
+        eprintln!("Error: --candles interval must be at least 1ms");
         std::process::exit(1);
     }
 
+    if cli.tick_size.is_some_and(|tick| !(tick.is_finite() && tick > 0.0)) {
+        eprintln!("Error: tick-size must be greater than 0");
+        std::process::exit(1);
+    }
+    if cli.per_entity_max_rate.is_some_and(|rate| !(rate.is_finite() && rate > 0.0)) {
+        eprintln!("Error: per-entity-max-rate must be greater than 0");
+        std::process::exit(1);
+    }
+    if !(cli.volume_scale.is_finite() && cli.volume_scale > 0.0)
+        || cli.volume_shape.is_some_and(|shape| !(shape.is_finite() && shape > 0.0))
+    {
+        eprintln!("Error: volume-scale and volume-shape must be greater than 0");
+        std::process::exit(1);
+    }
+    if !(cli.sigma.is_finite() && cli.sigma >= 0.0) {
+        eprintln!("Error: sigma must be a non-negative number");
+        std::process::exit(1);
+    }
+    if !(cli.theta.is_finite() && cli.theta > 0.0) {
+        eprintln!("Error: theta must be greater than 0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.correlation) {
+        eprintln!("Error: correlation must be between 0 and 1");
+        std::process::exit(1);
+    }
+    match (cli.price_model, cli.mu) {
+        (PriceModel::Ou, Some(mean)) if !(mean.is_finite() && mean > 0.0) => {
+            eprintln!("Error: mu, the ou mean price, must be greater than 0");
+            std::process::exit(1);
+        }
+        (_, Some(mu)) if !mu.is_finite() => {
+            eprintln!("Error: mu must be a finite number");
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+    if cli.ou_steady_state && cli.price_model != PriceModel::Ou {
+        eprintln!("Error: --ou-steady-state requires --price-model ou");
+        std::process::exit(1);
+    }
+    let price_range = stock::validate_price_range(cli.price_min, cli.price_max).unwrap_or_else(|e| {
+        eprintln!("Error: invalid price-min/price-max: {}", e);
+        std::process::exit(1);
+    });
+    let price_tiers = match cli.price_tiers.as_deref().map(stock::parse_price_tiers) {
+        None => Vec::new(),
+        Some(Ok(tiers)) => tiers,
+        Some(Err(e)) => {
+            eprintln!("Error: invalid price-tiers: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // The marker is forwarded verbatim, so it must already be valid JSON.
+    if let Some(marker) = &cli.eos_marker {
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(marker) {
+            eprintln!("Error: eos-marker must be valid JSON: {}", e);
+            std::process::exit(1);
+        }
+    }
+    // With stdout silenced, something else has to receive the stream.
+    #[cfg(unix)]
+    let has_uds = cli.uds.is_some();
+    #[cfg(not(unix))]
+    let has_uds = false;
+    let has_kafka = cli.broker.is_some() && cli.topic.is_some();
+    let has_other_output = cli.output.is_some()
+        || cli.http_endpoint.is_some()
+        || cli.ws_listen.is_some()
+        || cli.mqtt_broker.is_some();
+    if cli.quiet && !has_other_output && !has_kafka && !has_uds {
+        eprintln!(
+            "Error: --quiet needs another output \
+             (--output, --http-endpoint, --ws-listen, --mqtt-broker, --uds or --broker/--topic)"
+        );
+        std::process::exit(1);
+    }
+    // Multi-line messages would break the one-message-per-line framing everywhere else.
+    let pretty = cli.pretty && !(has_other_output || has_kafka || has_uds);
+    if cli.pretty && !pretty {
+        eprintln!("Warning: --pretty is ignored while another output is active, to keep messages newline-delimited");
+    }
+    if cli.http_timeout_ms == 0 {
+        eprintln!("Error: http-timeout-ms must be greater than 0");
+        std::process::exit(1);
+    }
+    if cli.heartbeat_secs == Some(0) {
+        eprintln!("Error: heartbeat-secs must be greater than 0");
+        std::process::exit(1);
+    }
+    if !(cli.inter_basket_gap.is_finite() && cli.inter_basket_gap >= 0.0) {
+        eprintln!("Error: inter-basket-gap must be a non-negative number of seconds");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.jitter) {
+        eprintln!("Error: jitter must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.abandon_rate) {
+        eprintln!("Error: abandon-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.fraud_rate) {
+        eprintln!("Error: fraud-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.online_rate) {
+        eprintln!("Error: online-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.return_rate) {
+        eprintln!("Error: return-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.promo_rate) {
+        eprintln!("Error: promo-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.coupon_rate) {
+        eprintln!("Error: coupon-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.null_rate) {
+        eprintln!("Error: null-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    if cli.basket_size_mean.is_some_and(|mean| !(mean.is_finite() && mean >= 1.0)) {
+        eprintln!("Error: basket-size-mean must be at least 1");
+        std::process::exit(1);
+    }
+    if !(cli.basket_size_dispersion.is_finite() && cli.basket_size_dispersion > 0.0) {
+        eprintln!("Error: basket-size-dispersion must be greater than 0");
+        std::process::exit(1);
+    }
+    if cli.lanes_per_store.is_some_and(|lanes| !(1..=supermarket_sales::MAX_LANES_PER_STORE).contains(&lanes)) {
+        eprintln!("Error: lanes-per-store must be between 1 and {}", supermarket_sales::MAX_LANES_PER_STORE);
+        std::process::exit(1);
+    }
+    if cli.customer_pool == Some(0) {
+        eprintln!("Error: customer-pool must be greater than 0");
+        std::process::exit(1);
+    }
+    if cli.store_pool == Some(0) {
+        eprintln!("Error: store-pool must be greater than 0");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.stockout_rate) {
+        eprintln!("Error: stockout-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+
+    let status_weights = match web_logs::parse_status_weights(&cli.status_weights) {
+        Ok(weights) => weights,
+        Err(e) => {
+            eprintln!("Error: invalid status-weights: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let store_formats = match supermarket_sales::parse_store_format_mix(&cli.store_format_mix) {
+        Ok(mix) => mix,
+        Err(e) => {
+            eprintln!("Error: invalid store-format-mix: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if !(cli.fx_volatility.is_finite() && cli.fx_volatility >= 0.0) {
+        eprintln!("Error: fx-volatility must be a non-negative number");
+        std::process::exit(1);
+    }
+    if !(0.0..=1.0).contains(&cli.foreign_txn_rate) {
+        eprintln!("Error: foreign-txn-rate must be between 0.0 and 1.0");
+        std::process::exit(1);
+    }
+    let fx = cli.currencies.as_deref().map(|spec| match currency::parse_currencies(spec) {
+        Ok(currencies) => FxRates::new(currencies, cli.fx_volatility),
+        Err(e) => {
+            eprintln!("Error: invalid currencies: {}", e);
+            std::process::exit(1);
+        }
+    });
+
+    let countries = match cli.store_countries.as_deref().map(country::parse_country_mix) {
+        None => Vec::new(),
+        Some(Ok(mix)) => mix,
+        Some(Err(e)) => {
+            eprintln!("Error: invalid store-countries: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let countries = match cli.locale.as_deref().map(country::parse_locale) {
+        None => countries,
+        Some(Ok(country)) => vec![(country, 1.0)],
+        Some(Err(e)) => {
+            eprintln!("Error: invalid locale: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let catalog = match cli.catalog.as_deref().map(Catalog::load) {
+        None => Catalog::builtin(),
+        Some(Ok(catalog)) => catalog,
+        Some(Err(e)) => {
+            eprintln!("Error: invalid catalog: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let category_targets = match cli
+        .category_revenue_targets
+        .as_deref()
+        .map(|spec| supermarket_sales::parse_category_targets(spec, &catalog))
+    {
+        None => Vec::new(),
+        Some(Ok(targets)) => targets,
+        Some(Err(e)) => {
+            eprintln!("Error: invalid category-revenue-targets: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let income_bands = match cli.income_bands.as_deref().map(weights::parse_weights) {
+        None => Vec::new(),
+        Some(Ok(bands)) => bands,
+        Some(Err(e)) => {
+            eprintln!("Error: invalid income-bands: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    // Printed even when drawn at random, so any run can be reproduced with `--seed`. It goes
+    // to stderr, with the run summary, to keep it out of the message stream.
+    eprintln!("Seed: {}", seed);
+    let clock = match cli.start_time {
+        Some(start) => Clock::Stepped {
+            now_ms: start * 1000,
+            step_ms: cli.time_step_ms,
+        },
+        None => Clock::System,
+    };
+
     // Generator manager section
     let mut generator: Box<dyn Generator> = match cli.module.as_str() {
-        "stock" => Box::new(StockDataGenerator::new(cli.variants as usize)),
-        "supermarket" => Box::new(SalesGenerator::new()),
+        "stock" => Box::new(StockDataGenerator::new(
+            cli.variants as usize,
+            StockConfig {
+                price_model: cli.price_model,
+                drift: cli.mu.filter(|_| cli.price_model == PriceModel::Gbm).unwrap_or(stock::DEFAULT_DRIFT),
+                volatility: cli.sigma,
+                mean: cli.mu.filter(|_| cli.price_model == PriceModel::Ou),
+                reversion_speed: cli.theta,
+                steady_state_start: cli.ou_steady_state,
+                correlation: cli.correlation,
+                tick_size: cli.tick_size,
+                seed,
+                rng: cli.rng,
+                empty_book: cli.empty_book,
+                clock,
+                per_entity_max_rate: cli.per_entity_max_rate,
+                emit_deltas: cli.emit_deltas,
+                volume_dist: cli.volume_dist,
+                volume_model: cli.volume_model,
+                volume_scale: cli.volume_scale,
+                volume_shape: cli.volume_shape,
+                price_range,
+                price_tiers,
+                max_clock_skew_ms: cli.max_clock_skew_ms,
+                tickers: cli.tickers,
+                candle_interval_ms: cli.candles.map(|interval| interval.as_millis() as u64),
+                market_hours: cli.market_hours,
+                messages: cli.stock_messages,
+            },
+        )),
+        "supermarket" => Box::new(SalesGenerator::new(SalesConfig {
+            abandon_rate: cli.abandon_rate,
+            store_formats,
+            seed,
+            rng: cli.rng,
+            clock,
+            inter_basket_gap: cli.inter_basket_gap,
+            region: cli.region,
+            countries,
+            stockout_rate: cli.stockout_rate,
+            fx,
+            foreign_txn_rate: cli.foreign_txn_rate,
+            catalog,
+            popularity: cli.popularity,
+            affinity: cli.basket_affinity,
+            category_targets,
+            store_pool: cli.store_pool,
+            customer_pool: cli.customer_pool,
+            key_by: cli.supermarket_key,
+            income_bands,
+            rich_demographics: cli.rich_demographics,
+            basket_size_dist: cli.basket_size_dist,
+            basket_size_mean: cli.basket_size_mean,
+            basket_size_dispersion: cli.basket_size_dispersion,
+            lanes_per_store: cli.lanes_per_store,
+            max_clock_skew_ms: cli.max_clock_skew_ms,
+            precompute_prices: cli.precompute_prices,
+            coupon_rate: cli.coupon_rate,
+            basket_summary: cli.emit_basket_summary,
+            seasonality: !cli.no_seasonality,
+            promo_rate: cli.promo_rate,
+            return_rate: cli.return_rate,
+            online_rate: cli.online_rate,
+            tax: if cli.tax_inclusive {
+                Some(TaxBasis::Inclusive)
+            } else {
+                cli.sales_tax.then_some(TaxBasis::Exclusive)
+            },
+        })),
+        "web_logs" => Box::new(WebLogsGenerator::new(
+            cli.variants as usize,
+            WebLogsConfig {
+                status_weights,
+                seed,
+                rng: cli.rng,
+                clock,
+            },
+        )),
+        "clickstream" => Box::new(ClickstreamGenerator::new(
+            cli.variants as usize,
+            ClickstreamConfig {
+                seed,
+                rng: cli.rng,
+                clock,
+            },
+        )),
+        "payments" => Box::new(PaymentsGenerator::new(
+            cli.variants as usize,
+            PaymentsConfig {
+                fraud_rate: cli.fraud_rate,
+                labels: !cli.omit_fraud_labels,
+                seed,
+                rng: cli.rng,
+                clock,
+            },
+        )),
+        "iot" => Box::new(IotSensorGenerator::new(
+            cli.variants as usize,
+            IotConfig {
+                seed,
+                rng: cli.rng,
+                clock,
+                ..IotConfig::default()
+            },
+        )),
         _ => {
             eprintln!("Unknown module: {}", cli.module);
             std::process::exit(1);
         }
     };
 
-    // Calculate sleep duration between messages.
-    let sleep_duration = Duration::from_secs_f64(1.0 / cli.mps as f64);
+    if let Some(window) = cli.metrics_window {
+        if window == 0 {
+            eprintln!("Error: metrics-window must be greater than 0");
+            std::process::exit(1);
+        }
+        let spec = cli.metrics.as_deref().unwrap_or(match cli.module.as_str() {
+            "stock" if cli.candles.is_some() => "count,avg:close:instrument",
+            "stock" if cli.stock_messages == StockMessages::TradesAndQuotes => "count:type,avg:price:instrument",
+            "stock" => "count,avg:price:instrument",
+            "iot" => "count,avg:temperature:device_id",
+            "web_logs" => "count:status,sum:bytes:path",
+            "clickstream" => "count:event_type",
+            "payments" => "count,sum:amount:country",
+            _ => "count,sum:total_price:product.category",
+        });
+        let aggregations = match metrics::parse_aggregations(spec) {
+            Ok(aggregations) => aggregations,
+            Err(e) => {
+                eprintln!("Error: invalid metrics: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let known = generator.fields();
+        for field in aggregations
+            .iter()
+            .flat_map(|a| a.field.iter().chain(a.group_by.iter()))
+        {
+            if !known.contains(&field.as_str()) {
+                eprintln!("Error: metrics field '{}' is not in the {} schema", field, cli.module);
+                std::process::exit(1);
+            }
+        }
+        generator = Box::new(MetricsGenerator::new(generator, window, aggregations));
+    }
+
+    let fields = cli.fields.as_deref().map(transform::parse_field_list);
+    if let Some(fields) = &fields {
+        if let Some(unknown) = transform::find_unknown_field(fields, &generator.fields()) {
+            eprintln!(
+                "Error: field '{}' is not in the {} schema (available: {})",
+                unknown,
+                cli.module,
+                generator.fields().join(", ")
+            );
+            std::process::exit(1);
+        }
+    }
+    if let Some(key_field) = &cli.key_field {
+        if !generator.fields().contains(&key_field.as_str()) {
+            eprintln!("Error: key field '{}' is not in the {} schema", key_field, cli.module);
+            std::process::exit(1);
+        }
+    }
+    let nullable_fields = cli.nullable_fields.as_deref().map(transform::parse_field_list);
+    if let Some(nullable_fields) = &nullable_fields {
+        if let Some(unknown) = transform::find_unknown_field(nullable_fields, &generator.fields()) {
+            eprintln!("Error: nullable field '{}' is not in the {} schema", unknown, cli.module);
+            std::process::exit(1);
+        }
+    }
+    let nulls = nullable_fields
+        .filter(|_| cli.null_rate > 0.0)
+        .map(|fields| NullInjection {
+            rate: cli.null_rate,
+            fields,
+            rng: SimRng::for_stage(cli.rng, seed, Stage::NullInjection),
+        });
+    let size_mix = match cli.size_mix.as_deref().map(transform::parse_size_mix) {
+        None => None,
+        Some(Ok(mix)) => {
+            let rng = SimRng::for_stage(cli.rng, seed, Stage::SizeMix);
+            Some(SizeMix::new(&mix, &generator.core_fields(), rng))
+        }
+        Some(Err(e)) => {
+            eprintln!("Error: invalid size-mix: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let padding = cli.pad_bytes.map(|bytes| Padding {
+        bytes,
+        rng: SimRng::for_stage(cli.rng, seed, Stage::Padding),
+    });
+    let template = cli.payload_template.as_deref().map(|spec| {
+        let template = PayloadTemplate::parse(spec).unwrap_or_else(|e| {
+            eprintln!("Error: invalid payload-template: {}", e);
+            std::process::exit(1);
+        });
+        if let Some(unknown) = template.fields().find(|f| !generator.fields().contains(f)) {
+            eprintln!("Error: payload-template field '{}' is not in the {} schema", unknown, cli.module);
+            std::process::exit(1);
+        }
+        template
+    });
+    // Fingerprint the emitted schema: the projected fields if `--fields` narrows it.
+    let fingerprint = cli.schema_fingerprint.then(|| match &fields {
+        Some(fields) => transform::schema_fingerprint(&transform::project_schema(&generator.schema(), fields)),
+        None => transform::schema_fingerprint(&generator.schema()),
+    });
+    // CSV columns follow the emitted fields, then whatever the pipeline appends, in order.
+    let csv_columns = (cli.format == OutputFormat::Csv).then(|| {
+        let known = generator.fields();
+        let selected: Vec<&str> = match &fields {
+            Some(fields) => fields.iter().map(String::as_str).collect(),
+            None => known.clone(),
+        };
+        let mut columns = csv::columns(&selected, &known);
+        let appended = [
+            (size_mix.is_some(), "detail_level"),
+            (padding.is_some(), "_padding"),
+            (fingerprint.is_some(), "_schema_fingerprint"),
+        ];
+        columns.extend(appended.iter().filter(|(on, _)| *on).map(|(_, column)| column.to_string()));
+        columns
+    });
+    let line_protocol = (cli.format == OutputFormat::LineProtocol).then(|| {
+        let Some(mapping) = generator.line_mapping() else {
+            eprintln!("Error: the {} module has no line-protocol mapping", cli.module);
+            std::process::exit(1);
+        };
+        if let Some(fields) = &fields {
+            let known = generator.fields();
+            let kept = csv::columns(&fields.iter().map(String::as_str).collect::<Vec<_>>(), &known);
+            if !mapping.fields.iter().any(|field| kept.iter().any(|k| k == field)) {
+                eprintln!("Error: --fields keeps none of the line-protocol fields ({})", mapping.fields.join(", "));
+                std::process::exit(1);
+            }
+        }
+        mapping
+    });
+    let mut pipeline = Pipeline {
+        fields,
+        padding,
+        fingerprint,
+        sort_keys: cli.sort_keys,
+        nulls,
+        size_mix,
+        cloudevents: cli.cloudevents.then(|| CloudEvents::new(&cli.module)),
+        template,
+    };
+
+    // Calculate the interval between messages.
+    let interval = Duration::from_secs_f64(1.0 / mps);
+
+    // Open the dead-letter file up front so a bad path fails before streaming starts.
+    let dead_letter = cli.dead_letter_file.as_ref().map(|path| {
+        Arc::new(DeadLetterFile::open(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot open dead-letter file {}: {}", path, e);
+            std::process::exit(1);
+        }))
+    });
+
+    let mut sink_names = Vec::new();
+    if !cli.quiet {
+        sink_names.push("stdout");
+    }
+    if cli.broker.is_some() && cli.topic.is_some() {
+        sink_names.push("kafka");
+    }
+    if cli.mqtt_broker.is_some() {
+        sink_names.push("mqtt");
+    }
+
+    // Connect the Unix socket sink before streaming so a bad path is reported up front.
+    #[cfg(unix)]
+    let uds_sink = cli.uds.as_ref().map(|path| {
+        sink_names.push("uds");
+        uds::UdsSink::connect(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot connect to Unix socket {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    // Resolve the webhook before streaming so a bad URL is reported up front.
+    let http_sink = cli.http_endpoint.as_ref().map(|url| {
+        sink_names.push("http");
+        HttpSink::new(url, Duration::from_millis(cli.http_timeout_ms)).unwrap_or_else(|e| {
+            eprintln!("Error: invalid http-endpoint {}: {}", url, e);
+            std::process::exit(1);
+        })
+    });
+    // Bind the WebSocket server before streaming so a taken address is reported up front.
+    let ws_sink = cli.ws_listen.as_ref().map(|addr| {
+        sink_names.push("ws");
+        let sink = WsSink::listen(addr).unwrap_or_else(|e| {
+            eprintln!("Error: cannot listen for WebSocket clients on {}: {}", addr, e);
+            std::process::exit(1);
+        });
+        eprintln!("WebSocket server listening on ws://{}", sink.local_addr());
+        sink
+    });
+    // Create the output file before streaming so an unwritable path is reported up front.
+    let file_sink = cli.output.as_ref().map(|path| {
+        sink_names.push("file");
+        FileSink::create(path).unwrap_or_else(|e| {
+            eprintln!("Error: cannot create output file {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    let stats = Arc::new(RunStats::new(&sink_names));
+
+    // Colorization only ever applies to stdout, never to Kafka payloads.
+    let colorize = cli.color.enabled();
+
+    // Create a flag to indicate whether the simulation is running. Sinks that can wait on
+    // a destination stop waiting once it clears.
+    let running = Arc::new(AtomicBool::new(true));
+
+    // Sinks receive every message in this order: Kafka, MQTT, the Unix socket, the webhook,
+    // WebSocket clients, the output file, then stdout.
+    let mut sinks = Sinks::new(stats.clone(), dead_letter.clone());
 
     // Set up Kafka producer if both broker and topic are provided.
-    let mut kafka_producer: Option<BaseProducer> = None;
     if let (Some(broker), Some(topic)) = (cli.broker.clone(), cli.topic.clone()) {
-        let producer: BaseProducer = ClientConfig::new()
-            .set("bootstrap.servers", &broker)
-            .create()
-            .expect("Kafka producer creation error");
-        kafka_producer = Some(producer);
+        let encoder = match cli.format {
+            OutputFormat::Json => None,
+            OutputFormat::Avro => {
+                let avro = AvroEncoder::from_json_schema(&generator.schema()).unwrap_or_else(|e| {
+                    eprintln!("Error: --format avro: {}", e);
+                    std::process::exit(1);
+                });
+                let avro = match &cli.schema_registry_url {
+                    Some(url) => {
+                        let subject = format!("{}-value", topic);
+                        let id = registry::register(url, &subject, &avro.schema()).unwrap_or_else(|e| {
+                            eprintln!("Error: cannot register the Avro schema: {}", e);
+                            std::process::exit(1);
+                        });
+                        println!("Kafka records are Avro-encoded (schema id {} under {})", id, subject);
+                        avro.with_registry_id(id)
+                    }
+                    None => {
+                        println!("Kafka records are Avro-encoded (schema fingerprint {:016x})", avro.fingerprint());
+                        avro
+                    }
+                };
+                Some(PayloadEncoder::Avro(avro))
+            }
+            OutputFormat::Msgpack => Some(PayloadEncoder::Msgpack),
+            OutputFormat::Csv | OutputFormat::LineProtocol | OutputFormat::CombinedLog => None,
+        };
+        let context = DeliveryContext::new(dead_letter.clone(), stats.clone(), cli.kafka_outage_policy)
+            .with_partitioning(cli.kafka_partition)
+            .with_encoder(encoder)
+            .with_shutdown(running.clone());
+        let sink = kafka::connect(&broker, topic.clone(), cli.ordering, context, cli.async_kafka)
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
         println!("Kafka producer initialized for topic: {}", topic);
+        sinks.add(sink);
+    }
+    // MQTT carries the same payloads as Kafka, published right after it.
+    if let (Some(broker), Some(topic)) = (&cli.mqtt_broker, &cli.mqtt_topic) {
+        let sink = MqttSink::connect(broker, topic, cli.mqtt_qos, stats.clone(), dead_letter.clone())
+            .unwrap_or_else(|e| {
+                eprintln!("Error: cannot connect to MQTT broker {}: {}", broker, e);
+                std::process::exit(1);
+            });
+        eprintln!("MQTT publisher connected for topic: {}", topic);
+        sinks.add(Box::new(sink));
+    }
+    #[cfg(unix)]
+    if let Some(sink) = uds_sink {
+        sinks.add(Box::new(sink));
+    }
+    if let Some(sink) = http_sink {
+        sinks.add(Box::new(sink));
+    }
+    if let Some(sink) = ws_sink {
+        sinks.add(Box::new(sink));
+    }
+    // MessagePack delimits its own values, so files and stdout can carry it too; CSV, line
+    // protocol and access-log lines are only meaningful there.
+    let measurement = match cli.metrics_window {
+        Some(_) => format!("{}_metrics", cli.module),
+        None => cli.module.clone(),
+    };
+    let encoder = || match cli.format {
+        OutputFormat::Json | OutputFormat::Avro => None,
+        OutputFormat::Msgpack => Some(PayloadEncoder::Msgpack),
+        OutputFormat::Csv => csv_columns.clone().map(|columns| PayloadEncoder::Csv(CsvEncoder::new(columns))),
+        OutputFormat::LineProtocol => line_protocol
+            .clone()
+            .map(|mapping| PayloadEncoder::LineProtocol(LineProtocol::new(&measurement, mapping))),
+        OutputFormat::CombinedLog => Some(PayloadEncoder::CombinedLog),
+    };
+    if let Some(sink) = file_sink {
+        sinks.add(Box::new(sink.with_encoder(encoder())));
+    }
+    if !cli.quiet {
+        sinks.add(Box::new(StdoutSink::new(colorize, pretty).with_encoder(encoder())));
     }
 
-    // Create a flag to indicate whether the simulation is running.
-    let running = Arc::new(AtomicBool::new(true));
     {
         let running = running.clone();
         ctrlc::set_handler(move || {
@@ -91,34 +1326,132 @@ fn main() {
             .expect("Error setting Ctrl-C handler");
     }
 
+    if let Some(port) = cli.health_port {
+        if let Err(e) = health::serve(port, running.clone(), stats.clone()) {
+            eprintln!("Error: cannot serve health checks on port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    }
+
     println!("Simulation started. Press Ctrl+C to stop.");
 
-    // Simulation loop: continue until interrupted.
-    while running.load(Ordering::SeqCst) {
-        let message = generator.generate();
+    let mut heartbeat = cli
+        .heartbeat_secs
+        .map(|secs| Heartbeat::new(Duration::from_secs(secs), Instant::now()));
 
-        // If a Kafka producer is available, send the message to Kafka.
-        if let Some(producer) = kafka_producer.as_mut() {
+    // Keys are read from the message as generated, before any transforms reshape it.
+    let mut key_field = cli.key_field.as_deref().map(KeyField::new);
+    // Otherwise records carry the module's natural key, so an entity's messages share a partition.
+    let mut message_key = |natural_key: String, message: &str| match &mut key_field {
+        Some(key_field) => key_field.extract(message),
+        None => natural_key,
+    };
+
+    if cli.find_max_rate {
+        let config = tune::RampConfig {
+            start_rate: mps,
+            step_duration: Duration::from_secs(RAMP_STEP_SECS),
+            max_backlog: RAMP_MAX_BACKLOG,
+        };
+        eprintln!(
+            "Finding the maximum sustainable rate: ramping from {} msg/s in {}s steps...",
+            mps, RAMP_STEP_SECS
+        );
+        let max_rate = tune::find_max_rate(
+            &config,
+            &tune::WallClock,
+            || {
+                if !running.load(Ordering::SeqCst) || generator.is_exhausted() {
+                    return None;
+                }
+                if let Some(message) = generator.try_generate() {
+                    let key = message_key(generator.key().unwrap_or_default(), &message);
+                    sinks.send(&pipeline.apply(message), &key);
+                }
+                Some(sinks.backlog())
+            },
+            |step| {
+                eprintln!(
+                    "  target {:.0} msg/s: achieved {:.0} msg/s, backlog {} -> {}",
+                    step.target,
+                    step.achieved,
+                    step.backlog,
+                    if step.sustained { "sustained" } else { "saturated" }
+                )
+            },
+        );
+        eprintln!("Maximum sustainable rate: {:.0} msg/s", max_rate);
+    }
 
-            let topic = cli.topic.as_ref().unwrap();
-            let record = BaseRecord::to(topic).payload(&message).key("");
-            if let Err((e, _)) = producer.send(record) {
-                eprintln!("Failed to send message to Kafka: {}", e);
+    // Simulation loop: continue until interrupted, `--count` messages have been emitted
+    // or `--duration` has elapsed.
+    let deadline = cli.duration.map(|duration| match Instant::now().checked_add(duration) {
+        Some(deadline) => deadline,
+        None => {
+            eprintln!("Error: duration is too long to schedule");
+            std::process::exit(1);
+        }
+    });
+    let mut batch = Batch::new();
+    let jitter = (cli.jitter > 0.0)
+        .then(|| Jitter::new(cli.jitter, SimRng::for_stage(cli.rng, seed, Stage::Jitter)));
+    let mut pacer = Pacer::new(interval, cli.ramp, jitter, Instant::now());
+    while !cli.find_max_rate && running.load(Ordering::SeqCst) {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        if generator.is_exhausted() {
+            eprintln!("Generator has nothing left to produce; shutting down.");
+            break;
+        }
+        // Generate as many messages per tick as the current rate calls for, never
+        // overshooting `--count`.
+        let batch_size = ((pacer.rate() * BATCH_TICK.as_secs_f64()) as usize).clamp(1, MAX_BATCH);
+        let remaining = cli.count.map_or(u64::MAX, |count| count - stats.messages());
+        generator.generate_batch(batch_size.min(remaining as usize), &mut batch);
+        if batch.is_empty() {
+            // Idle tick: emit a heartbeat if one is due so consumers know we're alive.
+            if heartbeat.as_mut().is_some_and(|h| h.poll(Instant::now())) {
+                let beat = heartbeat::heartbeat_message(&cli.module);
+                sinks.send_control(&beat, "fluxfakr-heartbeat");
             }
-            // Poll to handle any delivery callbacks.
-            producer.poll(Duration::from_millis(0));
+            thread::sleep(pacer.pace(1, Instant::now()));
+            continue;
+        }
+        if let Some(heartbeat) = heartbeat.as_mut() {
+            heartbeat.record_activity(Instant::now());
+        }
+        // Transforms take and return each buffer, so an empty pipeline reallocates nothing.
+        for (message, key) in batch.entries_mut() {
+            *key = message_key(std::mem::take(key), message);
+            *message = pipeline.apply(std::mem::take(message));
         }
-        println!("{}", message);
-        io::stdout().flush().unwrap();
-        thread::sleep(sleep_duration);
+        sinks.send_batch(batch.entries());
+        let sent = batch.len() as u32;
+        if cli.count.is_some_and(|count| stats.messages() >= count) {
+            break;
+        }
+        thread::sleep(pacer.pace(sent, Instant::now()));
     }
 
-    // Flush any remaining Kafka messages.
-    if let Some(producer) = kafka_producer.as_mut() {
-        producer.flush(Duration::from_secs(5)).unwrap();
+    // Shutting down: from here on health probes report 503.
+    running.store(false, Ordering::SeqCst);
+
+    // Emit the end-of-stream marker so consumers can tell a clean end from a crash.
+    if let Some(marker) = &cli.eos_marker {
+        sinks.send_control(marker, "fluxfakr-eos");
+    }
+
+    // Replay or dead-letter any outage backlog and flush every sink.
+    sinks.flush();
+    if let Some(dead_letter) = &dead_letter {
+        dead_letter.flush();
     }
 
     // On exit, dump the generator's internal state
     println!("\n--- Generator Internal State Dump ---");
     println!("{}", generator.dump());
+
+    // Operator-facing run summary goes to stderr so it never mixes with the data stream.
+    eprint!("{}", stats.summary(mps));
 }
@@ -0,0 +1,487 @@
+//! MQTT output: a minimal MQTT 3.1.1 publisher over plain TCP.
+
+use crate::dead_letter::DeadLetterFile;
+use crate::sink::{Sink, SinkError};
+use crate::stats::RunStats;
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Minimum delay between reconnection attempts, so a dead broker isn't hammered.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the broker may take to answer a CONNECT or PUBLISH.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound on QoS 1 messages held for replay while the broker is unreachable.
+const MAX_BUFFERED: usize = 100_000;
+/// How long shutdown keeps trying to deliver buffered QoS 1 messages.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+/// QoS 1 messages published ahead of their PUBACK; only a full window waits for one.
+const MAX_IN_FLIGHT: usize = 64;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const DISCONNECT: u8 = 0xE0;
+
+/// MQTT delivery guarantee, which also decides what happens while the broker is down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MqttQos {
+    /// At most once: fire and forget; messages are dropped (to the dead-letter file,
+    /// if set) while the broker is unreachable.
+    #[value(name = "0")]
+    AtMostOnce,
+    /// At least once: each message is held until the broker's PUBACK, and is buffered and
+    /// replayed in order after a reconnect.
+    #[value(name = "1")]
+    AtLeastOnce,
+}
+
+/// Append an MQTT variable-length "remaining length".
+fn push_remaining_length(packet: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+/// Append a length-prefixed UTF-8 string.
+fn push_string(body: &mut Vec<u8>, s: &str) {
+    body.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    body.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    push_remaining_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// CONNECT with a clean session and keep-alive disabled, so an idle stream isn't dropped.
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT");
+    body.extend_from_slice(&[4, 0x02, 0, 0]);
+    push_string(&mut body, client_id);
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &str, packet_id: Option<u16>) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_string(&mut body, topic);
+    if let Some(id) = packet_id {
+        body.extend_from_slice(&id.to_be_bytes());
+    }
+    body.extend_from_slice(payload.as_bytes());
+    packet(PUBLISH | if packet_id.is_some() { 0x02 } else { 0 }, &body)
+}
+
+/// Read one packet, returning its type (the high nibble of the header) and body.
+fn read_packet(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte)?;
+    let kind = byte[0] & 0xF0;
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        stream.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed remaining length"));
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok((kind, body))
+}
+
+/// Split the first complete packet off `received`, if it holds one, returning its type
+/// and body.
+fn take_packet(received: &mut Vec<u8>) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let (mut len, mut shift, mut header) = (0usize, 0, 1);
+    loop {
+        let Some(&byte) = received.get(header) else {
+            return Ok(None);
+        };
+        header += 1;
+        len |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed remaining length"));
+        }
+    }
+    if received.len() < header + len {
+        return Ok(None);
+    }
+    let kind = received[0] & 0xF0;
+    let body = received[header..header + len].to_vec();
+    received.drain(..header + len);
+    Ok(Some((kind, body)))
+}
+
+/// Publishes each message to one MQTT topic, reconnecting after the broker goes away.
+pub struct MqttSink {
+    addr: String,
+    topic: String,
+    qos: MqttQos,
+    client_id: String,
+    stream: Option<TcpStream>,
+    next_reconnect: Instant,
+    next_packet_id: u16,
+    /// QoS 1 messages not yet acknowledged, oldest first. The first `in_flight.len()`
+    /// have been published on the current connection.
+    pending: VecDeque<String>,
+    /// Packet ids of the published messages awaiting their PUBACK, oldest first.
+    in_flight: VecDeque<u16>,
+    /// Bytes read from the broker that don't yet make up a whole packet.
+    received: Vec<u8>,
+    stats: Arc<RunStats>,
+    dead_letter: Option<Arc<DeadLetterFile>>,
+}
+
+impl MqttSink {
+    /// Connect to the broker. Failing here lets `main` report a bad address before streaming.
+    pub fn connect(
+        addr: &str,
+        topic: &str,
+        qos: MqttQos,
+        stats: Arc<RunStats>,
+        dead_letter: Option<Arc<DeadLetterFile>>,
+    ) -> io::Result<Self> {
+        let mut sink = MqttSink {
+            addr: addr.to_string(),
+            topic: topic.to_string(),
+            qos,
+            client_id: format!("fluxfakr-{}", std::process::id()),
+            stream: None,
+            next_reconnect: Instant::now(),
+            next_packet_id: 1,
+            pending: VecDeque::new(),
+            in_flight: VecDeque::new(),
+            received: Vec::new(),
+            stats,
+            dead_letter,
+        };
+        sink.stream = Some(sink.open()?);
+        Ok(sink)
+    }
+
+    /// Open a connection and complete the CONNECT/CONNACK exchange.
+    fn open(&self) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        stream.write_all(&connect_packet(&self.client_id))?;
+        match read_packet(&mut stream)? {
+            (CONNACK, body) if body.len() == 2 && body[1] == 0 => Ok(stream),
+            (CONNACK, body) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused the connection (return code {})", body.get(1).copied().unwrap_or(0)),
+            )),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected CONNACK")),
+        }
+    }
+
+    fn ensure_connected(&mut self) -> io::Result<()> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if now < self.next_reconnect {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "waiting to reconnect"));
+        }
+        self.next_reconnect = now + RECONNECT_INTERVAL;
+        self.stream = Some(self.open()?);
+        self.stats.sink(self.name()).set_available(true);
+        eprintln!("Reconnected to MQTT broker {}", self.addr);
+        Ok(())
+    }
+
+    /// Drop a failed connection. It is re-established on a later call, and whatever was
+    /// in flight is published again on the new one.
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.in_flight.clear();
+        self.received.clear();
+        self.next_reconnect = Instant::now() + RECONNECT_INTERVAL;
+        let counters = self.stats.sink(self.name());
+        if counters.is_healthy() {
+            counters.record_trip();
+        }
+        counters.set_available(false);
+    }
+
+    /// Publish one message at QoS 0. On failure the connection is dropped.
+    fn publish(&mut self, payload: &str) -> io::Result<()> {
+        self.ensure_connected()?;
+        let packet = publish_packet(&self.topic, payload, None);
+        let result = self.stream.as_mut().unwrap().write_all(&packet);
+        if result.is_err() {
+            self.disconnect();
+        }
+        result
+    }
+
+    /// Publish buffered QoS 1 messages in order, up to `MAX_IN_FLIGHT` ahead of their
+    /// PUBACKs, and settle the acknowledgements that have arrived. Only a full window
+    /// waits for the broker, or with `settle_all`, anything still in flight. On failure
+    /// the connection is dropped and the unacknowledged messages stay buffered.
+    fn drain(&mut self, settle_all: bool) -> io::Result<()> {
+        self.ensure_connected()?;
+        let result = self.pump(settle_all);
+        if result.is_err() {
+            self.disconnect();
+        }
+        result
+    }
+
+    fn pump(&mut self, settle_all: bool) -> io::Result<()> {
+        self.collect_acks(false)?;
+        while self.in_flight.len() < self.pending.len() {
+            if self.in_flight.len() == MAX_IN_FLIGHT {
+                self.collect_acks(true)?;
+                continue;
+            }
+            let id = self.next_packet_id;
+            // Packet id 0 is reserved.
+            self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+            let packet = publish_packet(&self.topic, &self.pending[self.in_flight.len()], Some(id));
+            self.stream.as_mut().unwrap().write_all(&packet)?;
+            self.in_flight.push_back(id);
+        }
+        while settle_all && !self.in_flight.is_empty() {
+            self.collect_acks(true)?;
+        }
+        Ok(())
+    }
+
+    /// Read what the broker has sent, waiting (up to `IO_TIMEOUT`) for something only if
+    /// `wait`, and settle the PUBACKs among it. Reading stops once nothing is in flight:
+    /// the broker has nothing else for us, and a connection it has closed since is
+    /// noticed on a later call.
+    fn collect_acks(&mut self, wait: bool) -> io::Result<()> {
+        let mut chunk = [0u8; 4096];
+        while !self.in_flight.is_empty() {
+            let stream = self.stream.as_mut().unwrap();
+            stream.set_nonblocking(!wait)?;
+            let read = stream.read(&mut chunk);
+            stream.set_nonblocking(false)?;
+            match read {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "broker closed the connection")),
+                Ok(n) => self.received.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && !wait => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            while let Some((kind, body)) = take_packet(&mut self.received)? {
+                // Anything else the broker sends is not ours to handle.
+                if let (PUBACK, &[high, low]) = (kind, &body[..]) {
+                    self.settle(u16::from_be_bytes([high, low]));
+                }
+            }
+            if wait {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a QoS 1 message delivered once its PUBACK arrives. Brokers acknowledge in
+    /// order, but an unknown id is ignored rather than trusted.
+    fn settle(&mut self, id: u16) {
+        if let Some(i) = self.in_flight.iter().position(|&f| f == id) {
+            self.in_flight.remove(i);
+            self.pending.remove(i);
+            self.stats.sink(self.name()).record_sent();
+        }
+    }
+
+    fn dead_letter(&self, reason: &str, payload: &str) {
+        self.stats.sink(self.name()).record_failed();
+        if let Some(dead_letter) = &self.dead_letter {
+            dead_letter.record(self.name(), reason, payload);
+        }
+    }
+}
+
+impl Sink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        match self.qos {
+            MqttQos::AtMostOnce => Ok(self.publish(payload)?),
+            MqttQos::AtLeastOnce => {
+                if self.pending.len() >= MAX_BUFFERED {
+                    self.dead_letter("MQTT buffer full", payload);
+                    return Ok(());
+                }
+                self.pending.push_back(payload.to_string());
+                // A failure leaves the message buffered for the next attempt.
+                let _ = self.drain(false);
+                Ok(())
+            }
+        }
+    }
+
+    /// At QoS 1, outcomes are only known on PUBACK, so the sink counts them itself.
+    fn tracks_delivery(&self) -> bool {
+        self.qos == MqttQos::AtLeastOnce
+    }
+
+    fn backlog(&self) -> u64 {
+        self.pending.len() as u64
+    }
+
+    /// Deliver what's still buffered, within a deadline, then disconnect cleanly.
+    fn flush(&mut self) {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        while self.drain(true).is_err() && Instant::now() < deadline {
+            std::thread::sleep(RECONNECT_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+            self.next_reconnect = Instant::now();
+        }
+        if !self.pending.is_empty() {
+            eprintln!("{} MQTT messages could not be delivered before shutdown", self.pending.len());
+            for payload in std::mem::take(&mut self.pending) {
+                self.dead_letter("undelivered at shutdown", &payload);
+            }
+        }
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.write_all(&packet(DISCONNECT, &[]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn test_packet_encoding() {
+        let mut len = Vec::new();
+        push_remaining_length(&mut len, 321);
+        assert_eq!(len, vec![0xC1, 0x02]);
+        assert_eq!(
+            connect_packet("id"),
+            vec![0x10, 14, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 0, 0, 2, b'i', b'd']
+        );
+        assert_eq!(publish_packet("t", "x", None), vec![0x30, 4, 0, 1, b't', b'x']);
+        assert_eq!(publish_packet("t", "x", Some(7)), vec![0x32, 6, 0, 1, b't', 0, 7, b'x']);
+
+        // Packets are split off the read buffer only once complete.
+        let mut received = vec![PUBACK, 2, 0];
+        assert!(take_packet(&mut received).unwrap().is_none());
+        received.extend([7, PUBACK]);
+        assert_eq!(take_packet(&mut received).unwrap(), Some((PUBACK, vec![0, 7])));
+        assert_eq!(received, vec![PUBACK]);
+    }
+
+    /// Accept one client, acknowledging its CONNECT and then `publishes` PUBLISH packets;
+    /// returns their payloads.
+    fn broker_session(listener: &TcpListener, publishes: usize) -> Vec<String> {
+        let (mut stream, _) = listener.accept().unwrap();
+        assert_eq!(read_packet(&mut stream).unwrap().0, CONNECT);
+        stream.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+        let mut payloads = Vec::new();
+        for _ in 0..publishes {
+            let (kind, body) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, PUBLISH);
+            // Topic "t" (3 bytes), then the packet id, then the payload.
+            stream.write_all(&[PUBACK, 2, body[3], body[4]]).unwrap();
+            payloads.push(String::from_utf8(body[5..].to_vec()).unwrap());
+        }
+        payloads
+    }
+
+    #[test]
+    fn test_qos1_buffers_while_down_and_replays_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let stats = Arc::new(RunStats::new(&["mqtt"]));
+        let (hung_up, first_closed) = mpsc::channel();
+        let broker = thread::spawn(move || {
+            let first = broker_session(&listener, 1);
+            // The first connection is dropped here; the sink must reconnect.
+            hung_up.send(()).unwrap();
+            let second = broker_session(&listener, 3);
+            (first, second)
+        });
+        let mut sink = MqttSink::connect(&addr, "t", MqttQos::AtLeastOnce, stats.clone(), None).unwrap();
+        sink.send("a", "").unwrap();
+        first_closed.recv().unwrap();
+        // The broker acknowledged "a" and hung up, so these are buffered.
+        sink.send("b", "").unwrap();
+        sink.send("c", "").unwrap();
+        assert_eq!(sink.backlog(), 2);
+        assert!(!stats.sink("mqtt").is_healthy());
+
+        sink.next_reconnect = Instant::now();
+        sink.send("d", "").unwrap();
+        sink.flush();
+        let (first, second) = broker.join().unwrap();
+        assert_eq!(first, vec!["a"]);
+        assert_eq!(second, vec!["b", "c", "d"]);
+        assert_eq!(stats.sink("mqtt").sent(), 4);
+        assert!(stats.sink("mqtt").is_healthy());
+    }
+
+    #[test]
+    fn test_qos1_publishes_ahead_of_pubacks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let stats = Arc::new(RunStats::new(&["mqtt"]));
+        let (all_read, ack) = mpsc::channel();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_packet(&mut stream).unwrap().0, CONNECT);
+            stream.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+            // Hold back every PUBACK until all ten messages have arrived.
+            let ids: Vec<[u8; 2]> = (0..10)
+                .map(|_| {
+                    let (_, body) = read_packet(&mut stream).unwrap();
+                    [body[3], body[4]]
+                })
+                .collect();
+            all_read.send(()).unwrap();
+            for [high, low] in ids {
+                stream.write_all(&[PUBACK, 2, high, low]).unwrap();
+            }
+            // Wait for the DISCONNECT.
+            assert_eq!(read_packet(&mut stream).unwrap().0, DISCONNECT);
+        });
+        let mut sink = MqttSink::connect(&addr, "t", MqttQos::AtLeastOnce, stats.clone(), None).unwrap();
+        let start = Instant::now();
+        for n in 0..10 {
+            sink.send(&n.to_string(), "").unwrap();
+        }
+        // None of the sends waited on the broker.
+        ack.recv().unwrap();
+        assert!(start.elapsed() < IO_TIMEOUT, "sends blocked on PUBACKs");
+        assert_eq!((sink.backlog(), sink.in_flight.len()), (10, 10));
+        sink.flush();
+        broker.join().unwrap();
+        assert_eq!(stats.sink("mqtt").sent(), 10);
+        assert_eq!(sink.backlog(), 0);
+    }
+}
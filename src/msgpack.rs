@@ -0,0 +1,239 @@
+//! MessagePack encoding of JSON values, using the smallest representation of each.
+
+use serde_json::{Map, Number, Value};
+
+/// Write a length-prefixed header: the `fix` form holds lengths up to `fix_max` in the
+/// marker byte itself, otherwise a 16- or 32-bit length follows its marker.
+fn push_header(out: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, markers: [u8; 2]) {
+    if len <= fix_max {
+        out.push(fix | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(markers[0]);
+        out.extend((len as u16).to_be_bytes());
+    } else {
+        out.push(markers[1]);
+        out.extend((len as u32).to_be_bytes());
+    }
+}
+
+fn push_number(out: &mut Vec<u8>, n: &Number) {
+    if let Some(u) = n.as_u64() {
+        match u {
+            0..=0x7f => out.push(u as u8),
+            0x80..=0xff => out.extend([0xcc, u as u8]),
+            0x100..=0xffff => {
+                out.push(0xcd);
+                out.extend((u as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                out.push(0xce);
+                out.extend((u as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xcf);
+                out.extend(u.to_be_bytes());
+            }
+        }
+    } else if let Some(i) = n.as_i64() {
+        // Only negative values get here.
+        match i {
+            -32..=-1 => out.push(i as u8),
+            -0x80..=-33 => out.extend([0xd0, i as u8]),
+            -0x8000..=-0x81 => {
+                out.push(0xd1);
+                out.extend((i as i16).to_be_bytes());
+            }
+            -0x8000_0000..=-0x8001 => {
+                out.push(0xd2);
+                out.extend((i as i32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xd3);
+                out.extend(i.to_be_bytes());
+            }
+        }
+    } else {
+        out.push(0xcb);
+        out.extend(n.as_f64().unwrap_or_default().to_be_bytes());
+    }
+}
+
+/// Append the MessagePack encoding of `value` to `out`.
+pub fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => push_number(out, n),
+        Value::String(s) => {
+            match s.len() {
+                len @ 0..=31 => out.push(0xa0 | len as u8),
+                len @ 32..=0xff => out.extend([0xd9, len as u8]),
+                len if len <= u16::MAX as usize => {
+                    out.push(0xda);
+                    out.extend((len as u16).to_be_bytes());
+                }
+                len => {
+                    out.push(0xdb);
+                    out.extend((len as u32).to_be_bytes());
+                }
+            }
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            push_header(out, items.len(), 0x90, 15, [0xdc, 0xdd]);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(map) => {
+            push_header(out, map.len(), 0x80, 15, [0xde, 0xdf]);
+            for (key, value) in map {
+                encode(&Value::String(key.clone()), out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+/// Reads MessagePack data back into JSON values.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self.bytes(N)?;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&[u8], String> {
+        if self.data.len() < n {
+            return Err("truncated MessagePack data".to_string());
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, String> {
+        let bytes = self.bytes(len)?;
+        Ok(Value::String(std::str::from_utf8(bytes).map_err(|e| e.to_string())?.to_string()))
+    }
+
+    fn array(&mut self, len: usize) -> Result<Value, String> {
+        (0..len).map(|_| self.value()).collect::<Result<_, _>>().map(Value::Array)
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let Value::String(key) = self.value()? else {
+                return Err("MessagePack map key is not a string".to_string());
+            };
+            map.insert(key, self.value()?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let [marker] = self.take::<1>()?;
+        let u16_len = |r: &mut Self| r.take::<2>().map(|b| u16::from_be_bytes(b) as usize);
+        let u32_len = |r: &mut Self| r.take::<4>().map(|b| u32::from_be_bytes(b) as usize);
+        match marker {
+            0x00..=0x7f => Ok(Value::from(marker)),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize),
+            0x90..=0x9f => self.array((marker & 0x0f) as usize),
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize),
+            0xc0 => Ok(Value::Null),
+            0xc2 => Ok(Value::Bool(false)),
+            0xc3 => Ok(Value::Bool(true)),
+            0xca => Ok(Value::from(f32::from_be_bytes(self.take()?) as f64)),
+            0xcb => Ok(Value::from(f64::from_be_bytes(self.take()?))),
+            0xcc => Ok(Value::from(u8::from_be_bytes(self.take()?))),
+            0xcd => Ok(Value::from(u16::from_be_bytes(self.take()?))),
+            0xce => Ok(Value::from(u32::from_be_bytes(self.take()?))),
+            0xcf => Ok(Value::from(u64::from_be_bytes(self.take()?))),
+            0xd0 => Ok(Value::from(i8::from_be_bytes(self.take()?))),
+            0xd1 => Ok(Value::from(i16::from_be_bytes(self.take()?))),
+            0xd2 => Ok(Value::from(i32::from_be_bytes(self.take()?))),
+            0xd3 => Ok(Value::from(i64::from_be_bytes(self.take()?))),
+            0xd9 => {
+                let [len] = self.take::<1>()?;
+                self.string(len as usize)
+            }
+            0xda => {
+                let len = u16_len(self)?;
+                self.string(len)
+            }
+            0xdb => {
+                let len = u32_len(self)?;
+                self.string(len)
+            }
+            0xdc => {
+                let len = u16_len(self)?;
+                self.array(len)
+            }
+            0xdd => {
+                let len = u32_len(self)?;
+                self.array(len)
+            }
+            0xde => {
+                let len = u16_len(self)?;
+                self.map(len)
+            }
+            0xdf => {
+                let len = u32_len(self)?;
+                self.map(len)
+            }
+            0xe0..=0xff => Ok(Value::from(marker as i8)),
+            _ => Err(format!("unsupported MessagePack marker 0x{:02x}", marker)),
+        }
+    }
+}
+
+/// Decode one MessagePack value that fills all of `data`.
+pub fn decode(data: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader { data };
+    let value = reader.value()?;
+    if !reader.data.is_empty() {
+        return Err("trailing data after the MessagePack value".to_string());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encoded(value: Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode(&value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_encoding_uses_the_smallest_form() {
+        assert_eq!(encoded(json!({"a": 1, "b": [true, null]})), [0x82, 0xa1, b'a', 0x01, 0xa1, b'b', 0x92, 0xc3, 0xc0]);
+        assert_eq!(encoded(json!(-1)), [0xff]);
+        assert_eq!(encoded(json!(-200)), [0xd1, 0xff, 0x38]);
+        assert_eq!(encoded(json!(300)), [0xcd, 0x01, 0x2c]);
+        assert_eq!(encoded(json!(1.5)), [0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encoded(json!("x".repeat(40)))[..2], [0xd9, 40]);
+        assert_eq!(encoded(json!("x".repeat(300)))[..3], [0xda, 0x01, 0x2c]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "id": "TXN-1",
+            "n": [0, 127, 128, 65_536, u64::MAX, -32, -33, -40_000, i64::MIN],
+            "price": 183.872_638_883_312_33,
+            "nested": {"ok": false, "none": null, "long": "y".repeat(70_000)},
+            "many": (0..20).collect::<Vec<_>>(),
+        });
+        assert_eq!(decode(&encoded(value.clone())).unwrap(), value);
+        assert!(decode(&[0x92, 0x01]).is_err());
+        assert!(decode(&[0x01, 0x02]).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::clock;
+use crate::rng::SimRng;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// How far behind schedule the pacer lets the stream fall before it stops trying to
+/// catch up, so a long stall (a blocked sink, a suspended process) isn't followed by a
+/// burst of everything that was missed.
+const MAX_LAG: Duration = Duration::from_secs(1);
+
+/// The slowest supported rate, one message a day. Slower rates have intervals too long
+/// to schedule against the clock.
+pub const MIN_MPS: f64 = 1.0 / 86_400.0;
+
+/// Paces the main loop against a deadline schedule: each message moves the next send
+/// time on by one interval, and the loop only sleeps for what's left of it. Time spent
+/// generating and sending therefore comes out of the wait instead of adding to it, and
+/// a loop running behind sends without waiting until it has caught up.
+pub struct Pacer {
+    interval: Duration,
+    ramp: Option<Ramp>,
+    start: Instant,
+    next: Instant,
+    jitter: Option<Jitter>,
+}
+
+/// A rate that rises (or falls) linearly from `start_mps` to `end_mps` over `duration`,
+/// then holds at `end_mps`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ramp {
+    pub start_mps: f64,
+    pub end_mps: f64,
+    pub duration: Duration,
+}
+
+impl Ramp {
+    /// The rate `elapsed` into the ramp.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+        self.start_mps + (self.end_mps - self.start_mps) * progress
+    }
+}
+
+/// Parse a `--ramp` spec, `<start_mps>:<end_mps>:<duration>` (e.g. `10:1000:1m`).
+pub fn parse_ramp(spec: &str) -> Result<Ramp, String> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let [start, end, duration] = parts[..] else {
+        return Err(format!("expected <start_mps>:<end_mps>:<duration>, got '{}'", spec));
+    };
+    let rate = |part: &str| match part.trim().parse::<f64>() {
+        Ok(mps) if mps.is_finite() && mps >= MIN_MPS => Ok(mps),
+        _ => Err(format!("rates must be numbers of at least one message a day, got '{}'", part)),
+    };
+    Ok(Ramp {
+        start_mps: rate(start)?,
+        end_mps: rate(end)?,
+        duration: clock::parse_duration(duration)?,
+    })
+}
+
+/// Scales each step of the schedule by a random factor in `[1 - fraction, 1 + fraction]`.
+/// The factor averages 1, so traffic turns bursty while the long-run rate is unchanged.
+pub struct Jitter {
+    fraction: f64,
+    rng: SimRng,
+}
+
+impl Jitter {
+    pub fn new(fraction: f64, rng: SimRng) -> Self {
+        Jitter { fraction, rng }
+    }
+}
+
+impl Pacer {
+    /// A pacer sending one message per `interval`, or following `ramp` if given.
+    pub fn new(interval: Duration, ramp: Option<Ramp>, jitter: Option<Jitter>, now: Instant) -> Self {
+        Pacer {
+            interval,
+            ramp,
+            start: now,
+            next: now,
+            jitter,
+        }
+    }
+
+    /// The rate the schedule is currently at, in messages per second.
+    pub fn rate(&self) -> f64 {
+        match &self.ramp {
+            Some(ramp) => ramp.rate_at(self.next.saturating_duration_since(self.start)),
+            None => 1.0 / self.interval.as_secs_f64(),
+        }
+    }
+
+    /// Account for `sent` messages and return how long to wait before the next batch,
+    /// zero if the schedule is already due.
+    pub fn pace(&mut self, sent: u32, now: Instant) -> Duration {
+        let step = match self.ramp {
+            Some(_) => Duration::from_secs_f64(sent as f64 / self.rate()),
+            None => self.interval * sent,
+        };
+        self.next += match &mut self.jitter {
+            Some(jitter) => {
+                step.mul_f64(jitter.rng.random_range(1.0 - jitter.fraction..=1.0 + jitter.fraction))
+            }
+            None => step,
+        };
+        if let Some(floor) = now.checked_sub(MAX_LAG) {
+            self.next = self.next.max(floor);
+        }
+        self.next.saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RngAlgorithm;
+    use std::thread;
+
+    #[test]
+    fn test_pacer_subtracts_work_time() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Duration::from_millis(10), None, None, start);
+        // 4 ms of work after the first message leaves 6 ms to wait.
+        assert_eq!(
+            pacer.pace(1, start + Duration::from_millis(4)),
+            Duration::from_millis(6)
+        );
+        // Running 5 ms late, the next message is already due.
+        assert_eq!(pacer.pace(1, start + Duration::from_millis(25)), Duration::ZERO);
+        // A batch of three moves the deadline on by three intervals.
+        assert_eq!(
+            pacer.pace(3, start + Duration::from_millis(25)),
+            Duration::from_millis(25)
+        );
+    }
+
+    #[test]
+    fn test_pacer_caps_catch_up_after_a_stall() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Duration::from_millis(10), None, None, start);
+        // After a 10 s stall only MAX_LAG worth of messages goes out without waiting.
+        let resume = start + Duration::from_secs(10);
+        let mut burst = 0;
+        while pacer.pace(1, resume).is_zero() {
+            burst += 1;
+        }
+        // The first lands on the floor, then MAX_LAG / interval more are already due.
+        assert_eq!(burst, 101);
+    }
+
+    #[test]
+    fn test_paced_rate_matches_request() {
+        // Simulated clock: 10 s at 1,000 msg/s in batches of 10, each batch costing
+        // 3 ms of work. A plain sleep per message would only manage about 770 msg/s.
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Duration::from_millis(1), None, None, start);
+        let mut now = start;
+        let mut sent = 0;
+        while now < start + Duration::from_secs(10) {
+            now += Duration::from_millis(3);
+            sent += 10;
+            now += pacer.pace(10, now);
+        }
+        let rate = sent as f64 / now.duration_since(start).as_secs_f64();
+        assert!((rate - 1_000.0).abs() < 10.0, "rate {}", rate);
+    }
+
+    #[test]
+    fn test_paced_rate_matches_request_on_the_wall_clock() {
+        // Sleep overshoot is made up on the next wait, so real pacing stays on target.
+        let start = Instant::now();
+        let mut pacer = Pacer::new(Duration::from_micros(500), None, None, start);
+        let mut sent = 0;
+        while start.elapsed() < Duration::from_millis(500) {
+            sent += 5;
+            thread::sleep(pacer.pace(5, Instant::now()));
+        }
+        let rate = sent as f64 / start.elapsed().as_secs_f64();
+        assert!((rate - 2_000.0).abs() < 2_000.0 * 0.05, "rate {}", rate);
+    }
+
+    #[test]
+    fn test_jitter_keeps_the_mean_interval() {
+        // Waits between single messages, in ms, when each is sent as soon as it's due.
+        fn intervals(fraction: f64) -> Vec<f64> {
+            let start = Instant::now();
+            let jitter = Jitter::new(fraction, SimRng::new(RngAlgorithm::Chacha8, 7));
+            let mut pacer = Pacer::new(Duration::from_millis(10), None, Some(jitter), start);
+            let mut now = start;
+            (0..10_000)
+                .map(|_| {
+                    let wait = pacer.pace(1, now);
+                    now += wait;
+                    wait.as_secs_f64() * 1_000.0
+                })
+                .collect()
+        }
+        fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let squares: f64 = samples.iter().map(|x| (x - mean).powi(2)).sum();
+            (mean, squares / samples.len() as f64)
+        }
+        let (mean_none, variance_none) = mean_and_variance(&intervals(0.0));
+        let (mean_low, variance_low) = mean_and_variance(&intervals(0.2));
+        let (mean_high, variance_high) = mean_and_variance(&intervals(0.8));
+        for mean in [mean_none, mean_low, mean_high] {
+            assert!((mean - 10.0).abs() < 0.1, "mean interval {} ms", mean);
+        }
+        assert!(variance_none < 1e-9, "variance {}", variance_none);
+        assert!(variance_low > 0.5 && variance_high > 4.0 * variance_low);
+    }
+
+    #[test]
+    fn test_ramp_rises_then_holds() {
+        let ramp = parse_ramp("100:1000:10s").unwrap();
+        assert_eq!(ramp.rate_at(Duration::ZERO), 100.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(5)), 550.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(60)), 1000.0);
+        assert_eq!(parse_ramp("50:5:2m").unwrap().rate_at(Duration::from_secs(60)), 27.5);
+        for bad in ["100:1000", "0:1000:10s", "1e-300:1:1s", "100:fast:10s", "100:1000:0s", "1:2:3:4"] {
+            assert!(parse_ramp(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_ramp_sends_more_in_its_second_half() {
+        // Sending as soon as each message is due, count what falls in either half.
+        let start = Instant::now();
+        let ramp = parse_ramp("100:1000:10s").unwrap();
+        let mut pacer = Pacer::new(Duration::ZERO, Some(ramp), None, start);
+        let mut now = start;
+        let (mut first, mut second) = (0, 0);
+        while now < start + Duration::from_secs(10) {
+            if now < start + Duration::from_secs(5) {
+                first += 1;
+            } else {
+                second += 1;
+            }
+            now += pacer.pace(1, now);
+        }
+        // On average 325 msg/s, then 775 msg/s.
+        assert!((first as f64 - 1_625.0).abs() < 20.0, "first half {}", first);
+        assert!((second as f64 - 3_875.0).abs() < 20.0, "second half {}", second);
+        assert_eq!(pacer.rate(), 1000.0);
+    }
+}
@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use rand::Rng;
+
+/// Named geographic presets that keep generated locations inside one market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Region {
+    UsEast,
+    UsWest,
+    EuWest,
+    ApSoutheast,
+}
+
+/// A town in a region preset, with its approximate centre.
+#[derive(Debug)]
+pub struct Town {
+    pub name: &'static str,
+    pub state: &'static str,
+    pub country: &'static str,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Maximum distance (degrees) a location is scattered from its town centre.
+const TOWN_RADIUS_DEG: f64 = 0.05;
+
+const US_EAST: &[Town] = &[
+    Town { name: "New York", state: "NY", country: "USA", lat: 40.7128, lon: -74.0060 },
+    Town { name: "Boston", state: "MA", country: "USA", lat: 42.3601, lon: -71.0589 },
+    Town { name: "Philadelphia", state: "PA", country: "USA", lat: 39.9526, lon: -75.1652 },
+    Town { name: "Baltimore", state: "MD", country: "USA", lat: 39.2904, lon: -76.6122 },
+    Town { name: "Richmond", state: "VA", country: "USA", lat: 37.5407, lon: -77.4360 },
+    Town { name: "Hartford", state: "CT", country: "USA", lat: 41.7658, lon: -72.6734 },
+];
+
+const US_WEST: &[Town] = &[
+    Town { name: "Los Angeles", state: "CA", country: "USA", lat: 34.0522, lon: -118.2437 },
+    Town { name: "San Francisco", state: "CA", country: "USA", lat: 37.7749, lon: -122.4194 },
+    Town { name: "Seattle", state: "WA", country: "USA", lat: 47.6062, lon: -122.3321 },
+    Town { name: "Portland", state: "OR", country: "USA", lat: 45.5152, lon: -122.6784 },
+    Town { name: "San Diego", state: "CA", country: "USA", lat: 32.7157, lon: -117.1611 },
+    Town { name: "Sacramento", state: "CA", country: "USA", lat: 38.5816, lon: -121.4944 },
+];
+
+const EU_WEST: &[Town] = &[
+    Town { name: "London", state: "England", country: "UK", lat: 51.5074, lon: -0.1278 },
+    Town { name: "Manchester", state: "England", country: "UK", lat: 53.4808, lon: -2.2426 },
+    Town { name: "Dublin", state: "Leinster", country: "Ireland", lat: 53.3498, lon: -6.2603 },
+    Town { name: "Paris", state: "Île-de-France", country: "France", lat: 48.8566, lon: 2.3522 },
+    Town { name: "Lyon", state: "Auvergne-Rhône-Alpes", country: "France", lat: 45.7640, lon: 4.8357 },
+    Town { name: "Amsterdam", state: "North Holland", country: "Netherlands", lat: 52.3676, lon: 4.9041 },
+];
+
+const AP_SOUTHEAST: &[Town] = &[
+    Town { name: "Singapore", state: "Central", country: "Singapore", lat: 1.3521, lon: 103.8198 },
+    Town { name: "Kuala Lumpur", state: "Federal Territory", country: "Malaysia", lat: 3.1390, lon: 101.6869 },
+    Town { name: "Bangkok", state: "Bangkok", country: "Thailand", lat: 13.7563, lon: 100.5018 },
+    Town { name: "Jakarta", state: "Jakarta", country: "Indonesia", lat: -6.2088, lon: 106.8456 },
+    Town { name: "Manila", state: "Metro Manila", country: "Philippines", lat: 14.5995, lon: 120.9842 },
+    Town { name: "Ho Chi Minh City", state: "Ho Chi Minh", country: "Vietnam", lat: 10.8231, lon: 106.6297 },
+];
+
+impl Region {
+    /// Towns that generated locations cluster around.
+    pub fn towns(self) -> &'static [Town] {
+        match self {
+            Region::UsEast => US_EAST,
+            Region::UsWest => US_WEST,
+            Region::EuWest => EU_WEST,
+            Region::ApSoutheast => AP_SOUTHEAST,
+        }
+    }
+
+    /// Pick a town and a location scattered around its centre.
+    pub fn locate<R: Rng + ?Sized>(self, rng: &mut R) -> (&'static Town, f64, f64) {
+        let town = &self.towns()[rng.random_range(0..self.towns().len())];
+        let lat = town.lat + rng.random_range(-TOWN_RADIUS_DEG..=TOWN_RADIUS_DEG);
+        let lon = town.lon + rng.random_range(-TOWN_RADIUS_DEG..=TOWN_RADIUS_DEG);
+        (town, lat, lon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations_stay_near_region_towns() {
+        let mut rng = rand::rng();
+        for region in Region::value_variants() {
+            for _ in 0..200 {
+                let (town, lat, lon) = region.locate(&mut rng);
+                assert!((lat - town.lat).abs() <= TOWN_RADIUS_DEG + 1e-9);
+                assert!((lon - town.lon).abs() <= TOWN_RADIUS_DEG + 1e-9);
+            }
+        }
+    }
+}
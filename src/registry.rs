@@ -0,0 +1,149 @@
+//! Confluent Schema Registry client: registers the Avro schema at startup, so Kafka
+//! records can carry its id (the Confluent wire format) instead of a fingerprint.
+
+use crate::http;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long the registry gets to accept the connection and answer.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Register `schema` under `subject` with the registry at `url`, returning its id. The
+/// registry hands back the existing id for a schema it already holds.
+pub fn register(url: &str, subject: &str, schema: &Value) -> Result<u32, String> {
+    let endpoint = http::parse_url(url)?;
+    let path = format!("{}/subjects/{}/versions", endpoint.path.trim_end_matches('/'), subject);
+    let body = json!({ "schema": schema.to_string() }).to_string();
+    let addr = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", endpoint.host, e))?
+        .next()
+        .ok_or_else(|| format!("cannot resolve {}", endpoint.host))?;
+    let response = post(&addr, &endpoint, &path, &body).map_err(|e| format!("{}: {}", url, e))?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| format!("{}: incomplete HTTP response", url))?;
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("{}: bad status line", url))?;
+    let body = if head.to_ascii_lowercase().contains("transfer-encoding: chunked") {
+        dechunk(body).ok_or_else(|| format!("{}: malformed chunked response", url))?
+    } else {
+        body.to_string()
+    };
+    let reply: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+    if !(200..300).contains(&status) {
+        // The registry explains rejections, such as an incompatible schema, in `message`.
+        let reason = reply["message"].as_str().unwrap_or(body.trim());
+        return Err(format!("registry answered HTTP {}: {}", status, reason));
+    }
+    reply["id"]
+        .as_u64()
+        .and_then(|id| u32::try_from(id).ok())
+        .ok_or_else(|| format!("registry reply has no schema id: {}", body.trim()))
+}
+
+/// POST `body` and read the whole response; the connection is closed after it.
+fn post(addr: &SocketAddr, endpoint: &http::Endpoint, path: &str, body: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect_timeout(addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/vnd.schemaregistry.v1+json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        endpoint.host,
+        endpoint.port,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+/// Join the chunks of a chunked transfer-encoded body.
+fn dechunk(mut body: &str) -> Option<String> {
+    let mut out = String::new();
+    loop {
+        let (size, rest) = body.split_once("\r\n")?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        if size == 0 {
+            return Some(out);
+        }
+        out.push_str(rest.get(..size)?);
+        body = rest.get(size..)?.strip_prefix("\r\n")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answer one request with `response`, returning the request line and body.
+    fn serve(listener: TcpListener, response: String) -> thread::JoinHandle<(String, String)> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+            (request_line.trim_end().to_string(), String::from_utf8(body).unwrap())
+        })
+    }
+
+    #[test]
+    fn test_registers_the_schema_under_its_subject() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = serve(
+            listener,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n{\"id\":42}\r\n0\r\n\r\n".to_string(),
+        );
+        let schema = json!({"type": "record", "name": "Tick", "fields": []});
+        assert_eq!(register(&url, "ticks-value", &schema), Ok(42));
+
+        let (request_line, body) = server.join().unwrap();
+        assert_eq!(request_line, "POST /subjects/ticks-value/versions HTTP/1.1");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        // The schema travels as a string holding its JSON.
+        assert_eq!(serde_json::from_str::<Value>(body["schema"].as_str().unwrap()).unwrap(), schema);
+    }
+
+    #[test]
+    fn test_rejections_and_unreachable_registries_are_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let reply = r#"{"error_code":409,"message":"Schema being registered is incompatible"}"#;
+        let response = format!("HTTP/1.1 409 Conflict\r\nContent-Length: {}\r\n\r\n{}", reply.len(), reply);
+        let server = serve(listener, response);
+        let error = register(&url, "ticks-value", &json!("long")).unwrap_err();
+        assert!(error.contains("409") && error.contains("incompatible"), "{}", error);
+        server.join().unwrap();
+
+        // Nothing listens on a freshly released port.
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        assert!(register(&format!("http://127.0.0.1:{}", port), "ticks-value", &json!("long")).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use clap::ValueEnum;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::{ChaCha20Rng, ChaCha8Rng};
+use rand_pcg::Pcg64;
+
+/// Explicitly pinned random number generator algorithms.
+///
+/// `StdRng` is documented as free to change algorithm between `rand` releases,
+/// which would silently change every seeded dataset. These algorithms are
+/// fixed, so a given `--rng`/`--seed` pair reproduces the same stream across
+/// toolchain and dependency upgrades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RngAlgorithm {
+    /// ChaCha with 8 rounds: fast and statistically strong (the default).
+    Chacha8,
+    /// ChaCha with 20 rounds: cryptographic strength, slower.
+    Chacha20,
+    /// PCG XSL 128/64 (`Pcg64`): smallest state, fastest.
+    Pcg,
+}
+
+/// Pipeline stages that draw random numbers of their own, outside the generator. Each
+/// gets a stream derived from the run seed, so no stage replays another's numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    NullInjection = 1,
+    SizeMix = 2,
+    Padding = 3,
+    Jitter = 4,
+}
+
+/// A seeded generator of one of the pinned algorithms.
+#[derive(Debug, Clone)]
+pub enum SimRng {
+    ChaCha8(ChaCha8Rng),
+    ChaCha20(ChaCha20Rng),
+    Pcg(Pcg64),
+}
+
+impl SimRng {
+    pub fn new(algorithm: RngAlgorithm, seed: u64) -> Self {
+        match algorithm {
+            RngAlgorithm::Chacha8 => SimRng::ChaCha8(ChaCha8Rng::seed_from_u64(seed)),
+            RngAlgorithm::Chacha20 => SimRng::ChaCha20(ChaCha20Rng::seed_from_u64(seed)),
+            RngAlgorithm::Pcg => SimRng::Pcg(Pcg64::seed_from_u64(seed)),
+        }
+    }
+
+    /// The stream of `stage` for a run seeded with `seed`: the seed is mixed with the
+    /// stage (a SplitMix64 step), so each stage's stream differs from the generator's.
+    pub fn for_stage(algorithm: RngAlgorithm, seed: u64, stage: Stage) -> Self {
+        let mut z = seed ^ (stage as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        SimRng::new(algorithm, z ^ (z >> 31))
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SimRng::ChaCha8(rng) => rng.next_u32(),
+            SimRng::ChaCha20(rng) => rng.next_u32(),
+            SimRng::Pcg(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SimRng::ChaCha8(rng) => rng.next_u64(),
+            SimRng::ChaCha20(rng) => rng.next_u64(),
+            SimRng::Pcg(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            SimRng::ChaCha8(rng) => rng.fill_bytes(dest),
+            SimRng::ChaCha20(rng) => rng.fill_bytes(dest),
+            SimRng::Pcg(rng) => rng.fill_bytes(dest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_stream() {
+        for algorithm in [RngAlgorithm::Chacha8, RngAlgorithm::Chacha20, RngAlgorithm::Pcg] {
+            let mut a = SimRng::new(algorithm, 42);
+            let mut b = SimRng::new(algorithm, 42);
+            for _ in 0..100 {
+                assert_eq!(a.next_u64(), b.next_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn test_stages_draw_distinct_streams() {
+        let stages = [Stage::NullInjection, Stage::SizeMix, Stage::Padding, Stage::Jitter];
+        let mut firsts: Vec<u64> = stages
+            .iter()
+            .map(|&stage| SimRng::for_stage(RngAlgorithm::Chacha8, 42, stage).next_u64())
+            .collect();
+        firsts.push(SimRng::new(RngAlgorithm::Chacha8, 42).next_u64());
+        firsts.sort_unstable();
+        firsts.dedup();
+        assert_eq!(firsts.len(), stages.len() + 1);
+        // A stage's stream is still fixed by the seed.
+        let again = SimRng::for_stage(RngAlgorithm::Chacha8, 42, Stage::Jitter).next_u64();
+        assert_eq!(again, SimRng::for_stage(RngAlgorithm::Chacha8, 42, Stage::Jitter).next_u64());
+    }
+
+    #[test]
+    fn test_algorithms_are_pinned() {
+        // Known first outputs for seed 0. If these change, seeded datasets are no
+        // longer reproducible and the upgrade must be called out.
+        let first = |algorithm| SimRng::new(algorithm, 0).next_u64();
+        assert_eq!(first(RngAlgorithm::Chacha8), 13080132717333068652);
+        assert_eq!(first(RngAlgorithm::Chacha20), 449479075714955186);
+        assert_eq!(first(RngAlgorithm::Pcg), 2354861276966075475);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::avro::AvroEncoder;
+use crate::csv::{self, CsvEncoder};
+use crate::format::OutputFormat;
+use crate::generator::clickstream::{ClickstreamConfig, ClickstreamGenerator};
+use crate::generator::iot_sensor::{IotConfig, IotSensorGenerator};
+use crate::generator::payments::{PaymentsConfig, PaymentsGenerator};
+use crate::generator::stock::{StockConfig, StockDataGenerator, StockMessages};
+use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+use crate::generator::web_logs::{WebLogsConfig, WebLogsGenerator};
+use crate::generator::Generator;
+use clap::Parser;
+use serde_json::json;
+
+/// `fluxfakr schema`: print a module's message schema as a JSON Schema document (or the
+/// Avro schema `--format avro` encodes against, or the header `--format csv` writes) and
+/// exit, so consumers can register it or validate against it ahead of time.
+#[derive(Parser, Debug)]
+#[command(name = "fluxfakr schema")]
+pub struct SchemaArgs {
+    /// Generator module to describe (e.g., stock, supermarket, iot, web_logs, clickstream or payments)
+    #[arg(long)]
+    pub module: String,
+
+    /// Describe stock ticks with `--emit-deltas` switched on
+    #[arg(long)]
+    pub emit_deltas: bool,
+
+    /// Describe stock ticks with `--tickers` switched on
+    #[arg(long)]
+    pub tickers: bool,
+
+    /// Describe the OHLC bars of stock `--candles` instead of ticks
+    #[arg(long, conflicts_with = "emit_deltas")]
+    pub candles: bool,
+
+    /// Describe the stock messages of `--stock-messages`
+    #[arg(long, value_enum, default_value_t = StockMessages::Combined)]
+    pub stock_messages: StockMessages,
+
+    /// Describe payments with `--omit-fraud-labels` switched on
+    #[arg(long)]
+    pub omit_fraud_labels: bool,
+
+    /// Print the Avro schema used by `--format avro`, the CSV header of `--format csv`, the
+    /// tag and field mapping of `--format line-protocol` or the Apache log format of
+    /// `--format combined-log`, instead of JSON Schema
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+}
+
+/// The Apache `LogFormat` string of `--format combined-log` lines.
+const COMBINED_LOG_FORMAT: &str = r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-agent}i""#;
+
+/// The schema of `module` in its default configuration, rendered for printing, or `None`
+/// for an unknown module.
+pub fn module_schema(args: &SchemaArgs) -> Option<String> {
+    let generator: Box<dyn Generator> = match args.module.as_str() {
+        "stock" => Box::new(StockDataGenerator::new(
+            1,
+            StockConfig {
+                emit_deltas: args.emit_deltas,
+                tickers: args.tickers,
+                // Any interval; bars look the same.
+                candle_interval_ms: args.candles.then_some(60_000),
+                messages: args.stock_messages,
+                ..StockConfig::default()
+            },
+        )),
+        "supermarket" => Box::new(SalesGenerator::new(SalesConfig::default())),
+        "iot" => Box::new(IotSensorGenerator::new(1, IotConfig::default())),
+        "web_logs" => Box::new(WebLogsGenerator::new(1, WebLogsConfig::default())),
+        "clickstream" => Box::new(ClickstreamGenerator::new(1, ClickstreamConfig::default())),
+        "payments" => Box::new(PaymentsGenerator::new(
+            1,
+            PaymentsConfig {
+                labels: !args.omit_fraud_labels,
+                ..PaymentsConfig::default()
+            },
+        )),
+        _ => return None,
+    };
+    let schema = generator.schema();
+    let document = match args.format {
+        OutputFormat::Avro => AvroEncoder::from_json_schema(&schema)
+            .expect("built-in module schemas map to Avro")
+            .schema(),
+        OutputFormat::Csv => {
+            let fields = generator.fields();
+            return Some(CsvEncoder::new(csv::columns(&fields, &fields)).header());
+        }
+        OutputFormat::LineProtocol => {
+            let mapping = generator.line_mapping().expect("built-in modules map to line protocol");
+            json!({
+                "measurement": args.module,
+                "tags": mapping.tags,
+                "fields": mapping.fields,
+                "timestamp": mapping.timestamp,
+            })
+        }
+        // Apache's own name for the format, as a `LogFormat` directive would spell it.
+        OutputFormat::CombinedLog => return Some(COMBINED_LOG_FORMAT.to_string()),
+        // MessagePack carries the same fields as JSON.
+        OutputFormat::Json | OutputFormat::Msgpack => schema,
+    };
+    Some(serde_json::to_string_pretty(&document).expect("schemas serialize"))
+}
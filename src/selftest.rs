@@ -0,0 +1,448 @@
+use crate::clock::Clock;
+use crate::generator::clickstream::{ClickstreamConfig, ClickstreamGenerator};
+use crate::generator::iot_sensor::{IotConfig, IotSensorGenerator};
+use crate::generator::payments::{PaymentsConfig, PaymentsGenerator};
+use crate::generator::stock::{StockConfig, StockDataGenerator};
+use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+use crate::generator::web_logs::{WebLogsConfig, WebLogsGenerator};
+use crate::generator::Generator;
+use clap::Parser;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How many failures are listed per module before the rest are only counted.
+const MAX_REPORTED_FAILURES: usize = 5;
+
+/// `fluxfakr selftest`: run every module with its optional features switched on,
+/// validate every message, and report throughput and pass/fail per module.
+#[derive(Parser, Debug)]
+#[command(name = "fluxfakr selftest")]
+pub struct SelftestArgs {
+    /// Messages generated per module
+    #[arg(long, default_value_t = 10_000)]
+    pub count: u64,
+
+    /// Seed for every module's random number stream
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+}
+
+/// Per-module checks beyond the schema, keeping whatever state they need across messages.
+type Invariants = Box<dyn FnMut(&Value) -> Result<(), String>>;
+
+/// Result of self-testing one module.
+#[derive(Debug)]
+pub struct ModuleReport {
+    pub module: &'static str,
+    pub messages: u64,
+    /// Time spent generating, excluding validation.
+    pub elapsed: Duration,
+    pub failures: Vec<String>,
+    /// Failures beyond those listed in `failures`.
+    pub unlisted_failures: u64,
+}
+
+impl ModuleReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// One line of the report: module, messages, throughput and verdict.
+    pub fn summary(&self) -> String {
+        let rate = self.messages as f64 / self.elapsed.as_secs_f64().max(1e-9);
+        format!(
+            "{:<12} {:>8} msgs {:>12.0} msg/s  {}",
+            self.module,
+            self.messages,
+            rate,
+            if self.passed() { "PASS" } else { "FAIL" }
+        )
+    }
+}
+
+/// Every module, configured with its optional features enabled so they are exercised too.
+fn modules(seed: u64) -> Vec<(&'static str, Box<dyn Generator>, Invariants)> {
+    let stock = StockDataGenerator::new(
+        10,
+        StockConfig {
+            seed,
+            tick_size: Some(0.01),
+            emit_deltas: true,
+            tickers: true,
+            ..StockConfig::default()
+        },
+    );
+    let supermarket = SalesGenerator::new(SalesConfig {
+        seed,
+        abandon_rate: 0.1,
+        stockout_rate: 0.05,
+        coupon_rate: 0.2,
+        store_pool: Some(10),
+        lanes_per_store: Some(4),
+        rich_demographics: true,
+        ..SalesConfig::default()
+    });
+    let iot = IotSensorGenerator::new(
+        10,
+        IotConfig {
+            seed,
+            ..IotConfig::default()
+        },
+    );
+    let web_logs = WebLogsGenerator::new(
+        10,
+        WebLogsConfig {
+            seed,
+            ..WebLogsConfig::default()
+        },
+    );
+    // Users dwell on pages for seconds at a time; a simulated clock skips the waits.
+    let clickstream = ClickstreamGenerator::new(
+        10,
+        ClickstreamConfig {
+            seed,
+            clock: Clock::Stepped {
+                now_ms: 1_700_000_000_000,
+                step_ms: 0,
+            },
+            ..ClickstreamConfig::default()
+        },
+    );
+    let payments = PaymentsGenerator::new(
+        10,
+        PaymentsConfig {
+            seed,
+            fraud_rate: 0.05,
+            ..PaymentsConfig::default()
+        },
+    );
+    vec![
+        ("stock", Box::new(stock), stock_invariants()),
+        ("supermarket", Box::new(supermarket), supermarket_invariants()),
+        ("iot", Box::new(iot), iot_invariants()),
+        ("web_logs", Box::new(web_logs), web_logs_invariants()),
+        ("clickstream", Box::new(clickstream), clickstream_invariants()),
+        ("payments", Box::new(payments), payments_invariants()),
+    ]
+}
+
+/// Quotes are ordered (bid <= price <= ask) and each instrument's `seq` counts up by one.
+fn stock_invariants() -> Invariants {
+    let mut last_seq: HashMap<String, u64> = HashMap::new();
+    Box::new(move |v| {
+        let number = |field: &str| v[field].as_f64().ok_or(format!("{} is not a number", field));
+        let (bid, price, ask) = (number("bid")?, number("price")?, number("ask")?);
+        if !(0.0 < bid && bid <= price && price <= ask) {
+            return Err(format!("quote out of order: bid {} price {} ask {}", bid, price, ask));
+        }
+        let instrument = v["instrument"].as_str().ok_or("instrument is not a string")?;
+        let seq = v["seq"].as_u64().ok_or("seq is not a number")?;
+        let expected = last_seq.get(instrument).map_or(1, |last| last + 1);
+        if seq != expected {
+            return Err(format!("{} seq {} follows {}", instrument, seq, expected - 1));
+        }
+        last_seq.insert(instrument.to_string(), seq);
+        Ok(())
+    })
+}
+
+/// Sale totals match their line items, each event type carries consistent figures, and
+/// a basket's `seq` counts up by one from 1.
+fn supermarket_invariants() -> Invariants {
+    let mut last_seq: HashMap<String, u64> = HashMap::new();
+    Box::new(move |v| {
+        let number = |field: &Value| field.as_f64().unwrap_or(f64::NAN);
+        match v["event_type"].as_str() {
+            None => {
+                let quantity = number(&v["quantity"]);
+                let expected = number(&v["product"]["unit_price"]) * quantity;
+                if !(quantity >= 1.0 && (number(&v["total_price"]) - expected).abs() < 0.01) {
+                    return Err(format!("sale total does not match its line: {}", v));
+                }
+            }
+            Some("stockout") => {
+                if number(&v["quantity"]) != 0.0 || number(&v["total_price"]) != 0.0 {
+                    return Err(format!("stockout sold something: {}", v));
+                }
+            }
+            Some("basket_abandoned") => {
+                if number(&v["items_generated"]) >= number(&v["total_items"]) {
+                    return Err(format!("abandoned basket was complete: {}", v));
+                }
+            }
+            Some("coupon_redeemed") => {
+                if !v["discount_amount"].as_f64().is_some_and(|discount| discount > 0.0) {
+                    return Err(format!("coupon discount is not positive: {}", v));
+                }
+            }
+            Some(other) => return Err(format!("unknown event_type '{}'", other)),
+        }
+        let transaction = v["transaction_id"].as_str().ok_or("transaction_id is not a string")?;
+        let seq = v["seq"].as_u64().ok_or("seq is not a number")?;
+        // Transaction ids are random and can repeat, so seq 1 always starts afresh.
+        if seq != 1 && last_seq.get(transaction) != Some(&(seq - 1)) {
+            return Err(format!("{} seq {} is out of order", transaction, seq));
+        }
+        last_seq.insert(transaction.to_string(), seq);
+        Ok(())
+    })
+}
+
+/// Batteries never recharge, and each device's `seq` counts up by one. The physical
+/// bounds of the readings are in the schema.
+fn iot_invariants() -> Invariants {
+    let mut last: HashMap<String, (u64, f64)> = HashMap::new();
+    Box::new(move |v| {
+        let device = v["device_id"].as_str().ok_or("device_id is not a string")?;
+        let seq = v["seq"].as_u64().ok_or("seq is not a number")?;
+        let battery = v["battery"].as_f64().ok_or("battery is not a number")?;
+        if let Some(&(last_seq, last_battery)) = last.get(device) {
+            if seq != last_seq + 1 {
+                return Err(format!("{} seq {} follows {}", device, seq, last_seq));
+            }
+            if battery > last_battery {
+                return Err(format!("{} battery rose from {} to {}", device, last_battery, battery));
+            }
+        } else if seq != 1 {
+            return Err(format!("{} starts at seq {}", device, seq));
+        }
+        last.insert(device.to_string(), (seq, battery));
+        Ok(())
+    })
+}
+
+/// Not-modified responses have no body, and each client keeps one user agent.
+fn web_logs_invariants() -> Invariants {
+    let mut agents: HashMap<String, String> = HashMap::new();
+    Box::new(move |v| {
+        if v["status"] == 304 && v["bytes"] != 0 {
+            return Err(format!("304 response has a body: {}", v));
+        }
+        let ip = v["ip"].as_str().ok_or("ip is not a string")?;
+        let agent = v["user_agent"].as_str().ok_or("user_agent is not a string")?;
+        let expected = agents.entry(ip.to_string()).or_insert_with(|| agent.to_string());
+        if expected != agent {
+            return Err(format!("{} switched user agent to '{}'", ip, agent));
+        }
+        Ok(())
+    })
+}
+
+/// Sessions open on a landing page, and each session's `seq` counts up by one.
+fn clickstream_invariants() -> Invariants {
+    let mut last_seq: HashMap<String, u64> = HashMap::new();
+    Box::new(move |v| {
+        let session = v["session_id"].as_str().ok_or("session_id is not a string")?;
+        let seq = v["seq"].as_u64().ok_or("seq is not a number")?;
+        match last_seq.get(session) {
+            None if seq != 1 || v["event_type"] != "landing" => {
+                return Err(format!("session {} opens with: {}", session, v));
+            }
+            Some(last) if seq != last + 1 => {
+                return Err(format!("{} seq {} follows {}", session, seq, last));
+            }
+            _ => {}
+        }
+        last_seq.insert(session.to_string(), seq);
+        Ok(())
+    })
+}
+
+/// Fraud types label fraudulent transactions only, and a card spends in one home country
+/// except when travelling impossibly.
+fn payments_invariants() -> Invariants {
+    let mut homes: HashMap<String, String> = HashMap::new();
+    Box::new(move |v| {
+        let fraud = v["_is_fraud"].as_bool().ok_or("_is_fraud is not a boolean")?;
+        if fraud != v.get("_fraud_type").is_some() {
+            return Err(format!("fraud label and type disagree: {}", v));
+        }
+        let card = v["card_hash"].as_str().ok_or("card_hash is not a string")?;
+        let country = v["country"].as_str().ok_or("country is not a string")?;
+        if v["_fraud_type"] != "impossible_travel" {
+            let home = homes.entry(card.to_string()).or_insert_with(|| country.to_string());
+            if home != country {
+                return Err(format!("card {} spent in {} away from {} without being flagged", card, country, home));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Check a value against the subset of JSON Schema that `Generator::schema` uses:
+/// `type`, `enum`, `minimum`, `maximum`, `properties` (with no fields beyond them) and `required`.
+fn conforms(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let at = if path.is_empty() { "message" } else { path };
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        let matches = |t: &&str| match *t {
+            "object" => value.is_object(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => false,
+        };
+        if !types.iter().any(matches) {
+            return Err(format!("{} is not of type {}: {}", at, types.join(" or "), value));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{} is not one of the allowed values: {}", at, value));
+        }
+    }
+    if let (Some(minimum), Some(n)) = (schema.get("minimum").and_then(Value::as_f64), value.as_f64()) {
+        if n < minimum {
+            return Err(format!("{} is below its minimum of {}: {}", at, minimum, value));
+        }
+    }
+    if let (Some(maximum), Some(n)) = (schema.get("maximum").and_then(Value::as_f64), value.as_f64()) {
+        if n > maximum {
+            return Err(format!("{} is above its maximum of {}: {}", at, maximum, value));
+        }
+    }
+    if let Value::Object(map) = value {
+        for field in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            let field = field.as_str().unwrap_or_default();
+            if !map.contains_key(field) {
+                return Err(format!("{} is missing required field '{}'", at, field));
+            }
+        }
+        if let Some(properties) = schema.get("properties") {
+            for (key, child) in map {
+                let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                let Some(child_schema) = properties.get(key) else {
+                    return Err(format!("field '{}' is not in the schema", child_path));
+                };
+                conforms(child, child_schema, &child_path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check one message against the generator's schema and the module's invariants.
+fn validate(generator: &dyn Generator, message: &str, invariants: &mut Invariants) -> Result<(), String> {
+    let value: Value = serde_json::from_str(message).map_err(|e| format!("invalid JSON: {}", e))?;
+    conforms(&value, &generator.schema(), "")?;
+    if generator.key().is_none() {
+        return Err("message has no partition key".to_string());
+    }
+    invariants(&value)
+}
+
+/// Run one module for `count` messages, timing generation and validating every message.
+fn test_module(
+    module: &'static str,
+    mut generator: Box<dyn Generator>,
+    mut invariants: Invariants,
+    count: u64,
+) -> ModuleReport {
+    let mut report = ModuleReport {
+        module,
+        messages: 0,
+        elapsed: Duration::ZERO,
+        failures: Vec::new(),
+        unlisted_failures: 0,
+    };
+    while report.messages < count && !generator.is_exhausted() {
+        let start = Instant::now();
+        let message = generator.try_generate();
+        report.elapsed += start.elapsed();
+        let Some(message) = message else {
+            continue;
+        };
+        report.messages += 1;
+        if let Err(e) = validate(generator.as_ref(), &message, &mut invariants) {
+            if report.failures.len() < MAX_REPORTED_FAILURES {
+                report.failures.push(format!("message {}: {}", report.messages, e));
+            } else {
+                report.unlisted_failures += 1;
+            }
+        }
+    }
+    if report.messages < count {
+        report.failures.push(format!("exhausted after {} of {} messages", report.messages, count));
+    }
+    report
+}
+
+/// Self-test every module, printing a report; returns whether all of them passed.
+pub fn run(args: &SelftestArgs) -> bool {
+    let mut passed = true;
+    for (module, generator, invariants) in modules(args.seed) {
+        let report = test_module(module, generator, invariants, args.count);
+        println!("{}", report.summary());
+        for failure in &report.failures {
+            println!("    {}", failure);
+        }
+        if report.unlisted_failures > 0 {
+            println!("    ... and {} more failures", report.unlisted_failures);
+        }
+        passed &= report.passed();
+    }
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_module_passes() {
+        for (module, generator, invariants) in modules(7) {
+            let report = test_module(module, generator, invariants, 2_000);
+            assert!(report.passed(), "{}: {:?}", module, report.failures);
+            assert_eq!(report.messages, 2_000);
+        }
+    }
+
+    #[test]
+    fn test_schema_and_invariant_violations_fail() {
+        let mut generator: Box<dyn Generator> =
+            Box::new(StockDataGenerator::new(1, StockConfig::default()));
+        let mut invariants = stock_invariants();
+        let tick = generator.generate();
+        assert!(validate(generator.as_ref(), &tick, &mut invariants).is_ok());
+        let mut invariants = stock_invariants();
+        let bad_quote = r#"{"instrument":"STK0","price":10.0,"bid":11.0,"ask":12.0,"seq":1}"#;
+        assert!(validate(generator.as_ref(), bad_quote, &mut invariants).is_err());
+        let unknown = tick.replacen('{', r#"{"colour":"red","#, 1);
+        let err = validate(generator.as_ref(), &unknown, &mut invariants).unwrap_err();
+        assert!(err.contains("colour"), "{}", err);
+        let mut invariants = stock_invariants();
+        let wrong_type = tick.replace("\"seq\":", "\"seq\":\"x\",\"was\":");
+        let err = validate(generator.as_ref(), &wrong_type, &mut invariants).unwrap_err();
+        assert!(err.contains("seq is not of type integer"), "{}", err);
+        let missing = r#"{"instrument":"STK0","price":10.0,"bid":9.0,"ask":11.0,"seq":1}"#;
+        let err = validate(generator.as_ref(), missing, &mut invariants).unwrap_err();
+        assert!(err.contains("missing required field"), "{}", err);
+    }
+
+    /// Dotted paths of every property a schema describes.
+    fn schema_paths(schema: &Value, prefix: &str, out: &mut Vec<String>) {
+        for (key, child) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            schema_paths(child, &path, out);
+            out.push(path);
+        }
+    }
+
+    #[test]
+    fn test_schemas_describe_every_field() {
+        for (module, generator, _) in modules(7) {
+            let mut described = Vec::new();
+            schema_paths(&generator.schema(), "", &mut described);
+            let mut fields: Vec<String> = generator.fields().iter().map(|f| f.to_string()).collect();
+            described.sort();
+            fields.sort();
+            assert_eq!(described, fields, "{}", module);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use crate::color;
+use crate::dead_letter::DeadLetterFile;
+use crate::format::PayloadEncoder;
+use crate::stats::RunStats;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Why a sink could not take a message.
+#[derive(Debug)]
+pub struct SinkError(String);
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<io::Error> for SinkError {
+    fn from(e: io::Error) -> Self {
+        SinkError(e.to_string())
+    }
+}
+
+/// An output destination for generated messages.
+pub trait Sink {
+    /// Name the sink's counters are registered under in `RunStats`, and that
+    /// dead-letter entries are attributed to.
+    fn name(&self) -> &'static str;
+    /// Send one data message. `key` is its partition key, empty if unkeyed.
+    fn send(&mut self, payload: &str, key: &str) -> Result<(), SinkError>;
+    /// Send a control record (heartbeat or end-of-stream marker). `control` names it,
+    /// for sinks that can flag it out of band; the default sends it like data.
+    fn send_control(&mut self, payload: &str, _control: &str) -> Result<(), SinkError> {
+        self.send(payload, "")
+    }
+    /// Whether the sink records its own sent/failed counts, for sinks that only learn
+    /// the outcome later (e.g. from delivery reports). Otherwise `Sinks` counts
+    /// every `send` result.
+    fn tracks_delivery(&self) -> bool {
+        false
+    }
+    /// Messages accepted but not yet delivered.
+    fn backlog(&self) -> u64 {
+        0
+    }
+    /// Called after each batch of sends, for sinks that buffer writes within a batch.
+    fn end_batch(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+    /// Deliver anything still pending and release the sink, at shutdown.
+    fn flush(&mut self);
+}
+
+/// Writes newline-delimited messages to stdout, optionally pretty-printed and colorized.
+/// Writes are buffered and flushed once per batch, so a batch costs one write syscall.
+pub struct StdoutSink {
+    colorize: bool,
+    pretty: bool,
+    encoder: Option<PayloadEncoder>,
+    header: Option<String>,
+    out: BufWriter<io::Stdout>,
+}
+
+impl StdoutSink {
+    pub fn new(colorize: bool, pretty: bool) -> Self {
+        StdoutSink {
+            colorize,
+            pretty,
+            encoder: None,
+            header: None,
+            out: BufWriter::new(io::stdout()),
+        }
+    }
+
+    /// Print messages in another format instead of as JSON, one per line; binary formats
+    /// are hex-encoded.
+    pub fn with_encoder(mut self, encoder: Option<PayloadEncoder>) -> Self {
+        self.header = encoder.as_ref().and_then(PayloadEncoder::header);
+        self.encoder = encoder;
+        self
+    }
+}
+
+/// Lowercase hex of `data`, for printing binary payloads.
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Re-indent a JSON message across several lines, keeping its key order. Anything that
+/// isn't JSON (e.g. a payload template's output) is passed through as-is.
+fn pretty_json(payload: &str) -> Cow<'_, str> {
+    if serde_json::from_str::<serde::de::IgnoredAny>(payload).is_err() {
+        return Cow::Borrowed(payload);
+    }
+    let mut out = String::with_capacity(payload.len() * 2);
+    let mut depth = 0;
+    let mut chars = payload.chars().peekable();
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.extend(std::iter::repeat_n(' ', depth * 2));
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(c);
+                // Keep empty objects and arrays on one line.
+                if matches!(chars.peek(), Some('}' | ']')) {
+                    out.extend(chars.next());
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+impl Sink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        if let Some(encoder) = &self.encoder {
+            let data = encoder.encode(payload).map_err(SinkError)?;
+            if let Some(header) = self.header.take() {
+                writeln!(self.out, "{}", header)?;
+            }
+            if encoder.is_binary() {
+                writeln!(self.out, "{}", hex(&data))?;
+            } else {
+                self.out.write_all(&data)?;
+                self.out.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
+        let payload = if self.pretty { pretty_json(payload) } else { Cow::Borrowed(payload) };
+        if self.colorize {
+            writeln!(self.out, "{}", color::colorize_json(&payload))?;
+        } else {
+            writeln!(self.out, "{}", payload)?;
+        }
+        Ok(())
+    }
+
+    /// Formats whose messages can't hold a control record (CSV, line protocol) leave it out.
+    fn send_control(&mut self, payload: &str, _control: &str) -> Result<(), SinkError> {
+        if self.encoder.as_ref().is_some_and(|e| !e.encodes_control()) {
+            return Ok(());
+        }
+        self.send(payload, "")
+    }
+
+    fn end_batch(&mut self) -> Result<(), SinkError> {
+        Ok(self.out.flush()?)
+    }
+
+    fn flush(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+/// Writes messages to a file, newline-delimited JSON unless binary-encoded, buffered and
+/// flushed at shutdown.
+pub struct FileSink {
+    writer: BufWriter<File>,
+    encoder: Option<PayloadEncoder>,
+    header: Option<String>,
+}
+
+impl FileSink {
+    /// Create (or truncate) the file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(FileSink {
+            writer: BufWriter::new(File::create(path)?),
+            encoder: None,
+            header: None,
+        })
+    }
+
+    /// Write messages in another format instead of as JSON. Text formats keep one message
+    /// per line; binary ones are written back to back, so they must delimit their own values.
+    pub fn with_encoder(mut self, encoder: Option<PayloadEncoder>) -> Self {
+        self.header = encoder.as_ref().and_then(PayloadEncoder::header);
+        self.encoder = encoder;
+        self
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        let Some(encoder) = &self.encoder else {
+            writeln!(self.writer, "{}", payload)?;
+            return Ok(());
+        };
+        let data = encoder.encode(payload).map_err(SinkError)?;
+        if let Some(header) = self.header.take() {
+            writeln!(self.writer, "{}", header)?;
+        }
+        self.writer.write_all(&data)?;
+        if !encoder.is_binary() {
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Formats whose messages can't hold a control record (CSV, line protocol) leave it out.
+    fn send_control(&mut self, payload: &str, _control: &str) -> Result<(), SinkError> {
+        if self.encoder.as_ref().is_some_and(|e| !e.encodes_control()) {
+            return Ok(());
+        }
+        self.send(payload, "")
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            eprintln!("Failed to flush output file: {}", e);
+        }
+    }
+}
+
+/// Fans each message out to every configured sink, counting sends and dead-lettering
+/// failures. Sinks receive messages in the order they were added.
+pub struct Sinks {
+    sinks: Vec<Box<dyn Sink>>,
+    stats: Arc<RunStats>,
+    dead_letter: Option<Arc<DeadLetterFile>>,
+}
+
+impl Sinks {
+    pub fn new(stats: Arc<RunStats>, dead_letter: Option<Arc<DeadLetterFile>>) -> Self {
+        Sinks {
+            sinks: Vec::new(),
+            stats,
+            dead_letter,
+        }
+    }
+
+    pub fn add(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Send a data message to every sink, counting it as one generated message.
+    pub fn send(&mut self, payload: &str, key: &str) {
+        self.stats.record_message();
+        for i in 0..self.sinks.len() {
+            let result = self.sinks[i].send(payload, key);
+            self.account(i, payload, result);
+            self.end_batch(i);
+        }
+    }
+
+    /// Send a batch of `(payload, key)` data messages to every sink in turn, counting
+    /// each as a generated message. Sinks that buffer writes flush once per batch.
+    pub fn send_batch(&mut self, batch: &[(String, String)]) {
+        for _ in batch {
+            self.stats.record_message();
+        }
+        for i in 0..self.sinks.len() {
+            for (payload, key) in batch {
+                let result = self.sinks[i].send(payload, key);
+                self.account(i, payload, result);
+            }
+            self.end_batch(i);
+        }
+    }
+
+    /// Send a control record to every sink; it is not counted as a data message.
+    pub fn send_control(&mut self, payload: &str, control: &str) {
+        for i in 0..self.sinks.len() {
+            let result = self.sinks[i].send_control(payload, control);
+            self.account(i, payload, result);
+            self.end_batch(i);
+        }
+    }
+
+    /// Messages accepted by any sink but not yet delivered.
+    pub fn backlog(&self) -> u64 {
+        self.sinks.iter().map(|sink| sink.backlog()).sum()
+    }
+
+    /// Flush every sink, at shutdown.
+    pub fn flush(&mut self) {
+        for sink in &mut self.sinks {
+            sink.flush();
+        }
+    }
+
+    /// A failed end-of-batch flush is logged and counted, but its messages were already
+    /// handed over and can't be dead-lettered individually.
+    fn end_batch(&mut self, i: usize) {
+        if let Err(e) = self.sinks[i].end_batch() {
+            let sink = &self.sinks[i];
+            self.stats.sink(sink.name()).record_failed();
+            eprintln!("Failed to write messages to {}: {}", sink.name(), e);
+        }
+    }
+
+    fn account(&self, i: usize, payload: &str, result: Result<(), SinkError>) {
+        let sink = &self.sinks[i];
+        if sink.tracks_delivery() {
+            return;
+        }
+        let counters = self.stats.sink(sink.name());
+        match result {
+            Ok(()) => counters.record_sent(),
+            Err(e) => {
+                counters.record_failed();
+                eprintln!("Failed to write message to {}: {}", sink.name(), e);
+                if let Some(dead_letter) = &self.dead_letter {
+                    dead_letter.record(sink.name(), &e.to_string(), payload);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::CsvEncoder;
+    use std::sync::Mutex;
+
+    /// Records what it is sent; fails every send while `failing` is set.
+    struct MockSink {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+        failing: bool,
+        tracks_delivery: bool,
+    }
+
+    impl Sink for MockSink {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn send(&mut self, payload: &str, key: &str) -> Result<(), SinkError> {
+            if self.failing {
+                return Err(SinkError("mock failure".to_string()));
+            }
+            self.log.lock().unwrap().push(format!("{}:{}", key, payload));
+            Ok(())
+        }
+
+        fn tracks_delivery(&self) -> bool {
+            self.tracks_delivery
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    fn mock(name: &'static str, failing: bool, tracks_delivery: bool) -> (Box<dyn Sink>, Arc<Mutex<Vec<String>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let sink = MockSink {
+            name,
+            log: log.clone(),
+            failing,
+            tracks_delivery,
+        };
+        (Box::new(sink), log)
+    }
+
+    #[test]
+    fn test_fans_out_and_counts_per_sink() {
+        let stats = Arc::new(RunStats::new(&["good", "bad", "async"]));
+        let dir = std::env::temp_dir().join(format!("fluxfakr-sinks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dead_letter_path = dir.join("dead.jsonl");
+        let dead_letter = Arc::new(DeadLetterFile::open(&dead_letter_path).unwrap());
+        let mut sinks = Sinks::new(stats.clone(), Some(dead_letter.clone()));
+        let (good, good_log) = mock("good", false, false);
+        let (bad, _) = mock("bad", true, false);
+        let (async_sink, async_log) = mock("async", true, true);
+        sinks.add(good);
+        sinks.add(bad);
+        sinks.add(async_sink);
+
+        sinks.send("{\"a\":1}", "k1");
+        sinks.send("{\"a\":2}", "k2");
+        sinks.send_control("EOS", "fluxfakr-eos");
+        dead_letter.flush();
+
+        assert_eq!(*good_log.lock().unwrap(), vec!["k1:{\"a\":1}", "k2:{\"a\":2}", ":EOS"]);
+        assert!(async_log.lock().unwrap().is_empty());
+        assert_eq!(stats.messages(), 2, "control records are not data messages");
+        assert_eq!(stats.sink("good").sent(), 3);
+        assert_eq!(stats.sink("bad").failed(), 3);
+        // A sink that tracks its own deliveries is left to count them itself.
+        assert_eq!(stats.sink("async").sent() + stats.sink("async").failed(), 0);
+        let dead = std::fs::read_to_string(&dead_letter_path).unwrap();
+        assert_eq!(dead.lines().count(), 3);
+        assert!(dead.lines().all(|line| line.contains("\"sink\":\"bad\"")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pretty_json_reindents_json_only() {
+        assert_eq!(pretty_json(r#"{"a":1,"b":{"c":"x"}}"#), "{\n  \"a\": 1,\n  \"b\": {\n    \"c\": \"x\"\n  }\n}");
+        assert_eq!(pretty_json(r#"{"z":[],"a":"x, \"y\": {"}"#), "{\n  \"z\": [],\n  \"a\": \"x, \\\"y\\\": {\"\n}");
+        assert_eq!(pretty_json("price=10.5"), "price=10.5");
+    }
+
+    #[test]
+    fn test_file_sink_writes_each_format_on_flush() {
+        let dir = std::env::temp_dir().join(format!("fluxfakr-file-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.jsonl");
+        let mut sink = FileSink::create(&path).unwrap();
+        sink.send("{\"a\":1}", "k1").unwrap();
+        sink.send_control("{\"_eos\":true}", "fluxfakr-eos").unwrap();
+        sink.flush();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"a\":1}\n{\"_eos\":true}\n");
+        assert!(FileSink::create(&dir.join("missing/out.jsonl")).is_err());
+
+        let path = dir.join("out.msgpack");
+        let mut sink = FileSink::create(&path).unwrap().with_encoder(Some(PayloadEncoder::Msgpack));
+        sink.send("{\"a\":1}", "k1").unwrap();
+        sink.send("{\"b\":true}", "k2").unwrap();
+        assert!(sink.send("not json", "k3").is_err());
+        sink.flush();
+        assert_eq!(std::fs::read(&path).unwrap(), [0x81, 0xa1, b'a', 0x01, 0x81, 0xa1, b'b', 0xc3]);
+        assert_eq!(hex(&[0x81, 0x0a]), "810a");
+
+        let path = dir.join("out.csv");
+        let csv = CsvEncoder::new(vec!["a".to_string(), "b".to_string()]);
+        let mut sink = FileSink::create(&path).unwrap().with_encoder(Some(PayloadEncoder::Csv(csv)));
+        sink.send("{\"a\":1}", "k1").unwrap();
+        sink.send_control("{\"_eos\":true}", "fluxfakr-eos").unwrap();
+        sink.send("{\"a\":2,\"b\":\"x\"}", "k2").unwrap();
+        sink.flush();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,b\n1,\n2,x\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
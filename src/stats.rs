@@ -0,0 +1,177 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Sent/failed counters for one output sink, plus its availability for sinks
+/// guarded by a circuit breaker.
+#[derive(Default)]
+pub struct SinkCounters {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    trips: AtomicU64,
+    down: AtomicBool,
+    /// Whether the most recent send failed.
+    failing: AtomicBool,
+}
+
+impl SinkCounters {
+    pub fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.failing.store(false, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.failing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Count one outage (circuit breaker opening).
+    pub fn record_trip(&self) {
+        self.trips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_available(&self, available: bool) {
+        self.down.store(!available, Ordering::Relaxed);
+    }
+
+    /// A sink is healthy unless it is down or its most recent send failed.
+    pub fn is_healthy(&self) -> bool {
+        !self.down.load(Ordering::Relaxed) && !self.failing.load(Ordering::Relaxed)
+    }
+
+    /// Availability note for the summary, empty for a sink that never had an outage.
+    fn outage_note(&self) -> String {
+        match self.trips.load(Ordering::Relaxed) {
+            0 => String::new(),
+            trips => format!(
+                ", {} outage(s), currently {}",
+                trips,
+                if self.down.load(Ordering::Relaxed) { "down" } else { "up" }
+            ),
+        }
+    }
+}
+
+/// Run-wide counters, shared between the main loop and sink callbacks.
+pub struct RunStats {
+    started: Instant,
+    messages: AtomicU64,
+    sinks: Vec<(&'static str, SinkCounters)>,
+}
+
+impl RunStats {
+    /// Create counters for the named sinks, starting the run clock now.
+    pub fn new(sink_names: &[&'static str]) -> Self {
+        RunStats {
+            started: Instant::now(),
+            messages: AtomicU64::new(0),
+            sinks: sink_names
+                .iter()
+                .map(|name| (*name, SinkCounters::default()))
+                .collect(),
+        }
+    }
+
+    /// Count one generated data message.
+    pub fn record_message(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Counters for the named sink. Panics if the sink was not registered.
+    pub fn sink(&self, name: &str) -> &SinkCounters {
+        self.sinks
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, counters)| counters)
+            .unwrap_or_else(|| panic!("sink '{}' is not registered in stats", name))
+    }
+
+    /// Whether every sink is failing, i.e. nothing is getting through.
+    pub fn all_sinks_failing(&self) -> bool {
+        !self.sinks.iter().any(|(_, counters)| counters.is_healthy())
+    }
+
+    /// Operator-facing summary of the run so far.
+    pub fn summary(&self, configured_mps: f64) -> String {
+        self.summary_for(self.started.elapsed(), configured_mps)
+    }
+
+    fn summary_for(&self, elapsed: Duration, configured_mps: f64) -> String {
+        let secs = elapsed.as_secs_f64();
+        let achieved = if secs > 0.0 { self.messages() as f64 / secs } else { 0.0 };
+        let mut out = String::from("--- Run Summary ---\n");
+        let _ = writeln!(out, "messages: {}", self.messages());
+        let _ = writeln!(out, "elapsed: {:.2}s", secs);
+        let _ = writeln!(
+            out,
+            "rate: {:.2} msg/s achieved ({:.2} msg/s configured)",
+            achieved, configured_mps
+        );
+        for (name, counters) in &self.sinks {
+            let _ = writeln!(
+                out,
+                "{}: {} sent, {} failed{}",
+                name,
+                counters.sent(),
+                counters.failed(),
+                counters.outage_note()
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_reports_counts_and_rate() {
+        let stats = RunStats::new(&["stdout", "kafka"]);
+        for _ in 0..20 {
+            stats.record_message();
+            stats.sink("stdout").record_sent();
+        }
+        stats.sink("kafka").record_sent();
+        stats.sink("kafka").record_failed();
+
+        let summary = stats.summary_for(Duration::from_secs(4), 10.0);
+        assert!(summary.contains("messages: 20"));
+        assert!(summary.contains("elapsed: 4.00s"));
+        assert!(summary.contains("rate: 5.00 msg/s achieved (10.00 msg/s configured)"));
+        assert!(summary.contains("stdout: 20 sent, 0 failed"));
+        assert!(summary.contains("kafka: 1 sent, 1 failed\n"));
+    }
+
+    #[test]
+    fn test_all_sinks_failing() {
+        let stats = RunStats::new(&["stdout", "kafka"]);
+        assert!(!stats.all_sinks_failing());
+        stats.sink("kafka").set_available(false);
+        stats.sink("stdout").record_failed();
+        assert!(stats.all_sinks_failing());
+        stats.sink("stdout").record_sent();
+        assert!(!stats.all_sinks_failing());
+    }
+
+    #[test]
+    fn test_summary_reports_outages() {
+        let stats = RunStats::new(&["kafka"]);
+        stats.sink("kafka").record_trip();
+        stats.sink("kafka").set_available(false);
+        let summary = stats.summary_for(Duration::from_secs(1), 1.0);
+        assert!(summary.contains("kafka: 0 sent, 0 failed, 1 outage(s), currently down"));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::transform;
+use serde_json::Value;
+
+/// A piece of a parsed payload template.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// `{{payload}}`: the whole message.
+    Payload,
+    /// `{{some.field}}`: one field of the message, by dotted path.
+    Field(String),
+}
+
+/// A user-supplied envelope around each message, in Handlebars-style syntax:
+/// `{{payload}}` is replaced by the message and `{{field.path}}` by one of its fields.
+/// Strings are substituted JSON-escaped but unquoted, so they can sit inside a quoted
+/// string in the template; other values are substituted as JSON. Missing fields
+/// render as `null`.
+#[derive(Debug, Clone)]
+pub struct PayloadTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PayloadTemplate {
+    /// Parse a template, rejecting unterminated or empty placeholders.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            let after = &rest[open + 2..];
+            let close = after
+                .find("}}")
+                .ok_or_else(|| format!("unterminated '{{{{' at byte {}", template.len() - rest.len() + open))?;
+            let name = after[..close].trim();
+            segments.push(match name {
+                "" => return Err("empty placeholder '{{}}'".to_string()),
+                "payload" => Segment::Payload,
+                field => Segment::Field(field.to_string()),
+            });
+            rest = &after[close + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(PayloadTemplate { segments })
+    }
+
+    /// Dotted paths of the fields the template substitutes.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Field(field) => Some(field.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Render the template around one message.
+    pub fn render(&self, message: &str) -> String {
+        let parsed = if self.fields().next().is_some() {
+            serde_json::from_str::<Value>(message).ok()
+        } else {
+            None
+        };
+        let mut out = String::with_capacity(message.len() * 2);
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Payload => out.push_str(message),
+                Segment::Field(field) => {
+                    let path: Vec<&str> = field.split('.').collect();
+                    match parsed.as_ref().and_then(|value| transform::lookup(value, &path)) {
+                        Some(Value::String(text)) => {
+                            let quoted = Value::String(text.clone()).to_string();
+                            out.push_str(&quoted[1..quoted.len() - 1]);
+                        }
+                        Some(value) => out.push_str(&value.to_string()),
+                        None => out.push_str("null"),
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::stock::{StockConfig, StockDataGenerator};
+    use crate::generator::Generator;
+
+    #[test]
+    fn test_renders_envelope_around_stock_tick() {
+        let template =
+            PayloadTemplate::parse(r#"{"key":"{{ instrument }}","price":{{price}},"body":{{payload}}}"#)
+                .unwrap();
+        assert_eq!(template.fields().collect::<Vec<_>>(), vec!["instrument", "price"]);
+        let mut generator = StockDataGenerator::new(2, StockConfig::default());
+        let tick = generator.generate();
+        let rendered: Value = serde_json::from_str(&template.render(&tick)).unwrap();
+        let tick: Value = serde_json::from_str(&tick).unwrap();
+        assert_eq!(rendered["key"], tick["instrument"]);
+        assert_eq!(rendered["price"], tick["price"]);
+        assert_eq!(rendered["body"], tick);
+    }
+
+    #[test]
+    fn test_strings_are_escaped_and_missing_fields_are_null() {
+        let template = PayloadTemplate::parse(r#"{"name":"{{name}}","other":{{missing}}}"#).unwrap();
+        let rendered: Value =
+            serde_json::from_str(&template.render(r#"{"name":"say \"hi\"\n"}"#)).unwrap();
+        assert_eq!(rendered["name"], "say \"hi\"\n");
+        assert_eq!(rendered["other"], Value::Null);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_placeholders() {
+        assert!(PayloadTemplate::parse("{{payload").is_err());
+        assert!(PayloadTemplate::parse("x {{ }} y").is_err());
+        assert!(PayloadTemplate::parse("no placeholders").is_ok());
+    }
+}
@@ -0,0 +1,703 @@
+use crate::rng::SimRng;
+use crate::template::PayloadTemplate;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::{Alphanumeric, Distribution, SampleString};
+use rand::Rng;
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+
+/// Post-generation transformations applied to every message before it reaches the sinks.
+///
+/// Transformations operate generically on the serialized JSON, so they work for
+/// every generator without touching its message structs. When no transformation
+/// is configured the message passes through untouched (and unparsed).
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    /// Dotted field paths to keep; `None` keeps every field.
+    pub fields: Option<Vec<String>>,
+    /// Filler appended to every message as a `_padding` field.
+    pub padding: Option<Padding>,
+    /// Schema fingerprint appended to every message as a `_schema_fingerprint` field.
+    pub fingerprint: Option<String>,
+    /// Re-emit every message with its object keys sorted, at every level.
+    pub sort_keys: bool,
+    /// Random nulling of optional fields, to simulate data-quality issues.
+    pub nulls: Option<NullInjection>,
+    /// Random trimming of messages to a detail level, to vary payload sizes.
+    pub size_mix: Option<SizeMix>,
+    /// Wrap every message in a CloudEvents envelope.
+    pub cloudevents: Option<CloudEvents>,
+    /// Wrap every message in a user-supplied envelope.
+    pub template: Option<PayloadTemplate>,
+}
+
+/// CloudEvents 1.0 structured-mode JSON envelope. `type` and `source` derive from the
+/// module name; `id` is a per-run sequence number, unique within the source.
+#[derive(Debug)]
+pub struct CloudEvents {
+    pub module: String,
+    pub next_id: u64,
+}
+
+/// Sets each of `fields` to `null` with probability `rate`, independently per message.
+#[derive(Debug)]
+pub struct NullInjection {
+    pub rate: f64,
+    /// Dotted field paths that may be nulled.
+    pub fields: Vec<String>,
+    pub rng: SimRng,
+}
+
+/// How much of a message survives the `--size-mix` projection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetailLevel {
+    /// Only the generator's core top-level fields (`Generator::core_fields`).
+    Small,
+    /// Every top-level scalar; nested objects and arrays are dropped.
+    Medium,
+    /// The full message.
+    Large,
+}
+
+impl DetailLevel {
+    fn name(self) -> &'static str {
+        match self {
+            DetailLevel::Small => "small",
+            DetailLevel::Medium => "medium",
+            DetailLevel::Large => "large",
+        }
+    }
+}
+
+impl FromStr for DetailLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(DetailLevel::Small),
+            "medium" => Ok(DetailLevel::Medium),
+            "large" => Ok(DetailLevel::Large),
+            _ => Err(format!(
+                "unknown detail level '{}' (expected small, medium or large)",
+                s
+            )),
+        }
+    }
+}
+
+/// Parse a detail-level mix such as `small=0.3,medium=0.5,large=0.2`.
+pub fn parse_size_mix(spec: &str) -> Result<Vec<(DetailLevel, f64)>, String> {
+    crate::weights::parse_weights(spec)?
+        .into_iter()
+        .map(|(name, weight)| Ok((name.parse()?, weight)))
+        .collect()
+}
+
+/// Cuts each message down to a detail level drawn from the configured mix and tags it
+/// with that level as `detail_level`.
+#[derive(Debug)]
+pub struct SizeMix {
+    levels: Vec<DetailLevel>,
+    index: WeightedIndex<f64>,
+    /// Top-level fields kept at `DetailLevel::Small`.
+    core_fields: Vec<String>,
+    rng: SimRng,
+}
+
+impl SizeMix {
+    pub fn new(mix: &[(DetailLevel, f64)], core_fields: &[&str], rng: SimRng) -> Self {
+        SizeMix {
+            levels: mix.iter().map(|(level, _)| *level).collect(),
+            index: WeightedIndex::new(mix.iter().map(|(_, weight)| *weight))
+                .expect("size mix weights must contain a positive weight"),
+            core_fields: core_fields.iter().map(|f| f.to_string()).collect(),
+            rng,
+        }
+    }
+
+    fn apply(&mut self, value: &mut Value) {
+        let level = self.levels[self.index.sample(&mut self.rng)];
+        let Some(object) = value.as_object_mut() else {
+            return;
+        };
+        let scalar = |v: &Value| !v.is_object() && !v.is_array();
+        match level {
+            DetailLevel::Small => {
+                object.retain(|k, v| scalar(v) && self.core_fields.contains(k));
+            }
+            DetailLevel::Medium => object.retain(|_, v| scalar(v)),
+            DetailLevel::Large => {}
+        }
+        object.insert("detail_level".to_string(), json!(level.name()));
+    }
+}
+
+/// Appends `bytes` random alphanumeric characters to each message, to simulate fat
+/// payloads. Random filler keeps compression ratios realistic.
+#[derive(Debug)]
+pub struct Padding {
+    pub bytes: usize,
+    pub rng: SimRng,
+}
+
+impl Pipeline {
+    /// Apply the configured transformations to one message.
+    pub fn apply(&mut self, mut message: String) -> String {
+        if self.fields.is_some() || self.nulls.is_some() || self.size_mix.is_some() {
+            if let Ok(mut value) = serde_json::from_str::<Value>(&message) {
+                if let Some(nulls) = &mut self.nulls {
+                    inject_nulls(&mut value, nulls);
+                }
+                if let Some(fields) = &self.fields {
+                    value = project(&value, fields);
+                }
+                if let Some(size_mix) = &mut self.size_mix {
+                    size_mix.apply(&mut value);
+                }
+                message = value.to_string();
+            }
+        }
+        if let Some(padding) = &mut self.padding {
+            message = pad(message, padding);
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            if open_trailing_field(&mut message, "_schema_fingerprint") {
+                message.push('"');
+                message.push_str(fingerprint);
+                message.push_str("\"}");
+            }
+        }
+        if self.sort_keys {
+            if let Ok(value) = serde_json::from_str::<Value>(&message) {
+                message = sorted(value).to_string();
+            }
+        }
+        if let Some(cloudevents) = &mut self.cloudevents {
+            message = cloudevents.wrap(message);
+        }
+        if let Some(template) = &self.template {
+            message = template.render(&message);
+        }
+        message
+    }
+}
+
+impl CloudEvents {
+    pub fn new(module: &str) -> Self {
+        CloudEvents {
+            module: module.to_string(),
+            next_id: 1,
+        }
+    }
+
+    /// Wrap a payload as the event's `data`. The event `time` is taken from the payload's
+    /// `timestamp` (so simulated clocks carry through), falling back to wall-clock time.
+    fn wrap(&mut self, message: String) -> String {
+        let Ok(data) = serde_json::from_str::<Value>(&message) else {
+            return message;
+        };
+        let time = data
+            .get("timestamp")
+            .and_then(Value::as_i64)
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .unwrap_or_else(Utc::now);
+        let event = json!({
+            "specversion": "1.0",
+            "type": format!("io.fluxfakr.{}", self.module),
+            "source": format!("/fluxfakr/{}", self.module),
+            "id": self.next_id.to_string(),
+            "time": time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "datacontenttype": "application/json",
+            "data": data,
+        });
+        self.next_id += 1;
+        event.to_string()
+    }
+}
+
+/// Rebuild a JSON value with every object's keys in sorted order. Done explicitly so
+/// output stays canonical even if serde_json's `preserve_order` feature gets enabled.
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sorted).collect()),
+        other => other,
+    }
+}
+
+/// Null out each configured field present in the message with the configured probability.
+fn inject_nulls(value: &mut Value, nulls: &mut NullInjection) {
+    for field in &nulls.fields {
+        let path: Vec<&str> = field.split('.').collect();
+        let target = path
+            .iter()
+            .try_fold(&mut *value, |current, key| current.get_mut(key));
+        if let Some(target) = target {
+            if nulls.rng.random_bool(nulls.rate) {
+                *target = Value::Null;
+            }
+        }
+    }
+}
+
+/// Reopen a serialized JSON object to append a field named `name`: drops the closing
+/// brace and writes the separator and key, leaving the caller to write the value and
+/// `}`. Returns false, leaving the message untouched, if it is not an object.
+fn open_trailing_field(message: &mut String, name: &str) -> bool {
+    if !message.ends_with('}') {
+        return false;
+    }
+    message.pop();
+    if !message.ends_with('{') {
+        message.push(',');
+    }
+    message.push('"');
+    message.push_str(name);
+    message.push_str("\":");
+    true
+}
+
+/// Splice a `_padding` field into the end of a serialized JSON object, leaving the
+/// rest of the message byte-for-byte intact. Non-object messages pass through.
+fn pad(mut message: String, padding: &mut Padding) -> String {
+    if open_trailing_field(&mut message, "_padding") {
+        message.push('"');
+        Alphanumeric.append_string(&mut padding.rng, &mut message, padding.bytes);
+        message.push_str("\"}");
+    }
+    message
+}
+
+/// Stable fingerprint of a JSON Schema document: the 64-bit FNV-1a hash, in hex, of its
+/// compact JSON. Object keys serialize sorted, so equal schemas always hash alike. Unlike
+/// `std`'s hasher it never changes between releases, so fingerprints can be compared
+/// across runs and builds.
+pub fn schema_fingerprint(schema: &Value) -> String {
+    let hash = schema.to_string().bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Narrow a message schema to the dotted paths `project` keeps. Enclosing objects keep
+/// their own keywords but only the selected properties, and `required` lists drop the
+/// properties that were left out.
+pub fn project_schema(schema: &Value, fields: &[String]) -> Value {
+    let mut out = schema.clone();
+    out["properties"] = json!({});
+    for field in fields {
+        let path: Vec<&str> = field.split('.').collect();
+        let (mut source, mut target) = (schema, &mut out);
+        for (depth, part) in path.iter().enumerate() {
+            let Some(child) = source["properties"].get(*part) else {
+                break;
+            };
+            if depth + 1 == path.len() {
+                target["properties"][*part] = child.clone();
+            } else if target["properties"].get(*part).is_none() {
+                let mut shell = child.clone();
+                shell["properties"] = json!({});
+                target["properties"][*part] = shell;
+            }
+            source = child;
+            target = &mut target["properties"][*part];
+        }
+    }
+    prune_required(&mut out);
+    out
+}
+
+/// Drop `required` entries naming properties a projection left out, recursively.
+fn prune_required(schema: &mut Value) {
+    let Some(Value::Object(properties)) = schema.get_mut("properties") else {
+        return;
+    };
+    let kept: Vec<String> = properties.keys().cloned().collect();
+    for property in properties.values_mut() {
+        prune_required(property);
+    }
+    if let Some(Value::Array(required)) = schema.get_mut("required") {
+        required.retain(|name| name.as_str().is_some_and(|name| kept.iter().any(|k| k == name)));
+        if required.is_empty() {
+            schema.as_object_mut().unwrap().remove("required");
+        }
+    }
+}
+
+/// Parse a comma-separated field allowlist such as `instrument,price,store.town`.
+pub fn parse_field_list(spec: &str) -> Vec<String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Return the first requested field that is not one of the known field paths.
+pub fn find_unknown_field<'a>(requested: &'a [String], known: &[&str]) -> Option<&'a str> {
+    requested
+        .iter()
+        .map(String::as_str)
+        .find(|field| !known.contains(field))
+}
+
+/// Keep only the given dotted paths of a JSON object. Selecting a nested path
+/// (`store.town`) keeps the enclosing objects; selecting an object (`store`)
+/// keeps it whole. Paths absent from this particular message are skipped.
+pub fn project(value: &Value, fields: &[String]) -> Value {
+    let mut out = Value::Object(Map::new());
+    for field in fields {
+        let path: Vec<&str> = field.split('.').collect();
+        if let Some(selected) = lookup(value, &path) {
+            insert(&mut out, &path, selected.clone());
+        }
+    }
+    out
+}
+
+/// Message key taken from a field of each generated message (`--key-field`).
+#[derive(Debug)]
+pub struct KeyField {
+    path: String,
+    warned: bool,
+}
+
+impl KeyField {
+    pub fn new(path: &str) -> Self {
+        KeyField {
+            path: path.to_string(),
+            warned: false,
+        }
+    }
+
+    /// The field's value as a key: strings as-is, numbers and booleans in their JSON
+    /// form. A message without a scalar value there gets an empty key, with a warning
+    /// the first time.
+    pub fn extract(&mut self, message: &str) -> String {
+        let path: Vec<&str> = self.path.split('.').collect();
+        let value = serde_json::from_str::<Value>(message).ok();
+        match value.as_ref().and_then(|value| lookup(value, &path)) {
+            Some(Value::String(text)) => text.clone(),
+            Some(value @ (Value::Number(_) | Value::Bool(_))) => value.to_string(),
+            _ => {
+                if !self.warned {
+                    eprintln!(
+                        "Warning: key field '{}' is missing from a message; sending it with an empty key (further misses are not reported)",
+                        self.path
+                    );
+                    self.warned = true;
+                }
+                String::new()
+            }
+        }
+    }
+}
+
+/// Look up a dotted path in a JSON value.
+pub fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |current, key| current.get(key))
+}
+
+/// Insert a value at a dotted path, creating intermediate objects as needed.
+fn insert(target: &mut Value, path: &[&str], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = target;
+    for key in parents {
+        let Value::Object(map) = current else {
+            return;
+        };
+        current = map
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if let Value::Object(map) = current {
+        map.insert(last.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_field_extracts_nested_scalars() {
+        let mut key = KeyField::new("store.town");
+        assert_eq!(key.extract(r#"{"store":{"town":"Springfield"}}"#), "Springfield");
+        assert_eq!(key.extract(r#"{"store":{}}"#), "");
+        assert!(key.warned);
+        assert_eq!(KeyField::new("seq").extract(r#"{"seq":7}"#), "7");
+        assert_eq!(KeyField::new("store").extract(r#"{"store":{"town":"X"}}"#), "");
+    }
+
+    #[test]
+    fn test_project_nested_fields() {
+        let sale = json!({
+            "transaction_id": "TXN-1",
+            "store": {"town": "Springfield", "state": "IL", "country": "USA"},
+            "customer": {"age": 30, "income_band": "Low"},
+            "total_price": 9.98,
+        });
+        let fields = parse_field_list("transaction_id, store.town,customer");
+        let projected = project(&sale, &fields);
+        assert_eq!(
+            projected,
+            json!({
+                "transaction_id": "TXN-1",
+                "store": {"town": "Springfield"},
+                "customer": {"age": 30, "income_band": "Low"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_project_skips_fields_missing_from_message() {
+        let event = json!({"event_type": "basket_abandoned", "basket_id": "B-1"});
+        let projected = project(&event, &parse_field_list("basket_id,store.town"));
+        assert_eq!(projected, json!({"basket_id": "B-1"}));
+    }
+
+    #[test]
+    fn test_project_schema_keeps_selected_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "transaction_id": {"type": "string"},
+                "total_price": {"type": "number"},
+                "store": {
+                    "type": "object",
+                    "properties": {"town": {"type": "string"}, "state": {"type": "string"}},
+                    "required": ["town", "state"],
+                },
+            },
+            "required": ["transaction_id", "total_price", "store"],
+        });
+        let projected = project_schema(&schema, &parse_field_list("transaction_id,store.town"));
+        assert_eq!(
+            projected,
+            json!({
+                "type": "object",
+                "properties": {
+                    "transaction_id": {"type": "string"},
+                    "store": {"type": "object", "properties": {"town": {"type": "string"}}, "required": ["town"]},
+                },
+                "required": ["transaction_id", "store"],
+            })
+        );
+        // Selecting the whole object keeps it whole.
+        let whole = project_schema(&schema, &parse_field_list("store,store.town"));
+        assert_eq!(whole["properties"]["store"], schema["properties"]["store"]);
+    }
+
+    #[test]
+    fn test_find_unknown_field() {
+        let requested = parse_field_list("price,store.postcode");
+        assert_eq!(find_unknown_field(&requested, &["price", "store.town"]), Some("store.postcode"));
+        assert_eq!(find_unknown_field(&requested[..1], &["price"]), None);
+    }
+
+    #[test]
+    fn test_padding_grows_message_by_requested_bytes() {
+        let raw = r#"{"instrument":"STK0","price":101.5}"#.to_string();
+        let mut pipeline = Pipeline {
+            padding: Some(Padding {
+                bytes: 1024,
+                rng: SimRng::new(crate::rng::RngAlgorithm::Chacha8, 7),
+            }),
+            ..Pipeline::default()
+        };
+        let padded = pipeline.apply(raw.clone());
+        let growth = padded.len() - raw.len();
+        assert!((1024..1024 + 16).contains(&growth), "grew by {}", growth);
+        assert!(padded.starts_with(&raw[..raw.len() - 1]), "original fields must be untouched");
+
+        let value: Value = serde_json::from_str(&padded).unwrap();
+        assert_eq!(value["_padding"].as_str().unwrap().len(), 1024);
+        assert_eq!(pipeline.apply("{}".to_string()).len(), "{\"_padding\":\"\"}".len() + 1024);
+    }
+
+    #[test]
+    fn test_size_mix_trims_sales_to_tagged_detail_levels() {
+        use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+        use crate::generator::Generator;
+        let mut generator = SalesGenerator::new(SalesConfig::default());
+        let mix = parse_size_mix("small=1,medium=1,large=1").unwrap();
+        let mut pipeline = Pipeline {
+            size_mix: Some(SizeMix::new(
+                &mix,
+                &generator.core_fields(),
+                SimRng::new(crate::rng::RngAlgorithm::Chacha8, 3),
+            )),
+            ..Pipeline::default()
+        };
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..100 {
+            let v: Value = serde_json::from_str(&pipeline.apply(generator.generate())).unwrap();
+            let level = v["detail_level"].as_str().unwrap().to_string();
+            let nested = v.as_object().unwrap().values().any(|f| f.is_object());
+            match level.as_str() {
+                "small" => {
+                    assert!(v.as_object().unwrap().keys().all(|k| {
+                        k == "detail_level" || generator.core_fields().contains(&k.as_str())
+                    }));
+                    assert!(v.get("transaction_id").is_some());
+                }
+                "medium" => assert!(!nested && v.get("quantity").is_some()),
+                _ => assert!(nested && v["product"].get("brand").is_some()),
+            }
+            seen.insert(level);
+        }
+        assert_eq!(seen.len(), 3);
+        assert!(parse_size_mix("tiny=1").is_err());
+    }
+
+    #[test]
+    fn test_schema_fingerprint_is_stable_and_tracks_schema() {
+        use crate::generator::stock::{StockConfig, StockDataGenerator};
+        use crate::generator::Generator;
+        let plain = StockDataGenerator::new(2, StockConfig::default());
+        let deltas = StockDataGenerator::new(
+            2,
+            StockConfig {
+                emit_deltas: true,
+                ..StockConfig::default()
+            },
+        );
+        let fingerprint = schema_fingerprint(&plain.schema());
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(fingerprint, schema_fingerprint(&StockDataGenerator::new(5, StockConfig::default()).schema()));
+        assert_ne!(fingerprint, schema_fingerprint(&deltas.schema()));
+        // A changed type or required set changes the fingerprint, with the same field paths.
+        let mut retyped = plain.schema();
+        retyped["properties"]["volume"] = json!({"type": "number"});
+        assert_ne!(fingerprint, schema_fingerprint(&retyped));
+        let mut optional = plain.schema();
+        optional.as_object_mut().unwrap().remove("required");
+        assert_ne!(fingerprint, schema_fingerprint(&optional));
+
+        let mut pipeline = Pipeline {
+            fingerprint: Some(fingerprint.clone()),
+            ..Pipeline::default()
+        };
+        let mut generator = plain;
+        for _ in 0..5 {
+            let v: Value = serde_json::from_str(&pipeline.apply(generator.generate())).unwrap();
+            assert_eq!(v["_schema_fingerprint"], fingerprint.as_str());
+        }
+    }
+
+    #[test]
+    fn test_sort_keys_is_canonical_across_runs() {
+        use crate::clock::Clock;
+        use crate::generator::supermarket_sales::{SalesConfig, SalesGenerator};
+        use crate::generator::Generator;
+        let run = || {
+            let mut generator = SalesGenerator::new(SalesConfig {
+                seed: 11,
+                clock: Clock::Stepped {
+                    now_ms: 0,
+                    step_ms: 1_000,
+                },
+                ..SalesConfig::default()
+            });
+            let mut pipeline = Pipeline {
+                padding: Some(Padding {
+                    bytes: 8,
+                    rng: SimRng::new(crate::rng::RngAlgorithm::Chacha8, 1),
+                }),
+                sort_keys: true,
+                ..Pipeline::default()
+            };
+            (0..20).map(|_| pipeline.apply(generator.generate())).collect::<Vec<_>>()
+        };
+        let first = run();
+        assert_eq!(first, run());
+
+        fn keys_sorted(value: &Value) -> bool {
+            match value {
+                Value::Object(map) => {
+                    let keys: Vec<&String> = map.keys().collect();
+                    keys.windows(2).all(|w| w[0] < w[1]) && map.values().all(keys_sorted)
+                }
+                _ => true,
+            }
+        }
+        for message in &first {
+            // Struct fields are serialized in declaration order, so sorting has real work to do.
+            assert!(message.starts_with("{\"_padding\":"), "{}", message);
+            assert!(keys_sorted(&serde_json::from_str(message).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_null_rate_is_respected() {
+        let mut pipeline = Pipeline {
+            nulls: Some(NullInjection {
+                rate: 0.25,
+                fields: parse_field_list("customer.age,store.state"),
+                rng: SimRng::new(crate::rng::RngAlgorithm::Chacha8, 3),
+            }),
+            ..Pipeline::default()
+        };
+        let sale = json!({
+            "store": {"town": "Springfield", "state": "IL"},
+            "customer": {"age": 30},
+        })
+        .to_string();
+        let total = 10_000;
+        let (mut ages, mut states) = (0, 0);
+        for _ in 0..total {
+            let v: Value = serde_json::from_str(&pipeline.apply(sale.clone())).unwrap();
+            ages += v["customer"]["age"].is_null() as usize;
+            states += v["store"]["state"].is_null() as usize;
+            assert_eq!(v["store"]["town"], "Springfield", "only nullable fields are touched");
+        }
+        for nulled in [ages, states] {
+            let rate = nulled as f64 / total as f64;
+            assert!((0.22..0.28).contains(&rate), "null rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn test_cloudevents_envelope_has_required_attributes() {
+        let mut pipeline = Pipeline {
+            cloudevents: Some(CloudEvents::new("stock")),
+            ..Pipeline::default()
+        };
+        let payload = json!({"instrument": "STK0", "price": 101.5, "timestamp": 1_700_000_000});
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let event: Value = serde_json::from_str(&pipeline.apply(payload.to_string())).unwrap();
+            assert_eq!(event["specversion"], "1.0");
+            assert_eq!(event["type"], "io.fluxfakr.stock");
+            assert_eq!(event["source"], "/fluxfakr/stock");
+            assert_eq!(event["time"], "2023-11-14T22:13:20Z");
+            assert!(DateTime::parse_from_rfc3339(event["time"].as_str().unwrap()).is_ok());
+            assert_eq!(event["data"], payload);
+            ids.push(event["id"].as_str().unwrap().to_string());
+        }
+        ids.dedup();
+        assert_eq!(ids.len(), 3, "event ids must be unique");
+    }
+
+    #[test]
+    fn test_pipeline_applies_projection() {
+        let mut pipeline = Pipeline {
+            fields: Some(parse_field_list("price")),
+            ..Pipeline::default()
+        };
+        let out = pipeline.apply(r#"{"instrument":"STK0","price":101.5}"#.to_string());
+        assert_eq!(out, r#"{"price":101.5}"#);
+
+        let mut identity = Pipeline::default();
+        let raw = r#"{"b":1,"a":2}"#.to_string();
+        assert_eq!(identity.apply(raw.clone()), raw);
+    }
+}
@@ -0,0 +1,215 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A step is sustained if it achieves at least this fraction of its target rate.
+const RATE_TOLERANCE: f64 = 0.95;
+/// Bisection steps run between the last sustained and first failed rate.
+const REFINE_STEPS: u32 = 3;
+/// Upper bound on the ramp, so an infinitely fast sink still terminates.
+const MAX_RATE: f64 = 10_000_000.0;
+
+/// Parameters of a `--find-max-rate` ramp.
+#[derive(Debug, Clone)]
+pub struct RampConfig {
+    /// Rate (msg/s) of the first step.
+    pub start_rate: f64,
+    /// How long each rate is held and measured.
+    pub step_duration: Duration,
+    /// Sink backlog (queued, undelivered messages) above which the sink counts as saturated.
+    pub max_backlog: u64,
+}
+
+/// The time a ramp runs on: the wall clock in a real run, simulated in tests so that
+/// steps measure exactly what the sink costs rather than how busy the machine is.
+pub trait Timer {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock, sleeping the thread.
+pub struct WallClock;
+
+impl Timer for WallClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Measurement of one ramp step.
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub target: f64,
+    pub achieved: f64,
+    pub backlog: u64,
+    pub sustained: bool,
+}
+
+/// Hold `target` msg/s for one step, pacing `emit` against a deadline schedule.
+/// `emit` sends one message and returns the sink backlog, or `None` to stop.
+fn run_step(
+    target: f64,
+    config: &RampConfig,
+    timer: &impl Timer,
+    emit: &mut impl FnMut() -> Option<u64>,
+) -> Option<StepResult> {
+    let start = timer.now();
+    let mut sent: u64 = 0;
+    let mut backlog = 0;
+    while timer.now() - start < config.step_duration {
+        backlog = emit()?;
+        sent += 1;
+        let due = start + Duration::from_secs_f64(sent as f64 / target);
+        if let Some(wait) = due.checked_duration_since(timer.now()) {
+            timer.sleep(wait);
+        }
+    }
+    let achieved = sent as f64 / (timer.now() - start).as_secs_f64();
+    Some(StepResult {
+        target,
+        achieved,
+        backlog,
+        sustained: achieved >= RATE_TOLERANCE * target && backlog <= config.max_backlog,
+    })
+}
+
+/// Find the highest rate the sinks sustain: double the rate from `start_rate` until a
+/// step falls short (achieved rate below 95% of target, or the backlog over its limit),
+/// then bisect between the last sustained and the first failed rate. `report` sees
+/// every step. Returns the highest sustained rate; 0.0 if even the first step failed,
+/// or whatever was sustained so far if `emit` stops early.
+pub fn find_max_rate(
+    config: &RampConfig,
+    timer: &impl Timer,
+    mut emit: impl FnMut() -> Option<u64>,
+    mut report: impl FnMut(&StepResult),
+) -> f64 {
+    let mut best = 0.0;
+    let mut target = config.start_rate;
+    let failed = loop {
+        let Some(step) = run_step(target, config, timer, &mut emit) else {
+            return best;
+        };
+        report(&step);
+        if !step.sustained {
+            break target;
+        }
+        best = target;
+        if target >= MAX_RATE {
+            return best;
+        }
+        target = (target * 2.0).min(MAX_RATE);
+    };
+    let (mut low, mut high) = (best, failed);
+    for _ in 0..REFINE_STEPS {
+        let mid = (low + high) / 2.0;
+        let Some(step) = run_step(mid, config, timer, &mut emit) else {
+            break;
+        };
+        report(&step);
+        if step.sustained {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Simulated time: it only moves when the ramp sleeps or the sink does work.
+    struct SimulatedClock {
+        now: Cell<Instant>,
+    }
+
+    impl SimulatedClock {
+        fn new() -> Self {
+            SimulatedClock { now: Cell::new(Instant::now()) }
+        }
+
+        fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    impl Timer for SimulatedClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+
+    #[test]
+    fn test_finds_capacity_of_a_slow_sink() {
+        let config = RampConfig {
+            start_rate: 50.0,
+            step_duration: Duration::from_millis(200),
+            max_backlog: 1000,
+        };
+        // A sink that takes 2ms per message tops out around 500 msg/s.
+        let clock = SimulatedClock::new();
+        let mut steps = Vec::new();
+        let max = find_max_rate(
+            &config,
+            &clock,
+            || {
+                clock.advance(Duration::from_millis(2));
+                Some(0)
+            },
+            |step| steps.push(step.clone()),
+        );
+        assert!((100.0..=500.0).contains(&max), "found {}", max);
+        assert!(steps.iter().any(|s| !s.sustained));
+    }
+
+    #[test]
+    fn test_backlog_over_limit_counts_as_saturation() {
+        let config = RampConfig {
+            start_rate: 100.0,
+            step_duration: Duration::from_millis(50),
+            max_backlog: 2,
+        };
+        let mut queued = 0;
+        let max = find_max_rate(
+            &config,
+            &SimulatedClock::new(),
+            || {
+                queued += 1;
+                Some(queued)
+            },
+            |_| {},
+        );
+        assert_eq!(max, 0.0, "the very first step overflows the backlog");
+    }
+
+    #[test]
+    fn test_stopping_early_returns_best_so_far() {
+        let config = RampConfig {
+            start_rate: 1000.0,
+            step_duration: Duration::from_millis(50),
+            max_backlog: 1000,
+        };
+        // Enough for the first step (50 messages) but not the second.
+        let mut budget = 80;
+        let max = find_max_rate(
+            &config,
+            &SimulatedClock::new(),
+            || {
+                budget -= 1;
+                (budget > 0).then_some(0)
+            },
+            |_| {},
+        );
+        assert_eq!(max, 1000.0);
+    }
+}
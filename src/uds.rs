@@ -0,0 +1,135 @@
+//! Unix domain socket output. Only available on Unix platforms.
+
+use crate::sink::{Sink, SinkError};
+use std::io::{self, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Minimum delay between reconnection attempts, so a dead collector isn't hammered.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes newline-delimited messages to a Unix domain socket, reconnecting
+/// after the peer goes away.
+pub struct UdsSink {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    next_reconnect: Instant,
+    line: Vec<u8>,
+}
+
+impl UdsSink {
+    /// Connect to the socket. Failing here lets `main` report a bad path before streaming.
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let stream = UnixStream::connect(&path)?;
+        Ok(UdsSink {
+            path,
+            stream: Some(stream),
+            next_reconnect: Instant::now(),
+            line: Vec::new(),
+        })
+    }
+
+    /// Write one message followed by a newline. On failure the connection is
+    /// dropped and re-established on a later call.
+    pub fn write_line(&mut self, payload: &str) -> io::Result<()> {
+        if self.stream.is_none() {
+            self.reconnect()?;
+        }
+        self.line.clear();
+        self.line.extend_from_slice(payload.as_bytes());
+        self.line.push(b'\n');
+        let stream = self.stream.as_mut().unwrap();
+        if let Err(e) = stream.write_all(&self.line) {
+            self.stream = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        if now < self.next_reconnect {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "waiting to reconnect"));
+        }
+        self.next_reconnect = now + RECONNECT_INTERVAL;
+        self.stream = Some(UnixStream::connect(&self.path)?);
+        eprintln!("Reconnected to Unix socket {}", self.path.display());
+        Ok(())
+    }
+
+    /// Flush and shut the connection down cleanly.
+    pub fn close(&mut self) {
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.flush();
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Sink for UdsSink {
+    fn name(&self) -> &'static str {
+        "uds"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        Ok(self.write_line(payload)?)
+    }
+
+    fn flush(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("fluxfakr-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_writes_newline_delimited_messages() {
+        let path = socket_path("uds-write");
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut sink = UdsSink::connect(&path).unwrap();
+        let (conn, _) = listener.accept().unwrap();
+
+        sink.write_line(r#"{"a":1}"#).unwrap();
+        sink.write_line(r#"{"a":2}"#).unwrap();
+        sink.close();
+
+        let lines: Vec<String> = BufReader::new(conn).lines().map(Result::unwrap).collect();
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reconnects_after_peer_disconnects() {
+        let path = socket_path("uds-reconnect");
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut sink = UdsSink::connect(&path).unwrap();
+        drop(listener.accept().unwrap());
+
+        // Writes fail once the peer is gone; the sink then drops the connection.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.write_line("lost").is_ok() && Instant::now() < deadline {}
+        assert!(sink.stream.is_none());
+
+        // After the backoff, the next send reconnects and succeeds.
+        sink.next_reconnect = Instant::now();
+        sink.write_line("after reconnect").unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        sink.close();
+        let lines: Vec<String> = BufReader::new(conn).lines().map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["after reconnect"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
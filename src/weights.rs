@@ -0,0 +1,47 @@
+/// Parse a comma-separated list of `name=weight` pairs, e.g. `express=0.2,standard=0.8`.
+///
+/// Weights must be finite and non-negative, and at least one must be positive.
+/// They are relative, so they need not sum to 1.
+pub fn parse_weights(spec: &str) -> Result<Vec<(String, f64)>, String> {
+    let mut weights = Vec::new();
+    for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, weight) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=weight, got '{}'", pair))?;
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid weight '{}' for '{}'", weight.trim(), name.trim()))?;
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(format!("weight for '{}' must be a non-negative number", name.trim()));
+        }
+        weights.push((name.trim().to_string(), weight));
+    }
+    if !weights.iter().any(|(_, w)| *w > 0.0) {
+        return Err("at least one weight must be positive".to_string());
+    }
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_weights() {
+        let weights = parse_weights("express=0.2, standard=0.8").unwrap();
+        assert_eq!(
+            weights,
+            vec![("express".to_string(), 0.2), ("standard".to_string(), 0.8)]
+        );
+    }
+
+    #[test]
+    fn test_parse_weights_rejects_bad_input() {
+        assert!(parse_weights("express").is_err());
+        assert!(parse_weights("express=abc").is_err());
+        assert!(parse_weights("express=-1").is_err());
+        assert!(parse_weights("express=0").is_err());
+        assert!(parse_weights("").is_err());
+    }
+}
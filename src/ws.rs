@@ -0,0 +1,360 @@
+//! WebSocket broadcast output: a small server that pushes every message to all
+//! connected clients as a text frame.
+
+use crate::sink::{Sink, SinkError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a new client may take to send its upgrade request.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+/// A client that can't take a frame within this long is dropped, so one slow browser
+/// can't hold up the others.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Messages queued for the broadcaster before sends start failing.
+const QUEUE_CAPACITY: usize = 10_000;
+/// Appended to the client's key to derive `Sec-WebSocket-Accept` (RFC 6455).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// SHA-1 digest, needed only for the handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard padded base64.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+/// An unmasked server-to-client frame with the given opcode.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Upgrade a new connection to a WebSocket. A request that isn't a WebSocket upgrade is
+/// answered 400, and one for a protocol version other than 13 (RFC 6455) 426.
+fn handshake(mut stream: TcpStream) -> io::Result<TcpStream> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let (mut key, mut upgrade, mut version) = (None, false, None);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            } else if name.eq_ignore_ascii_case("sec-websocket-version") {
+                version = Some(value.to_string());
+            }
+        }
+    }
+    let key = match key {
+        Some(key) if upgrade && version.as_deref() == Some("13") => key,
+        Some(_) if upgrade => {
+            stream.write_all(
+                b"HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported WebSocket version"));
+        }
+        _ => {
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WebSocket upgrade request"));
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Broadcasts every message to the WebSocket clients connected at the time. Clients are
+/// accepted on a background thread and upgraded on one of their own, so a client slow to
+/// send its request doesn't hold up the next. Another thread, fed by a bounded channel,
+/// writes the frames, so slow clients never block the generator loop.
+pub struct WsSink {
+    addr: SocketAddr,
+    sender: Option<SyncSender<String>>,
+    broadcaster: Option<JoinHandle<()>>,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl WsSink {
+    /// Bind the server up front, so a taken address fails before streaming.
+    pub fn listen(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let clients: Arc<Mutex<Vec<TcpStream>>> = Arc::new(Mutex::new(Vec::new()));
+        {
+            let clients = clients.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let clients = clients.clone();
+                    thread::spawn(move || {
+                        let peer = stream.peer_addr().ok();
+                        if let Ok(client) = handshake(stream) {
+                            if let Some(peer) = peer {
+                                eprintln!("WebSocket client connected from {}", peer);
+                            }
+                            clients.lock().unwrap().push(client);
+                        }
+                    });
+                }
+            });
+        }
+        let (sender, receiver) = mpsc::sync_channel::<String>(QUEUE_CAPACITY);
+        let broadcaster = {
+            let clients = clients.clone();
+            thread::spawn(move || {
+                let broadcast = |frame: &[u8]| {
+                    // A failed or timed-out write means the client is gone or too slow.
+                    clients.lock().unwrap().retain_mut(|client| client.write_all(frame).is_ok());
+                };
+                for message in receiver {
+                    broadcast(&frame(0x1, message.as_bytes()));
+                }
+                // The sender was dropped at shutdown: say goodbye.
+                broadcast(&frame(0x8, &[]));
+            })
+        };
+        Ok(WsSink {
+            addr,
+            sender: Some(sender),
+            broadcaster: Some(broadcaster),
+            clients,
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Sink for WsSink {
+    fn name(&self) -> &'static str {
+        "ws"
+    }
+
+    fn send(&mut self, payload: &str, _key: &str) -> Result<(), SinkError> {
+        let Some(sender) = &self.sender else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "server closed").into());
+        };
+        match sender.try_send(payload.to_string()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(io::Error::new(io::ErrorKind::WouldBlock, "broadcast queue full").into()),
+            Err(TrySendError::Disconnected(_)) => Err(io::Error::new(io::ErrorKind::NotConnected, "broadcaster stopped").into()),
+        }
+    }
+
+    /// Broadcast what's queued, send every client a close frame, and disconnect them.
+    fn flush(&mut self) {
+        self.sender = None;
+        if let Some(broadcaster) = self.broadcaster.take() {
+            let _ = broadcaster.join();
+        }
+        for client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Instant;
+
+    #[test]
+    fn test_handshake_digests() {
+        let hex: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        // The worked example from RFC 6455, section 1.3.
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_frame_lengths() {
+        assert_eq!(frame(0x1, b"hi"), vec![0x81, 2, b'h', b'i']);
+        assert_eq!(&frame(0x1, &[0; 300])[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(&frame(0x1, &[0; 70_000])[..10], &[0x81, 127, 0, 0, 0, 0, 0, 1, 0x11, 0x70]);
+    }
+
+    fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        let mut payload = vec![0; (header[1] & 0x7f) as usize];
+        stream.read_exact(&mut payload).unwrap();
+        (header[0], payload)
+    }
+
+    /// Send an upgrade request with the given extra header lines, returning the response
+    /// head.
+    fn request(client: &mut TcpStream, headers: &str) -> String {
+        write!(
+            client,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n",
+            headers
+        )
+        .unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            response.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        response
+    }
+
+    const UPGRADE: &str = "Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n";
+
+    #[test]
+    fn test_broadcasts_to_connected_clients() {
+        let mut sink = WsSink::listen("127.0.0.1:0").unwrap();
+        // Sent before anyone connects, so nobody receives it.
+        sink.send("early", "").unwrap();
+
+        let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+        let response = request(&mut client, UPGRADE);
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.clients.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        sink.send(r#"{"a":1}"#, "").unwrap();
+        sink.flush();
+
+        assert_eq!(read_frame(&mut client), (0x81, br#"{"a":1}"#.to_vec()));
+        assert_eq!(read_frame(&mut client), (0x88, Vec::new()));
+        assert!(sink.send("late", "").is_err());
+    }
+
+    #[test]
+    fn test_rejects_plain_http() {
+        let sink = WsSink::listen("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn test_rejects_bad_upgrade_headers() {
+        let sink = WsSink::listen("127.0.0.1:0").unwrap();
+        let cases = [
+            ("Sec-WebSocket-Version: 13\r\n", "HTTP/1.1 400"),
+            ("Upgrade: h2c\r\nSec-WebSocket-Version: 13\r\n", "HTTP/1.1 400"),
+            ("Upgrade: websocket\r\n", "HTTP/1.1 426"),
+            ("Upgrade: websocket\r\nSec-WebSocket-Version: 8\r\n", "HTTP/1.1 426"),
+        ];
+        for (headers, status) in cases {
+            let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+            let response = request(&mut client, headers);
+            assert!(response.starts_with(status), "{:?} got {}", headers, response);
+        }
+        assert!(sink.clients.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_a_silent_client_does_not_hold_up_the_next() {
+        let sink = WsSink::listen("127.0.0.1:0").unwrap();
+        // Connects but never sends its request.
+        let _silent = TcpStream::connect(sink.local_addr()).unwrap();
+        let start = Instant::now();
+        let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+        assert!(request(&mut client, UPGRADE).starts_with("HTTP/1.1 101"));
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT, "handshake waited on the silent client");
+    }
+}
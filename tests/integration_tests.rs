@@ -1,32 +1,73 @@
 use assert_cmd::prelude::*;
-use std::process::{Command, Stdio};
+use std::process::{Command, Output, Stdio};
 use std::time::Duration;
 use std::thread;
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
 
-/// Integration test that launches the built app, lets it run briefly,
-/// sends SIGINT, and then verifies the output includes JSON messages and a CSV dump.
-#[test]
-fn integration_test_market_module_messages_and_app_graceful_shutdown() -> Result<(), Box<dyn std::error::Error>> {
-    // Spawn the binary with a slow message rate so that we can capture output easily.
-    // Here, we use --module market, --mps 1 (one message per second), and one variant.
+/// Launch the built app with the given arguments, let it run for `run_for`,
+/// then send SIGINT (as if the user pressed Ctrl+C) and collect its output.
+fn run_and_interrupt(args: &[&str], run_for: Duration) -> Result<Output, Box<dyn std::error::Error>> {
     let child = Command::cargo_bin("fluxfakr")?
-        .args(&["--module", "stock", "--mps", "1", "--variants", "1"])
+        .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()?;
 
-    // Let the app run for a few seconds so that it produces some output.
-    thread::sleep(Duration::from_secs(3));
+    // Let the app run so that it produces some output.
+    thread::sleep(run_for);
 
     // Send SIGINT to the process to trigger graceful shutdown.
-    // (This simulates the user pressing Ctrl+C.)
     let pid = child.id() as i32;
     kill(Pid::from_raw(pid), Signal::SIGINT)?;
 
     // Wait for the process to exit and capture its output.
-    let output = child.wait_with_output()?;
+    Ok(child.wait_with_output()?)
+}
+
+/// Primary smoke test: a seeded, simulated-clock run with `--count` is fully
+/// deterministic, so it needs no sleeping or signalling and can assert exactly.
+#[test]
+fn integration_test_count_run_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let args = [
+        "--module", "stock", "--mps", "1000", "--variants", "2", "--count", "10",
+        "--seed", "7", "--start-time", "1700000000",
+    ];
+    let output = Command::cargo_bin("fluxfakr")?.args(args).output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+
+    let messages: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(messages.len(), 10);
+    for (i, message) in messages.iter().enumerate() {
+        assert_eq!(message["timestamp"], 1_700_000_000 + i as u64);
+    }
+    assert!(stdout.contains("--- Generator Internal State Dump ---"));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("messages: 10"));
+    assert!(stderr.contains("Seed: 7") && !stdout.contains("Seed:"));
+
+    // The same seed and clock reproduce the stream exactly.
+    let rerun = Command::cargo_bin("fluxfakr")?.args(args).output()?;
+    assert_eq!(String::from_utf8(rerun.stdout)?, stdout);
+
+    Ok(())
+}
+
+/// Integration test that launches the built app, lets it run briefly,
+/// sends SIGINT, and then verifies the output includes JSON messages and a CSV dump.
+#[test]
+fn integration_test_market_module_messages_and_app_graceful_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+    // Use a slow message rate (one message per second) and one variant so that
+    // we can capture output easily.
+    let output = run_and_interrupt(
+        &["--module", "stock", "--mps", "1", "--variants", "1"],
+        Duration::from_secs(3),
+    )?;
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
     let combined_output = format!("{}{}", stdout, stderr);
@@ -45,6 +86,55 @@ fn integration_test_market_module_messages_and_app_graceful_shutdown() -> Result
         combined_output.contains("Generator Internal State Dump"),
         "Expected output to contain the CSV dump (internal state)"
     );
+    // The run summary goes to stderr on exit.
+    assert!(
+        stderr.contains("--- Run Summary ---") && stderr.contains("stdout:"),
+        "Expected a run summary on stderr"
+    );
+    // The end-of-stream marker is opt-in and must not appear by default.
+    assert!(!combined_output.contains("_eos"), "Unexpected end-of-stream marker");
+
+    Ok(())
+}
 
+/// With `--eos-marker`, the last streamed line before the state dump is the sentinel.
+#[test]
+fn integration_test_eos_marker_emitted_on_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+    let output = run_and_interrupt(
+        &["--module", "stock", "--mps", "10", "--variants", "1", "--eos-marker"],
+        Duration::from_secs(1),
+    )?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stream = stdout.split("--- Generator Internal State Dump ---").next().unwrap();
+    let last_line = stream.lines().rev().find(|line| !line.trim().is_empty()).unwrap();
+    assert_eq!(last_line, r#"{"_eos":true}"#);
+
+    Ok(())
+}
+
+/// Throughput of the stock stream written to /dev/null at an unreachable rate, plain and
+/// with `--emit-deltas`: the median of five 2,000,000-message runs. Run it on a release
+/// build with `cargo test --release --test integration_tests bench_stock_throughput -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_stock_throughput() -> Result<(), Box<dyn std::error::Error>> {
+    const MESSAGES: u32 = 2_000_000;
+    for extra in [&[][..], &["--emit-deltas"][..]] {
+        let mut rates = Vec::new();
+        for _ in 0..5 {
+            let start = std::time::Instant::now();
+            let status = Command::cargo_bin("fluxfakr")?
+                .args(["--module", "stock", "--variants", "10", "--mps", "100000000", "--seed", "1"])
+                .args(["--count", &MESSAGES.to_string()])
+                .args(extra)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            assert!(status.success());
+            rates.push(MESSAGES as f64 / start.elapsed().as_secs_f64());
+        }
+        rates.sort_by(f64::total_cmp);
+        println!("stock {:<14} {:>9.0} msg/s", extra.join(" "), rates[rates.len() / 2]);
+    }
     Ok(())
 }